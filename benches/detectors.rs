@@ -0,0 +1,193 @@
+//! Run time of the threshold-based detectors over a shared `DatasetStats`.
+//!
+//! The dataset is synthetic: `BENCH_TXS` transactions (default 100 000) sent by
+//! 10 000 accounts, one in ten of them by a single busy hot wallet, plus an
+//! ERC-20 transfer for every fifth transaction.
+//!
+//! The same six detectors (`large_tx`, `structuring`, `high_fee`,
+//! `high_priority_fee`, `unusual_op`, `whales`) are run
+//!
+//! * `shared` – in one `AnalysisContext`, so percentiles and per-sender means
+//!   are computed once for all of them, and
+//! * `per_detector` – each in a fresh context, so every detector computes the
+//!   whole `DatasetStats` for itself. This measures what sharing the
+//!   statistics saves across detectors: six sorts of the dataset instead of
+//!   one, not the cost detectors had before the statistics existed.
+//!
+//! `legacy_per_sender` reproduces the percentiles as `detect_whales` and
+//! `detect_structuring` computed them before `DatasetStats`: both sorted every
+//! value of the dataset again for each sender. With up to 10 000 senders this
+//! is quadratic in the dataset size, so it only runs when `BENCH_TXS` is at
+//! most 20 000; compare it with `stats`, which sorts the values once.
+//!
+//! ```sh
+//! cargo bench --bench detectors
+//! BENCH_TXS=5000 cargo bench --bench detectors
+//! ```
+
+use chrono::{DateTime, Utc};
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use eth_analyzer::{
+    analize::{
+        detect_high_fee, detect_high_priority_fee, detect_large_tx, detect_structuring,
+        detect_unusual_op, detect_whales,
+    },
+    config::AnalyzerConfig,
+    detector::AnalysisContext,
+    models::{TokenTransfer, TransactionRecord, TxStorage},
+    stats::{DatasetStats, Distribution},
+};
+use ethers::types::{Address, H256, U256};
+use std::hint::black_box;
+use std::sync::Arc;
+use tokio::runtime::Runtime;
+
+const SENDERS: u64 = 10_000;
+
+/// Largest `BENCH_TXS` the `legacy_per_sender` benchmark runs on.
+const LEGACY_MAX_TXS: u64 = 20_000;
+
+fn env_or(name: &str, default: u64) -> u64 {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+/// Synthetic storage, see the module documentation.
+async fn dataset() -> Arc<TxStorage> {
+    let txs = env_or("BENCH_TXS", 100_000);
+    let start = DateTime::<Utc>::from_timestamp(1_700_000_000, 0).unwrap();
+    let hot_wallet = Address::from_low_u64_be(SENDERS + 1);
+    let token = Address::from_low_u64_be(SENDERS + 2);
+
+    let storage = TxStorage::new();
+    for n in 0..txs {
+        let from = if n.is_multiple_of(10) {
+            hot_wallet
+        } else {
+            Address::from_low_u64_be(n % SENDERS + 1)
+        };
+        let tx = TransactionRecord {
+            hash: H256::from_low_u64_be(n + 1),
+            from,
+            to: Some(Address::from_low_u64_be((n * 7) % SENDERS + 1)),
+            value: U256::exp10(15) * (n % 1000),
+            gas: 21_000,
+            gas_price: U256::exp10(9) * (20 + n % 40),
+            block_number: n / 4,
+            timestamp: start + chrono::Duration::seconds(n as i64 * 3),
+            input: "0x".to_string(),
+            gas_used: Some(21_000),
+            effective_gas_price: Some(U256::exp10(9) * (20 + n % 40)),
+            status: Some(true),
+            contract_address: None,
+            tx_type: Some(2),
+            max_fee_per_gas: Some(U256::exp10(9) * 80),
+            max_priority_fee_per_gas: Some(U256::exp10(9)),
+            base_fee_per_gas: Some(U256::exp10(9) * 19),
+        };
+        if n.is_multiple_of(5) {
+            storage
+                .insert_transfer(TokenTransfer {
+                    tx_hash: tx.hash,
+                    log_index: 0,
+                    block_number: tx.block_number,
+                    timestamp: tx.timestamp,
+                    token,
+                    from: tx.from,
+                    to: Address::from_low_u64_be((n * 13) % SENDERS + 1),
+                    amount: U256::from(n % 5000),
+                })
+                .await;
+        }
+        storage.insert(tx).await;
+    }
+    Arc::new(storage)
+}
+
+/// Runs the six detectors, each in `context()`.
+async fn run_detectors(
+    config: &AnalyzerConfig,
+    context: impl Fn() -> Arc<AnalysisContext>,
+) -> usize {
+    let mut findings = 0;
+    findings += detect_large_tx(&context(), &config.large_tx).await.len();
+    findings += detect_structuring(&context(), &config.structuring)
+        .await
+        .len();
+    findings += detect_high_fee(&context(), &config.high_fee).await.len();
+    findings += detect_high_priority_fee(&context(), &config.high_priority_fee)
+        .await
+        .len();
+    findings += detect_unusual_op(&context(), &config.unusual_op)
+        .await
+        .len();
+    findings += detect_whales(&context(), &config.whales).await.len();
+    findings
+}
+
+/// The percentiles of `detect_whales` and `detect_structuring` before
+/// `DatasetStats`: for each sender, both detectors sorted all the values again.
+async fn legacy_per_sender_percentiles(storage: &TxStorage, config: &AnalyzerConfig) -> usize {
+    let all_txs = storage.all_txs.read().await;
+    let mut above = 0;
+    for entry in storage.by_sender.iter() {
+        for perc in [config.whales.percentile, config.structuring.percentile] {
+            let values = Distribution::new(all_txs.iter().map(|tx| tx.value).collect());
+            let threshold = values.percentile(perc);
+            above += entry
+                .value()
+                .iter()
+                .filter(|tx| tx.value > threshold)
+                .count();
+        }
+    }
+    above
+}
+
+fn detectors(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let storage = runtime.block_on(dataset());
+    let txs = runtime.block_on(async { storage.all_txs.read().await.len() }) as u64;
+    let config = AnalyzerConfig::default();
+
+    let mut group = c.benchmark_group("detectors");
+    group.sample_size(10);
+    group.measurement_time(std::time::Duration::from_secs(20));
+    group.throughput(Throughput::Elements(txs));
+    group.bench_function("stats", |b| {
+        b.to_async(&runtime)
+            .iter(|| async { black_box(DatasetStats::compute(storage.as_ref()).await) })
+    });
+    group.bench_function("shared", |b| {
+        b.to_async(&runtime).iter(|| async {
+            let ctx = Arc::new(AnalysisContext::new(storage.clone()));
+            black_box(run_detectors(&config, || ctx.clone()).await)
+        })
+    });
+    // One `DatasetStats` per detector, not the old per-sender recomputation.
+    group.bench_function("per_detector", |b| {
+        b.to_async(&runtime).iter(|| async {
+            black_box(
+                run_detectors(&config, || Arc::new(AnalysisContext::new(storage.clone()))).await,
+            )
+        })
+    });
+    if txs <= LEGACY_MAX_TXS {
+        group.bench_function("legacy_per_sender", |b| {
+            b.to_async(&runtime).iter(|| async {
+                black_box(legacy_per_sender_percentiles(&storage, &config).await)
+            })
+        });
+    } else {
+        eprintln!(
+            "Skipping legacy_per_sender: quadratic, run it with BENCH_TXS <= {}",
+            LEGACY_MAX_TXS
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, detectors);
+criterion_main!(benches);
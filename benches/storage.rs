@@ -0,0 +1,198 @@
+//! Memory footprint and ingestion throughput of `TxStorage`.
+//!
+//! The dataset is synthetic: `BENCH_BLOCKS` blocks (default 100 000) of
+//! `BENCH_TXS_PER_BLOCK` transactions (default 4) sent by 10 000 accounts, a
+//! third of them ERC-20 `transfer` calls. Every record is ingested into the
+//! shared-record `TxStorage` and, for comparison, into a layout that copies the
+//! record into `all_txs`, `by_sender` and `by_reciever` like the storage did
+//! before records were shared.
+//!
+//! ```sh
+//! cargo bench --bench storage
+//! BENCH_BLOCKS=10000 cargo bench --bench storage
+//! ```
+//!
+//! Memory is measured with a counting global allocator and printed before the
+//! throughput results.
+
+use chrono::{DateTime, Utc};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use dashmap::DashMap;
+use eth_analyzer::models::{TransactionRecord, TxStorage};
+use ethers::types::{Address, H256, U256};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::runtime::Runtime;
+
+/// Global allocator that keeps track of the bytes currently allocated.
+struct CountingAllocator;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            ALLOCATED.fetch_add(new_size, Ordering::Relaxed);
+            ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        }
+        new_ptr
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+const SENDERS: u64 = 10_000;
+
+/// The storage layout before records were shared: three full copies.
+#[derive(Default)]
+struct CopiedStorage {
+    by_sender: DashMap<Address, Vec<TransactionRecord>>,
+    by_reciever: DashMap<Address, Vec<TransactionRecord>>,
+    all_txs: Vec<TransactionRecord>,
+}
+
+impl CopiedStorage {
+    fn insert(&mut self, tx: TransactionRecord) {
+        self.by_sender.entry(tx.from).or_default().push(tx.clone());
+        if let Some(to) = tx.to {
+            self.by_reciever.entry(to).or_default().push(tx.clone());
+        }
+        self.all_txs.push(tx);
+    }
+}
+
+fn env_or(name: &str, default: u64) -> u64 {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+/// Synthetic blocks of transactions, see the module documentation.
+fn dataset() -> Vec<Vec<TransactionRecord>> {
+    let blocks = env_or("BENCH_BLOCKS", 100_000);
+    let txs_per_block = env_or("BENCH_TXS_PER_BLOCK", 4);
+    let start = DateTime::<Utc>::from_timestamp(1_700_000_000, 0).unwrap();
+
+    (0..blocks)
+        .map(|block| {
+            (0..txs_per_block)
+                .map(|i| {
+                    let n = block * txs_per_block + i;
+                    let input = if n.is_multiple_of(3) {
+                        format!("0xa9059cbb{:0128x}", n)
+                    } else {
+                        "0x".to_string()
+                    };
+                    TransactionRecord {
+                        hash: H256::from_low_u64_be(n + 1),
+                        from: Address::from_low_u64_be(n % SENDERS + 1),
+                        to: Some(Address::from_low_u64_be((n * 7) % SENDERS + 1)),
+                        value: U256::exp10(15) * (n % 1000),
+                        gas: 21_000,
+                        gas_price: U256::exp10(9) * 30,
+                        block_number: block,
+                        timestamp: start + chrono::Duration::seconds(block as i64 * 12),
+                        input,
+                        gas_used: Some(21_000),
+                        effective_gas_price: Some(U256::exp10(9) * 28),
+                        status: Some(true),
+                        contract_address: None,
+                        tx_type: Some(2),
+                        max_fee_per_gas: Some(U256::exp10(9) * 40),
+                        max_priority_fee_per_gas: Some(U256::exp10(9)),
+                        base_fee_per_gas: Some(U256::exp10(9) * 27),
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+async fn ingest_shared(blocks: Vec<Vec<TransactionRecord>>) -> TxStorage {
+    let storage = TxStorage::new();
+    for tx in blocks.into_iter().flatten() {
+        storage.insert(tx).await;
+    }
+    storage
+}
+
+fn ingest_copied(blocks: Vec<Vec<TransactionRecord>>) -> CopiedStorage {
+    let mut storage = CopiedStorage::default();
+    for tx in blocks.into_iter().flatten() {
+        storage.insert(tx);
+    }
+    storage
+}
+
+/// Bytes still allocated by the value `build` returns.
+fn retained<T>(build: impl FnOnce() -> T) -> usize {
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    let value = build();
+    let after = ALLOCATED.load(Ordering::Relaxed);
+    drop(black_box(value));
+    after.saturating_sub(before)
+}
+
+fn memory(_: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let data = dataset();
+    let txs = data.iter().map(Vec::len).sum::<usize>();
+
+    // The records themselves move into the storage, so the dataset is cloned
+    // inside the measurement.
+    let shared = retained(|| runtime.block_on(ingest_shared(data.clone())));
+    let copied = retained(|| ingest_copied(data.clone()));
+
+    let mib = |bytes: usize| bytes as f64 / (1024.0 * 1024.0);
+    println!(
+        "storage/memory: {} blocks, {} transactions",
+        data.len(),
+        txs
+    );
+    println!(
+        "storage/memory: shared records {:.1} MiB ({} B/tx), copied records {:.1} MiB ({} B/tx)",
+        mib(shared),
+        shared / txs.max(1),
+        mib(copied),
+        copied / txs.max(1)
+    );
+}
+
+fn ingestion(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let data = dataset();
+    let txs = data.iter().map(Vec::len).sum::<usize>() as u64;
+
+    let mut group = c.benchmark_group("storage/ingest");
+    group.sample_size(10);
+    group.measurement_time(std::time::Duration::from_secs(10));
+    group.throughput(Throughput::Elements(txs));
+    group.bench_function("shared", |b| {
+        b.to_async(&runtime)
+            .iter_batched(|| data.clone(), ingest_shared, BatchSize::PerIteration)
+    });
+    group.bench_function("copied", |b| {
+        b.iter_batched(|| data.clone(), ingest_copied, BatchSize::PerIteration)
+    });
+    group.finish();
+}
+
+criterion_group!(benches, memory, ingestion);
+criterion_main!(benches);
@@ -0,0 +1,76 @@
+use ethers::{types::Address, utils::to_checksum};
+use serde::{de, Deserialize, Deserializer};
+use std::fmt;
+
+/// Error returned by [`parse_address`] for text that is not an address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidAddress(pub String);
+
+impl fmt::Display for InvalidAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "`{}` is not an address", self.0)
+    }
+}
+
+impl std::error::Error for InvalidAddress {}
+
+/// Parses an address written in any case.
+///
+/// Addresses are compared as 20-byte values, never as text, so lowercase,
+/// uppercase and EIP-55 checksummed spellings of an address are the same
+/// [`Address`]. Every address read from outside the node (imported files,
+/// sanctions lists, the configuration) goes through this function.
+///
+/// # Parameters
+/// * `value` – 40 hex digits with an optional `0x`/`0X` prefix. Surrounding
+///   whitespace is ignored.
+///
+/// # Errors
+/// Returns [`InvalidAddress`] for any other text. A mixed-case address with a
+/// wrong checksum is accepted: the checksum is a typing aid, not part of the
+/// address.
+///
+/// # Example
+/// ```rust,ignore
+/// let checksummed = parse_address("0x7a250d5630B4cF539739dF2C5dAcb4c659F2488D")?;
+/// let lowercase = parse_address("0x7a250d5630b4cf539739df2c5dacb4c659f2488d")?;
+/// assert_eq!(checksummed, lowercase);
+/// ```
+pub fn parse_address(value: &str) -> Result<Address, InvalidAddress> {
+    let trimmed = value.trim();
+    let digits = trimmed
+        .strip_prefix("0x")
+        .or_else(|| trimmed.strip_prefix("0X"))
+        .unwrap_or(trimmed);
+    if digits.len() != 40 || !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(InvalidAddress(value.to_string()));
+    }
+    digits
+        .parse()
+        .map_err(|_| InvalidAddress(value.to_string()))
+}
+
+/// Canonical text form of an address: `0x` followed by 40 lowercase hex digits.
+///
+/// Used for the addresses of anomalies, patterns, CSV exports and the SQLite
+/// store, so two findings about the same account always carry the same string.
+pub fn format_address(address: &Address) -> String {
+    format!("{:?}", address)
+}
+
+/// EIP-55 checksummed form of an address, for display to people.
+pub fn checksummed(address: &Address) -> String {
+    to_checksum(address, None)
+}
+
+/// Deserializes a list of addresses with [`parse_address`], for configuration
+/// fields (`#[serde(deserialize_with = "deserialize_addresses")]`).
+pub fn deserialize_addresses<'de, D>(deserializer: D) -> Result<Vec<Address>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|value| parse_address(value).map_err(de::Error::custom))
+        .collect()
+}
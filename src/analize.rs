@@ -1,13 +1,25 @@
 use crate::{
-    config::{K_LOCAL, K_LOCAL_FEE, PERC, THRESHOLD_TIME},
-    models::{Anomaly, BusinessPattern, Severity, SharedTxStorage, TransactionRecord},
-    scanner::fetch_sanctioned_addresses,
+    address::format_address,
+    config::{
+        ActiveTradersConfig, BatchPaymentsConfig, HighFeeConfig, HighFrequencyConfig,
+        HighPriorityFeeConfig, LargeTxConfig, RegularPaymentsConfig, StructuringConfig,
+        TimeAnomalyConfig, UnusualOpConfig, WhalesConfig,
+    },
+    detector::AnalysisContext,
+    models::{Anomaly, BusinessPattern, Severity, TransactionRecord},
+    sanctions::SanctionsList,
+    stats::Baseline,
+    units::{cmp_scaled, format_eth, format_gwei, mean, saturating_sum},
 };
+#[cfg(doc)]
+use crate::query::TxQuery;
 use chrono::{DateTime, Duration, Timelike, Utc};
 use ethers::prelude::*;
-use once_cell::sync::Lazy;
-use std::collections::{HashMap, HashSet};
-use tokio::sync::RwLock;
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::{HashMap, HashSet},
+    ops::Range,
+};
 
 abigen!(
     UniswapV2Factory,
@@ -17,43 +29,57 @@ abigen!(
     ]"#
 );
 
-static FLAGGED_HASHES: Lazy<RwLock<HashSet<String>>> = Lazy::new(|| RwLock::new(HashSet::new()));
-
 /// Scans all stored transactions and flags unusually large transfers as anomalies.
 /// # Overview
-/// This function inspects every transaction in [`SharedTxStorage`] and determines
+/// This function inspects every transaction in [`TxQuery`] source and determines
 /// whether it is significantly larger than typical activity, based on two criteria:
 ///
-/// * **Local threshold:** Compares the transaction value against the historical mean
-///   transaction value for the same sender (`local_mean`). If the value is more than
-///   `K_LOCAL * local_mean`, it is considered anomalous.
+/// * **Local threshold:** Compares the transaction value against the median and
+///   median absolute deviation (MAD) of the sender's *other* transactions (see
+///   [`Baseline`]). If its robust z-score, `(value - median) / (1.4826 * MAD)`,
+///   exceeds `config.z_threshold`, it is considered anomalous. When all other
+///   values are equal there is no MAD, and the value must exceed `config.flat_ratio`
+///   times them instead; a history of zero-value calls never flags a payment.
+///   Senders with fewer than `config.min_history` other transactions are not
+///   judged locally. Values are compared exactly in wei (see [`cmp_scaled`]).
 /// * **Global threshold:** Compares the transaction value against a global threshold
-///   derived from all transactions (`global_threshold`). If the value exceeds it,
+///   derived from all transactions (`global_threshold` at `config.percentile`). If the value exceeds it,
 ///   it is considered anomalous.
+///
 /// If either the local or global condition is triggered, an [`Anomaly::LargeTx`]
 /// is created and added to the result vector. If both conditions are triggered,
 /// the anomaly is marked with a higher severity (`Severity::Strong`).
+///
+/// With `config.include_tokens`, the same check runs over the ERC-20 transfers in
+/// `storage.token_transfers`, separately for every token: both thresholds are
+/// computed from transfers of that token only, and the anomaly's `token` is set.
 /// # Parameters
-/// * `storage` — A shared transaction storage that provides read access to all
-///   recorded transactions via an asynchronous `RwLock`.
+/// * `ctx` — The analysis context of the current run; its storage provides read
+///   access to all recorded transactions via an asynchronous `RwLock`.
+/// * `config` — Percentile and local multiplier, see [`LargeTxConfig`].
 /// # Returns
 /// A `Vec<Anomaly>` containing one [`Anomaly::LargeTx`] entry for each flagged
-/// transaction. If no transactions exceed either threshold, an empty vector is returned.
+/// transaction, with the sender's median and the z-score whenever the sender has
+/// enough history. If no transactions exceed either threshold, an empty vector is returned.
 /// # Side Effects
-/// * Each flagged transaction hash is inserted into the global `FLAGGED_HASHES` set
-///   to prevent duplicate flagging in subsequent analyses.
+/// * Each flagged transaction hash is recorded in the context with
+///   [`AnalysisContext::flag`] so that `detect_unusual_op` does not report it again.
 /// # Behavior
 /// * **Strong severity:** Both local and global thresholds are exceeded.
 /// * **Weak severity:** Only one of the thresholds is exceeded.
 /// * Non-anomalous transactions are ignored and do not appear in the result.
+/// * A few huge transfers do not hide each other: the median and the MAD barely
+///   move with extreme values, and the judged value is left out of its own
+///   baseline. A mean that includes the value, as used before, made a single
+///   outlier raise its own threshold and could never flag a sender's only transaction.
 /// # Panics
-/// This function does **not panic** on invalid timestamps — it falls back to
-/// `Utc::now()` if the timestamp cannot be parsed from RFC 3339 format.
-/// /// # Example
+/// This function does **not panic**: timestamps are already typed in
+/// [`TransactionRecord`], so nothing is parsed here.
+/// # Example
 ///
 /// ```rust,ignore
 /// // Given a populated `SharedTxStorage`
-/// let anomalies = detect_large_tx(&storage).await;
+/// let anomalies = detect_large_tx(&ctx, &config.large_tx).await;
 /// for anomaly in anomalies {
 ///     match anomaly {
 ///         Anomaly::LargeTx { tx_hash, severity, .. } => {
@@ -63,86 +89,97 @@ static FLAGGED_HASHES: Lazy<RwLock<HashSet<String>>> = Lazy::new(|| RwLock::new(
 ///     }
 /// }
 /// ```
-pub async fn detect_large_tx(storage: &SharedTxStorage) -> Vec<Anomaly> {
+pub async fn detect_large_tx(ctx: &AnalysisContext, config: &LargeTxConfig) -> Vec<Anomaly> {
+    let stats = ctx.stats().await;
     let mut anomalies: Vec<Anomaly> = Vec::new();
-    let all_txs = storage.all_txs.read().await;
-    let global_thershold = global_threshold(storage).await;
-    for tx in all_txs.iter() {
-        let sender = tx.from.clone();
-        let local_mean = local_mean(storage, &sender);
-
-        let local_flag = local_mean > 0.0 && tx.value > K_LOCAL * local_mean;
-        let global_flag = tx.value > global_thershold;
-
-        let timestamp = DateTime::parse_from_rfc3339(&tx.timestamp)
-            .unwrap_or_else(|_| Utc::now().into())
-            .with_timezone(&Utc);
-        match (local_flag, global_flag) {
-            (true, true) => {
-                anomalies.push(Anomaly::LargeTx {
-                    tx_hash: tx.hash.clone(),
-                    severity: Severity::Strong,
-                    reasons: vec![format!("Suspiciously large transaction: {}", &tx.value)],
-                    timestamp: timestamp,
-                });
-                let mut hashes = FLAGGED_HASHES.write().await;
-                hashes.insert(tx.hash.clone());
-            }
-            (true, false) | (false, true) => {
-                anomalies.push(Anomaly::LargeTx {
-                    tx_hash: tx.hash.clone(),
-                    severity: Severity::Weak,
-                    reasons: vec![format!("Suspiciously large transaction: {}", &tx.value)],
-                    timestamp: timestamp,
-                });
-                let mut hashes = FLAGGED_HASHES.write().await;
-                hashes.insert(tx.hash.clone());
+    let mut flagged = Vec::new();
+    for asset in stats.assets(config.include_tokens) {
+        let global_thershold = asset.values.percentile(config.percentile);
+        for movement in asset.movements.iter() {
+            let baseline = asset
+                .baseline(&movement.from, movement.amount)
+                .filter(|baseline| baseline.history >= config.min_history);
+
+            let local_flag = baseline.is_some_and(|baseline| {
+                baseline.exceeds(movement.amount, config.z_threshold, config.flat_ratio)
+            });
+            let global_flag = movement.amount > global_thershold;
+
+            let severity = match (local_flag, global_flag) {
+                (true, true) => Severity::Strong,
+                (true, false) | (false, true) => Severity::Weak,
+                (false, false) => continue,
+            };
+            let mut reasons = vec![format!(
+                "Suspiciously large transaction: {}",
+                asset.format_amount(movement.amount)
+            )];
+            if let Some(baseline) = baseline.filter(|_| local_flag) {
+                reasons.push(robust_reason(&baseline, movement.amount, |median| {
+                    asset.format_amount(median)
+                }));
             }
-            (false, false) => {}
-        };
+            anomalies.push(Anomaly::LargeTx {
+                tx_hash: format!("{:?}", movement.hash),
+                token: asset.token_label(),
+                severity,
+                median: baseline.map(|baseline| baseline.median),
+                z_score: baseline.and_then(|baseline| baseline.z_score(movement.amount)),
+                reasons,
+                timestamp: movement.timestamp,
+            });
+            flagged.push(movement.hash);
+        }
     }
+    ctx.flag_all(flagged).await;
 
     anomalies
 }
 
 /// Detects accounts that perform an unusually high number of transactions
-/// within a fixed time window and flags them as anomalies.
+/// within a sliding time window and flags them as anomalies.
 ///
 /// # Overview
 ///
 /// This function analyzes transactions grouped by sender address (using
 /// `storage.by_sender`) and counts how many transactions each sender
-/// has submitted within the **last hour**.  
-/// If the count exceeds [`THRESHOLD_TIME`], an [`Anomaly::HighFrequency`]
+/// has submitted within every window of `config.window_minutes` (one hour by
+/// default) of the analyzed data.  
+/// If a count exceeds `config.threshold`, an [`Anomaly::HighFrequency`]
 /// is created for that sender and added to the results.
 ///
 /// # Parameters
 ///
-/// * `storage` – A shared transaction storage containing a mapping from
-///   sender addresses to their corresponding list of transactions (`by_sender`).
+/// * `ctx` – The analysis context whose storage maps sender addresses to
+///   their corresponding list of transactions (`by_sender`).
+/// * `config` – Window length and transaction count threshold, see [`HighFrequencyConfig`].
 ///
 /// # Returns
 ///
 /// Returns a `Vec<Anomaly>` where each element represents a sender that
-/// exceeded the transaction frequency threshold in the last hour.
+/// exceeded the transaction frequency threshold in the window.
 ///
 /// # Behavior
 ///
-/// * Uses `Utc::now()` as the end of the interval and checks transactions
-///   that occurred between `start_interval = now - 1 hour` and `end_interval = now`.
-/// * If a sender's transaction count exceeds [`THRESHOLD_TIME`], a
-///   [`HighFrequency`] anomaly is generated with:
+/// * Windows are anchored on the data, not on the current time: the first one
+///   starts at the earliest transaction of the storage and each next one
+///   `config.step_minutes` later, so a historical block range is analyzed the
+///   same way whenever it is run. A window holds the transactions from its start
+///   (inclusive) to its end (exclusive).
+/// * If a sender's transaction count exceeds `config.threshold` in any window, one
+///   [`HighFrequency`] anomaly is generated for its busiest (then earliest) window with:
 ///   * `sender` — the offending account address as a string
-///   * `count` — number of transactions in the interval
+///   * `count` — number of transactions in that window
+///   * `window_start`, `window_end` — the bounds of that window
 ///   * `reasons` — human-readable message describing the anomaly
-/// * All transactions from that sender are also inserted into the global
-///   [`FLAGGED_HASHES`] set to mark them as already flagged.
+/// * The transactions of every window above the threshold are also flagged in
+///   the context to mark them as already reported.
 ///
 /// # Side Effects
 ///
-/// * Modifies the global `FLAGGED_HASHES` to include all hashes of
-///   transactions belonging to flagged senders. This prevents re-flagging
-///   the same transactions in subsequent runs.
+/// * Flags the hashes of the transactions in the offending windows in
+///   `ctx`. This prevents `detect_unusual_op` from reporting the same
+///   transactions again in this run.
 ///
 /// # Panics
 ///
@@ -152,47 +189,68 @@ pub async fn detect_large_tx(storage: &SharedTxStorage) -> Vec<Anomaly> {
 /// # Example
 ///
 /// ```rust,ignore
-/// let anomalies = detect_high_frequency(&storage).await;
+/// let anomalies = detect_high_frequency(&ctx, &config.high_frequency).await;
 /// for anomaly in anomalies {
 ///     match anomaly {
 ///         Anomaly::HighFrequency { sender, count, .. } => {
-///             println!("Sender {} made {} txs in the last hour!", sender, count);
+///             println!("Sender {} made {} txs within an hour!", sender, count);
 ///         }
 ///         _ => {}
 ///     }
 /// }
 /// ```
-pub async fn detect_high_frequency(storage: &SharedTxStorage) -> Vec<Anomaly> {
-    let end_interval = Utc::now();
-    let start_interval = end_interval - Duration::hours(1);
+pub async fn detect_high_frequency(
+    ctx: &AnalysisContext,
+    config: &HighFrequencyConfig,
+) -> Vec<Anomaly> {
+    let storage = &ctx.storage;
+    let length = Duration::minutes(config.window_minutes.into());
+    let step = Duration::minutes(config.step_minutes.into());
+    let by_sender = storage.txs_by_sender().await;
+    let Some(origin) = by_sender
+        .iter()
+        .flat_map(|(_, txs)| txs.iter().map(|tx| tx.timestamp))
+        .min()
+    else {
+        return Vec::new();
+    };
 
-    let by_senders = &storage.by_sender;
     let mut anomalies: Vec<Anomaly> = Vec::new();
-    for entry in by_senders.iter_mut() {
-        let sender = entry.key();
-        let txs = entry.value();
-
-        let count = txs_in_interval(txs, start_interval, end_interval)
+    for (sender, mut txs) in by_sender {
+        txs.sort_by_key(|tx| tx.timestamp);
+        let timestamps: Vec<DateTime<Utc>> = txs.iter().map(|tx| tx.timestamp).collect();
+        let busy: Vec<(Window, Range<usize>)> = sliding_windows(&timestamps, origin, length, step)
+            .into_iter()
+            .filter(|(_, range)| range.len() > config.threshold)
+            .collect();
+        let Some((window, range)) = busy
             .iter()
-            .count();
+            .max_by_key(|(window, range)| (range.len(), Reverse(window.start)))
+        else {
+            continue;
+        };
 
-        if count > THRESHOLD_TIME {
-            anomalies.push(Anomaly::HighFrequency {
-                sender: sender.to_string(),
-                count: count,
-                reasons: vec![format!("Too many transactions per hour: {}", &count)],
-            });
-            let mut hashes = FLAGGED_HASHES.write().await;
-            for tx in txs.iter() {
-                hashes.insert(tx.hash.clone());
-            }
-        }
+        let count = range.len();
+        anomalies.push(Anomaly::HighFrequency {
+            sender: format_address(&sender),
+            count,
+            window_start: window.start,
+            window_end: window.end,
+            reasons: vec![format!(
+                "Too many transactions per {} minutes: {} between {} and {}",
+                config.window_minutes, &count, window.start, window.end
+            )],
+        });
+        let flagged: Vec<H256> = busy
+            .iter()
+            .flat_map(|(_, range)| txs[range.clone()].iter().map(|tx| tx.hash))
+            .collect();
+        ctx.flag_all(flagged).await;
     }
 
     anomalies
 }
 
-
 /// Detects potential structuring behavior in account transactions.
 ///
 /// # Overview
@@ -200,18 +258,19 @@ pub async fn detect_high_frequency(storage: &SharedTxStorage) -> Vec<Anomaly> {
 /// Structuring (or "smurfing") refers to splitting large transactions into
 /// multiple smaller transactions to avoid detection thresholds.  
 /// This function analyzes transactions grouped by sender (`storage.by_sender`)
-/// over the **last hour** and flags accounts that may be performing structuring.
+/// within sliding windows of `config.window_minutes` (one hour by default) and
+/// flags accounts that may be performing structuring.
 ///
-/// For each sender, the function calculates:
-/// * `count` – number of transactions in the interval
-/// * `txs_sum` – total sum of transactions in the interval
+/// For each sender and window, the function calculates:
+/// * `count` – number of transactions in the window
+/// * `txs_sum` – total sum of transactions in the window
 /// * `local_mean` – sender-specific average transaction sum
 /// * `global_threshold` – global average transaction threshold
 ///
 /// Three flags are checked:
-/// 1. `local_flag` – triggered if `txs_sum > K_LOCAL * local_mean`
+/// 1. `local_flag` – triggered if `txs_sum > config.k_local * local_mean`
 /// 2. `global_flag` – triggered if `txs_sum > global_threshold`
-/// 3. `count_flag` – triggered if `count > 10`
+/// 3. `count_flag` – triggered if `count > config.count_threshold`
 ///
 /// An anomaly is marked:
 /// * `Severity::Strong` if all three flags are true
@@ -219,37 +278,45 @@ pub async fn detect_high_frequency(storage: &SharedTxStorage) -> Vec<Anomaly> {
 ///
 /// # Parameters
 ///
-/// * `storage` – Shared transaction storage containing a mapping from sender addresses
+/// * `ctx` – Analysis context whose storage maps sender addresses
 ///   to their corresponding list of transactions (`by_sender`).
+/// * `config` – Window length and thresholds, see [`StructuringConfig`].
 ///
 /// # Returns
 ///
 /// Returns a `Vec<Anomaly>` containing `Anomaly::Structuring` entries for senders
-/// suspected of structuring behavior. Each anomaly includes:
+/// suspected of structuring behavior, one per sender and asset for its most
+/// suspicious window (`Strong` first, then the largest sum, then the earliest).
+/// Each anomaly includes:
 /// * `sender` – the account address
-/// * `count` – number of transactions in the interval
+/// * `count` – number of transactions in the window
 /// * `severity` – `Weak` or `Strong` depending on flags
+/// * `window_start`, `window_end` – the bounds of the window
 /// * `reasons` – human-readable message describing why the sender was flagged
 ///
 /// # Side Effects
 ///
-/// * All transactions for flagged senders are added to the global
-///   `FLAGGED_HASHES` set to prevent duplicate flagging in future analyses.
+/// * The transactions of every suspicious window are flagged in `ctx` to prevent
+///   duplicate reports from `detect_unusual_op`.
 ///
 /// # Behavior
 ///
-/// * Evaluates transactions within a 1-hour rolling window (from `Utc::now() - 1h` to `Utc::now()`).
+/// * Evaluates every window of the analyzed data: the first starts at its earliest
+///   transaction and each next one `config.step_minutes` later. A window holds
+///   the transactions from its start (inclusive) to its end (exclusive).
 /// * Uses `local_mean` and `global_threshold` to identify anomalous sums.
 /// * Accounts that exceed only some thresholds are still flagged but with `Weak` severity.
 /// * Accounts that exceed all thresholds are flagged with `Strong` severity.
+/// * With `config.include_tokens`, ERC-20 transfers are checked per sender and
+///   token as well; the anomaly's `token` names the token contract.
 ///
 /// # Example
 ///
 /// ```rust,ignore
-/// let anomalies = detect_structuring(&storage).await;
+/// let anomalies = detect_structuring(&ctx, &config.structuring).await;
 /// for anomaly in anomalies {
 ///     match anomaly {
-///         Anomaly::Structuring { sender, count, severity, reasons } => {
+///         Anomaly::Structuring { sender, count, severity, reasons, .. } => {
 ///             println!(
 ///                 "Sender {} flagged with {} severity: {} transactions",
 ///                 sender, format!("{:?}", severity), count
@@ -262,65 +329,79 @@ pub async fn detect_high_frequency(storage: &SharedTxStorage) -> Vec<Anomaly> {
 ///     }
 /// }
 /// ```
-pub async fn detect_structuring(storage: &SharedTxStorage) -> Vec<Anomaly> {
-    let end_interval = Utc::now();
-    let start_interval = end_interval - Duration::hours(1);
+pub async fn detect_structuring(ctx: &AnalysisContext, config: &StructuringConfig) -> Vec<Anomaly> {
+    let length = Duration::minutes(config.window_minutes.into());
+    let step = Duration::minutes(config.step_minutes.into());
+    let assets = ctx.stats().await.assets(config.include_tokens);
+    let Some(origin) = assets
+        .iter()
+        .flat_map(|asset| asset.movements.iter().map(|m| m.timestamp))
+        .min()
+    else {
+        return Vec::new();
+    };
 
-    let by_senders = &storage.by_sender;
     let mut anomalies: Vec<Anomaly> = Vec::new();
-    for entry in by_senders.iter() {
-        let sender = entry.key();
-        let txs = entry.value();
-
-        let count = txs_in_interval(txs, start_interval, end_interval)
-            .iter()
-            .count();
-
-        let txs_sum = txs_in_interval(txs, start_interval, end_interval)
-            .iter()
-            .map(|tx| tx.value)
-            .sum::<f64>();
-
-        let local_mean = local_mean(storage, sender);
-        let global_thershold = global_threshold(storage).await;
-
-        let local_flag = local_mean > 0.0 && txs_sum > K_LOCAL * local_mean;
-        let global_flag = txs_sum > global_thershold;
-        let count_flag = count > 10;
-
-        match (local_flag, global_flag, count_flag) {
-            (true, true, true) => {
-                anomalies.push(Anomaly::Structuring {
-                    sender: sender.to_string(),
-                    count: count,
-                    severity: Severity::Strong,
-                    reasons: vec![format!(
-                        "Suspected structuring\n Transations count: {},\n Transations sum: {}",
-                        &count, &txs_sum
-                    )],
-                });
-                let mut hashes = FLAGGED_HASHES.write().await;
-                for tx in txs.iter() {
-                    hashes.insert(tx.hash.clone());
-                }
+    for asset in assets {
+        let global_thershold = asset.values.percentile(config.percentile);
+        for (sender, movements) in asset.by_sender.iter() {
+            let local_mean = asset.local_mean(sender);
+            let timestamps: Vec<DateTime<Utc>> = movements.iter().map(|m| m.timestamp).collect();
+
+            let mut suspicious = Vec::new();
+            for (window, range) in sliding_windows(&timestamps, origin, length, step) {
+                let count = range.len();
+                let txs_sum = saturating_sum(movements[range.clone()].iter().map(|m| &m.amount));
+
+                let local_flag = !local_mean.is_zero()
+                    && cmp_scaled(txs_sum, config.k_local, local_mean) == Ordering::Greater;
+                let global_flag = txs_sum > global_thershold;
+                let count_flag = count > config.count_threshold;
+
+                let severity = match (local_flag, global_flag, count_flag) {
+                    (true, true, true) => Severity::Strong,
+                    (false, false, false) => continue,
+                    _ => Severity::Weak,
+                };
+                suspicious.push((window, range, severity, txs_sum));
             }
-            (false, false, false) => {}
-            _ => {
-                anomalies.push(Anomaly::Structuring {
-                    sender: sender.to_string(),
-                    count: count,
-                    severity: Severity::Weak,
-                    reasons: vec![format!(
-                        "Suspected structuring\n Transations count: {},\n Transations sum: {}",
-                        &count, &txs_sum
-                    )],
-                });
-                let mut hashes = FLAGGED_HASHES.write().await;
-                for tx in txs.iter() {
-                    hashes.insert(tx.hash.clone());
-                }
-            }
-        };
+            // Report the most suspicious window: strongest, then largest sum, then earliest.
+            let Some((window, range, severity, txs_sum)) =
+                suspicious
+                    .iter()
+                    .max_by_key(|(window, _, severity, txs_sum)| {
+                        (
+                            *severity == Severity::Strong,
+                            *txs_sum,
+                            Reverse(window.start),
+                        )
+                    })
+            else {
+                continue;
+            };
+
+            let count = range.len();
+            anomalies.push(Anomaly::Structuring {
+                sender: format_address(sender),
+                token: asset.token_label(),
+                count,
+                severity: severity.clone(),
+                window_start: window.start,
+                window_end: window.end,
+                reasons: vec![format!(
+                    "Suspected structuring between {} and {}\n Transations count: {},\n Transations sum: {}",
+                    window.start,
+                    window.end,
+                    &count,
+                    asset.format_amount(*txs_sum)
+                )],
+            });
+            let flagged: Vec<H256> = suspicious
+                .iter()
+                .flat_map(|(_, range, ..)| movements[range.clone()].iter().map(|m| m.hash))
+                .collect();
+            ctx.flag_all(flagged).await;
+        }
     }
 
     anomalies
@@ -329,37 +410,44 @@ pub async fn detect_structuring(storage: &SharedTxStorage) -> Vec<Anomaly> {
 /// Detects transactions with unusually high fees.
 ///
 /// This asynchronous function analyzes all transactions in the provided
-/// [`SharedTxStorage`] and identifies transactions with suspiciously high fees
+/// [`TxQuery`] source and identifies transactions with suspiciously high fees
 /// compared to both the sender's typical transaction fees (local mean)
 /// and a global threshold (e.g., percentile of all fees).
 ///
 /// # Parameters
 ///
-/// * `storage` – Shared transaction storage (`SharedTxStorage`) containing all transactions to analyze.
+/// * `ctx` – Analysis context whose storage ([`TxQuery`]) contains all transactions to analyze.
+/// * `config` – Percentile and local multiplier, see [`HighFeeConfig`].
 ///
 /// # Returns
 ///
 /// Returns a `Vec<Anomaly>` containing detected high-fee anomalies:
-/// * Each anomaly includes the transaction hash, calculated fee in ETH,
-///   severity (`Strong` or `Weak`), reasons for detection, and timestamp.
+/// * Each anomaly includes the transaction hash, the exact fee in wei,
+///   severity (`Strong` or `Weak`), the sender's median fee and the fee's
+///   z-score (when the sender has enough history), reasons for detection, and timestamp.
 ///
 /// # Detection Logic
 ///
-/// 1. **Fee Calculation** – Transaction fee is computed as:
-///    `fee_eth = gas_price_gwei * gas / 1e9`.
-/// 2. **Local Comparison** – Compares fee against the sender's historical mean fee
-///    multiplied by `K_LOCAL_FEE`.
+/// 1. **Fee Calculation** – Transaction fee is computed exactly in wei with
+///    [`TransactionRecord::fee`]: `gas_used * effective_gas_price` when the scanner
+///    fetched receipts, otherwise the gas limit times the gas price.
+///    All comparisons below use exact integer arithmetic.
+/// 2. **Local Comparison** – Compares the fee against the median and MAD of the
+///    sender's other fees (see [`Baseline`]): the fee is locally high when its
+///    robust z-score exceeds `config.z_threshold`, or, when all other fees are
+///    equal, when it exceeds `config.flat_ratio` times them. Senders with fewer
+///    than `config.min_history` other transactions are only compared globally.
 /// 3. **Global Comparison** – Compares fee against a global percentile threshold of all fees.
 /// 4. **Severity Assignment**:
 ///    * `Strong` if both local and global thresholds are exceeded.
 ///    * `Weak` if only one threshold is exceeded.
-/// 5. **Flagging** – Transaction hashes of detected anomalies are added to
-///    the global `FLAGGED_HASHES` set.
+/// 5. **Flagging** – Transaction hashes of detected anomalies are flagged in
+///    the analysis context.
 ///
 /// # Example
 ///
 /// ```rust,ignore
-/// let anomalies: Vec<Anomaly> = detect_high_fee(&storage).await;
+/// let anomalies: Vec<Anomaly> = detect_high_fee(&ctx, &config.high_fee).await;
 /// for anomaly in anomalies {
 ///     println!("{:?}", anomaly);
 /// }
@@ -367,98 +455,209 @@ pub async fn detect_structuring(storage: &SharedTxStorage) -> Vec<Anomaly> {
 ///
 /// # Notes
 ///
-/// * Timestamps are parsed from RFC 3339 strings in the transactions.
-/// * Fees are expressed in ETH for clarity.
+/// * Fees are kept exactly in wei; the reasons format them in ETH for clarity.
 /// * The function is asynchronous due to read access to the shared storage.
-pub async fn detect_high_fee(storage: &SharedTxStorage) -> Vec<Anomaly> {
-    let all_txs = storage.all_txs.read().await;
-    let all_fees: Vec<f64> = all_txs
-        .iter()
-        .map(|tx| tx.gas_price_gwei * tx.gas as f64 / 1e9)
-        .collect();
-    let global_threshold = percentile(&all_fees);
+pub async fn detect_high_fee(ctx: &AnalysisContext, config: &HighFeeConfig) -> Vec<Anomaly> {
+    let storage = &ctx.storage;
+    let all_txs = storage.all_txs().await;
+    let stats = ctx.stats().await;
+    let global_threshold = stats.fees.percentile(config.percentile);
 
     let mut anomalies: Vec<Anomaly> = Vec::new();
+    let mut flagged = Vec::new();
     for tx in all_txs.iter() {
-        let sender = &tx.from;
-        let fee_eth = tx.gas_price_gwei * tx.gas as f64 / 1e9;
-        let local_mean = local_mean_fee(storage, sender);
-
-        let local_flag = local_mean > 0.0 && fee_eth > K_LOCAL_FEE * local_mean;
-        let global_flag = fee_eth > global_threshold;
-
-        let timestamp = DateTime::parse_from_rfc3339(&tx.timestamp)
-            .unwrap_or_else(|_| Utc::now().into())
-            .with_timezone(&Utc);
-        match (local_flag, global_flag) {
-            (true, true) => {
-                anomalies.push(Anomaly::HighFee {
-                    tx_hash: tx.hash.clone(),
-                    fee_eth: fee_eth,
-                    severity: Severity::Strong,
-                    reasons: vec![format!("Suspiciously high fee: {}", fee_eth)],
-                    timestamp: timestamp,
-                });
-                let mut hashes = FLAGGED_HASHES.write().await;
-                hashes.insert(tx.hash.clone());
-            }
-            (true, false) | (false, true) => {
-                anomalies.push(Anomaly::HighFee {
-                    tx_hash: tx.hash.clone(),
-                    fee_eth: fee_eth,
-                    severity: Severity::Weak,
-                    reasons: vec![format!("Suspiciously high fee: {}", fee_eth)],
-                    timestamp: timestamp,
-                });
-                let mut hashes = FLAGGED_HASHES.write().await;
-                hashes.insert(tx.hash.clone());
-            }
-            (false, false) => {}
+        let fee = tx.fee();
+        let baseline = stats
+            .fee_baseline(&tx.from, fee)
+            .filter(|baseline| baseline.history >= config.min_history);
+
+        let local_flag = baseline
+            .is_some_and(|baseline| baseline.exceeds(fee, config.z_threshold, config.flat_ratio));
+        let global_flag = fee > global_threshold;
+
+        let severity = match (local_flag, global_flag) {
+            (true, true) => Severity::Strong,
+            (true, false) | (false, true) => Severity::Weak,
+            (false, false) => continue,
+        };
+        let mut reasons = vec![format!("Suspiciously high fee: {} ETH", format_eth(fee))];
+        if let Some(baseline) = baseline.filter(|_| local_flag) {
+            reasons.push(robust_reason(&baseline, fee, |median| {
+                format!("{} ETH", format_eth(median))
+            }));
         }
+        anomalies.push(Anomaly::HighFee {
+            tx_hash: format!("{:?}", tx.hash),
+            fee,
+            severity,
+            median: baseline.map(|baseline| baseline.median),
+            z_score: baseline.and_then(|baseline| baseline.z_score(fee)),
+            reasons,
+            timestamp: tx.timestamp,
+        });
+        flagged.push(tx.hash);
     }
+    ctx.flag_all(flagged).await;
     anomalies
 }
 
-pub async fn detect_blacklist_adresses(storage: &SharedTxStorage) -> Vec<Anomaly> {
-    let blacklist: HashSet<String> = match fetch_sanctioned_addresses().await {
-        Ok(set) => set,
-        Err(_) => {
-            eprintln!("⚠️ Не удалось загрузить санкционные адреса");
-            HashSet::new()
-        }
-    };
+/// Explains a local flag of `value` against the sender's `baseline`.
+fn robust_reason(baseline: &Baseline, value: U256, format: impl Fn(U256) -> String) -> String {
+    match baseline.z_score(value) {
+        Some(z_score) => format!(
+            "Robust z-score {:.1} against the median {} of the sender's {} other transactions",
+            z_score,
+            format(baseline.median),
+            baseline.history
+        ),
+        None => format!(
+            "Above the sender's {} other transactions, which all moved exactly {}",
+            baseline.history,
+            format(baseline.median)
+        ),
+    }
+}
+
+/// Detects transactions whose priority fee (tip) is abnormal relative to the
+/// base fee of the block that included them.
+///
+/// On post-London blocks most of what a transaction pays per gas is the
+/// block's base fee, which is burned and the same for everyone in the block.
+/// Only the tip on top of it reflects how much a sender was willing to
+/// overpay, e.g. to front-run or to get a transaction in at any price.
+///
+/// # Parameters
+///
+/// * `ctx` – Analysis context whose storage ([`TxQuery`]) contains all transactions to analyze.
+/// * `config` – Percentile and base fee multiplier, see [`HighPriorityFeeConfig`].
+///
+/// # Returns
+///
+/// Returns a `Vec<Anomaly>` with one [`Anomaly::HighPriorityFee`] per flagged
+/// transaction, carrying the tip and base fee per gas in wei.
+///
+/// # Detection Logic
+///
+/// 1. **Tip Calculation** – The tip per gas is the price paid per gas minus the
+///    block's base fee (see [`TransactionRecord::priority_fee_per_gas`]).
+///    Transactions from pre-London blocks have no base fee and are skipped.
+/// 2. **Block Comparison** – Compares the tip against `config.k_base_fee` times
+///    the block's base fee.
+/// 3. **Global Comparison** – Compares the tip against a global percentile
+///    threshold of all tips.
+/// 4. **Severity Assignment**:
+///    * `Strong` if both thresholds are exceeded.
+///    * `Weak` if only one threshold is exceeded.
+/// 5. **Flagging** – Transaction hashes of detected anomalies are flagged in
+///    the analysis context.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let anomalies = detect_high_priority_fee(&ctx, &config.high_priority_fee).await;
+/// ```
+///
+/// # Notes
+///
+/// * The tip is exact when receipts were fetched (effective gas price) and
+///   derived from the transaction's gas price otherwise.
+pub async fn detect_high_priority_fee(
+    ctx: &AnalysisContext,
+    config: &HighPriorityFeeConfig,
+) -> Vec<Anomaly> {
+    let storage = &ctx.storage;
+    let all_txs = storage.all_txs().await;
+    let global_threshold = ctx
+        .stats()
+        .await
+        .priority_fees
+        .percentile(config.percentile);
+
+    let mut anomalies: Vec<Anomaly> = Vec::new();
+    let mut flagged = Vec::new();
+    for tx in all_txs.iter() {
+        let (Some(base_fee), Some(tip)) = (tx.base_fee_per_gas, tx.priority_fee_per_gas()) else {
+            continue;
+        };
 
-    let all_txs = storage.all_txs.read().await;
+        let block_flag = cmp_scaled(tip, config.k_base_fee, base_fee) == Ordering::Greater;
+        let global_flag = tip > global_threshold;
+
+        let severity = match (block_flag, global_flag) {
+            (true, true) => Severity::Strong,
+            (true, false) | (false, true) => Severity::Weak,
+            (false, false) => continue,
+        };
+        anomalies.push(Anomaly::HighPriorityFee {
+            tx_hash: format!("{:?}", tx.hash),
+            priority_fee_per_gas: tip,
+            base_fee_per_gas: base_fee,
+            severity,
+            reasons: vec![format!(
+                "Suspiciously high priority fee: {} gwei over a base fee of {} gwei",
+                format_gwei(tip),
+                format_gwei(base_fee)
+            )],
+            timestamp: tx.timestamp,
+        });
+        flagged.push(tx.hash);
+    }
+    ctx.flag_all(flagged).await;
+    anomalies
+}
+
+/// Flags transactions sent from or to a sanctioned address.
+///
+/// # Parameters
+/// * `ctx` – Analysis context whose storage ([`TxQuery`]) contains all transactions to analyze.
+/// * `blacklist` – Sanctioned addresses, usually loaded with
+///   [`load_sanctions`](crate::sanctions::load_sanctions).
+///
+/// # Returns
+/// One `BlacklistedAddress` anomaly per sanctioned sender or receiver of a transaction.
+///
+/// # Example
+/// ```rust,ignore
+/// let blacklist = load_sanctions(&sources_from_config(&config.sanctions)).await?;
+/// let anomalies = detect_blacklist_adresses(&ctx, &blacklist).await;
+/// ```
+///
+/// # Notes
+/// * Loading the list is left to the caller, so a list that cannot be loaded is
+///   an error there instead of an empty result here. The `blacklist` detector
+///   reports it as a `SanctionsListUnavailable` anomaly.
+pub async fn detect_blacklist_adresses(
+    ctx: &AnalysisContext,
+    blacklist: &SanctionsList,
+) -> Vec<Anomaly> {
+    let storage = &ctx.storage;
+    let all_txs = storage.all_txs().await;
     let mut anomalies = Vec::new();
 
     for tx in all_txs.iter() {
-        let timestamp = DateTime::parse_from_rfc3339(&tx.timestamp)
-            .unwrap_or_else(|_| Utc::now().into())
-            .with_timezone(&Utc);
+        let timestamp = tx.timestamp;
         if blacklist.contains(&tx.from) {
             anomalies.push(Anomaly::BlacklistedAddress {
-                tx_hash: tx.hash.clone(),
-                addres: tx.from.clone(),
+                tx_hash: format!("{:?}", tx.hash),
+                addres: format_address(&tx.from),
                 reasons: vec![format!(
-                    "Transactions from a sanctioned address: {}",
-                    &tx.from
+                    "Transactions from a sanctioned address: {:?}",
+                    tx.from
                 )],
-                timestamp: timestamp,
+                timestamp,
             });
-            let mut hashes = FLAGGED_HASHES.write().await;
-            hashes.insert(tx.hash.clone());
+            ctx.flag(tx.hash).await;
         }
 
         if let Some(to) = &tx.to {
             if blacklist.contains(to) {
                 anomalies.push(Anomaly::BlacklistedAddress {
-                    tx_hash: tx.hash.clone(),
-                    addres: to.clone(),
-                    reasons: vec![format!("Transactions to a sanctioned address: {}", to)],
-                    timestamp: timestamp,
+                    tx_hash: format!("{:?}", tx.hash),
+                    addres: format_address(to),
+                    reasons: vec![format!("Transactions to a sanctioned address: {:?}", to)],
+                    timestamp,
                 });
-                let mut hashes = FLAGGED_HASHES.write().await;
-                hashes.insert(tx.hash.clone());
+                ctx.flag(tx.hash).await;
             }
         }
     }
@@ -469,11 +668,12 @@ pub async fn detect_blacklist_adresses(storage: &SharedTxStorage) -> Vec<Anomaly
 /// Detects transactions with unusual operational characteristics.
 ///
 /// This asynchronous function analyzes all transactions in the provided
-/// [`SharedTxStorage`] and flags transactions that deviate significantly
+/// [`TxQuery`] source and flags transactions that deviate significantly
 /// from typical values or exhibit unusual input data.
 ///
 /// # Parameters
-/// * `storage` – Shared transaction storage (`SharedTxStorage`) containing all transactions to analyze.
+/// * `ctx` – Analysis context whose storage ([`TxQuery`]) contains all transactions to analyze.
+/// * `config` – Percentile and input length limit, see [`UnusualOpConfig`].
 ///
 /// # Returns
 /// Returns a `Vec<Anomaly>` containing detected unusual operation anomalies:
@@ -483,63 +683,61 @@ pub async fn detect_blacklist_adresses(storage: &SharedTxStorage) -> Vec<Anomaly
 /// # Detection Logic
 /// 1. **Value Threshold** – Compares transaction value against a global percentile threshold of all transaction values.
 /// 2. **Gas Price Threshold** – Compares transaction gas price against a global percentile threshold of all gas prices.
-/// 3. **Input Data Check** – Flags transactions with input not starting with `"0x"` or unusually long input (>100 characters).
-/// 4. **Skip Already Flagged** – Transactions already present in `FLAGGED_HASHES` are skipped.
+/// 3. **Input Data Check** – Flags transactions with input not starting with `"0x"` or unusually long input
+///    (more than `config.max_input_len` characters).
+/// 4. **Skip Already Flagged** – Transactions flagged in `ctx` by the detectors
+///    `unusual_op` depends on are skipped.
 /// 5. **Severity Assignment**:
 ///    * `Strong` if transaction value or gas price is unusual.
 ///    * `Weak` if only the input data is unusual.
 ///
 /// # Example
 /// ```rust,ignore
-/// let anomalies: Vec<Anomaly> = detect_unusual_op(&storage).await;
+/// let anomalies: Vec<Anomaly> = detect_unusual_op(&ctx, &config.unusual_op).await;
 /// for anomaly in anomalies {
 ///     println!("{:?}", anomaly);
 /// }
 /// ```
 ///
 /// # Notes
-/// * Timestamps are parsed from RFC 3339 strings in the transactions.
 /// * Transactions with unusual values or gas prices are considered more severe than those
 ///   with only unusual input data.
 /// * The function is asynchronous due to read access to the shared storage.
-pub async fn detect_unusual_op(storage: &SharedTxStorage) -> Vec<Anomaly> {
-    let all_txs = storage.all_txs.read().await;
+pub async fn detect_unusual_op(ctx: &AnalysisContext, config: &UnusualOpConfig) -> Vec<Anomaly> {
+    let storage = &ctx.storage;
+    let all_txs = storage.all_txs().await;
     let mut anomalies = Vec::new();
 
-    let values = all_txs.iter().map(|tx| tx.value).collect();
-    let gas_prices = all_txs.iter().map(|tx| tx.gas_price_gwei).collect();
-
-    let value_threshold = percentile(&values);
-    let gas_threshold = percentile(&gas_prices);
+    let stats = ctx.stats().await;
+    let value_threshold = stats.native().values.percentile(config.percentile);
+    let gas_threshold = stats.gas_prices.percentile(config.percentile);
 
+    let flagged_hashes = ctx.flagged_hashes().await;
     for tx in all_txs.iter() {
-        let flagged_hashes = FLAGGED_HASHES.read().await;
         if flagged_hashes.contains(&tx.hash) {
             continue;
         }
 
         let unusual_value = tx.value > value_threshold;
-        let unusual_gas = tx.gas_price_gwei > gas_threshold;
-        let unusual_input = !tx.input.starts_with("0x") || tx.input.len() > 100;
+        let unusual_gas = tx.gas_price > gas_threshold;
+        let unusual_input = !tx.input.starts_with("0x") || tx.input.len() > config.max_input_len;
 
         if unusual_value || unusual_gas || unusual_input {
-            let timestamp = DateTime::parse_from_rfc3339(&tx.timestamp)
-                .unwrap_or_else(|_| Utc::now().into())
-                .with_timezone(&Utc);
+            let timestamp = tx.timestamp;
             anomalies.push(Anomaly::UnusualOp {
-                tx_hash: tx.hash.clone(),
+                tx_hash: format!("{:?}", tx.hash),
                 severity: if unusual_value || unusual_gas {
                     Severity::Strong
                 } else {
                     Severity::Weak
                 },
                 reasons: vec![format!(
-                    "Unusual operation: value={}, gas={}, input_len={}",
-                    tx.value,
-                    tx.gas_price_gwei,
+                    "Unusual operation: value={} ETH, gas_price={} gwei, input_len={}",
+                    format_eth(tx.value),
+                    format_gwei(tx.gas_price),
                     tx.input.len()
                 )],
-                timestamp: timestamp,
+                timestamp,
             });
         }
     }
@@ -550,63 +748,68 @@ pub async fn detect_unusual_op(storage: &SharedTxStorage) -> Vec<Anomaly> {
 /// Detects time-based anomalies in transactions.
 ///
 /// This asynchronous function analyzes all transactions in the provided
-/// [`SharedTxStorage`] and identifies transactions that occur at unusual times
+/// [`TxQuery`] source and identifies transactions that occur at unusual times
 /// (e.g., very early hours) or as part of burst activity within short intervals.
 ///
 /// # Parameters
 ///
-/// * `storage` – Shared transaction storage (`SharedTxStorage`) containing all transactions to analyze.
+/// * `ctx` – Analysis context whose storage ([`TxQuery`]) contains all transactions to analyze.
+/// * `config` – Night hours and burst window, see [`TimeAnomalyConfig`].
 ///
 /// # Returns
 /// Returns a `Vec<Anomaly>` containing detected time anomalies:
-/// * `TimeAnomaly` – Transactions occurring at unusual times (before 6 AM UTC), marked as `Weak`.
-/// * `BurstActivity` – Senders performing multiple transactions in a short interval (≥5 transactions within 10 minutes).
+/// * `TimeAnomaly` – Transactions occurring at unusual times (before 6 AM UTC by default), marked as `Weak`.
+/// * `BurstActivity` – Senders performing multiple transactions in a short interval
+///   (≥5 transactions within 10 minutes by default).
 ///
 /// # Detection Logic
 ///
-/// 1. **Unusual Time Detection** – Flags transactions occurring between 00:00 and 06:00 UTC as `TimeAnomaly`.
+/// 1. **Unusual Time Detection** – Flags transactions occurring between 00:00 and
+///    `config.night_end_hour` UTC as `TimeAnomaly`.
 /// 2. **Burst Activity Detection** – Groups transactions by sender and sorts them by timestamp.  
-///    Counts consecutive transactions within a 10-minute window:
-///    * If ≥5 transactions occur in this interval, a `BurstActivity` anomaly is recorded for the sender.
+///    Counts consecutive transactions within a `config.burst_window_minutes` window:
+///    * If at least `config.burst_min_count` transactions occur in this interval, a
+///      `BurstActivity` anomaly is recorded for the sender.
 /// 3. **Severity Assignment** – Time anomalies are marked as `Weak`. Burst activity severity is not explicitly assigned.
 ///
 /// # Example
 /// ```rust,ignore
-/// let anomalies: Vec<Anomaly> = detect_time_anomalies(&storage).await;
+/// let anomalies: Vec<Anomaly> = detect_time_anomalies(&ctx, &config.time_anomalies).await;
 /// for anomaly in anomalies {
 ///     println!("{:?}", anomaly);
 /// }
 /// ```
 ///
 /// # Notes
-/// * Timestamps are expected in RFC 3339 format in the transaction records.
 /// * The function is asynchronous due to read access to the shared storage.
-/// * Burst activity detection considers only transactions per sender and a sliding 10-minute window.
-pub async fn detect_time_anomalies(storage: &SharedTxStorage) -> Vec<Anomaly> {
-    let all_txs = storage.all_txs.read().await;
+/// * Burst activity detection considers only transactions per sender and a sliding window.
+pub async fn detect_time_anomalies(
+    ctx: &AnalysisContext,
+    config: &TimeAnomalyConfig,
+) -> Vec<Anomaly> {
+    let storage = &ctx.storage;
+    let all_txs = storage.all_txs().await;
     let mut anomalies: Vec<Anomaly> = Vec::new();
 
-    let burst_interval = chrono::Duration::minutes(10);
+    let burst_interval = chrono::Duration::minutes(config.burst_window_minutes.into());
 
-    let mut tx_times: HashMap<String, Vec<chrono::DateTime<chrono::Utc>>> = HashMap::new();
+    let mut tx_times: HashMap<Address, Vec<DateTime<Utc>>> = HashMap::new();
 
     for tx in all_txs.iter() {
-        let ts: chrono::DateTime<chrono::Utc> = tx.timestamp.parse().unwrap();
+        let ts = tx.timestamp;
         let hour = ts.hour();
 
-        if hour <= 6 {
-            let timestamp = DateTime::parse_from_rfc3339(&tx.timestamp)
-                .unwrap_or_else(|_| Utc::now().into())
-                .with_timezone(&Utc);
+        if hour <= config.night_end_hour {
+            let timestamp = tx.timestamp;
             anomalies.push(Anomaly::TimeAnomaly {
-                tx_hash: tx.hash.clone(),
+                tx_hash: format!("{:?}", tx.hash),
                 severity: Severity::Weak,
                 reasons: vec!["Transaction in unusual time".to_string()],
-                timestamp: timestamp,
+                timestamp,
             });
         }
 
-        tx_times.entry(tx.from.clone()).or_default().push(ts);
+        tx_times.entry(tx.from).or_default().push(ts);
     }
 
     for (sender, times) in tx_times.iter() {
@@ -616,17 +819,17 @@ pub async fn detect_time_anomalies(storage: &SharedTxStorage) -> Vec<Anomaly> {
         for i in 0..sorted_times.len() {
             let mut count = 1;
             let start = sorted_times[i];
-            for j in i + 1..sorted_times.len() {
-                if sorted_times[j] - start <= burst_interval {
+            for time in sorted_times.iter().skip(i + 1) {
+                if *time - start <= burst_interval {
                     count += 1;
                 } else {
                     break;
                 }
             }
-            if count >= 5 {
+            if count >= config.burst_min_count {
                 anomalies.push(Anomaly::BurstActivity {
-                    sender: sender.to_string(),
-                    reasons: vec![format!("Detected Burst activity from: {}", sender.clone())],
+                    sender: format_address(sender),
+                    reasons: vec![format!("Detected Burst activity from: {:?}", sender)],
                 });
                 break;
             }
@@ -638,12 +841,13 @@ pub async fn detect_time_anomalies(storage: &SharedTxStorage) -> Vec<Anomaly> {
 
 /// Detects regular (recurring) payment patterns from transaction history.
 /// This asynchronous function analyzes all transactions in the provided
-/// [`SharedTxStorage`] grouped by sender and receiver. It identifies senders
+/// [`TxQuery`] source grouped by sender and receiver. It identifies senders
 /// who make repeated payments with relatively consistent amounts, suggesting
 /// a regular payment pattern (e.g., subscriptions, salaries, or automated transfers).
 ///
 /// # Parameters
-/// * `storage` – Shared transaction storage (`SharedTxStorage`) containing all transactions to analyze.
+/// * `ctx` – Analysis context whose storage ([`TxQuery`]) contains all transactions to analyze.
+/// * `config` – Group size and amount tolerance, see [`RegularPaymentsConfig`].
 ///
 /// # Returns
 ///
@@ -653,16 +857,18 @@ pub async fn detect_time_anomalies(storage: &SharedTxStorage) -> Vec<Anomaly> {
 /// # Detection Logic
 /// 1. **Group by Sender and Receiver** – Transactions are grouped by the sender
 ///    and the optional receiver address (`tx.to`).
-/// 2. **Filter Small Groups** – Only groups with 2 or more transactions are considered.
+/// 2. **Filter Small Groups** – Only groups with at least `config.min_count` transactions are considered.
 /// 3. **Sort by Timestamp** – Transactions within each group are sorted chronologically.
 /// 4. **Calculate Value Consistency** – Computes the average transaction value and
 ///    average deviation from the mean.  
-///    * If the deviation is within 10% of the average value, the pattern is considered regular.
+///    * If the deviation is within `config.max_deviation_ratio` (10% by default) of the average
+///      value, the pattern is considered regular.
 /// 5. **Record Pattern** – Adds a `BusinessPattern::RegularPayments` entry for the sender.
 ///
 /// # Example
 /// ```rust,ignore
-/// let patterns: Vec<BusinessPattern> = detect_regular_payments(&storage).await;
+/// let patterns: Vec<BusinessPattern> =
+///     detect_regular_payments(&ctx, &config.regular_payments).await;
 /// for pattern in patterns {
 ///     println!("{:?}", pattern);
 /// }
@@ -670,51 +876,49 @@ pub async fn detect_time_anomalies(storage: &SharedTxStorage) -> Vec<Anomaly> {
 ///
 /// # Notes
 ///
-/// * Timestamps are expected in RFC 3339 format in the transaction records.
 /// * The function is asynchronous due to read access to the shared storage.
 /// * Only the amount consistency is considered; timing regularity is not strictly enforced.
-pub async fn detect_regular_payments(storage: &SharedTxStorage) -> Vec<BusinessPattern> {
-    let by_sender = &storage.by_sender;
+pub async fn detect_regular_payments(
+    ctx: &AnalysisContext,
+    config: &RegularPaymentsConfig,
+) -> Vec<BusinessPattern> {
+    let storage = &ctx.storage;
 
     let mut patterns: Vec<BusinessPattern> = Vec::new();
-    for entry in by_sender {
-        let sender = entry.key();
-        let txs = entry.value();
-
-        let mut groups: HashMap<Option<String>, Vec<&TransactionRecord>> = HashMap::new();
-        for tx in txs {
-            groups.entry(tx.to.clone()).or_default().push(tx);
+    for (sender, txs) in storage.txs_by_sender().await {
+        let mut groups: HashMap<Option<Address>, Vec<&TransactionRecord>> = HashMap::new();
+        for tx in &txs {
+            groups.entry(tx.to).or_default().push(tx);
         }
 
         for (_to, group) in groups {
-            if group.len() < 2 {
+            if group.len() < config.min_count {
                 continue;
             }
 
             let mut sorted: Vec<&TransactionRecord> = group.clone();
-            sorted.sort_by_key(|tx| tx.timestamp.clone());
-
-            let mut intervals: Vec<i64> = vec![];
-            for i in 1..sorted.len() {
-                let prev: DateTime<Utc> = sorted[i - 1].timestamp.parse().unwrap();
-                let curr: DateTime<Utc> = sorted[i].timestamp.parse().unwrap();
-                intervals.push((curr - prev).num_seconds());
-            }
+            sorted.sort_by_key(|tx| tx.timestamp);
 
-            if intervals.len() > 0 {
-                let mut sum_deviation = 0.0;
-                let total: f64 = sorted.iter().map(|tx| tx.value).fold(0.0, |acc, x| acc + x);
-                let avg_value = total / sorted.len() as f64;
-                for tx in sorted.iter() {
-                    sum_deviation += (tx.value - avg_value).abs();
-                }
-                let sum_deviation_avg: f64 = sum_deviation / (sorted.len() as f64);
-                let threshold: f64 = avg_value * 0.1;
+            let intervals: Vec<i64> = sorted
+                .windows(2)
+                .map(|pair| (pair[1].timestamp - pair[0].timestamp).num_seconds())
+                .collect();
 
-                if sum_deviation_avg < threshold {
+            if !intervals.is_empty() {
+                let values: Vec<U256> = sorted.iter().map(|tx| tx.value).collect();
+                let avg_value = mean(&values);
+                let deviations: Vec<U256> = values
+                    .iter()
+                    .map(|value| abs_diff(*value, avg_value))
+                    .collect();
+                let deviation_avg = mean(&deviations);
+
+                if cmp_scaled(deviation_avg, config.max_deviation_ratio, avg_value)
+                    == Ordering::Less
+                {
                     patterns.push(BusinessPattern::RegularPayments {
-                        sender: sender.to_string(),
-                        message: format!("Detected regular payments from {}", sender),
+                        sender: format_address(&sender),
+                        message: format!("Detected regular payments from {:?}", sender),
                     });
                 }
             }
@@ -727,11 +931,12 @@ pub async fn detect_regular_payments(storage: &SharedTxStorage) -> Vec<BusinessP
 /// Detects batch payments made by senders within short time intervals.
 ///
 /// This asynchronous function analyzes all transactions in the provided
-/// [`SharedTxStorage`] and identifies senders performing multiple transactions
+/// [`TxQuery`] source and identifies senders performing multiple transactions
 /// within a short period, suggesting batch payments (e.g., mass payouts or automated transfers).
 ///
 /// # Parameters
-/// * `storage` – Shared transaction storage (`SharedTxStorage`) containing all transactions to analyze.
+/// * `ctx` – Analysis context whose storage ([`TxQuery`]) contains all transactions to analyze.
+/// * `config` – Batch window and size, see [`BatchPaymentsConfig`].
 ///
 /// # Returns
 /// Returns a `Vec<BusinessPattern>` containing detected batch payment patterns:
@@ -739,42 +944,40 @@ pub async fn detect_regular_payments(storage: &SharedTxStorage) -> Vec<BusinessP
 ///
 /// # Detection Logic
 /// 1. **Sort Transactions** – Transactions for each sender are sorted by timestamp.
-/// 2. **Sliding Window** – Counts consecutive transactions within a 5-minute window.
-/// 3. **Threshold Check** – If at least 5 transactions occur within this interval, a `BatchPayments` pattern is recorded.
+/// 2. **Sliding Window** – Counts consecutive transactions within a `config.interval_minutes` window.
+/// 3. **Threshold Check** – If at least `config.min_count` transactions occur within this interval,
+///    a `BatchPayments` pattern is recorded.
 ///
 /// # Example
 /// ```rust,ignore
-/// let patterns: Vec<BusinessPattern> = detect_batch_payments(&storage).await;
+/// let patterns: Vec<BusinessPattern> = detect_batch_payments(&ctx, &config.batch_payments).await;
 /// for pattern in patterns {
 ///     println!("{:?}", pattern);
 /// }
 /// ```
 ///
 /// # Notes
-/// * Timestamps are expected in RFC 3339 format.
 /// * The function is asynchronous due to read access to the shared storage.
 /// * Only timing of transactions is considered; values are not analyzed.
-pub async fn detect_batch_payments(storage: &SharedTxStorage) -> Vec<BusinessPattern> {
+pub async fn detect_batch_payments(
+    ctx: &AnalysisContext,
+    config: &BatchPaymentsConfig,
+) -> Vec<BusinessPattern> {
+    let storage = &ctx.storage;
     let mut patterns: Vec<BusinessPattern> = Vec::new();
 
-    let batch_interval = Duration::minutes(5);
-    let batch_threshold = 5;
-
-    let by_sender = &storage.by_sender;
-
-    for entry in by_sender {
-        let txs = entry.value();
+    let batch_interval = Duration::minutes(config.interval_minutes.into());
+    let batch_threshold = config.min_count;
 
-        let mut sorted: Vec<TransactionRecord> = txs.clone();
-        sorted.sort_by_key(|tx| tx.timestamp.clone());
+    for (sender, mut sorted) in storage.txs_by_sender().await {
+        sorted.sort_by_key(|tx| tx.timestamp);
 
         let mut i = 0;
         while i < sorted.len() {
-            let start: DateTime<Utc> = sorted[i].timestamp.parse().unwrap();
+            let start = sorted[i].timestamp;
             let mut batch_count = 1;
-            for j in i + 1..sorted.len() {
-                let ts: DateTime<Utc> = sorted[j].timestamp.parse().unwrap();
-                if ts - start <= batch_interval {
+            for tx in sorted.iter().skip(i + 1) {
+                if tx.timestamp - start <= batch_interval {
                     batch_count += 1;
                 } else {
                     break;
@@ -783,12 +986,11 @@ pub async fn detect_batch_payments(storage: &SharedTxStorage) -> Vec<BusinessPat
 
             if batch_count >= batch_threshold {
                 patterns.push(BusinessPattern::BatchPayments {
-                    sender: entry.key().clone(),
-                    count: batch_count.clone(),
+                    sender: format_address(&sender),
+                    count: batch_count,
                     message: format!(
-                        "Detected batch payments from {}:\n Payments count: {}",
-                        entry.key().clone(),
-                        batch_count.clone()
+                        "Detected batch payments from {:?}:\n Payments count: {}",
+                        sender, batch_count
                     ),
                 });
                 i += batch_count;
@@ -804,12 +1006,12 @@ pub async fn detect_batch_payments(storage: &SharedTxStorage) -> Vec<BusinessPat
 /// Detects transactions sent to known decentralized exchange (DEX) contracts.
 ///
 /// This asynchronous function scans all transactions stored in the provided
-/// [`SharedTxStorage`] and identifies those whose recipient (`to` address)
+/// [`TxQuery`] source and identifies those whose recipient (`to` address)
 /// matches a known DEX contract. Each detected transaction is recorded as a
 /// `BusinessPattern::DEXTrade`.
 ///
 /// # Parameters
-/// * `storage` – Shared transaction storage (`SharedTxStorage`) containing all transactions to analyze.
+/// * `ctx` – Analysis context whose storage ([`TxQuery`]) contains all transactions to analyze.
 /// * `dex_contracts` – A `HashSet` of known DEX contract addresses (`H160`) to check against.
 ///
 /// # Returns
@@ -818,37 +1020,34 @@ pub async fn detect_batch_payments(storage: &SharedTxStorage) -> Vec<BusinessPat
 ///
 /// # Detection Logic
 /// 1. **Iterate All Transactions** – Each transaction in `storage.all_txs` is checked.
-/// 2. **Address Parsing** – If the transaction has a `to` address, it is parsed as `H160`.
-///    * Panics if the address is invalid.
-/// 3. **DEX Check** – If the parsed address is in the `dex_contracts` set, a
+/// 2. **DEX Check** – If the transaction has a `to` address and it is in the `dex_contracts` set, a
 ///    `BusinessPattern::DEXTrade` pattern is created with the DEX address and a message.
 ///
 /// # Example
 /// ```rust,ignore
 /// let dex_contracts: HashSet<H160> = get_known_dex_contracts();
-/// let patterns: Vec<BusinessPattern> = detect_dex_trade(&storage, &dex_contracts).await;
+/// let patterns: Vec<BusinessPattern> = detect_dex_trade(&ctx, &dex_contracts).await;
 /// for pattern in patterns {
 ///     println!("{:?}", pattern);
 /// }
 /// ```
 ///
 /// # Notes
-/// * The function panics if a `to` address cannot be parsed as a valid `H160`.
-/// * Use a sanitized and verified list of DEX contract addresses to avoid panics.
+/// * Contract creations (no `to` address) are ignored.
 pub async fn detect_dex_trade(
-    storage: &SharedTxStorage,
+    ctx: &AnalysisContext,
     dex_contracts: &HashSet<H160>,
 ) -> Vec<BusinessPattern> {
-    let all_txs = storage.all_txs.read().await;
+    let storage = &ctx.storage;
+    let all_txs = storage.all_txs().await;
     let mut patterns: Vec<BusinessPattern> = Vec::new();
 
     for tx in all_txs.iter() {
-        if let Some(to) = &tx.to {
-            let to_addres: H160 = to.parse().expect("Invalid address");
+        if let Some(to_addres) = tx.to {
             if dex_contracts.contains(&to_addres) {
                 patterns.push(BusinessPattern::DEXTrade {
-                    dex: to_addres.to_string(),
-                    message: format!("Detected trading with DEX: {}", to_addres.to_string()),
+                    dex: format_address(&to_addres),
+                    message: format!("Detected trading with DEX: {:?}", to_addres),
                 });
             }
         }
@@ -860,14 +1059,14 @@ pub async fn detect_dex_trade(
 /// Detects NFT-related transactions based on known ERC721/ERC1155 method selectors.
 ///
 /// This asynchronous function scans all transactions in the provided
-/// [`SharedTxStorage`] and identifies those likely related to NFT activity.
+/// [`TxQuery`] source and identifies those likely related to NFT activity.
 /// It checks the transaction `input` data for known NFT method selectors:
 /// * `0x80ac58cd` — ERC721 `safeTransferFrom`
 /// * `0xd9b67a26` — ERC1155 `safeTransferFrom`
 ///
 /// # Parameters
 ///
-/// * `storage` – Shared transaction storage (`SharedTxStorage`) containing all transactions to analyze.
+/// * `ctx` – Analysis context whose storage ([`TxQuery`]) contains all transactions to analyze.
 ///
 /// # Returns
 /// Returns a `Vec<BusinessPattern>` containing detected NFT activity patterns:
@@ -882,7 +1081,7 @@ pub async fn detect_dex_trade(
 ///
 /// # Example
 /// ```rust,ignore
-/// let patterns: Vec<BusinessPattern> = detect_nft_activity(&storage).await;
+/// let patterns: Vec<BusinessPattern> = detect_nft_activity(&ctx).await;
 /// for pattern in patterns {
 ///     println!("{:?}", pattern);
 /// }
@@ -892,15 +1091,16 @@ pub async fn detect_dex_trade(
 /// * Only ERC721 and ERC1155 transfer methods are detected. Other NFT interactions
 ///   may not be captured.
 /// * The function is asynchronous due to read access to the shared storage.
-pub async fn detect_nft_activity(storage: &SharedTxStorage) -> Vec<BusinessPattern> {
+pub async fn detect_nft_activity(ctx: &AnalysisContext) -> Vec<BusinessPattern> {
+    let storage = &ctx.storage;
     let mut patterns: Vec<BusinessPattern> = Vec::new();
-    let all_txs = storage.all_txs.read().await;
+    let all_txs = storage.all_txs().await;
     for tx in all_txs.iter() {
         if let Some(selector) = tx.input.get(0..10) {
             if selector == "0x80ac58cd" || selector == "0xd9b67a26" {
                 patterns.push(BusinessPattern::NFTActivity {
-                    tx_hash: tx.hash.clone(),
-                    message: format!("Detected NFT activity: {}", tx.hash.clone()),
+                    tx_hash: format!("{:?}", tx.hash),
+                    message: format!("Detected NFT activity: {:?}", tx.hash),
                 });
             }
         }
@@ -912,11 +1112,11 @@ pub async fn detect_nft_activity(storage: &SharedTxStorage) -> Vec<BusinessPatte
 /// Detects liquidity provider transactions on known decentralized exchanges (DEXs).
 ///
 /// This asynchronous function scans all transactions in the provided
-/// [`SharedTxStorage`] and identifies transactions likely related to providing liquidity
+/// [`TxQuery`] source and identifies transactions likely related to providing liquidity
 /// on DEX contracts. It checks both the recipient address and the transaction input data.
 ///
 /// # Parameters
-/// * `storage` – Shared transaction storage (`SharedTxStorage`) containing all transactions to analyze.
+/// * `ctx` – Analysis context whose storage ([`TxQuery`]) contains all transactions to analyze.
 /// * `dex_contracts` – A `HashSet` of known DEX contract addresses (`H160`) to check against.
 ///
 /// # Returns
@@ -934,30 +1134,28 @@ pub async fn detect_nft_activity(storage: &SharedTxStorage) -> Vec<BusinessPatte
 /// # Example
 /// ```rust,ignore
 /// let dex_contracts: HashSet<H160> = get_known_dex_contracts();
-/// let patterns: Vec<BusinessPattern> = detect_liquid_provider(&storage, &dex_contracts).await;
+/// let patterns: Vec<BusinessPattern> = detect_liquid_provider(&ctx, &dex_contracts).await;
 /// for pattern in patterns {
 ///     println!("{:?}", pattern);
 /// }
 /// ```
 ///
 /// # Notes
-/// * The function panics if a `to` address cannot be parsed as a valid `H160`.
 /// * Only transactions with known liquidity provision selectors are detected.
 /// * The function is asynchronous due to read access to the shared storage.
 pub async fn detect_liquid_provider(
-    storage: &SharedTxStorage,
+    ctx: &AnalysisContext,
     dex_contracts: &HashSet<H160>,
 ) -> Vec<BusinessPattern> {
-    let all_txs = storage.all_txs.read().await;
+    let storage = &ctx.storage;
+    let all_txs = storage.all_txs().await;
     let mut patterns: Vec<BusinessPattern> = Vec::new();
 
     for tx in all_txs.iter() {
-        if let Some(to) = &tx.to {
-            let to_addres: H160 = to.parse().expect("Invalid address");
+        if let Some(to_addres) = tx.to {
             if dex_contracts.contains(&to_addres) {
                 if let Some(selector) = &tx.input.get(0..10) {
-                    if selector.to_string() == "0xe8e33700" || selector.to_string() == "0xf305d719"
-                    {
+                    if *selector == "0xe8e33700" || *selector == "0xf305d719" {
                         patterns.push(BusinessPattern::LiquidityProvider);
                     }
                 }
@@ -971,12 +1169,13 @@ pub async fn detect_liquid_provider(
 /// Detects "whale" accounts based on transaction activity.
 ///
 /// This asynchronous function analyzes all senders in the provided
-/// [`SharedTxStorage`] and identifies accounts whose average transaction
+/// [`TxQuery`] source and identifies accounts whose average transaction
 /// value (`local_mean`) exceeds the global transaction value threshold.  
 /// Such accounts are considered "whales" — users with unusually high transaction amounts.
 ///
 /// # Parameters
-/// * `storage` – Shared transaction storage (`SharedTxStorage`) containing all transactions to analyze.
+/// * `ctx` – Analysis context whose storage ([`TxQuery`]) contains all transactions to analyze.
+/// * `config` – Percentile of the global threshold, see [`WhalesConfig`].
 ///
 /// # Returns
 /// Returns a `Vec<BusinessPattern>` containing detected whale patterns:
@@ -990,7 +1189,7 @@ pub async fn detect_liquid_provider(
 ///
 /// # Example
 /// ```rust,ignore
-/// let patterns: Vec<BusinessPattern> = detect_whales(&storage).await;
+/// let patterns: Vec<BusinessPattern> = detect_whales(&ctx, &config.whales).await;
 /// for pattern in patterns {
 ///     println!("{:?}", pattern);
 /// }
@@ -998,19 +1197,20 @@ pub async fn detect_liquid_provider(
 ///
 /// # Notes
 /// * The function is asynchronous because it reads from shared transaction storage.
-/// * `local_mean` and `global_threshold` are assumed to be implemented elsewhere.
+/// * With `config.include_tokens`, whales are also searched per ERC-20 token,
+///   comparing transfer amounts of the same token only. Such patterns carry the token address.
 /// * Only the sender's average transaction value is considered; transaction frequency is not analyzed.
-pub async fn detect_whales(storage: &SharedTxStorage) -> Vec<BusinessPattern> {
+pub async fn detect_whales(ctx: &AnalysisContext, config: &WhalesConfig) -> Vec<BusinessPattern> {
     let mut patterns: Vec<BusinessPattern> = Vec::new();
-    for entry in storage.by_sender.iter() {
-        let sender = entry.key();
-        let global_threshold = global_threshold(&storage).await;
-        let local_mean = local_mean(&storage, &sender);
-
-        if local_mean > global_threshold {
-            patterns.push(BusinessPattern::Whales {
-                sender: sender.clone(),
-            });
+    for asset in ctx.stats().await.assets(config.include_tokens) {
+        let global_threshold = asset.values.percentile(config.percentile);
+        for sender in asset.by_sender.keys() {
+            if asset.local_mean(sender) > global_threshold {
+                patterns.push(BusinessPattern::Whales {
+                    sender: format_address(sender),
+                    token: asset.token_label(),
+                });
+            }
         }
     }
 
@@ -1020,13 +1220,14 @@ pub async fn detect_whales(storage: &SharedTxStorage) -> Vec<BusinessPattern> {
 /// Detects active traders interacting with known decentralized exchanges (DEXs).
 ///
 /// This asynchronous function scans all transactions in the provided
-/// [`SharedTxStorage`] and identifies senders who have made a high number of
+/// [`TxQuery`] source and identifies senders who have made a high number of
 /// transactions to known DEX contracts. Accounts exceeding a transaction
 /// count threshold are considered "active traders".
 ///
 /// # Parameters
-/// * `storage` – Shared transaction storage (`SharedTxStorage`) containing all transactions to analyze.
+/// * `ctx` – Analysis context whose storage ([`TxQuery`]) contains all transactions to analyze.
 /// * `dex_contracts` – A `HashSet` of known DEX contract addresses (`H160`) to check against.
+/// * `config` – Transaction count threshold, see [`ActiveTradersConfig`].
 ///
 /// # Returns
 /// Returns a `Vec<BusinessPattern>` containing detected active trader patterns:
@@ -1036,33 +1237,33 @@ pub async fn detect_whales(storage: &SharedTxStorage) -> Vec<BusinessPattern> {
 /// 1. **Iterate All Transactions** – For each transaction in `storage.all_txs`:
 ///    * Parse the `to` address as `H160`.
 ///    * If the address is in the `dex_contracts` set, increment the sender's counter.
-/// 2. **Threshold Check** – Senders with more than 10 transactions to DEXs are flagged.
+/// 2. **Threshold Check** – Senders with more than `config.threshold` transactions to DEXs are flagged.
 /// 3. **Pattern Creation** – For each flagged sender, a `BusinessPattern::ActiveTraders` entry is added.
 ///
 /// # Example
 /// ```rust,ignore
 /// let dex_contracts: HashSet<H160> = get_known_dex_contracts();
-/// let patterns: Vec<BusinessPattern> = detect_active_traders(&storage, &dex_contracts).await;
+/// let patterns: Vec<BusinessPattern> =
+///     detect_active_traders(&ctx, &dex_contracts, &config.active_traders).await;
 /// for pattern in patterns {
 ///     println!("{:?}", pattern);
 /// }
 /// ```
 ///
 /// # Notes
-/// * The function panics if a `to` address cannot be parsed as a valid `H160`.
 /// * The function is asynchronous due to read access to the shared storage.
-/// * The threshold of 10 transactions is hardcoded but can be adjusted as needed.
 pub async fn detect_active_traders(
-    storage: &SharedTxStorage,
+    ctx: &AnalysisContext,
     dex_contracts: &HashSet<H160>,
+    config: &ActiveTradersConfig,
 ) -> Vec<BusinessPattern> {
-    let all_txs = storage.all_txs.read().await;
+    let storage = &ctx.storage;
+    let all_txs = storage.all_txs().await;
     let mut patterns: Vec<BusinessPattern> = Vec::new();
 
     let mut counter = HashMap::new();
     for tx in all_txs.iter() {
-        if let Some(to) = &tx.to {
-            let to_addres: H160 = to.parse().expect("Invalid address");
+        if let Some(to_addres) = tx.to {
             if dex_contracts.contains(&to_addres) {
                 *counter.entry(&tx.from).or_insert(0) += 1;
             }
@@ -1070,10 +1271,10 @@ pub async fn detect_active_traders(
     }
 
     for (address, count) in counter.iter() {
-        if *count > 10 {
+        if *count > config.threshold {
             patterns.push(BusinessPattern::ActiveTraders {
-                sender: address.to_string(),
-                message: format!("Detected active trader: {}", address),
+                sender: format_address(address),
+                message: format!("Detected active trader: {:?}", address),
             });
         }
     }
@@ -1084,11 +1285,11 @@ pub async fn detect_active_traders(
 /// Detects potential arbitrage transactions on known decentralized exchanges (DEXs).
 ///
 /// This asynchronous function scans all transactions in the provided
-/// [`SharedTxStorage`] and identifies transactions that interact with known DEX contracts
+/// [`TxQuery`] source and identifies transactions that interact with known DEX contracts
 /// using common arbitrage-related operations, such as multi-call or token swaps.
 ///
 /// # Parameters
-/// * `storage` – Shared transaction storage (`SharedTxStorage`) containing all transactions to analyze.
+/// * `ctx` – Analysis context whose storage ([`TxQuery`]) contains all transactions to analyze.
 /// * `dex_contracts` – A `HashSet` of known DEX contract addresses (`H160`) to check against.
 ///
 /// # Returns
@@ -1107,32 +1308,31 @@ pub async fn detect_active_traders(
 /// # Example
 /// ```rust,ignore
 /// let dex_contracts: HashSet<H160> = get_known_dex_contracts();
-/// let patterns: Vec<BusinessPattern> = detect_arbitrage(&storage, &dex_contracts).await;
+/// let patterns: Vec<BusinessPattern> = detect_arbitrage(&ctx, &dex_contracts).await;
 /// for pattern in patterns {
 ///     println!("{:?}", pattern);
 /// }
 /// ```
 ///
 /// # Notes
-/// * The function panics if a `to` address cannot be parsed as a valid `H160`.
 /// * The function is asynchronous due to read access to the shared storage.
 /// * Detection is based on simple substring matching in the transaction `input`; some arbitrage transactions may not be detected if they use uncommon patterns.
 pub async fn detect_arbitrage(
-    storage: &SharedTxStorage,
+    ctx: &AnalysisContext,
     dex_contracts: &HashSet<H160>,
 ) -> Vec<BusinessPattern> {
-    let all_txs = storage.all_txs.read().await;
+    let storage = &ctx.storage;
+    let all_txs = storage.all_txs().await;
     let mut patterns: Vec<BusinessPattern> = Vec::new();
     for tx in all_txs.iter() {
-        if let Some(to) = &tx.to {
-            let to_addres: H160 = to.parse().expect("Invalid address");
-            if dex_contracts.contains(&to_addres) {
-                if tx.input.contains("multicall") || tx.input.contains("swapExactTokensForTokens") {
-                    patterns.push(BusinessPattern::Arbitrage {
-                        sender: tx.from.to_string(),
-                        message: format!("Detected possible arbtrage from: {}", tx.from.clone()),
-                    });
-                }
+        if let Some(to_addres) = tx.to {
+            if dex_contracts.contains(&to_addres)
+                && (tx.input.contains("multicall") || tx.input.contains("swapExactTokensForTokens"))
+            {
+                patterns.push(BusinessPattern::Arbitrage {
+                    sender: format_address(&tx.from),
+                    message: format!("Detected possible arbtrage from: {:?}", tx.from),
+                });
             }
         }
     }
@@ -1140,68 +1340,49 @@ pub async fn detect_arbitrage(
     patterns
 }
 
-fn percentile(values: &Vec<f64>) -> f64 {
-    if values.is_empty() {
-        return 0.0;
-    }
-
-    let mut sorted: Vec<f64> = values.clone();
-    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
-    let idx = ((PERC / 100.0) * (sorted.len() as f64 - 1.0)) as usize;
-    sorted[idx]
-}
-
-fn local_mean(storage: &SharedTxStorage, sender: &String) -> f64 {
-    let local_values: Vec<f64> = storage
-        .by_sender
-        .get(sender)
-        .map(|vec| vec.iter().map(|t| t.value).collect())
-        .unwrap_or_else(|| vec![]);
-
-    let local_mean = if local_values.is_empty() {
-        0.0
-    } else {
-        local_values.iter().sum::<f64>() / local_values.len() as f64
-    };
-
-    local_mean
-}
-
-async fn global_threshold(storage: &SharedTxStorage) -> f64 {
-    let all_txs = storage.all_txs.read().await;
-    let all_values: Vec<f64> = all_txs.iter().map(|tx| tx.value).collect();
-    let global_thershold = percentile(&all_values);
-
-    global_thershold
-}
-
-fn txs_in_interval(
-    txs: &Vec<TransactionRecord>,
+/// A window of a sliding-window detector, `start` inclusive and `end` exclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Window {
     start: DateTime<Utc>,
     end: DateTime<Utc>,
-) -> Vec<&TransactionRecord> {
-    txs.iter()
-        .filter(|tx| {
-            let ts: DateTime<Utc> = tx.timestamp.parse().unwrap();
-            start <= ts && ts <= end
-        })
-        .collect()
 }
 
-fn local_mean_fee(storage: &SharedTxStorage, sender: &str) -> f64 {
-    storage
-        .by_sender
-        .get(sender)
-        .map(|txs| {
-            let fees: Vec<f64> = txs
-                .iter()
-                .map(|tx| tx.gas_price_gwei * tx.gas as f64 / 1e9)
-                .collect();
-            if fees.is_empty() {
-                0.0
-            } else {
-                fees.iter().sum::<f64>() / fees.len() as f64
-            }
-        })
-        .unwrap_or(0.0)
+/// Windows of `length`, `step` apart and laid from `origin`, that hold at least
+/// one of `timestamps`, each with the range of indices of `timestamps` inside it.
+///
+/// `timestamps` must be sorted and not earlier than `origin`. Windows holding
+/// none of them are skipped, so a sparse sender over months of data stays cheap.
+fn sliding_windows(
+    timestamps: &[DateTime<Utc>],
+    origin: DateTime<Utc>,
+    length: Duration,
+    step: Duration,
+) -> Vec<(Window, Range<usize>)> {
+    let step_ms = step.num_milliseconds().max(1);
+    let length_ms = length.num_milliseconds();
+    let mut windows = Vec::new();
+    let mut next = 0;
+    for timestamp in timestamps {
+        let offset = (*timestamp - origin).num_milliseconds();
+        // Windows `k` with `k * step <= offset < k * step + length`.
+        let first = (offset - length_ms).div_euclid(step_ms) + 1;
+        let last = offset.div_euclid(step_ms);
+        for k in first.max(next)..=last {
+            let start = origin + Duration::milliseconds(k * step_ms);
+            let end = start + length;
+            let range = timestamps.partition_point(|t| *t < start)
+                ..timestamps.partition_point(|t| *t < end);
+            windows.push((Window { start, end }, range));
+        }
+        next = next.max(last + 1);
+    }
+    windows
+}
+
+fn abs_diff(a: U256, b: U256) -> U256 {
+    if a > b {
+        a - b
+    } else {
+        b - a
+    }
 }
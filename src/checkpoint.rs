@@ -0,0 +1,136 @@
+use std::collections::BTreeSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+/// Persistent record of the blocks a scan has completed.
+///
+/// The checkpoint is a plain text file with one block number per line. It is
+/// append-only: [`mark_completed`](Self::mark_completed) writes and syncs one
+/// line per block, so a crash loses at most the block being written. A last
+/// line without its newline (an interrupted write) is ignored when the file is
+/// opened again.
+///
+/// # Example
+/// ```rust,ignore
+/// let mut checkpoint = Checkpoint::open("txs.ndjson.checkpoint")?;
+/// let pending = checkpoint.pending(19_000_000, 19_010_000);
+/// let report = scan_blocks(&provider, pending, &storage, &options, |block| {
+///     write_block(&mut output, block)?;
+///     checkpoint.mark_completed(block.number)
+/// })
+/// .await;
+/// ```
+#[derive(Debug)]
+pub struct Checkpoint {
+    path: PathBuf,
+    file: File,
+    completed: BTreeSet<u64>,
+}
+
+impl Checkpoint {
+    /// Opens the checkpoint at `path`, loading the blocks it already lists.
+    /// The file is created if it does not exist.
+    ///
+    /// # Errors
+    /// Returns an I/O error if the file cannot be read or created, or
+    /// [`ErrorKind::InvalidData`] if a complete line is not a block number.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        // Everything after the last newline is an interrupted write.
+        let complete = content.rfind('\n').map_or("", |end| &content[..=end]);
+
+        let mut completed = BTreeSet::new();
+        for line in complete
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+        {
+            completed.insert(parse_line(line)?);
+        }
+
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        if complete.len() < content.len() {
+            file.set_len(complete.len() as u64)?;
+        }
+
+        Ok(Self {
+            path,
+            file,
+            completed,
+        })
+    }
+
+    /// Creates an empty checkpoint at `path`, discarding any previous content.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        File::create(path.as_ref())?;
+        Self::open(path)
+    }
+
+    /// Path of the checkpoint file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Blocks completed so far, in ascending order.
+    pub fn completed(&self) -> &BTreeSet<u64> {
+        &self.completed
+    }
+
+    /// `true` if `block_number` was completed by this or an earlier run.
+    pub fn is_completed(&self, block_number: u64) -> bool {
+        self.completed.contains(&block_number)
+    }
+
+    /// Blocks of `start_block..=end_block` that are not completed yet.
+    pub fn pending(&self, start_block: u64, end_block: u64) -> Vec<u64> {
+        (start_block..=end_block)
+            .filter(|block_number| !self.is_completed(*block_number))
+            .collect()
+    }
+
+    /// Forgets every completed block from `block_number` on, e.g. after a
+    /// chain reorganization orphaned them, so they are pending again.
+    ///
+    /// The remaining blocks are written to a temporary file that then replaces
+    /// the checkpoint, so a crash leaves either the old or the new list.
+    pub fn rollback_from(&mut self, block_number: u64) -> io::Result<()> {
+        if self.completed.split_off(&block_number).is_empty() {
+            return Ok(());
+        }
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_path);
+        let mut tmp = BufWriter::new(File::create(&tmp_path)?);
+        for completed in &self.completed {
+            writeln!(tmp, "{}", completed)?;
+        }
+        tmp.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        fs::rename(&tmp_path, &self.path)?;
+        self.file = OpenOptions::new().append(true).open(&self.path)?;
+        Ok(())
+    }
+
+    /// Records `block_number` as completed and syncs the file to disk.
+    pub fn mark_completed(&mut self, block_number: u64) -> io::Result<()> {
+        if !self.completed.insert(block_number) {
+            return Ok(());
+        }
+        writeln!(self.file, "{}", block_number)?;
+        self.file.sync_data()
+    }
+}
+
+fn parse_line(line: &str) -> io::Result<u64> {
+    line.parse().map_err(|_| {
+        io::Error::new(
+            ErrorKind::InvalidData,
+            format!("invalid block number in checkpoint: {:?}", line),
+        )
+    })
+}
@@ -0,0 +1,293 @@
+use clap::{Args, Parser, Subcommand};
+use eth_analyzer::{
+    import::ImportFormat,
+    watch::{DEFAULT_MAX_FAILED_POLLS, DEFAULT_RETAIN_BLOCKS},
+};
+use std::path::PathBuf;
+
+/// Command-line interface of the analyzer binary.
+///
+/// Every subcommand that talks to a node takes the RPC endpoint and the block
+/// range as flags, each of which can also be provided through an environment
+/// variable so runs can be scripted without editing `main`.
+///
+/// # Example
+///
+/// ```text
+/// ETH_RPC_URL=https://mainnet.infura.io/v3/<key> eth_analyzer export --last 50 \
+///     --detectors large_tx,high_fee,whales --anomalies-out out/anomalies.csv
+/// ```
+#[derive(Debug, Parser)]
+#[command(
+    name = "eth_analyzer",
+    version,
+    about = "Scan Ethereum blocks and detect transaction anomalies and business patterns"
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Scan a block range and dump its transactions as newline-delimited JSON.
+    Scan(ScanArgs),
+    /// Scan a block range, run the detectors and print a summary.
+    Analyze(AnalyzeArgs),
+    /// Scan a block range, run the detectors and export the findings to CSV.
+    Export(ExportArgs),
+    /// Follow the chain head and print new findings as JSON lines as blocks arrive.
+    Watch(WatchArgs),
+    /// Render exported CSV files with the Python visualization script.
+    Report(ReportArgs),
+    /// List the available detectors with their kind and configuration.
+    Detectors(ListArgs),
+}
+
+/// Where `analyze` and `export` read transactions from: the node endpoint, a
+/// transaction store or transaction dumps, and the block range to read.
+#[derive(Debug, Args)]
+pub struct SourceArgs {
+    /// JSON-RPC endpoint of an Ethereum node. Not needed to analyze a `--db`
+    /// store or `--import` files, or to replay `--replay-rpc` fixtures.
+    #[arg(
+        long,
+        env = "ETH_RPC_URL",
+        required_unless_present_any = ["db", "import", "replay_rpc"]
+    )]
+    pub rpc_url: Option<String>,
+
+    /// SQLite transaction store written by `scan --db`; the block range is read
+    /// from it instead of the node.
+    #[arg(long, env = "ETH_ANALYZER_DB")]
+    pub db: Option<PathBuf>,
+
+    /// Transaction dumps `analyze` and `export` read instead of the node:
+    /// NDJSON as written by `scan`, CSV, or saved `eth_getBlockByNumber`
+    /// responses. Comma-separated or repeated. Without `--from-block` and
+    /// `--to-block` every imported block is analyzed.
+    #[arg(long, value_delimiter = ',', conflicts_with = "db")]
+    pub import: Vec<PathBuf>,
+
+    /// Format of the `--import` files: `ndjson`, `csv` or `blocks`. Inferred from
+    /// the extension when omitted (`.ndjson`/`.jsonl`, `.csv`, `.json`).
+    #[arg(long, requires = "import")]
+    pub import_format: Option<ImportFormat>,
+
+    /// Token transfers imported along with `--import`, as NDJSON written by
+    /// `scan --transfers-output`.
+    #[arg(long, value_delimiter = ',', requires = "import")]
+    pub import_transfers: Vec<PathBuf>,
+
+    #[command(flatten)]
+    pub range: BlockRangeArgs,
+
+    #[command(flatten)]
+    pub fetch: FetchArgs,
+}
+
+/// The block range to read.
+#[derive(Debug, Args)]
+pub struct BlockRangeArgs {
+    /// First block of the range (inclusive). Defaults to `to_block - last`.
+    #[arg(long, env = "ETH_FROM_BLOCK")]
+    pub from_block: Option<u64>,
+
+    /// Last block of the range (inclusive). Defaults to the latest block, or the
+    /// highest stored block when `analyze` or `export` read from `--db`.
+    #[arg(long, env = "ETH_TO_BLOCK")]
+    pub to_block: Option<u64>,
+
+    /// Number of blocks before `--to-block` to scan when `--from-block` is not set.
+    #[arg(long, env = "ETH_LAST_BLOCKS", default_value_t = 10)]
+    pub last: u64,
+}
+
+/// What to fetch for every block and how to pace requests to the node.
+#[derive(Debug, Args)]
+pub struct FetchArgs {
+    /// Fetch transaction receipts for the actual gas used, fee and status.
+    #[arg(long, env = "ETH_FETCH_RECEIPTS")]
+    pub receipts: bool,
+
+    /// Fetch block logs and decode ERC-20 `Transfer` events for the value-based detectors.
+    #[arg(long, env = "ETH_FETCH_TOKEN_TRANSFERS")]
+    pub token_transfers: bool,
+
+    /// Maximum number of blocks fetched concurrently.
+    #[arg(long, env = "ETH_MAX_IN_FLIGHT", default_value_t = 8)]
+    pub max_in_flight: usize,
+
+    /// Retries per block after a transient RPC error before the block is reported as failed.
+    #[arg(long, env = "ETH_MAX_RETRIES", default_value_t = 3)]
+    pub max_retries: u32,
+
+    /// Delay before the first retry in milliseconds; doubles with every further attempt.
+    #[arg(long, env = "ETH_RETRY_BACKOFF_MS", default_value_t = 500)]
+    pub retry_backoff_ms: u64,
+
+    /// Maximum number of RPC requests per second. Unlimited when omitted.
+    #[arg(long, env = "ETH_RATE_LIMIT")]
+    pub rate_limit: Option<u32>,
+
+    /// Number of recent blocks compared with the node to detect chain reorganizations; 0 disables it.
+    #[arg(long, env = "ETH_REORG_DEPTH", default_value_t = 64)]
+    pub reorg_depth: u64,
+
+    /// Save every answer of the node to this fixture directory, for replaying
+    /// the run later with `--replay-rpc`.
+    #[arg(long, env = "ETH_RECORD_RPC", conflicts_with = "replay_rpc")]
+    pub record_rpc: Option<PathBuf>,
+
+    /// Answer RPC requests from a fixture directory written by `--record-rpc`
+    /// instead of the node.
+    #[arg(long, env = "ETH_REPLAY_RPC")]
+    pub replay_rpc: Option<PathBuf>,
+}
+
+/// Which detectors to run and with which thresholds.
+#[derive(Debug, Args)]
+pub struct DetectorArgs {
+    /// Comma-separated detector names. All detectors run when omitted.
+    #[arg(long, env = "ETH_ANALYZER_DETECTORS", value_delimiter = ',')]
+    pub detectors: Vec<String>,
+
+    /// Comma-separated detector names to skip.
+    #[arg(long, env = "ETH_ANALYZER_EXCLUDE", value_delimiter = ',')]
+    pub exclude: Vec<String>,
+
+    /// Detector configuration file (`.toml` or `.json`). Built-in defaults are used when omitted.
+    #[arg(long, short, env = "ETH_ANALYZER_CONFIG")]
+    pub config: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct ScanArgs {
+    /// JSON-RPC endpoint of an Ethereum node. Not needed to replay
+    /// `--replay-rpc` fixtures.
+    #[arg(long, env = "ETH_RPC_URL", required_unless_present_any = ["replay_rpc"])]
+    pub rpc_url: Option<String>,
+
+    /// SQLite transaction store every scanned block is also written to.
+    #[arg(long, env = "ETH_ANALYZER_DB")]
+    pub db: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub range: BlockRangeArgs,
+
+    #[command(flatten)]
+    pub fetch: FetchArgs,
+
+    /// Output file for the scanned transactions (one JSON object per line).
+    #[arg(long, short, default_value = "transactions.ndjson")]
+    pub output: PathBuf,
+
+    /// Output file for decoded token transfers (one JSON object per line).
+    /// Requires `--token-transfers`.
+    #[arg(long, requires = "token_transfers")]
+    pub transfers_output: Option<PathBuf>,
+
+    /// Continue an interrupted scan: skip the blocks listed in the checkpoint
+    /// and append to the output files instead of overwriting them.
+    #[arg(long)]
+    pub resume: bool,
+
+    /// Checkpoint file listing completed blocks. Defaults to `<output>.checkpoint`.
+    #[arg(long, env = "ETH_SCAN_CHECKPOINT")]
+    pub checkpoint: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct AnalyzeArgs {
+    #[command(flatten)]
+    pub source: SourceArgs,
+
+    #[command(flatten)]
+    pub detectors: DetectorArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct ExportArgs {
+    #[command(flatten)]
+    pub source: SourceArgs,
+
+    #[command(flatten)]
+    pub detectors: DetectorArgs,
+
+    /// Output CSV file for detected anomalies.
+    #[arg(long, env = "ETH_ANALYZER_ANOMALIES", default_value = "anomalies.csv")]
+    pub anomalies_out: PathBuf,
+
+    /// Output CSV file for detected business patterns.
+    #[arg(long, env = "ETH_ANALYZER_PATTERNS", default_value = "patterns.csv")]
+    pub patterns_out: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct WatchArgs {
+    /// JSON-RPC endpoint of an Ethereum node. Not needed to replay
+    /// `--replay-rpc` fixtures.
+    #[arg(long, env = "ETH_RPC_URL", required_unless_present_any = ["replay_rpc"])]
+    pub rpc_url: Option<String>,
+
+    /// First block to ingest. Defaults to the current head.
+    #[arg(long, env = "ETH_FROM_BLOCK")]
+    pub from_block: Option<u64>,
+
+    /// Seconds between two polls of the chain head.
+    #[arg(long, env = "ETH_POLL_INTERVAL", default_value_t = 12)]
+    pub poll_interval: u64,
+
+    /// Keep only this many recent blocks in memory. Every poll reruns the detectors
+    /// over the retained blocks, so this also bounds the time of a poll. Should
+    /// cover the longest detector window.
+    #[arg(
+        long,
+        env = "ETH_RETAIN_BLOCKS",
+        default_value_t = DEFAULT_RETAIN_BLOCKS,
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub retain_blocks: u64,
+
+    /// Give up on a block once it failed on this many polls. Blocks that fall out
+    /// of the retained blocks are given up on as well.
+    #[arg(
+        long,
+        env = "ETH_MAX_FAILED_POLLS",
+        default_value_t = DEFAULT_MAX_FAILED_POLLS,
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    pub max_failed_polls: u32,
+
+    #[command(flatten)]
+    pub fetch: FetchArgs,
+
+    #[command(flatten)]
+    pub detectors: DetectorArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct ListArgs {
+    /// Detector configuration file (`.toml` or `.json`). Built-in defaults are used when omitted.
+    #[arg(long, short, env = "ETH_ANALYZER_CONFIG")]
+    pub config: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct ReportArgs {
+    /// Anomalies CSV produced by `export`.
+    #[arg(long, env = "ETH_ANALYZER_ANOMALIES", default_value = "anomalies.csv")]
+    pub anomalies: PathBuf,
+
+    /// Patterns CSV produced by `export`.
+    #[arg(long, env = "ETH_ANALYZER_PATTERNS", default_value = "patterns.csv")]
+    pub patterns: PathBuf,
+
+    /// Visualization script to run.
+    #[arg(long, default_value = "src/viz.py")]
+    pub script: PathBuf,
+
+    /// Python interpreter used to run the script.
+    #[arg(long, env = "ETH_ANALYZER_PYTHON", default_value = "python3")]
+    pub python: String,
+}
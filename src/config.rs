@@ -1,4 +1,601 @@
-pub const PERC: f64 = 99.0;
-pub const K_LOCAL: f64 = 5.0;
-pub const THRESHOLD_TIME: usize = 20;
-pub const K_LOCAL_FEE: f64 = 8.0;
+use crate::address::{deserialize_addresses, parse_address};
+use ethers::types::Address;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+/// Runtime configuration of every detector threshold.
+///
+/// The configuration is split into one section per detector. Every section
+/// and every field is optional in a configuration file: missing values fall
+/// back to the defaults below, which match the thresholds the analyzer has
+/// always used.
+///
+/// # Example
+///
+/// ```toml
+/// [large_tx]
+/// percentile = 99.5
+/// z_threshold = 5.0
+///
+/// [time_anomalies]
+/// night_end_hour = 5
+///
+/// [dex]
+/// routers = ["0x7a250d5630B4cF539739dF2C5dAcb4c659F2488D"]
+/// ```
+///
+/// ```rust,ignore
+/// let config = AnalyzerConfig::from_file("eth_analyzer.toml")?;
+/// let anomalies = detect_large_tx(&ctx, &config.large_tx).await;
+/// ```
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AnalyzerConfig {
+    pub large_tx: LargeTxConfig,
+    pub high_frequency: HighFrequencyConfig,
+    pub structuring: StructuringConfig,
+    pub high_fee: HighFeeConfig,
+    pub high_priority_fee: HighPriorityFeeConfig,
+    pub unusual_op: UnusualOpConfig,
+    pub time_anomalies: TimeAnomalyConfig,
+    pub regular_payments: RegularPaymentsConfig,
+    pub batch_payments: BatchPaymentsConfig,
+    pub active_traders: ActiveTradersConfig,
+    pub whales: WhalesConfig,
+    pub dex: DexConfig,
+    pub sanctions: SanctionsConfig,
+}
+
+/// Thresholds of [`detect_large_tx`](crate::analize::detect_large_tx).
+///
+/// * `percentile` – Percentile of all transaction values used as the global threshold.
+/// * `z_threshold` – A transaction is locally large when its robust z-score against
+///   the median and MAD of the sender's other values exceeds `z_threshold`.
+/// * `flat_ratio` – When all of the sender's other values are equal there is no
+///   MAD, and a transaction is locally large when it exceeds `flat_ratio` times
+///   that value. A history of zero values never flags.
+/// * `min_history` – Senders with fewer other transactions are only compared with
+///   the global threshold.
+/// * `include_tokens` – Also check ERC-20 transfers, each token against its own
+///   thresholds.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LargeTxConfig {
+    pub percentile: f64,
+    pub z_threshold: f64,
+    pub flat_ratio: f64,
+    pub min_history: usize,
+    pub include_tokens: bool,
+}
+
+impl Default for LargeTxConfig {
+    fn default() -> Self {
+        Self {
+            percentile: 99.0,
+            z_threshold: 3.5,
+            flat_ratio: 10.0,
+            min_history: 5,
+            include_tokens: true,
+        }
+    }
+}
+
+/// Thresholds of [`detect_high_frequency`](crate::analize::detect_high_frequency).
+///
+/// * `window_minutes` – Length of the observed time window.
+/// * `step_minutes` – Distance between the starts of two consecutive windows. Windows
+///   are laid from the first transaction of the analyzed data, not from the current time.
+/// * `threshold` – A sender is flagged when it sends more than `threshold`
+///   transactions within the window.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HighFrequencyConfig {
+    pub window_minutes: u32,
+    pub step_minutes: u32,
+    pub threshold: usize,
+}
+
+impl Default for HighFrequencyConfig {
+    fn default() -> Self {
+        Self {
+            window_minutes: 60,
+            step_minutes: 10,
+            threshold: 20,
+        }
+    }
+}
+
+/// Thresholds of [`detect_structuring`](crate::analize::detect_structuring).
+///
+/// * `window_minutes` – Length of the observed time window.
+/// * `step_minutes` – Distance between the starts of two consecutive windows, laid
+///   from the first transaction of the analyzed data.
+/// * `percentile` – Percentile of all transaction values used as the global threshold.
+/// * `k_local` – The window sum is locally large when it exceeds `k_local` times
+///   the sender's mean value.
+/// * `count_threshold` – The window is suspicious when it holds more than
+///   `count_threshold` transactions.
+/// * `include_tokens` – Also check ERC-20 transfers, per sender and token.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StructuringConfig {
+    pub window_minutes: u32,
+    pub step_minutes: u32,
+    pub percentile: f64,
+    pub k_local: f64,
+    pub count_threshold: usize,
+    pub include_tokens: bool,
+}
+
+impl Default for StructuringConfig {
+    fn default() -> Self {
+        Self {
+            window_minutes: 60,
+            step_minutes: 10,
+            percentile: 99.0,
+            k_local: 5.0,
+            count_threshold: 10,
+            include_tokens: true,
+        }
+    }
+}
+
+/// Thresholds of [`detect_high_fee`](crate::analize::detect_high_fee).
+///
+/// * `percentile` – Percentile of all fees used as the global threshold.
+/// * `z_threshold` – A fee is locally high when its robust z-score against the
+///   median and MAD of the sender's other fees exceeds `z_threshold`.
+/// * `flat_ratio` – When all of the sender's other fees are equal there is no
+///   MAD, and a fee is locally high when it exceeds `flat_ratio` times that fee.
+/// * `min_history` – Senders with fewer other transactions are only compared with
+///   the global threshold.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HighFeeConfig {
+    pub percentile: f64,
+    pub z_threshold: f64,
+    pub flat_ratio: f64,
+    pub min_history: usize,
+}
+
+impl Default for HighFeeConfig {
+    fn default() -> Self {
+        Self {
+            percentile: 99.0,
+            z_threshold: 3.5,
+            flat_ratio: 10.0,
+            min_history: 5,
+        }
+    }
+}
+
+/// Thresholds of [`detect_high_priority_fee`](crate::analize::detect_high_priority_fee).
+///
+/// * `percentile` – Percentile of all priority fees per gas used as the global threshold.
+/// * `k_base_fee` – A priority fee is high relative to its block when it exceeds
+///   `k_base_fee` times the block's base fee per gas.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HighPriorityFeeConfig {
+    pub percentile: f64,
+    pub k_base_fee: f64,
+}
+
+impl Default for HighPriorityFeeConfig {
+    fn default() -> Self {
+        Self {
+            percentile: 99.0,
+            k_base_fee: 2.0,
+        }
+    }
+}
+
+/// Thresholds of [`detect_unusual_op`](crate::analize::detect_unusual_op).
+///
+/// * `percentile` – Percentile of all values and gas prices above which a
+///   transaction is unusual.
+/// * `max_input_len` – Input data longer than this many characters is unusual.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UnusualOpConfig {
+    pub percentile: f64,
+    pub max_input_len: usize,
+}
+
+impl Default for UnusualOpConfig {
+    fn default() -> Self {
+        Self {
+            percentile: 99.0,
+            max_input_len: 100,
+        }
+    }
+}
+
+/// Thresholds of [`detect_time_anomalies`](crate::analize::detect_time_anomalies).
+///
+/// * `night_end_hour` – Transactions at or before this UTC hour are unusual.
+/// * `burst_window_minutes` – Length of the burst window.
+/// * `burst_min_count` – Minimum number of transactions within the window that
+///   makes a burst.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimeAnomalyConfig {
+    pub night_end_hour: u32,
+    pub burst_window_minutes: u32,
+    pub burst_min_count: usize,
+}
+
+impl Default for TimeAnomalyConfig {
+    fn default() -> Self {
+        Self {
+            night_end_hour: 6,
+            burst_window_minutes: 10,
+            burst_min_count: 5,
+        }
+    }
+}
+
+/// Thresholds of [`detect_regular_payments`](crate::analize::detect_regular_payments).
+///
+/// * `min_count` – Minimum number of payments to the same receiver.
+/// * `max_deviation_ratio` – Maximum mean absolute deviation of the amounts,
+///   relative to their average.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RegularPaymentsConfig {
+    pub min_count: usize,
+    pub max_deviation_ratio: f64,
+}
+
+impl Default for RegularPaymentsConfig {
+    fn default() -> Self {
+        Self {
+            min_count: 2,
+            max_deviation_ratio: 0.1,
+        }
+    }
+}
+
+/// Thresholds of [`detect_batch_payments`](crate::analize::detect_batch_payments).
+///
+/// * `interval_minutes` – Length of the batch window.
+/// * `min_count` – Minimum number of payments within the window that makes a batch.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BatchPaymentsConfig {
+    pub interval_minutes: u32,
+    pub min_count: usize,
+}
+
+impl Default for BatchPaymentsConfig {
+    fn default() -> Self {
+        Self {
+            interval_minutes: 5,
+            min_count: 5,
+        }
+    }
+}
+
+/// Thresholds of [`detect_active_traders`](crate::analize::detect_active_traders).
+///
+/// * `threshold` – A sender is an active trader when it sends more than
+///   `threshold` transactions to DEX contracts.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ActiveTradersConfig {
+    pub threshold: usize,
+}
+
+impl Default for ActiveTradersConfig {
+    fn default() -> Self {
+        Self { threshold: 10 }
+    }
+}
+
+/// Thresholds of [`detect_whales`](crate::analize::detect_whales).
+///
+/// * `percentile` – Percentile of all transaction values a sender's mean value
+///   must exceed.
+/// * `include_tokens` – Also look for whales of every ERC-20 token, comparing
+///   transfer amounts of the same token only.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WhalesConfig {
+    pub percentile: f64,
+    pub include_tokens: bool,
+}
+
+impl Default for WhalesConfig {
+    fn default() -> Self {
+        Self {
+            percentile: 99.0,
+            include_tokens: true,
+        }
+    }
+}
+
+/// Known DEX router contracts used by the DEX-related pattern detectors.
+///
+/// Defaults to the Uniswap V2 and SushiSwap routers.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DexConfig {
+    #[serde(deserialize_with = "deserialize_addresses")]
+    pub routers: Vec<Address>,
+}
+
+impl Default for DexConfig {
+    fn default() -> Self {
+        Self {
+            routers: vec![
+                parse_address("0x7a250d5630B4cF539739dF2C5dAcb4c659F2488D")
+                    .expect("valid Uniswap V2 router address"),
+                parse_address("0xd9e1cE17f2641f24aE83637ab66a2cca9C378B9F")
+                    .expect("valid SushiSwap router address"),
+            ],
+        }
+    }
+}
+
+/// Sanctions lists checked by
+/// [`detect_blacklist_adresses`](crate::analize::detect_blacklist_adresses).
+///
+/// * `sources` – Lists merged into one set of sanctioned addresses, each a local
+///   file (`{ file = "ofac.csv" }`) or a URL (`{ url = "https://..." }`). Defaults
+///   to the OFAC list of Ethereum addresses published by 0xB10C.
+/// * `cache_dir` – Where downloaded lists are cached. Defaults to
+///   `eth_analyzer/sanctions` in the system temporary directory.
+/// * `cache_ttl_hours` – Age after which a cached download is fetched again,
+///   and after which the `blacklist` detector reloads the lists.
+///
+/// See [`crate::sanctions`] for the accepted list formats.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SanctionsConfig {
+    pub sources: Vec<SanctionsSourceConfig>,
+    pub cache_dir: Option<PathBuf>,
+    pub cache_ttl_hours: u64,
+}
+
+impl Default for SanctionsConfig {
+    fn default() -> Self {
+        Self {
+            sources: vec![SanctionsSourceConfig::Url(
+                crate::sanctions::OFAC_ETH_LIST_URL.to_string(),
+            )],
+            cache_dir: None,
+            cache_ttl_hours: 24,
+        }
+    }
+}
+
+/// Where one sanctions list is read from.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase", deny_unknown_fields)]
+pub enum SanctionsSourceConfig {
+    /// A list on the local disk.
+    File(PathBuf),
+    /// A list downloaded over HTTP(S) and cached in `cache_dir`.
+    Url(String),
+}
+
+/// Errors returned while loading or validating an [`AnalyzerConfig`].
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The configuration file could not be read.
+    Io(std::io::Error),
+    /// The file is not valid TOML for [`AnalyzerConfig`].
+    Toml(toml::de::Error),
+    /// The file is not valid JSON for [`AnalyzerConfig`].
+    Json(serde_json::Error),
+    /// The file extension is neither `.toml` nor `.json`.
+    UnsupportedFormat(String),
+    /// A value is outside of its allowed range.
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "cannot read configuration: {}", e),
+            ConfigError::Toml(e) => write!(f, "invalid TOML configuration: {}", e),
+            ConfigError::Json(e) => write!(f, "invalid JSON configuration: {}", e),
+            ConfigError::UnsupportedFormat(path) => write!(
+                f,
+                "unsupported configuration format for {} (expected .toml or .json)",
+                path
+            ),
+            ConfigError::Invalid(msg) => write!(f, "invalid configuration: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Io(e) => Some(e),
+            ConfigError::Toml(e) => Some(e),
+            ConfigError::Json(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl AnalyzerConfig {
+    /// Loads and validates a configuration file.
+    ///
+    /// The format is chosen from the file extension: `.toml` or `.json`.
+    ///
+    /// # Errors
+    /// Returns a [`ConfigError`] if the file cannot be read, cannot be parsed,
+    /// or contains out-of-range values.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path).map_err(ConfigError::Io)?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => Self::from_toml_str(&content),
+            Some("json") => Self::from_json_str(&content),
+            _ => Err(ConfigError::UnsupportedFormat(path.display().to_string())),
+        }
+    }
+
+    /// Parses and validates a TOML configuration.
+    pub fn from_toml_str(content: &str) -> Result<Self, ConfigError> {
+        let config: Self = toml::from_str(content).map_err(ConfigError::Toml)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Parses and validates a JSON configuration.
+    pub fn from_json_str(content: &str) -> Result<Self, ConfigError> {
+        let config: Self = serde_json::from_str(content).map_err(ConfigError::Json)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Checks that every threshold is within its allowed range.
+    ///
+    /// # Errors
+    /// Returns [`ConfigError::Invalid`] naming the first offending field.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        check_percentile("large_tx.percentile", self.large_tx.percentile)?;
+        check_multiplier("large_tx.z_threshold", self.large_tx.z_threshold)?;
+        check_multiplier("large_tx.flat_ratio", self.large_tx.flat_ratio)?;
+        check_positive("large_tx.min_history", self.large_tx.min_history)?;
+
+        check_window(
+            "high_frequency",
+            self.high_frequency.window_minutes,
+            self.high_frequency.step_minutes,
+        )?;
+
+        check_window(
+            "structuring",
+            self.structuring.window_minutes,
+            self.structuring.step_minutes,
+        )?;
+        check_percentile("structuring.percentile", self.structuring.percentile)?;
+        check_multiplier("structuring.k_local", self.structuring.k_local)?;
+
+        check_percentile("high_fee.percentile", self.high_fee.percentile)?;
+        check_multiplier("high_fee.z_threshold", self.high_fee.z_threshold)?;
+        check_multiplier("high_fee.flat_ratio", self.high_fee.flat_ratio)?;
+        check_positive("high_fee.min_history", self.high_fee.min_history)?;
+
+        check_percentile(
+            "high_priority_fee.percentile",
+            self.high_priority_fee.percentile,
+        )?;
+        check_multiplier(
+            "high_priority_fee.k_base_fee",
+            self.high_priority_fee.k_base_fee,
+        )?;
+
+        check_percentile("unusual_op.percentile", self.unusual_op.percentile)?;
+
+        if self.time_anomalies.night_end_hour > 23 {
+            return Err(ConfigError::Invalid(format!(
+                "time_anomalies.night_end_hour must be between 0 and 23, got {}",
+                self.time_anomalies.night_end_hour
+            )));
+        }
+        check_positive(
+            "time_anomalies.burst_window_minutes",
+            self.time_anomalies.burst_window_minutes as usize,
+        )?;
+        check_positive(
+            "time_anomalies.burst_min_count",
+            self.time_anomalies.burst_min_count,
+        )?;
+
+        if self.regular_payments.min_count < 2 {
+            return Err(ConfigError::Invalid(format!(
+                "regular_payments.min_count must be at least 2, got {}",
+                self.regular_payments.min_count
+            )));
+        }
+        check_multiplier(
+            "regular_payments.max_deviation_ratio",
+            self.regular_payments.max_deviation_ratio,
+        )?;
+
+        check_positive(
+            "batch_payments.interval_minutes",
+            self.batch_payments.interval_minutes as usize,
+        )?;
+        check_positive("batch_payments.min_count", self.batch_payments.min_count)?;
+
+        check_percentile("whales.percentile", self.whales.percentile)?;
+
+        if self.sanctions.sources.is_empty() {
+            return Err(ConfigError::Invalid(
+                "sanctions.sources must list at least one source".to_string(),
+            ));
+        }
+        for source in &self.sanctions.sources {
+            if let SanctionsSourceConfig::Url(url) = source {
+                if !(url.starts_with("http://") || url.starts_with("https://")) {
+                    return Err(ConfigError::Invalid(format!(
+                        "sanctions.sources: {} is not an http(s) URL",
+                        url
+                    )));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A sliding window needs a positive length and a step no longer than the
+/// window, otherwise the data between two windows is never looked at.
+fn check_window(section: &str, window_minutes: u32, step_minutes: u32) -> Result<(), ConfigError> {
+    check_positive(
+        &format!("{}.window_minutes", section),
+        window_minutes as usize,
+    )?;
+    check_positive(&format!("{}.step_minutes", section), step_minutes as usize)?;
+    if step_minutes > window_minutes {
+        return Err(ConfigError::Invalid(format!(
+            "{}.step_minutes must not exceed window_minutes ({}), got {}",
+            section, window_minutes, step_minutes
+        )));
+    }
+    Ok(())
+}
+
+fn check_percentile(field: &str, value: f64) -> Result<(), ConfigError> {
+    if value.is_finite() && value > 0.0 && value <= 100.0 {
+        Ok(())
+    } else {
+        Err(ConfigError::Invalid(format!(
+            "{} must be in (0, 100], got {}",
+            field, value
+        )))
+    }
+}
+
+fn check_multiplier(field: &str, value: f64) -> Result<(), ConfigError> {
+    if value.is_finite() && value > 0.0 {
+        Ok(())
+    } else {
+        Err(ConfigError::Invalid(format!(
+            "{} must be a positive number, got {}",
+            field, value
+        )))
+    }
+}
+
+fn check_positive(field: &str, value: usize) -> Result<(), ConfigError> {
+    if value > 0 {
+        Ok(())
+    } else {
+        Err(ConfigError::Invalid(format!(
+            "{} must be greater than 0",
+            field
+        )))
+    }
+}
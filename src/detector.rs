@@ -0,0 +1,986 @@
+use crate::{
+    analize::{
+        detect_active_traders, detect_arbitrage, detect_batch_payments, detect_blacklist_adresses,
+        detect_dex_trade, detect_high_fee, detect_high_frequency, detect_high_priority_fee,
+        detect_large_tx, detect_liquid_provider, detect_nft_activity, detect_regular_payments,
+        detect_structuring, detect_time_anomalies, detect_unusual_op, detect_whales,
+    },
+    config::{
+        ActiveTradersConfig, AnalyzerConfig, BatchPaymentsConfig, DexConfig, HighFeeConfig,
+        HighFrequencyConfig, HighPriorityFeeConfig, LargeTxConfig, RegularPaymentsConfig,
+        SanctionsConfig, StructuringConfig, TimeAnomalyConfig, UnusualOpConfig, WhalesConfig,
+    },
+    models::{Anomaly, BusinessPattern},
+    query::TxQuery,
+    sanctions::{load_sanctions, sources_from_config, SanctionsError, SanctionsList},
+    stats::DatasetStats,
+};
+use async_trait::async_trait;
+use ethers::types::{H160, H256};
+use log::{error, info};
+use serde::Serialize;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{
+    sync::{Mutex, OnceCell, RwLock},
+    task::JoinSet,
+};
+
+/// State of one analysis run: the transactions to analyze and everything the
+/// detectors share with each other while they run.
+///
+/// A context is created per run and dropped with it, so separate runs (and
+/// tests) never see each other's flags and several analyses can run
+/// concurrently over the same or different storages. Clones share the state of
+/// the run, which is how [`DetectorRegistry::run`] hands it to detectors
+/// running as separate tasks.
+///
+/// # Fields
+/// * `storage` – The transactions to analyze, behind the read-only [`TxQuery`]
+///   trait so detectors work the same over any source.
+/// * `flagged` – Hashes of transactions already reported by an anomaly detector
+///   in this run. Written through [`flag`](Self::flag) and read by detectors
+///   that declare the flagging detectors as [`Detector::dependencies`].
+/// * `stats` – Thresholds and per-sender means of the storage, computed by the
+///   first detector that asks for them (see [`stats`](Self::stats)).
+///
+/// # Example
+/// ```rust,ignore
+/// let ctx = AnalysisContext::new(storage);
+/// let findings = registry.run(&ctx).await;
+/// println!("{} transactions flagged", ctx.flagged_hashes().await.len());
+/// ```
+#[derive(Clone)]
+pub struct AnalysisContext {
+    pub storage: Arc<dyn TxQuery>,
+    flagged: Arc<RwLock<HashSet<H256>>>,
+    stats: Arc<OnceCell<DatasetStats>>,
+}
+
+impl AnalysisContext {
+    /// Creates a context over the given source with no flagged transactions.
+    ///
+    /// Accepts a [`SharedTxStorage`](crate::models::SharedTxStorage) as well as
+    /// any other shared [`TxQuery`] implementation.
+    pub fn new<Q: TxQuery + 'static>(storage: Arc<Q>) -> Self {
+        Self {
+            storage,
+            flagged: Arc::new(RwLock::new(HashSet::new())),
+            stats: Arc::new(OnceCell::new()),
+        }
+    }
+
+    /// Statistics of the storage, computed once per run and shared by every
+    /// detector. Detectors running concurrently wait for the same computation.
+    pub async fn stats(&self) -> &DatasetStats {
+        self.stats
+            .get_or_init(|| DatasetStats::compute(self.storage.as_ref()))
+            .await
+    }
+
+    /// Marks a transaction as already reported in this run.
+    pub async fn flag(&self, hash: H256) {
+        self.flagged.write().await.insert(hash);
+    }
+
+    /// Marks several transactions as already reported in this run.
+    pub async fn flag_all<I>(&self, hashes: I)
+    where
+        I: IntoIterator<Item = H256>,
+    {
+        self.flagged.write().await.extend(hashes);
+    }
+
+    pub async fn is_flagged(&self, hash: H256) -> bool {
+        self.flagged.read().await.contains(&hash)
+    }
+
+    /// Snapshot of every transaction hash flagged so far in this run.
+    pub async fn flagged_hashes(&self) -> HashSet<H256> {
+        self.flagged.read().await.clone()
+    }
+}
+
+/// What a detector reports: suspicious activity or regular business behavior.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum DetectorKind {
+    Anomaly,
+    Pattern,
+}
+
+impl fmt::Display for DetectorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DetectorKind::Anomaly => write!(f, "anomaly"),
+            DetectorKind::Pattern => write!(f, "pattern"),
+        }
+    }
+}
+
+/// Results of one or more detectors.
+///
+/// # Fields
+/// * `anomalies` – Detected [`Anomaly`] entries.
+/// * `patterns` – Detected [`BusinessPattern`] entries.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Findings {
+    pub anomalies: Vec<Anomaly>,
+    pub patterns: Vec<BusinessPattern>,
+}
+
+impl Findings {
+    /// Findings made only of anomalies.
+    pub fn from_anomalies(anomalies: Vec<Anomaly>) -> Self {
+        Self {
+            anomalies,
+            patterns: Vec::new(),
+        }
+    }
+
+    /// Findings made only of business patterns.
+    pub fn from_patterns(patterns: Vec<BusinessPattern>) -> Self {
+        Self {
+            anomalies: Vec::new(),
+            patterns,
+        }
+    }
+
+    /// Appends all findings of `other`.
+    pub fn extend(&mut self, other: Findings) {
+        self.anomalies.extend(other.anomalies);
+        self.patterns.extend(other.patterns);
+    }
+
+    /// Total number of anomalies and patterns.
+    pub fn len(&self) -> usize {
+        self.anomalies.len() + self.patterns.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.anomalies.is_empty() && self.patterns.is_empty()
+    }
+}
+
+/// Wall time one detector took in a [`DetectorRegistry::run_report`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DetectorTiming {
+    pub name: String,
+    pub elapsed: Duration,
+}
+
+/// Findings of a registry run and the time every detector took.
+///
+/// # Fields
+/// * `findings` – Findings of all detectors, merged in run order.
+/// * `timings` – Wall time of every detector that ran, in run order. Detectors
+///   run concurrently, so the times add up to more than the run took.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct RunReport {
+    pub findings: Findings,
+    pub timings: Vec<DetectorTiming>,
+}
+
+impl RunReport {
+    /// Timings ordered from the slowest detector to the fastest.
+    pub fn slowest(&self) -> Vec<&DetectorTiming> {
+        let mut timings: Vec<&DetectorTiming> = self.timings.iter().collect();
+        timings.sort_by_key(|timing| std::cmp::Reverse(timing.elapsed));
+        timings
+    }
+}
+
+/// A single analysis over the transactions of an [`AnalysisContext`].
+///
+/// Implement this trait to plug a custom analysis into a [`DetectorRegistry`]
+/// from another crate.
+///
+/// # Required methods
+/// * `name` – Unique name used to enable or disable the detector (e.g. `"large_tx"`).
+/// * `kind` – Whether the detector reports anomalies or business patterns.
+/// * `run` – Performs the analysis and returns its [`Findings`].
+///
+/// # Provided methods
+/// * `config` – Settings the detector runs with, for reports and `list` output.
+///   Defaults to `null`.
+/// * `dependencies` – Names of detectors that must run before this one, e.g.
+///   because it reads the transactions they flagged in the context. Defaults
+///   to none.
+///
+/// # Example
+/// ```rust,ignore
+/// struct ContractCreations;
+///
+/// #[async_trait]
+/// impl Detector for ContractCreations {
+///     fn name(&self) -> &str { "contract_creations" }
+///     fn kind(&self) -> DetectorKind { DetectorKind::Anomaly }
+///     async fn run(&self, ctx: &AnalysisContext) -> Findings {
+///         let all_txs = ctx.storage.all_txs().await;
+///         // ...
+///         Findings::default()
+///     }
+/// }
+///
+/// registry.register(ContractCreations)?;
+/// ```
+#[async_trait]
+pub trait Detector: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn kind(&self) -> DetectorKind;
+
+    fn config(&self) -> serde_json::Value {
+        serde_json::Value::Null
+    }
+
+    fn dependencies(&self) -> &[&str] {
+        &[]
+    }
+
+    async fn run(&self, ctx: &AnalysisContext) -> Findings;
+}
+
+/// Errors returned by [`DetectorRegistry`] operations.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RegistryError {
+    /// No detector with this name is registered.
+    UnknownDetector(String),
+    /// A detector with this name is already registered.
+    DuplicateDetector(String),
+    /// Registering this detector would create a dependency cycle.
+    DependencyCycle(String),
+}
+
+impl fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegistryError::UnknownDetector(name) => write!(f, "unknown detector '{}'", name),
+            RegistryError::DuplicateDetector(name) => {
+                write!(f, "detector '{}' is already registered", name)
+            }
+            RegistryError::DependencyCycle(name) => {
+                write!(f, "dependencies of detector '{}' form a cycle", name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RegistryError {}
+
+/// An ordered set of detectors that can be enabled or disabled by name and
+/// run in one pass.
+///
+/// Detectors are ordered by their dependencies: every detector comes after the
+/// registered detectors listed in its [`Detector::dependencies`], and otherwise
+/// in registration order. A [`run`](Self::run) starts every detector as soon as
+/// the detectors it depends on have finished, so independent detectors run
+/// concurrently, and merges the findings in that order, so the output is
+/// deterministic. Dependencies that are not registered are ignored; disabled
+/// dependencies are skipped, not run implicitly.
+///
+/// # Example
+/// ```rust,ignore
+/// let mut registry = DetectorRegistry::with_builtin(&config);
+/// registry.register(MyDetector::new())?;
+/// registry.disable("blacklist")?;
+///
+/// let findings = registry.run(&AnalysisContext::new(storage)).await;
+/// ```
+#[derive(Default)]
+pub struct DetectorRegistry {
+    detectors: Vec<Arc<dyn Detector>>,
+    disabled: HashSet<String>,
+    order: Vec<usize>,
+}
+
+impl DetectorRegistry {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a registry with every built-in detector, configured from `config`.
+    pub fn with_builtin(config: &AnalyzerConfig) -> Self {
+        let mut registry = Self::new();
+        let builtin: Vec<Box<dyn Detector>> = vec![
+            Box::new(LargeTxDetector(config.large_tx.clone())),
+            Box::new(HighFrequencyDetector(config.high_frequency.clone())),
+            Box::new(StructuringDetector(config.structuring.clone())),
+            Box::new(HighFeeDetector(config.high_fee.clone())),
+            Box::new(HighPriorityFeeDetector(config.high_priority_fee.clone())),
+            Box::new(BlacklistDetector::new(config.sanctions.clone())),
+            Box::new(UnusualOpDetector(config.unusual_op.clone())),
+            Box::new(TimeAnomalyDetector(config.time_anomalies.clone())),
+            Box::new(RegularPaymentsDetector(config.regular_payments.clone())),
+            Box::new(BatchPaymentsDetector(config.batch_payments.clone())),
+            Box::new(DexTradeDetector(config.dex.clone())),
+            Box::new(NftActivityDetector),
+            Box::new(LiquidityProviderDetector(config.dex.clone())),
+            Box::new(ActiveTradersDetector(
+                config.dex.clone(),
+                config.active_traders.clone(),
+            )),
+            Box::new(ArbitrageDetector(config.dex.clone())),
+            Box::new(WhalesDetector(config.whales.clone())),
+        ];
+        for detector in builtin {
+            registry
+                .register_boxed(detector)
+                .expect("built-in detector names are unique");
+        }
+        registry
+    }
+
+    /// Adds a detector after the already registered ones.
+    ///
+    /// # Errors
+    /// Returns [`RegistryError::DuplicateDetector`] if the name is taken and
+    /// [`RegistryError::DependencyCycle`] if its dependencies, together with
+    /// those of the registered detectors, form a cycle.
+    pub fn register<D: Detector + 'static>(&mut self, detector: D) -> Result<(), RegistryError> {
+        self.register_boxed(Box::new(detector))
+    }
+
+    /// Same as [`register`](Self::register) for an already boxed detector.
+    pub fn register_boxed(&mut self, detector: Box<dyn Detector>) -> Result<(), RegistryError> {
+        if self.get(detector.name()).is_some() {
+            return Err(RegistryError::DuplicateDetector(
+                detector.name().to_string(),
+            ));
+        }
+        self.detectors.push(Arc::from(detector));
+        match self.run_order() {
+            Some(order) => {
+                self.order = order;
+                Ok(())
+            }
+            None => {
+                let detector = self.detectors.pop().expect("detector was just pushed");
+                Err(RegistryError::DependencyCycle(detector.name().to_string()))
+            }
+        }
+    }
+
+    /// Looks up a registered detector by name.
+    pub fn get(&self, name: &str) -> Option<&dyn Detector> {
+        self.detectors
+            .iter()
+            .find(|detector| detector.name() == name)
+            .map(|detector| detector.as_ref())
+    }
+
+    /// Iterates over all registered detectors, enabled or not, in run order.
+    pub fn detectors(&self) -> impl Iterator<Item = &dyn Detector> {
+        self.order
+            .iter()
+            .map(|&index| self.detectors[index].as_ref())
+    }
+
+    /// Names of all registered detectors in run order.
+    pub fn names(&self) -> Vec<&str> {
+        self.detectors().map(|detector| detector.name()).collect()
+    }
+
+    pub fn is_enabled(&self, name: &str) -> bool {
+        self.get(name).is_some() && !self.disabled.contains(name)
+    }
+
+    /// Enables a previously disabled detector.
+    pub fn enable(&mut self, name: &str) -> Result<(), RegistryError> {
+        self.check_registered(name)?;
+        self.disabled.remove(name);
+        Ok(())
+    }
+
+    /// Excludes a detector from [`run`](Self::run).
+    pub fn disable(&mut self, name: &str) -> Result<(), RegistryError> {
+        self.check_registered(name)?;
+        self.disabled.insert(name.to_string());
+        Ok(())
+    }
+
+    /// Enables exactly the named detectors and disables every other one.
+    ///
+    /// # Errors
+    /// Returns [`RegistryError::UnknownDetector`] for the first unknown name;
+    /// the registry is left unchanged in that case.
+    pub fn select<S: AsRef<str>>(&mut self, names: &[S]) -> Result<(), RegistryError> {
+        for name in names {
+            self.check_registered(name.as_ref())?;
+        }
+        let selected: HashSet<&str> = names.iter().map(|name| name.as_ref()).collect();
+        self.disabled = self
+            .names()
+            .into_iter()
+            .filter(|name| !selected.contains(name))
+            .map(str::to_string)
+            .collect();
+        Ok(())
+    }
+
+    /// Runs every enabled detector and merges their findings, see
+    /// [`run_report`](Self::run_report).
+    pub async fn run(&self, ctx: &AnalysisContext) -> Findings {
+        self.run_report(ctx).await.findings
+    }
+
+    /// Runs every enabled detector and reports their findings and wall times.
+    ///
+    /// # Overview
+    /// Every detector runs as its own tokio task, so on a multi-threaded
+    /// runtime independent detectors use several cores. A detector starts once
+    /// all enabled detectors it depends on have finished: `unusual_op`, for
+    /// instance, waits for the detectors whose flags it reads. All tasks share
+    /// `ctx`, including its [`stats`](AnalysisContext::stats), which are
+    /// computed once by the first detector that needs them.
+    ///
+    /// # Returns
+    /// The findings merged in run order, whatever order the detectors finish
+    /// in, and the wall time of each detector, also logged at `info` level.
+    ///
+    /// # Panics
+    /// A panic in a detector is resumed here once its task has stopped.
+    pub async fn run_report(&self, ctx: &AnalysisContext) -> RunReport {
+        let enabled: Vec<usize> = self
+            .order
+            .iter()
+            .copied()
+            .filter(|&i| !self.disabled.contains(self.detectors[i].name()))
+            .collect();
+        let positions: HashMap<&str, usize> = enabled
+            .iter()
+            .map(|&i| (self.detectors[i].name(), i))
+            .collect();
+
+        // Number of unfinished dependencies of each detector, and the detectors
+        // waiting for each one.
+        let mut waiting: HashMap<usize, usize> = HashMap::new();
+        let mut dependents: HashMap<usize, Vec<usize>> = HashMap::new();
+        for &i in &enabled {
+            let dependencies: HashSet<usize> = self.detectors[i]
+                .dependencies()
+                .iter()
+                .filter_map(|name| positions.get(name).copied())
+                .collect();
+            waiting.insert(i, dependencies.len());
+            for dependency in dependencies {
+                dependents.entry(dependency).or_default().push(i);
+            }
+        }
+
+        let mut tasks = JoinSet::new();
+        for &i in &enabled {
+            if waiting[&i] == 0 {
+                self.spawn(&mut tasks, i, ctx);
+            }
+        }
+        let mut results: HashMap<usize, (Findings, Duration)> = HashMap::new();
+        while let Some(joined) = tasks.join_next().await {
+            let (i, findings, elapsed) = match joined {
+                Ok(result) => result,
+                Err(e) => match e.try_into_panic() {
+                    Ok(panic) => std::panic::resume_unwind(panic),
+                    Err(e) => panic!("detector task failed: {}", e),
+                },
+            };
+            info!(
+                "{}: {} anomalies, {} patterns in {:.3?}",
+                self.detectors[i].name(),
+                findings.anomalies.len(),
+                findings.patterns.len(),
+                elapsed
+            );
+            for &dependent in dependents.get(&i).into_iter().flatten() {
+                let remaining = waiting.get_mut(&dependent).expect("dependent is enabled");
+                *remaining -= 1;
+                if *remaining == 0 {
+                    self.spawn(&mut tasks, dependent, ctx);
+                }
+            }
+            results.insert(i, (findings, elapsed));
+        }
+
+        let mut report = RunReport::default();
+        for i in enabled {
+            if let Some((findings, elapsed)) = results.remove(&i) {
+                report.findings.extend(findings);
+                report.timings.push(DetectorTiming {
+                    name: self.detectors[i].name().to_string(),
+                    elapsed,
+                });
+            }
+        }
+        report
+    }
+
+    /// Starts the detector at `index` as a task of `tasks`.
+    fn spawn(
+        &self,
+        tasks: &mut JoinSet<(usize, Findings, Duration)>,
+        index: usize,
+        ctx: &AnalysisContext,
+    ) {
+        let detector = self.detectors[index].clone();
+        let ctx = ctx.clone();
+        tasks.spawn(async move {
+            let started = Instant::now();
+            let findings = detector.run(&ctx).await;
+            (index, findings, started.elapsed())
+        });
+    }
+
+    /// Orders the detectors so that each one comes after its registered
+    /// dependencies, keeping registration order among independent detectors.
+    /// Returns `None` if the dependencies form a cycle.
+    fn run_order(&self) -> Option<Vec<usize>> {
+        let index: HashMap<&str, usize> = self
+            .detectors
+            .iter()
+            .enumerate()
+            .map(|(i, detector)| (detector.name(), i))
+            .collect();
+        let dependencies: Vec<Vec<usize>> = self
+            .detectors
+            .iter()
+            .map(|detector| {
+                detector
+                    .dependencies()
+                    .iter()
+                    .filter_map(|name| index.get(name).copied())
+                    .collect()
+            })
+            .collect();
+
+        let mut order = Vec::with_capacity(self.detectors.len());
+        let mut placed = vec![false; self.detectors.len()];
+        while order.len() < self.detectors.len() {
+            let next = (0..self.detectors.len()).find(|&i| {
+                !placed[i] && dependencies[i].iter().all(|&dependency| placed[dependency])
+            })?;
+            placed[next] = true;
+            order.push(next);
+        }
+        Some(order)
+    }
+
+    fn check_registered(&self, name: &str) -> Result<(), RegistryError> {
+        match self.get(name) {
+            Some(_) => Ok(()),
+            None => Err(RegistryError::UnknownDetector(name.to_string())),
+        }
+    }
+}
+
+fn dex_set(config: &DexConfig) -> HashSet<H160> {
+    config.routers.iter().copied().collect()
+}
+
+fn to_value<T: Serialize>(config: &T) -> serde_json::Value {
+    serde_json::to_value(config).unwrap_or(serde_json::Value::Null)
+}
+
+/// Built-in [`detect_large_tx`] detector.
+pub struct LargeTxDetector(pub LargeTxConfig);
+
+#[async_trait]
+impl Detector for LargeTxDetector {
+    fn name(&self) -> &str {
+        "large_tx"
+    }
+
+    fn kind(&self) -> DetectorKind {
+        DetectorKind::Anomaly
+    }
+
+    fn config(&self) -> serde_json::Value {
+        to_value(&self.0)
+    }
+
+    async fn run(&self, ctx: &AnalysisContext) -> Findings {
+        Findings::from_anomalies(detect_large_tx(ctx, &self.0).await)
+    }
+}
+
+/// Built-in [`detect_high_frequency`] detector.
+pub struct HighFrequencyDetector(pub HighFrequencyConfig);
+
+#[async_trait]
+impl Detector for HighFrequencyDetector {
+    fn name(&self) -> &str {
+        "high_frequency"
+    }
+
+    fn kind(&self) -> DetectorKind {
+        DetectorKind::Anomaly
+    }
+
+    fn config(&self) -> serde_json::Value {
+        to_value(&self.0)
+    }
+
+    async fn run(&self, ctx: &AnalysisContext) -> Findings {
+        Findings::from_anomalies(detect_high_frequency(ctx, &self.0).await)
+    }
+}
+
+/// Built-in [`detect_structuring`] detector.
+pub struct StructuringDetector(pub StructuringConfig);
+
+#[async_trait]
+impl Detector for StructuringDetector {
+    fn name(&self) -> &str {
+        "structuring"
+    }
+
+    fn kind(&self) -> DetectorKind {
+        DetectorKind::Anomaly
+    }
+
+    fn config(&self) -> serde_json::Value {
+        to_value(&self.0)
+    }
+
+    async fn run(&self, ctx: &AnalysisContext) -> Findings {
+        Findings::from_anomalies(detect_structuring(ctx, &self.0).await)
+    }
+}
+
+/// Built-in [`detect_high_fee`] detector.
+pub struct HighFeeDetector(pub HighFeeConfig);
+
+#[async_trait]
+impl Detector for HighFeeDetector {
+    fn name(&self) -> &str {
+        "high_fee"
+    }
+
+    fn kind(&self) -> DetectorKind {
+        DetectorKind::Anomaly
+    }
+
+    fn config(&self) -> serde_json::Value {
+        to_value(&self.0)
+    }
+
+    async fn run(&self, ctx: &AnalysisContext) -> Findings {
+        Findings::from_anomalies(detect_high_fee(ctx, &self.0).await)
+    }
+}
+
+/// Built-in [`detect_high_priority_fee`] detector.
+pub struct HighPriorityFeeDetector(pub HighPriorityFeeConfig);
+
+#[async_trait]
+impl Detector for HighPriorityFeeDetector {
+    fn name(&self) -> &str {
+        "high_priority_fee"
+    }
+
+    fn kind(&self) -> DetectorKind {
+        DetectorKind::Anomaly
+    }
+
+    fn config(&self) -> serde_json::Value {
+        to_value(&self.0)
+    }
+
+    async fn run(&self, ctx: &AnalysisContext) -> Findings {
+        Findings::from_anomalies(detect_high_priority_fee(ctx, &self.0).await)
+    }
+}
+
+/// Built-in [`detect_blacklist_adresses`] detector.
+///
+/// Loads the sanctions lists of its configuration on its first run and keeps
+/// them in memory for `cache_ttl_hours`, so a watcher rerunning the detectors
+/// on every poll reloads them only once the TTL expired. When they cannot be
+/// loaded, it reports a single `SanctionsListUnavailable` anomaly instead of
+/// finding nothing, and tries again on the next run.
+pub struct BlacklistDetector {
+    config: SanctionsConfig,
+    loaded: Mutex<Option<(Instant, Arc<SanctionsList>)>>,
+}
+
+impl BlacklistDetector {
+    pub fn new(config: SanctionsConfig) -> Self {
+        Self {
+            config,
+            loaded: Mutex::new(None),
+        }
+    }
+
+    /// The loaded sanctions list, reloaded once it is older than the TTL.
+    async fn sanctions(&self) -> Result<Arc<SanctionsList>, SanctionsError> {
+        let ttl = Duration::from_secs(self.config.cache_ttl_hours * 3600);
+        let mut loaded = self.loaded.lock().await;
+        if let Some((loaded_at, list)) = loaded.as_ref() {
+            if loaded_at.elapsed() < ttl {
+                return Ok(Arc::clone(list));
+            }
+        }
+        let list = Arc::new(load_sanctions(&sources_from_config(&self.config)).await?);
+        *loaded = Some((Instant::now(), Arc::clone(&list)));
+        Ok(list)
+    }
+}
+
+#[async_trait]
+impl Detector for BlacklistDetector {
+    fn name(&self) -> &str {
+        "blacklist"
+    }
+
+    fn kind(&self) -> DetectorKind {
+        DetectorKind::Anomaly
+    }
+
+    fn config(&self) -> serde_json::Value {
+        to_value(&self.config)
+    }
+
+    async fn run(&self, ctx: &AnalysisContext) -> Findings {
+        match self.sanctions().await {
+            Ok(blacklist) => {
+                Findings::from_anomalies(detect_blacklist_adresses(ctx, &blacklist).await)
+            }
+            Err(e) => {
+                error!("Sanctions list unavailable: {}", e);
+                Findings::from_anomalies(vec![Anomaly::SanctionsListUnavailable {
+                    reasons: vec![e.to_string()],
+                }])
+            }
+        }
+    }
+}
+
+/// Built-in [`detect_unusual_op`] detector.
+pub struct UnusualOpDetector(pub UnusualOpConfig);
+
+#[async_trait]
+impl Detector for UnusualOpDetector {
+    fn name(&self) -> &str {
+        "unusual_op"
+    }
+
+    fn kind(&self) -> DetectorKind {
+        DetectorKind::Anomaly
+    }
+
+    fn config(&self) -> serde_json::Value {
+        to_value(&self.0)
+    }
+
+    /// Skips transactions already reported by the other anomaly detectors.
+    fn dependencies(&self) -> &[&str] {
+        &[
+            "large_tx",
+            "high_frequency",
+            "structuring",
+            "high_fee",
+            "high_priority_fee",
+            "blacklist",
+        ]
+    }
+
+    async fn run(&self, ctx: &AnalysisContext) -> Findings {
+        Findings::from_anomalies(detect_unusual_op(ctx, &self.0).await)
+    }
+}
+
+/// Built-in [`detect_time_anomalies`] detector.
+pub struct TimeAnomalyDetector(pub TimeAnomalyConfig);
+
+#[async_trait]
+impl Detector for TimeAnomalyDetector {
+    fn name(&self) -> &str {
+        "time_anomalies"
+    }
+
+    fn kind(&self) -> DetectorKind {
+        DetectorKind::Anomaly
+    }
+
+    fn config(&self) -> serde_json::Value {
+        to_value(&self.0)
+    }
+
+    async fn run(&self, ctx: &AnalysisContext) -> Findings {
+        Findings::from_anomalies(detect_time_anomalies(ctx, &self.0).await)
+    }
+}
+
+/// Built-in [`detect_regular_payments`] detector.
+pub struct RegularPaymentsDetector(pub RegularPaymentsConfig);
+
+#[async_trait]
+impl Detector for RegularPaymentsDetector {
+    fn name(&self) -> &str {
+        "regular_payments"
+    }
+
+    fn kind(&self) -> DetectorKind {
+        DetectorKind::Pattern
+    }
+
+    fn config(&self) -> serde_json::Value {
+        to_value(&self.0)
+    }
+
+    async fn run(&self, ctx: &AnalysisContext) -> Findings {
+        Findings::from_patterns(detect_regular_payments(ctx, &self.0).await)
+    }
+}
+
+/// Built-in [`detect_batch_payments`] detector.
+pub struct BatchPaymentsDetector(pub BatchPaymentsConfig);
+
+#[async_trait]
+impl Detector for BatchPaymentsDetector {
+    fn name(&self) -> &str {
+        "batch_payments"
+    }
+
+    fn kind(&self) -> DetectorKind {
+        DetectorKind::Pattern
+    }
+
+    fn config(&self) -> serde_json::Value {
+        to_value(&self.0)
+    }
+
+    async fn run(&self, ctx: &AnalysisContext) -> Findings {
+        Findings::from_patterns(detect_batch_payments(ctx, &self.0).await)
+    }
+}
+
+/// Built-in [`detect_dex_trade`] detector.
+pub struct DexTradeDetector(pub DexConfig);
+
+#[async_trait]
+impl Detector for DexTradeDetector {
+    fn name(&self) -> &str {
+        "dex_trade"
+    }
+
+    fn kind(&self) -> DetectorKind {
+        DetectorKind::Pattern
+    }
+
+    fn config(&self) -> serde_json::Value {
+        to_value(&self.0)
+    }
+
+    async fn run(&self, ctx: &AnalysisContext) -> Findings {
+        Findings::from_patterns(detect_dex_trade(ctx, &dex_set(&self.0)).await)
+    }
+}
+
+/// Built-in [`detect_nft_activity`] detector.
+pub struct NftActivityDetector;
+
+#[async_trait]
+impl Detector for NftActivityDetector {
+    fn name(&self) -> &str {
+        "nft_activity"
+    }
+
+    fn kind(&self) -> DetectorKind {
+        DetectorKind::Pattern
+    }
+
+    async fn run(&self, ctx: &AnalysisContext) -> Findings {
+        Findings::from_patterns(detect_nft_activity(ctx).await)
+    }
+}
+
+/// Built-in [`detect_liquid_provider`] detector.
+pub struct LiquidityProviderDetector(pub DexConfig);
+
+#[async_trait]
+impl Detector for LiquidityProviderDetector {
+    fn name(&self) -> &str {
+        "liquidity_provider"
+    }
+
+    fn kind(&self) -> DetectorKind {
+        DetectorKind::Pattern
+    }
+
+    fn config(&self) -> serde_json::Value {
+        to_value(&self.0)
+    }
+
+    async fn run(&self, ctx: &AnalysisContext) -> Findings {
+        Findings::from_patterns(detect_liquid_provider(ctx, &dex_set(&self.0)).await)
+    }
+}
+
+/// Built-in [`detect_active_traders`] detector.
+pub struct ActiveTradersDetector(pub DexConfig, pub ActiveTradersConfig);
+
+#[async_trait]
+impl Detector for ActiveTradersDetector {
+    fn name(&self) -> &str {
+        "active_traders"
+    }
+
+    fn kind(&self) -> DetectorKind {
+        DetectorKind::Pattern
+    }
+
+    fn config(&self) -> serde_json::Value {
+        serde_json::json!({ "dex": to_value(&self.0), "active_traders": to_value(&self.1) })
+    }
+
+    async fn run(&self, ctx: &AnalysisContext) -> Findings {
+        Findings::from_patterns(detect_active_traders(ctx, &dex_set(&self.0), &self.1).await)
+    }
+}
+
+/// Built-in [`detect_arbitrage`] detector.
+pub struct ArbitrageDetector(pub DexConfig);
+
+#[async_trait]
+impl Detector for ArbitrageDetector {
+    fn name(&self) -> &str {
+        "arbitrage"
+    }
+
+    fn kind(&self) -> DetectorKind {
+        DetectorKind::Pattern
+    }
+
+    fn config(&self) -> serde_json::Value {
+        to_value(&self.0)
+    }
+
+    async fn run(&self, ctx: &AnalysisContext) -> Findings {
+        Findings::from_patterns(detect_arbitrage(ctx, &dex_set(&self.0)).await)
+    }
+}
+
+/// Built-in [`detect_whales`] detector.
+pub struct WhalesDetector(pub WhalesConfig);
+
+#[async_trait]
+impl Detector for WhalesDetector {
+    fn name(&self) -> &str {
+        "whales"
+    }
+
+    fn kind(&self) -> DetectorKind {
+        DetectorKind::Pattern
+    }
+
+    fn config(&self) -> serde_json::Value {
+        to_value(&self.0)
+    }
+
+    async fn run(&self, ctx: &AnalysisContext) -> Findings {
+        Findings::from_patterns(detect_whales(ctx, &self.0).await)
+    }
+}
@@ -0,0 +1,355 @@
+use async_trait::async_trait;
+use ethers::{
+    providers::{JsonRpcClient, JsonRpcError, ProviderError, RpcError},
+    utils::{hex, keccak256},
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt::{self, Debug};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Errors returned by [`RecordingClient`] and [`ReplayClient`].
+///
+/// JSON-RPC error responses are kept as [`FixtureError::Rpc`] whether they come
+/// from the node or from a fixture, so the scanner classifies a replayed error
+/// exactly like the recorded one (see [`RpcError::as_error_response`]).
+#[derive(Debug)]
+pub enum FixtureError {
+    /// The node answered with a JSON-RPC error.
+    Rpc(JsonRpcError),
+    /// A response could not be converted to the requested type.
+    Json(serde_json::Error),
+    /// A fixture file could not be read or written.
+    Io(io::Error),
+    /// The fixture directory holds no response for this request.
+    Missing { method: String, params: String },
+    /// The wrapped transport failed without an answer from the node, e.g. a
+    /// connection error. Such failures are not recorded.
+    Transport(ProviderError),
+}
+
+impl fmt::Display for FixtureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FixtureError::Rpc(e) => write!(f, "{}", e),
+            FixtureError::Json(e) => write!(f, "invalid RPC response: {}", e),
+            FixtureError::Io(e) => write!(f, "RPC fixture error: {}", e),
+            FixtureError::Missing { method, params } => {
+                write!(f, "no recorded response for {} {}", method, params)
+            }
+            FixtureError::Transport(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for FixtureError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FixtureError::Rpc(e) => Some(e),
+            FixtureError::Json(e) => Some(e),
+            FixtureError::Io(e) => Some(e),
+            FixtureError::Missing { .. } => None,
+            FixtureError::Transport(e) => Some(e),
+        }
+    }
+}
+
+impl RpcError for FixtureError {
+    fn as_error_response(&self) -> Option<&JsonRpcError> {
+        match self {
+            FixtureError::Rpc(e) => Some(e),
+            FixtureError::Transport(e) => RpcError::as_error_response(e),
+            _ => None,
+        }
+    }
+
+    fn as_serde_error(&self) -> Option<&serde_json::Error> {
+        match self {
+            FixtureError::Json(e) => Some(e),
+            FixtureError::Transport(e) => RpcError::as_serde_error(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<FixtureError> for ProviderError {
+    fn from(e: FixtureError) -> Self {
+        ProviderError::JsonRpcClientError(Box::new(e))
+    }
+}
+
+impl From<io::Error> for FixtureError {
+    fn from(e: io::Error) -> Self {
+        FixtureError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for FixtureError {
+    fn from(e: serde_json::Error) -> Self {
+        FixtureError::Json(e)
+    }
+}
+
+/// One answer of the node: a result or a JSON-RPC error.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum RecordedResponse {
+    Result(Value),
+    Error {
+        code: i64,
+        message: String,
+        #[serde(default)]
+        data: Option<Value>,
+    },
+}
+
+impl RecordedResponse {
+    fn into_result<R: DeserializeOwned>(self) -> Result<R, FixtureError> {
+        match self {
+            RecordedResponse::Result(value) => Ok(serde_json::from_value(value)?),
+            RecordedResponse::Error {
+                code,
+                message,
+                data,
+            } => Err(FixtureError::Rpc(JsonRpcError {
+                code,
+                message,
+                data,
+            })),
+        }
+    }
+}
+
+/// Contents of one fixture file: every answer to one request, in the order
+/// they were received.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Fixture {
+    method: String,
+    params: Value,
+    responses: Vec<RecordedResponse>,
+}
+
+/// File name of the fixture of a request: the method and a digest of its
+/// parameters, so the same request always maps to the same file.
+fn fixture_name(method: &str, params: &Value) -> String {
+    let digest = keccak256(params.to_string());
+    format!("{}-{}.json", method, hex::encode(&digest[..8]))
+}
+
+/// A JSON-RPC transport that forwards every request to another transport and
+/// saves the answers to a fixture directory for [`ReplayClient`].
+///
+/// # Overview
+/// Each distinct request (method and parameters) gets one JSON file in the
+/// directory holding every answer it received, results and JSON-RPC errors
+/// alike, in order. Files are rewritten after every answer, so an interrupted
+/// recording still replays everything it saw.
+///
+/// Wrap it in a [`Provider`](ethers::providers::Provider) to use it wherever a
+/// [`Middleware`](ethers::providers::Middleware) is expected, e.g. by
+/// [`crate::scanner::scan_block`].
+///
+/// # Example
+/// ```rust,ignore
+/// let http = Http::from_str(&rpc_url)?;
+/// let provider = Arc::new(Provider::new(RecordingClient::create(http, "fixtures/19000000")?));
+/// scan_block(&provider, 19_000_000, 19_000_010, &storage, &options).await;
+/// ```
+///
+/// # Notes
+/// * Transport failures without an answer (connection errors, timeouts) are
+///   not recorded.
+/// * Recording the same request into an existing directory appends to its
+///   answers.
+#[derive(Debug)]
+pub struct RecordingClient<C> {
+    inner: C,
+    dir: PathBuf,
+    fixtures: Mutex<HashMap<String, Fixture>>,
+}
+
+impl<C: JsonRpcClient> RecordingClient<C> {
+    /// Records the answers of `inner` into `dir`, creating it if needed.
+    pub fn create(inner: C, dir: impl AsRef<Path>) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let fixtures = read_fixtures(&dir)?;
+        Ok(Self {
+            inner,
+            dir,
+            fixtures: Mutex::new(fixtures),
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn record(
+        &self,
+        method: &str,
+        params: Value,
+        response: RecordedResponse,
+    ) -> Result<(), FixtureError> {
+        let name = fixture_name(method, &params);
+        let mut fixtures = self.fixtures.lock().unwrap_or_else(|e| e.into_inner());
+        let fixture = fixtures.entry(name.clone()).or_insert_with(|| Fixture {
+            method: method.to_string(),
+            params,
+            responses: Vec::new(),
+        });
+        fixture.responses.push(response);
+        fs::write(self.dir.join(name), serde_json::to_vec_pretty(fixture)?)?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<C: JsonRpcClient> JsonRpcClient for RecordingClient<C> {
+    type Error = FixtureError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        let key = serde_json::to_value(&params)?;
+        match self.inner.request::<T, Value>(method, params).await {
+            Ok(value) => {
+                self.record(method, key, RecordedResponse::Result(value.clone()))?;
+                Ok(serde_json::from_value(value)?)
+            }
+            Err(e) => match e.as_error_response() {
+                Some(error) => {
+                    let error = error.clone();
+                    self.record(
+                        method,
+                        key,
+                        RecordedResponse::Error {
+                            code: error.code,
+                            message: error.message.clone(),
+                            data: error.data.clone(),
+                        },
+                    )?;
+                    Err(FixtureError::Rpc(error))
+                }
+                None => Err(FixtureError::Transport(e.into())),
+            },
+        }
+    }
+}
+
+/// A JSON-RPC transport that answers from a fixture directory written by
+/// [`RecordingClient`], without any network access.
+///
+/// # Overview
+/// Requests are matched by method and parameters, not by order, so concurrent
+/// scans replay deterministically. The answers of a request are served in the
+/// order they were recorded and the last one is repeated once they run out:
+/// a block that failed twice before succeeding fails twice again on replay.
+///
+/// # Example
+/// ```rust,ignore
+/// let provider = Arc::new(Provider::new(ReplayClient::open("tests/fixtures/rpc/receipts")?));
+/// let report = scan_block(&provider, 7, 7, &storage, &options).await;
+/// ```
+///
+/// # Notes
+/// * A request that was never recorded fails with [`FixtureError::Missing`],
+///   which names the method and parameters to record.
+#[derive(Debug)]
+pub struct ReplayClient {
+    fixtures: HashMap<String, Fixture>,
+    served: Mutex<HashMap<String, usize>>,
+}
+
+impl ReplayClient {
+    /// Loads every fixture file of `dir`.
+    pub fn open(dir: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            fixtures: read_fixtures(dir.as_ref())?,
+            served: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn next_response(&self, method: &str, params: &Value) -> Option<RecordedResponse> {
+        let name = fixture_name(method, params);
+        let fixture = self.fixtures.get(&name)?;
+        let mut served = self.served.lock().unwrap_or_else(|e| e.into_inner());
+        let count = served.entry(name).or_default();
+        let index = (*count).min(fixture.responses.len().checked_sub(1)?);
+        *count += 1;
+        fixture.responses.get(index).cloned()
+    }
+}
+
+#[async_trait]
+impl JsonRpcClient for ReplayClient {
+    type Error = FixtureError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        let params = serde_json::to_value(&params)?;
+        match self.next_response(method, &params) {
+            Some(response) => response.into_result(),
+            None => Err(FixtureError::Missing {
+                method: method.to_string(),
+                params: params.to_string(),
+            }),
+        }
+    }
+}
+
+/// A transport that talks to the node directly, records its answers or
+/// replays them, chosen at runtime (e.g. from command-line flags).
+#[derive(Debug)]
+pub enum FixtureClient<C> {
+    Live(C),
+    Recording(RecordingClient<C>),
+    Replay(ReplayClient),
+}
+
+#[async_trait]
+impl<C: JsonRpcClient> JsonRpcClient for FixtureClient<C> {
+    type Error = FixtureError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        match self {
+            FixtureClient::Live(client) => client
+                .request(method, params)
+                .await
+                .map_err(|e| FixtureError::Transport(e.into())),
+            FixtureClient::Recording(client) => client.request(method, params).await,
+            FixtureClient::Replay(client) => client.request(method, params).await,
+        }
+    }
+}
+
+fn read_fixtures(dir: &Path) -> io::Result<HashMap<String, Fixture>> {
+    let mut fixtures = HashMap::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_none_or(|extension| extension != "json") {
+            continue;
+        }
+        let fixture: Fixture = serde_json::from_slice(&fs::read(&path)?).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), e),
+            )
+        })?;
+        fixtures.insert(fixture_name(&fixture.method, &fixture.params), fixture);
+    }
+    Ok(fixtures)
+}
@@ -0,0 +1,465 @@
+use crate::{
+    address,
+    models::{TokenTransfer, TransactionRecord},
+    scanner::{block_timestamp, scanned_block, InvalidBlockField, ScannedBlock},
+};
+use chrono::{DateTime, Utc};
+use ethers::types::{Address, Block, Transaction, H256, U256};
+use serde::{de::DeserializeOwned, Deserialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// On-disk formats transactions can be imported from.
+///
+/// * `Ndjson` – One JSON [`TransactionRecord`] per line, as written by `scan`.
+/// * `Csv` – One transaction per row with a header line, see [`read_csv`].
+/// * `RpcBlocks` – `eth_getBlockByNumber` results with full transactions, see
+///   [`read_rpc_blocks`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    Ndjson,
+    Csv,
+    RpcBlocks,
+}
+
+impl ImportFormat {
+    /// Infers the format from the file extension: `.ndjson` and `.jsonl` are
+    /// [`Ndjson`](Self::Ndjson), `.csv` is [`Csv`](Self::Csv) and `.json` is
+    /// [`RpcBlocks`](Self::RpcBlocks).
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "ndjson" | "jsonl" => Some(ImportFormat::Ndjson),
+            "csv" => Some(ImportFormat::Csv),
+            "json" => Some(ImportFormat::RpcBlocks),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for ImportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "ndjson" | "jsonl" => Ok(ImportFormat::Ndjson),
+            "csv" => Ok(ImportFormat::Csv),
+            "blocks" | "rpc" => Ok(ImportFormat::RpcBlocks),
+            other => Err(format!(
+                "unknown import format `{}` (expected ndjson, csv or blocks)",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ImportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportFormat::Ndjson => write!(f, "ndjson"),
+            ImportFormat::Csv => write!(f, "csv"),
+            ImportFormat::RpcBlocks => write!(f, "blocks"),
+        }
+    }
+}
+
+/// Errors returned while importing a transaction dump.
+///
+/// `record` is the 1-based line of an NDJSON file, the line of a CSV row, or
+/// the position of a block in an `eth_getBlockByNumber` dump.
+#[derive(Debug)]
+pub enum ImportError {
+    /// The file could not be opened or read.
+    Io(io::Error),
+    /// A record is not valid JSON or does not have the expected fields.
+    Json {
+        record: usize,
+        source: serde_json::Error,
+    },
+    /// The CSV file is malformed or a row misses a required column.
+    Csv(csv::Error),
+    /// A field holds a value that cannot be converted.
+    InvalidField {
+        record: usize,
+        field: &'static str,
+        value: String,
+    },
+    /// The format was not given and cannot be inferred from the file name.
+    UnknownFormat(PathBuf),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::Io(e) => write!(f, "cannot read import file: {}", e),
+            ImportError::Json { record, source } => {
+                write!(f, "invalid JSON in record {}: {}", record, source)
+            }
+            ImportError::Csv(e) => write!(f, "invalid CSV: {}", e),
+            ImportError::InvalidField {
+                record,
+                field,
+                value,
+            } if value.is_empty() => write!(f, "missing `{}` in record {}", field, record),
+            ImportError::InvalidField {
+                record,
+                field,
+                value,
+            } => write!(
+                f,
+                "invalid value `{}` for `{}` in record {}",
+                value, field, record
+            ),
+            ImportError::UnknownFormat(path) => write!(
+                f,
+                "cannot infer the import format of {}; pass it explicitly",
+                path.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ImportError::Io(e) => Some(e),
+            ImportError::Json { source, .. } => Some(source),
+            ImportError::Csv(e) => Some(e),
+            ImportError::InvalidField { .. } | ImportError::UnknownFormat(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for ImportError {
+    fn from(e: io::Error) -> Self {
+        ImportError::Io(e)
+    }
+}
+
+impl From<csv::Error> for ImportError {
+    fn from(e: csv::Error) -> Self {
+        ImportError::Csv(e)
+    }
+}
+
+/// Reads a transaction dump from disk and groups it into blocks.
+///
+/// # Parameters
+/// * `path` – File to read.
+/// * `format` – Format of the file; inferred with [`ImportFormat::from_path`]
+///   when `None`.
+///
+/// # Returns
+/// The blocks of the file in ascending order, ready for
+/// [`crate::scanner::store_block`] or a [`crate::store::TxStore`]. Only
+/// `eth_getBlockByNumber` dumps carry block headers; NDJSON and CSV blocks have
+/// none, so they are not checked for reorgs.
+///
+/// # Example
+/// ```rust,ignore
+/// let storage = Arc::new(TxStorage::new());
+/// for block in import_file(Path::new("case-1234.csv"), None)? {
+///     store_block(&storage, block).await;
+/// }
+/// let findings = registry.run(&AnalysisContext::new(storage)).await;
+/// ```
+pub fn import_file(
+    path: &Path,
+    format: Option<ImportFormat>,
+) -> Result<Vec<ScannedBlock>, ImportError> {
+    let format = format
+        .or_else(|| ImportFormat::from_path(path))
+        .ok_or_else(|| ImportError::UnknownFormat(path.to_path_buf()))?;
+    let reader = BufReader::new(File::open(path)?);
+    match format {
+        ImportFormat::Ndjson => Ok(group_by_block(read_ndjson(reader)?, Vec::new())),
+        ImportFormat::Csv => Ok(group_by_block(read_csv(reader)?, Vec::new())),
+        ImportFormat::RpcBlocks => read_rpc_blocks(reader),
+    }
+}
+
+/// Reads newline-delimited JSON records, e.g. the [`TransactionRecord`]s and
+/// [`TokenTransfer`]s written by `scan`. Blank lines are skipped.
+pub fn read_ndjson<T: DeserializeOwned, R: BufRead>(reader: R) -> Result<Vec<T>, ImportError> {
+    let mut records = Vec::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record = serde_json::from_str(&line).map_err(|source| ImportError::Json {
+            record: index + 1,
+            source,
+        })?;
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// One CSV row before conversion. Columns other than the required ones may be
+/// missing from the file or left empty.
+#[derive(Deserialize)]
+struct CsvTransaction {
+    hash: String,
+    from: String,
+    #[serde(default)]
+    to: String,
+    value: String,
+    #[serde(default)]
+    gas: String,
+    #[serde(default)]
+    gas_price: String,
+    block_number: String,
+    timestamp: String,
+    #[serde(default)]
+    input: String,
+    #[serde(default)]
+    gas_used: String,
+    #[serde(default)]
+    effective_gas_price: String,
+    #[serde(default)]
+    status: String,
+    #[serde(default)]
+    contract_address: String,
+    #[serde(default)]
+    tx_type: String,
+    #[serde(default)]
+    max_fee_per_gas: String,
+    #[serde(default)]
+    max_priority_fee_per_gas: String,
+    #[serde(default)]
+    base_fee_per_gas: String,
+}
+
+/// Reads transactions from CSV with a header line.
+///
+/// # Columns
+/// * Required: `hash`, `from`, `value`, `block_number`, `timestamp`.
+/// * Optional: `to` (empty for contract creations), `gas`, `gas_price`,
+///   `input`, and the receipt and EIP-1559 fields of [`TransactionRecord`]
+///   under the same names.
+///
+/// # Notes
+/// * Amounts are in wei, as decimal or `0x` hexadecimal integers.
+/// * `timestamp` is either Unix seconds or RFC 3339.
+/// * `status` accepts `1`/`0` and `true`/`false`.
+/// * Columns can be in any order; unknown columns are ignored.
+pub fn read_csv<R: Read>(reader: R) -> Result<Vec<TransactionRecord>, ImportError> {
+    let mut reader = csv::Reader::from_reader(reader);
+    let headers = reader.headers()?.clone();
+    let mut txs = Vec::new();
+    for row in reader.records() {
+        let row = row?;
+        let record = row
+            .position()
+            .map_or(0, |position| position.line() as usize);
+        let tx: CsvTransaction = row.deserialize(Some(&headers))?;
+        txs.push(TransactionRecord {
+            hash: required(record, "hash", &tx.hash, parse_h256)?,
+            from: required(record, "from", &tx.from, parse_address)?,
+            to: optional(record, "to", &tx.to, parse_address)?,
+            value: required(record, "value", &tx.value, parse_u256)?,
+            gas: optional(record, "gas", &tx.gas, parse_u64)?.unwrap_or_default(),
+            gas_price: optional(record, "gas_price", &tx.gas_price, parse_u256)?
+                .unwrap_or_default(),
+            block_number: required(record, "block_number", &tx.block_number, parse_u64)?,
+            timestamp: required(record, "timestamp", &tx.timestamp, parse_timestamp)?,
+            input: if tx.input.trim().is_empty() {
+                "0x".to_string()
+            } else {
+                tx.input.trim().to_lowercase()
+            },
+            gas_used: optional(record, "gas_used", &tx.gas_used, parse_u64)?,
+            effective_gas_price: optional(
+                record,
+                "effective_gas_price",
+                &tx.effective_gas_price,
+                parse_u256,
+            )?,
+            status: optional(record, "status", &tx.status, parse_status)?,
+            contract_address: optional(
+                record,
+                "contract_address",
+                &tx.contract_address,
+                parse_address,
+            )?,
+            tx_type: optional(record, "tx_type", &tx.tx_type, parse_u64)?,
+            max_fee_per_gas: optional(record, "max_fee_per_gas", &tx.max_fee_per_gas, parse_u256)?,
+            max_priority_fee_per_gas: optional(
+                record,
+                "max_priority_fee_per_gas",
+                &tx.max_priority_fee_per_gas,
+                parse_u256,
+            )?,
+            base_fee_per_gas: optional(
+                record,
+                "base_fee_per_gas",
+                &tx.base_fee_per_gas,
+                parse_u256,
+            )?,
+        });
+    }
+    Ok(txs)
+}
+
+/// Reads blocks in the format returned by `eth_getBlockByNumber` with full
+/// transactions (second parameter `true`).
+///
+/// The document is a single block or an array of blocks, each either bare or
+/// wrapped in its JSON-RPC response (`{"jsonrpc": "2.0", "id": 1, "result": {..}}`),
+/// so the output of a batch request can be saved and imported as is. `null`
+/// results (unknown blocks) are skipped.
+///
+/// # Notes
+/// * Blocks carry no receipts, so the receipt fields of the records stay
+///   empty and fees fall back to [`TransactionRecord::max_fee`].
+/// * Block hashes are kept as headers, like a scan of the same blocks would.
+pub fn read_rpc_blocks<R: Read>(reader: R) -> Result<Vec<ScannedBlock>, ImportError> {
+    let document: serde_json::Value = serde_json::from_reader(reader)
+        .map_err(|source| ImportError::Json { record: 1, source })?;
+    let items = match document {
+        serde_json::Value::Array(items) => items,
+        item => vec![item],
+    };
+
+    let mut blocks = Vec::with_capacity(items.len());
+    for (index, item) in items.into_iter().enumerate() {
+        let record = index + 1;
+        let block = match item {
+            serde_json::Value::Object(mut response) if response.contains_key("result") => {
+                response.remove("result").unwrap_or_default()
+            }
+            block => block,
+        };
+        if block.is_null() {
+            continue;
+        }
+        let block: Block<Transaction> =
+            serde_json::from_value(block).map_err(|source| ImportError::Json { record, source })?;
+        let number = block
+            .number
+            .ok_or_else(|| ImportError::InvalidField {
+                record,
+                field: "number",
+                value: "null".to_string(),
+            })?
+            .as_u64();
+        let invalid = |err: InvalidBlockField| ImportError::InvalidField {
+            record,
+            field: err.field,
+            value: err.value,
+        };
+        let timestamp = block_timestamp(&block).map_err(invalid)?;
+        blocks.push(
+            scanned_block(block, number, timestamp, HashMap::new(), Vec::new()).map_err(invalid)?,
+        );
+    }
+    blocks.sort_by_key(|block| block.number);
+    Ok(blocks)
+}
+
+/// Groups loose transactions and token transfers into blocks without headers,
+/// in ascending block order. Transactions keep their relative order.
+pub fn group_by_block(
+    txs: Vec<TransactionRecord>,
+    transfers: Vec<TokenTransfer>,
+) -> Vec<ScannedBlock> {
+    let mut blocks: BTreeMap<u64, ScannedBlock> = BTreeMap::new();
+    let empty = |number| ScannedBlock {
+        number,
+        header: None,
+        txs: Vec::new(),
+        transfers: Vec::new(),
+    };
+    for tx in txs {
+        blocks
+            .entry(tx.block_number)
+            .or_insert_with(|| empty(tx.block_number))
+            .txs
+            .push(tx);
+    }
+    for transfer in transfers {
+        blocks
+            .entry(transfer.block_number)
+            .or_insert_with(|| empty(transfer.block_number))
+            .transfers
+            .push(transfer);
+    }
+    blocks.into_values().collect()
+}
+
+fn required<T>(
+    record: usize,
+    field: &'static str,
+    value: &str,
+    parse: fn(&str) -> Option<T>,
+) -> Result<T, ImportError> {
+    optional(record, field, value, parse)?.ok_or_else(|| ImportError::InvalidField {
+        record,
+        field,
+        value: String::new(),
+    })
+}
+
+/// `None` for an empty cell, an error for a cell that does not parse.
+fn optional<T>(
+    record: usize,
+    field: &'static str,
+    value: &str,
+    parse: fn(&str) -> Option<T>,
+) -> Result<Option<T>, ImportError> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Ok(None);
+    }
+    parse(value)
+        .map(Some)
+        .ok_or_else(|| ImportError::InvalidField {
+            record,
+            field,
+            value: value.to_string(),
+        })
+}
+
+fn parse_h256(value: &str) -> Option<H256> {
+    value.parse().ok()
+}
+
+fn parse_address(value: &str) -> Option<Address> {
+    address::parse_address(value).ok()
+}
+
+fn parse_u256(value: &str) -> Option<U256> {
+    match value.strip_prefix("0x") {
+        Some(hex) => U256::from_str_radix(hex, 16).ok(),
+        None => U256::from_dec_str(value).ok(),
+    }
+}
+
+fn parse_u64(value: &str) -> Option<u64> {
+    match value.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+fn parse_timestamp(value: &str) -> Option<DateTime<Utc>> {
+    match value.parse::<i64>() {
+        Ok(seconds) => DateTime::<Utc>::from_timestamp(seconds, 0),
+        Err(_) => DateTime::parse_from_rfc3339(value)
+            .ok()
+            .map(|timestamp| timestamp.with_timezone(&Utc)),
+    }
+}
+
+fn parse_status(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "1" | "true" => Some(true),
+        "0" | "false" => Some(false),
+        _ => None,
+    }
+}
@@ -1,7 +1,20 @@
-/// # Modules Overview
+//! # Modules Overview
+//!
+//! This crate contains modules for analyzing blockchain transactions, detecting
+//! anomalies and business patterns, configuring the scanner, and exporting data to CSV files.
+
+/// `address`
+///
+/// Canonical address handling: parses addresses in any case (lowercase, uppercase or
+/// EIP-55 checksummed) at every input boundary and formats them in one lowercase form
+/// for findings, exports and the store, so the same account always matches.
 ///
-/// This crate contains modules for analyzing blockchain transactions, detecting
-/// anomalies and business patterns, configuring the scanner, and exporting data to CSV files.
+/// Example usage:
+/// ```rust,ignore
+/// let router = address::parse_address("0x7a250d5630B4cF539739dF2C5dAcb4c659F2488D")?;
+/// assert_eq!(address::format_address(&router), "0x7a250d5630b4cf539739df2c5dacb4c659f2488d");
+/// ```
+pub mod address;
 
 /// `analize`
 ///
@@ -13,12 +26,38 @@
 ///
 /// Example usage:
 /// ```rust,ignore
-/// let anomalies = analize::detect_large_tx(&storage).await;
-/// let patterns = analize::detect_regular_payments(&storage).await;
+/// let anomalies = analize::detect_large_tx(&ctx, &config.large_tx).await;
+/// let patterns = analize::detect_regular_payments(&ctx, &config.regular_payments).await;
 /// ```
 pub mod analize;
 
+/// `checkpoint`
+///
+/// Persists the block numbers a scan has completed in an append-only file, so an
+/// interrupted scan can be resumed and skip already-ingested blocks.
+///
+/// Example usage:
+/// ```rust,ignore
+/// let mut checkpoint = checkpoint::Checkpoint::open("txs.ndjson.checkpoint")?;
+/// let pending = checkpoint.pending(start_block, end_block);
+/// scanner::scan_blocks(&provider, pending, &storage, &options, |block| {
+///     checkpoint.mark_completed(block.number)
+/// })
+/// .await;
+/// ```
+pub mod checkpoint;
 
+/// `config`
+///
+/// Runtime configuration of every detector threshold. [`config::AnalyzerConfig`] is
+/// loaded from a TOML or JSON file, validated, and falls back to defaults for any
+/// missing value. Each `detect_*` function receives its own section of the configuration.
+///
+/// Example usage:
+/// ```rust,ignore
+/// let config = config::AnalyzerConfig::from_file("eth_analyzer.toml")?;
+/// let anomalies = analize::detect_large_tx(&ctx, &config.large_tx).await;
+/// ```
 pub mod config;
 
 /// `csv`
@@ -35,29 +74,172 @@ pub mod config;
 /// ```
 pub mod csv;
 
+/// `detector`
+///
+/// Defines the [`detector::Detector`] trait implemented by every analysis, the
+/// [`detector::AnalysisContext`] detectors read from, and the [`detector::DetectorRegistry`]
+/// that enables, disables and runs detectors by name in one pass. Built-in detectors
+/// wrap the `analize::detect_*` functions; third-party crates can register their own.
+///
+/// Example usage:
+/// ```rust,ignore
+/// let mut registry = detector::DetectorRegistry::with_builtin(&config);
+/// registry.disable("blacklist")?;
+/// let findings = registry.run(&detector::AnalysisContext::new(storage)).await;
+/// ```
+pub mod detector;
+
+/// `fixture`
+///
+/// Record-and-replay JSON-RPC transports. [`fixture::RecordingClient`] saves
+/// every answer of the node (results and errors) to a fixture directory and
+/// [`fixture::ReplayClient`] serves them back offline, so scans of production
+/// ranges can be reproduced exactly in tests.
+///
+/// Example usage:
+/// ```rust,ignore
+/// let provider = Arc::new(Provider::new(fixture::ReplayClient::open("tests/fixtures/rpc/receipts")?));
+/// let report = scanner::scan_block(&provider, 7, 7, &storage, &options).await;
+/// ```
+pub mod fixture;
+
+/// `import`
+///
+/// Loads transactions from dumps on disk instead of a node: NDJSON records as
+/// written by `scan`, CSV exports and saved `eth_getBlockByNumber` responses.
+/// The blocks it returns go through the same storage path as scanned blocks, so
+/// the whole analysis runs without network access.
+///
+/// Example usage:
+/// ```rust,ignore
+/// for block in import::import_file(Path::new("case-1234.csv"), None)? {
+///     scanner::store_block(&storage, block).await;
+/// }
+/// ```
+pub mod import;
+
 /// `models`
 ///
 /// Defines core data structures used in the scanner and analyzers:
 /// * `TransactionRecord` – Represents a blockchain transaction.
-/// * `TxStorage` and `SharedTxStorage` – In-memory storage of transactions, organized by sender, receiver, and overall list.
+/// * `TxStorage` and `SharedTxStorage` – In-memory storage of transactions, organized by sender, receiver, hash, block,
+///   method selector, and overall list, with idempotent insertion. Records are shared between the indexes as `TxRef`s.
 /// * `Anomaly` and `BusinessPattern` – Enums representing detected anomalies and business patterns.
 /// * `Severity` – Enum representing the strength of anomalies (Weak or Strong).
 ///
 /// Example usage:
 /// ```rust,ignore
-/// let tx = models::TransactionRecord { hash: "...", from: "...", to: Some("...".to_string()), value: 1.0, ... };
+/// let tx = models::TransactionRecord { hash: "...", from: "...", to: Some("...".to_string()), value: units::parse_eth("1.0")?, ... };
 /// ```
 pub mod models;
 
+/// `query`
+///
+/// The read-only [`query::TxQuery`] trait detectors read transactions through:
+/// all transactions, by sender, by receiver, by block range and by time range.
+/// It is implemented for `TxStorage`, so the in-memory store, one loaded from a
+/// persisted database or a test fixture can back the same analysis.
+///
+/// Example usage:
+/// ```rust,ignore
+/// let ctx = AnalysisContext::new(storage);
+/// let recent = ctx.storage.in_blocks(latest - 10..=latest).await;
+/// let sent = ctx.storage.sent_by(&sender).await;
+/// ```
+pub mod query;
+
+/// `sanctions`
+///
+/// Sanctions lists for the `blacklist` detector. Lists are read from local files
+/// or downloaded from URLs with an on-disk cache and TTL, merged into one
+/// [`sanctions::SanctionsList`], and fail loudly when they cannot be loaded.
+/// Implement [`sanctions::SanctionsSource`] to read lists from elsewhere.
+///
+/// Example usage:
+/// ```rust,ignore
+/// let blacklist = sanctions::load_sanctions(&sanctions::sources_from_config(&config.sanctions)).await?;
+/// let anomalies = analize::detect_blacklist_adresses(&ctx, &blacklist).await;
+/// ```
+pub mod sanctions;
+
 /// `scanner`
 ///
 /// Contains functions to scan blockchain data, retrieve blocks and transactions,
-/// and populate `TxStorage`. This module interfaces with a blockchain provider
+/// and populate `TxStorage`, optionally with ERC-20 transfers decoded from block logs.
+/// This module interfaces with a blockchain provider
 /// (implementing the `Middleware` trait from `ethers`) and supports asynchronous
-/// fetching and processing of blocks.
+/// fetching and processing of blocks with bounded concurrency, retries with
+/// exponential backoff and an optional request rate limit. Block hashes are
+/// recorded so chain reorganizations can be detected and rolled back.
 ///
 /// Example usage:
 /// ```rust,ignore
-/// scanner::scan_block(&provider, start_block, end_block, &storage).await?;
+/// let options = scanner::ScanOptions {
+///     fetch_receipts: true,
+///     fetch_token_transfers: true,
+///     ..scanner::ScanOptions::default()
+/// };
+/// let report = scanner::scan_block(&provider, start_block, end_block, &storage, &options).await;
+/// assert!(report.is_complete(), "failed blocks: {:?}", report.failed);
+/// if let Some(reorg) = scanner::reconcile_reorg(&provider, &storage, &options).await? {
+///     println!("{} transactions orphaned at block {}", reorg.orphaned.len(), reorg.fork_block);
+/// }
 /// ```
 pub mod scanner;
+
+/// `stats`
+///
+/// Statistics shared by the detectors of one run: sorted value, fee, tip and gas
+/// price distributions for percentile thresholds, and per-sender means per asset.
+/// Computed once per `AnalysisContext` instead of once per detector or transaction.
+///
+/// Example usage:
+/// ```rust,ignore
+/// let stats = ctx.stats().await;
+/// let threshold = stats.native().values.percentile(99.0);
+/// ```
+pub mod stats;
+
+/// `store`
+///
+/// Persistent transaction storage. The [`store::TxStore`] trait is implemented by the
+/// in-memory `TxStorage` and by [`store::SqliteStore`], a single SQLite file that
+/// blocks are ingested into once and loaded from by block range for every later
+/// analysis, without asking the node again.
+///
+/// Example usage:
+/// ```rust,ignore
+/// let store = store::SqliteStore::open("mainnet.sqlite")?;
+/// store.insert_block(header, &txs, &transfers).await?;
+/// let storage = store::load_storage(&store, 19_000_000..=19_100_000).await?;
+/// ```
+pub mod store;
+
+/// `units`
+///
+/// Lossless helpers for the wei amounts stored in `TransactionRecord`: exact decimal
+/// formatting and parsing in ETH and Gwei, 512-bit means and comparisons against
+/// configured multipliers, and explicitly lossy `f64` conversions for plotting.
+///
+/// Example usage:
+/// ```rust,ignore
+/// let wei = units::parse_eth("1.5")?;
+/// assert_eq!(units::format_eth(wei), "1.5");
+/// ```
+pub mod units;
+
+/// `watch`
+///
+/// Continuous monitoring: follows the chain head, ingests every new block into
+/// `TxStorage` and runs the detectors after each batch, reporting only findings
+/// that were not reported before and retracting those invalidated by a reorg.
+///
+/// Example usage:
+/// ```rust,ignore
+/// watch::watch(&provider, head, &registry, &storage, &watch::WatchOptions::default(), |update| {
+///     println!("block {}: {} new findings", update.head, update.findings.len());
+///     std::ops::ControlFlow::Continue(())
+/// })
+/// .await;
+/// ```
+pub mod watch;
@@ -1,150 +1,624 @@
-mod analize;
-mod config;
-mod csv;
-mod models;
-mod scanner;
-
-use analize::{
-    detect_active_traders, detect_arbitrage, detect_batch_payments, detect_blacklist_adresses,
-    detect_dex_trade, detect_high_fee, detect_high_frequency, detect_large_tx,
-    detect_liquid_provider, detect_nft_activity, detect_regular_payments, detect_structuring,
-    detect_time_anomalies, detect_unusual_op, detect_whales,
+mod cli;
+
+use clap::Parser;
+use cli::{
+    AnalyzeArgs, BlockRangeArgs, Cli, Command, DetectorArgs, ExportArgs, FetchArgs, ListArgs,
+    ReportArgs, ScanArgs, SourceArgs, WatchArgs,
+};
+use eth_analyzer::{
+    checkpoint::Checkpoint,
+    config::AnalyzerConfig,
+    csv::{export_anomalies_csv, export_patterns_csv},
+    detector::{AnalysisContext, DetectorRegistry, Findings, RegistryError},
+    fixture::{FixtureClient, RecordingClient, ReplayClient},
+    import::{group_by_block, import_file, read_ndjson},
+    models::{SharedTxStorage, TokenTransfer, TxStorage},
+    scanner::{
+        find_reorg, reconcile_reorg, scan_block, scan_blocks, store_block, ScanOptions, ScanReport,
+        ScannedBlock,
+    },
+    store::{load_storage, SqliteStore, TxStore},
+    watch::{self, WatchOptions, WatchUpdate},
 };
-use csv::{export_anomalies_csv, export_patterns_csv};
-use ethers::prelude::*;
 use ethers::providers::{Http, Middleware, Provider};
-use log::{error, info};
-use models::TxStorage;
-use scanner::scan_block;
-use std::process::Command;
-use std::{collections::HashSet, env, sync::Arc};
-
-abigen!(
-    UniswapV2Factory,
-    r#"[
-        function allPairsLength() external view returns (uint256)
-        function allPairs(uint256) external view returns (address)
-    ]"#
-);
-
-abigen!(
-    ERC20,
-    r#"[
-        function symbol() external view returns (string)
-        function decimals() external view returns (uint8)
-    ]"#
-);
+use log::{error, info, warn};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::process::Command as Process;
+use std::str::FromStr;
+use std::time::Duration;
+use std::{error::Error, sync::Arc};
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+async fn main() -> Result<(), Box<dyn Error>> {
     env_logger::init();
 
-    let rpc_url = "https://mainnet.infura.io/v3/aeadc00d6e1d4e25b3ecbe34617e1165";
-    let provider = Arc::new(Provider::<Http>::try_from(rpc_url)?);
-    let last_block = provider.get_block_number().await?.as_u64();
-    let start_block = last_block - 10;
-    let end_block = last_block;
-    let uniswap_v2_router: Address = "0x7a250d5630B4cF539739dF2C5dAcb4c659F2488D".parse()?;
-    let sushiswap_router: Address = "0xd9e1cE17f2641f24aE83637ab66a2cca9C378B9F".parse()?;
+    let cli = Cli::parse();
+    match cli.command {
+        Command::Scan(args) => scan(args).await,
+        Command::Analyze(args) => analyze(args).await,
+        Command::Export(args) => export(args).await,
+        Command::Watch(args) => watch(args).await,
+        Command::Report(args) => report(args),
+        Command::Detectors(args) => list_detectors(args),
+    }
+}
 
-    let mut dex_routers = HashSet::new();
-    dex_routers.insert(uniswap_v2_router);
-    dex_routers.insert(sushiswap_router);
+async fn scan(args: ScanArgs) -> Result<(), Box<dyn Error>> {
+    let provider =
+        connect(args.rpc_url.as_deref(), &args.fetch).map_err(|e| format!("{} to scan", e))?;
+    let latest = provider.get_block_number().await?.as_u64();
+    let (start_block, end_block) = block_range(latest, &args.range)?;
+    let store = match &args.db {
+        Some(path) => Some(SqliteStore::open(path)?),
+        None => None,
+    };
 
-    let storage = Arc::new(TxStorage::new());
-    let _records = scan_block(&provider, start_block, end_block, &storage).await;
-    let all_txs = storage.all_txs.read().await;
-    info!("Total transactions: {}", all_txs.len());
-
-    let large_tx = detect_large_tx(&storage).await;
-    info!("Large transactions anomaly count: {}", large_tx.len());
-    let high_frequency = detect_high_frequency(&storage).await;
-    info!("High Frequency anomaly count: {}", high_frequency.len());
-    let structuring = detect_structuring(&storage).await;
-    info!("Structuring anomaly count: {}", structuring.len());
-    let high_fee = detect_high_fee(&storage).await;
-    info!("High fee anomaly count: {}", high_fee.len());
-    let blacklist_addresses = detect_blacklist_adresses(&storage).await;
-    info!("Blacklist anomaly count: {}", blacklist_addresses.len());
-    let unusual_op = detect_unusual_op(&storage).await;
-    info!("Unusual operations anomaly count: {}", unusual_op.len());
-    let time_anomaly = detect_time_anomalies(&storage).await;
-    info!("Time anomaly count: {}", time_anomaly.len());
-
-    let project_dir = env::current_dir().unwrap();
-    let file_path = project_dir.join("anomalies.csv");
-    let mut anomalies = Vec::new();
-    anomalies.extend(large_tx);
-    anomalies.extend(high_frequency);
-    anomalies.extend(structuring);
-    anomalies.extend(high_fee);
-    anomalies.extend(blacklist_addresses);
-    anomalies.extend(unusual_op);
-    anomalies.extend(time_anomaly);
-
-    info!("Anomaly count: {}", anomalies.len());
-
-    if let Err(e) = export_anomalies_csv(&anomalies, file_path.to_str().unwrap()) {
-        error!("Error CSV writing: {:?}", e);
+    // Without `--resume` the output and the checkpoint start over; with it,
+    // completed blocks are skipped and new records are appended.
+    let checkpoint_path = args.checkpoint.clone().unwrap_or_else(|| {
+        let mut path = args.output.clone().into_os_string();
+        path.push(".checkpoint");
+        PathBuf::from(path)
+    });
+    let checkpoint = if args.resume {
+        Checkpoint::open(&checkpoint_path)?
     } else {
-        info!("Anomalies succesfully exported to CSV");
-    }
-
-    let file_path = project_dir.join("patterns.csv");
-    let regular_payments = detect_regular_payments(&storage).await;
-    info!("Regular payments pattern count: {}", regular_payments.len());
-    let batch_payments = detect_batch_payments(&storage).await;
-    info!("Batch Payments pattern count: {}", batch_payments.len());
-    let dex_trade = detect_dex_trade(&storage, &dex_routers).await;
-    info!("DEX trade pattern count: {}", dex_trade.len());
-    let nft_activity = detect_nft_activity(&storage).await;
-    info!("NFT activity pattern count: {}", nft_activity.len());
-    let liquiditi_provider = detect_liquid_provider(&storage, &dex_routers).await;
+        Checkpoint::create(&checkpoint_path)?
+    };
+    let mut output = ScanOutput {
+        txs: open_ndjson(&args.output, args.resume)?,
+        transfers: match &args.transfers_output {
+            Some(path) => Some(open_ndjson(path, args.resume)?),
+            None => None,
+        },
+        store,
+        checkpoint,
+    };
+
+    let pending = output.checkpoint.pending(start_block, end_block);
     info!(
-        "Liquidity provider pattern count: {}",
-        liquiditi_provider.len()
+        "Scanning blocks {}..={}: {} pending, {} already in {}",
+        start_block,
+        end_block,
+        pending.len(),
+        (end_block - start_block + 1) as usize - pending.len(),
+        output.checkpoint.path().display()
     );
-    let active_traders = detect_active_traders(&storage, &dex_routers).await;
-    info!("Active Traders pattern count: {}", active_traders.len());
-    let arbitrage = detect_arbitrage(&storage, &dex_routers).await;
-    info!("Arbitrage pattern count: {}", arbitrage.len());
-    let whales = detect_whales(&storage).await;
-    info!("Whales pattern count: {}", whales.len());
-
-    let mut patterns = Vec::new();
-    patterns.extend(regular_payments);
-    patterns.extend(batch_payments);
-    patterns.extend(dex_trade);
-    patterns.extend(nft_activity);
-    patterns.extend(liquiditi_provider);
-    patterns.extend(active_traders);
-    patterns.extend(arbitrage);
-    patterns.extend(whales);
-
-    info!("Pattern count: {}", patterns.len());
-
-    if let Err(e) = export_patterns_csv(&patterns, file_path.to_str().unwrap()) {
-        error!("Error CSV writing: {:?}", e);
-    } else {
-        info!("Patterns succesfully exported to CSV");
+
+    let storage = Arc::new(TxStorage::new());
+    let options = scan_options(&args.fetch);
+    let report = scan_blocks(&provider, pending, &storage, &options, |block| {
+        output.write_block(block)
+    })
+    .await;
+    log_report(&report);
+    // A reorg near the head may have orphaned blocks while the range was
+    // scanned: roll them back in every output and scan them again.
+    if let Some(fork_block) = find_reorg(&provider, &storage, options.reorg_depth).await? {
+        let orphaned = storage.rollback_from(fork_block).await;
+        warn!(
+            "Chain reorganization at block {}: {} transactions orphaned, rescanning up to block {}",
+            fork_block,
+            orphaned.len(),
+            end_block
+        );
+        output.rollback_from(fork_block, &args).await?;
+        let pending = output.checkpoint.pending(fork_block, end_block);
+        let rescan = scan_blocks(&provider, pending, &storage, &options, |block| {
+            output.write_block(block)
+        })
+        .await;
+        log_report(&rescan);
+    }
+
+    info!(
+        "{} transactions written to {}",
+        storage.all_txs.read().await.len(),
+        args.output.display()
+    );
+    if let Some(path) = &args.transfers_output {
+        info!(
+            "{} token transfers written to {}",
+            storage.token_transfers.read().await.len(),
+            path.display()
+        );
+    }
+    if let Some(store) = &output.store {
+        info!("Blocks added to {}", store.path().display());
+    }
+    if !output.checkpoint.pending(start_block, end_block).is_empty() {
+        info!("Run again with --resume to retry the failed blocks");
+    }
+    Ok(())
+}
+
+/// Everything `scan` writes a scanned block to.
+struct ScanOutput {
+    txs: BufWriter<File>,
+    transfers: Option<BufWriter<File>>,
+    store: Option<SqliteStore>,
+    checkpoint: Checkpoint,
+}
+
+impl ScanOutput {
+    /// Writes `block` to the outputs and then marks it as completed.
+    fn write_block(&mut self, block: &ScannedBlock) -> io::Result<()> {
+        write_ndjson(&mut self.txs, &block.txs)?;
+        if let Some(out) = self.transfers.as_mut() {
+            write_ndjson(out, &block.transfers)?;
+        }
+        if let Some(store) = &self.store {
+            store
+                .write_block(block.header, &block.txs, &block.transfers)
+                .map_err(io::Error::other)?;
+        }
+        self.checkpoint.mark_completed(block.number)
+    }
+
+    /// Removes every block from `fork_block` on from the NDJSON files, the
+    /// `--db` store and the checkpoint, so they can be scanned again.
+    async fn rollback_from(
+        &mut self,
+        fork_block: u64,
+        args: &ScanArgs,
+    ) -> Result<(), Box<dyn Error>> {
+        self.txs = truncate_ndjson_from(&args.output, fork_block)?;
+        if let (Some(out), Some(path)) = (self.transfers.as_mut(), &args.transfers_output) {
+            *out = truncate_ndjson_from(path, fork_block)?;
+        }
+        if let Some(store) = &self.store {
+            store.rollback_from(fork_block).await?;
+        }
+        self.checkpoint.rollback_from(fork_block)?;
+        Ok(())
+    }
+}
+
+/// Opens an NDJSON output file, truncating it unless `append` is set. When
+/// appending, a last line cut off by a crash is removed first.
+fn open_ndjson(path: &Path, append: bool) -> io::Result<BufWriter<File>> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .read(true)
+        .write(true)
+        .truncate(!append)
+        .open(path)?;
+    if append {
+        let len = complete_lines_len(&mut file)?;
+        file.set_len(len)?;
+        file.seek(SeekFrom::Start(len))?;
+    }
+    Ok(BufWriter::new(file))
+}
+
+/// Length of `file` up to and including its last newline.
+fn complete_lines_len(file: &mut File) -> io::Result<u64> {
+    let mut end = file.seek(SeekFrom::End(0))?;
+    let mut chunk = [0u8; 8192];
+    while end > 0 {
+        let start = end.saturating_sub(chunk.len() as u64);
+        let chunk = &mut chunk[..(end - start) as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(chunk)?;
+        if let Some(pos) = chunk.iter().rposition(|byte| *byte == b'\n') {
+            return Ok(start + pos as u64 + 1);
+        }
+        end = start;
+    }
+    Ok(0)
+}
+
+/// Removes the records of every block from `fork_block` on from the NDJSON file
+/// at `path` and reopens it for appending.
+///
+/// The kept lines are written to a temporary file that then replaces `path`,
+/// so a crash leaves either the old or the new content.
+fn truncate_ndjson_from(path: &Path, fork_block: u64) -> io::Result<BufWriter<File>> {
+    let mut tmp_path = path.to_path_buf().into_os_string();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+    let mut tmp = BufWriter::new(File::create(&tmp_path)?);
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        let record: serde_json::Value = serde_json::from_str(&line)?;
+        let orphaned = record["block_number"]
+            .as_u64()
+            .is_some_and(|block_number| block_number >= fork_block);
+        if !orphaned {
+            writeln!(tmp, "{}", line)?;
+        }
+    }
+    tmp.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    std::fs::rename(&tmp_path, path)?;
+    open_ndjson(path, true)
+}
+
+/// Appends `records` as newline-delimited JSON and syncs them to disk, so a
+/// block is durable before it is marked as completed in the checkpoint.
+fn write_ndjson<T: serde::Serialize>(
+    writer: &mut BufWriter<File>,
+    records: &[T],
+) -> io::Result<()> {
+    for record in records {
+        serde_json::to_writer(&mut *writer, record)?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()?;
+    writer.get_ref().sync_data()
+}
+
+async fn analyze(args: AnalyzeArgs) -> Result<(), Box<dyn Error>> {
+    let registry = load_registry(&args.detectors)?;
+    let storage = scan_source(&args.source).await?;
+    let findings = run_detectors(storage, &registry).await;
+
+    println!("Anomalies: {}", findings.anomalies.len());
+    for anomaly in &findings.anomalies {
+        println!("{:?}", anomaly);
+    }
+    println!("Patterns: {}", findings.patterns.len());
+    for pattern in &findings.patterns {
+        println!("{:?}", pattern);
+    }
+    Ok(())
+}
+
+async fn export(args: ExportArgs) -> Result<(), Box<dyn Error>> {
+    let registry = load_registry(&args.detectors)?;
+    let storage = scan_source(&args.source).await?;
+    let findings = run_detectors(storage, &registry).await;
+
+    export_anomalies_csv(&findings.anomalies, path_str(&args.anomalies_out)?)?;
+    info!(
+        "Anomalies succesfully exported to {}",
+        args.anomalies_out.display()
+    );
+    export_patterns_csv(&findings.patterns, path_str(&args.patterns_out)?)?;
+    info!(
+        "Patterns succesfully exported to {}",
+        args.patterns_out.display()
+    );
+    Ok(())
+}
+
+async fn watch(args: WatchArgs) -> Result<(), Box<dyn Error>> {
+    let registry = load_registry(&args.detectors)?;
+    let provider = connect(args.rpc_url.as_deref(), &args.fetch)?;
+    let start_block = match args.from_block {
+        Some(block) => block,
+        None => provider.get_block_number().await?.as_u64(),
+    };
+    let options = WatchOptions {
+        poll_interval: Duration::from_secs(args.poll_interval),
+        retain_blocks: args.retain_blocks,
+        max_failed_polls: args.max_failed_polls,
+        scan: scan_options(&args.fetch),
+    };
+
+    info!("Watching from block {}", start_block);
+    let storage = Arc::new(TxStorage::new());
+    let mut stdout = io::stdout().lock();
+    let mut output_error = None;
+    watch::watch(
+        &provider,
+        start_block,
+        &registry,
+        &storage,
+        &options,
+        |update| {
+            info!(
+                "Block {}: {} new findings, {} retracted",
+                update.head,
+                update.findings.len(),
+                update.retracted.len()
+            );
+            match print_update(&mut stdout, &update) {
+                Ok(()) => ControlFlow::Continue(()),
+                Err(e) => {
+                    // Stop watching once nobody reads the output anymore.
+                    output_error = Some(e);
+                    ControlFlow::Break(())
+                }
+            }
+        },
+    )
+    .await;
+    match output_error {
+        Some(e) => Err(e.into()),
+        None => Ok(()),
+    }
+}
+
+/// Prints a watch update as JSON lines tagged with the head it was found at: the
+/// reorg if any, the blocks given up on, then the retracted findings
+/// (`"retracted": true`), then the new ones.
+fn print_update(out: &mut impl Write, update: &WatchUpdate) -> io::Result<()> {
+    let head = update.head;
+    if let Some(fork_block) = update.fork_block {
+        let reorg =
+            serde_json::json!({ "fork_block": fork_block, "orphaned": update.orphaned.len() });
+        writeln!(
+            out,
+            "{}",
+            serde_json::json!({ "block": head, "reorg": reorg })
+        )?;
+    }
+    for failed in &update.given_up {
+        let given_up =
+            serde_json::json!({ "block_number": failed.block_number, "error": failed.error });
+        writeln!(
+            out,
+            "{}",
+            serde_json::json!({ "block": head, "given_up": given_up })
+        )?;
     }
+    print_findings(out, head, &update.retracted, true)?;
+    print_findings(out, head, &update.findings, false)?;
+    out.flush()
+}
 
-    let project_dir = env::current_dir()?;
-    let anomalies_path = project_dir.join("anomalies.csv");
-    let patterns_path = project_dir.join("patterns.csv");
+/// Prints each finding as one JSON object per line.
+fn print_findings(
+    out: &mut impl Write,
+    head: u64,
+    findings: &Findings,
+    retracted: bool,
+) -> io::Result<()> {
+    for anomaly in &findings.anomalies {
+        let mut line = serde_json::json!({ "block": head, "anomaly": anomaly });
+        if retracted {
+            line["retracted"] = true.into();
+        }
+        writeln!(out, "{}", line)?;
+    }
+    for pattern in &findings.patterns {
+        let mut line = serde_json::json!({ "block": head, "pattern": pattern });
+        if retracted {
+            line["retracted"] = true.into();
+        }
+        writeln!(out, "{}", line)?;
+    }
+    Ok(())
+}
 
-    let viz_path = project_dir.join("src/viz.py");
-    let _python_path = project_dir.join("venv/bin/python3");
-    let status = Command::new("python3")
-        .arg(viz_path.to_str().unwrap())
-        .arg(anomalies_path.to_str().unwrap())
-        .arg(patterns_path.to_str().unwrap())
+fn report(args: ReportArgs) -> Result<(), Box<dyn Error>> {
+    let status = Process::new(&args.python)
+        .arg(&args.script)
+        .arg(&args.anomalies)
+        .arg(&args.patterns)
         .status()?;
 
-    match status.success() {
-        true => info!("Plotter succesfully created!"),
-        false => error!("Error"),
+    if status.success() {
+        info!("Plotter succesfully created!");
+        Ok(())
+    } else {
+        error!("Visualization script exited with {}", status);
+        Err(format!("{} exited with {}", args.script.display(), status).into())
     }
+}
 
+fn list_detectors(args: ListArgs) -> Result<(), Box<dyn Error>> {
+    let registry = DetectorRegistry::with_builtin(&load_config(&args.config)?);
+    for detector in registry.detectors() {
+        println!(
+            "{:<20} {:<8} {}",
+            detector.name(),
+            detector.kind(),
+            detector.config()
+        );
+    }
     Ok(())
 }
+
+/// Resolves the block range of `source` and loads it into a fresh storage,
+/// from the `--db` store if given and from the node otherwise.
+async fn scan_source(source: &SourceArgs) -> Result<SharedTxStorage, Box<dyn Error>> {
+    if let Some(path) = &source.db {
+        let store = SqliteStore::open(path)?;
+        let Some((_, last_stored)) = store.block_bounds().await? else {
+            return Err(format!("{} contains no blocks", path.display()).into());
+        };
+        let (start_block, end_block) = block_range(last_stored, &source.range)?;
+        info!(
+            "Loading blocks {}..={} from {}",
+            start_block,
+            end_block,
+            path.display()
+        );
+        let storage = load_storage(&store, start_block..=end_block).await?;
+        info!("Total transactions: {}", storage.all_txs.read().await.len());
+        return Ok(storage);
+    }
+
+    if !source.import.is_empty() {
+        return import_source(source).await;
+    }
+
+    let provider = connect(source.rpc_url.as_deref(), &source.fetch)?;
+    let latest = provider.get_block_number().await?.as_u64();
+    let (start_block, end_block) = block_range(latest, &source.range)?;
+
+    info!("Scanning blocks {}..={}", start_block, end_block);
+    let storage = Arc::new(TxStorage::new());
+    let options = scan_options(&source.fetch);
+    let report = scan_block(&provider, start_block, end_block, &storage, &options).await;
+    log_report(&report);
+    // A reorg near the head may have orphaned blocks while the range was scanned.
+    if let Some(reorg) = reconcile_reorg(&provider, &storage, &options).await? {
+        log_report(&reorg.rescan);
+    }
+    info!("Total transactions: {}", storage.all_txs.read().await.len());
+    if source.fetch.token_transfers {
+        info!(
+            "Total token transfers: {}",
+            storage.token_transfers.read().await.len()
+        );
+    }
+
+    Ok(storage)
+}
+
+/// Loads the `--import` files into a fresh storage, keeping only the blocks
+/// between `--from-block` and `--to-block` when they are given.
+async fn import_source(source: &SourceArgs) -> Result<SharedTxStorage, Box<dyn Error>> {
+    let range = source.range.from_block.unwrap_or(0)..=source.range.to_block.unwrap_or(u64::MAX);
+    if range.is_empty() {
+        return Err(format!(
+            "Invalid block range: from {} is after to {}",
+            range.start(),
+            range.end()
+        )
+        .into());
+    }
+
+    let mut blocks = Vec::new();
+    for path in &source.import {
+        let imported = import_file(path, source.import_format)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        info!("Imported {} blocks from {}", imported.len(), path.display());
+        blocks.extend(imported);
+    }
+    let mut transfers = Vec::new();
+    for path in &source.import_transfers {
+        let file = BufReader::new(File::open(path)?);
+        let imported: Vec<TokenTransfer> =
+            read_ndjson(file).map_err(|e| format!("{}: {}", path.display(), e))?;
+        info!(
+            "Imported {} token transfers from {}",
+            imported.len(),
+            path.display()
+        );
+        transfers.extend(imported);
+    }
+    blocks.extend(group_by_block(Vec::new(), transfers));
+
+    let storage = Arc::new(TxStorage::new());
+    for block in blocks {
+        if range.contains(&block.number) {
+            store_block(&storage, block).await;
+        }
+    }
+    info!("Total transactions: {}", storage.all_txs.read().await.len());
+    Ok(storage)
+}
+
+/// Connects to `rpc_url`, recording its answers with `--record-rpc`, or
+/// answers from the `--replay-rpc` fixtures without any node.
+fn connect(
+    rpc_url: Option<&str>,
+    fetch: &FetchArgs,
+) -> Result<Arc<Provider<FixtureClient<Http>>>, Box<dyn Error>> {
+    let client = match (&fetch.replay_rpc, rpc_url) {
+        (Some(dir), _) => FixtureClient::Replay(ReplayClient::open(dir)?),
+        (None, Some(rpc_url)) => {
+            let http = Http::from_str(rpc_url)?;
+            match &fetch.record_rpc {
+                Some(dir) => FixtureClient::Recording(RecordingClient::create(http, dir)?),
+                None => FixtureClient::Live(http),
+            }
+        }
+        (None, None) => return Err("--rpc-url or --replay-rpc is required".into()),
+    };
+    Ok(Arc::new(Provider::new(client)))
+}
+
+/// Resolves `--from-block`, `--to-block` and `--last` into an inclusive range;
+/// `latest` is the block `--to-block` defaults to.
+fn block_range(latest: u64, range: &BlockRangeArgs) -> Result<(u64, u64), Box<dyn Error>> {
+    let end_block = range.to_block.unwrap_or(latest);
+    let start_block = range
+        .from_block
+        .unwrap_or_else(|| end_block.saturating_sub(range.last));
+    if start_block > end_block {
+        return Err(format!(
+            "Invalid block range: from {} is after to {}",
+            start_block, end_block
+        )
+        .into());
+    }
+    Ok((start_block, end_block))
+}
+
+fn scan_options(fetch: &FetchArgs) -> ScanOptions {
+    ScanOptions {
+        fetch_receipts: fetch.receipts,
+        fetch_token_transfers: fetch.token_transfers,
+        max_in_flight: fetch.max_in_flight,
+        max_retries: fetch.max_retries,
+        initial_backoff: Duration::from_millis(fetch.retry_backoff_ms),
+        requests_per_second: fetch.rate_limit,
+        reorg_depth: fetch.reorg_depth,
+        ..ScanOptions::default()
+    }
+}
+
+fn log_report(report: &ScanReport) {
+    info!(
+        "Scanned {} blocks with {} retries",
+        report.scanned_blocks, report.retries
+    );
+    if !report.is_complete() {
+        let failed: Vec<String> = report
+            .failed
+            .iter()
+            .map(|failed| failed.block_number.to_string())
+            .collect();
+        error!(
+            "{} blocks could not be scanned and are missing from the results: {}",
+            failed.len(),
+            failed.join(", ")
+        );
+    }
+}
+
+/// Builds the detector registry from the configuration file and applies the
+/// `--detectors` / `--exclude` selection before any block is scanned.
+fn load_registry(args: &DetectorArgs) -> Result<DetectorRegistry, Box<dyn Error>> {
+    let mut registry = DetectorRegistry::with_builtin(&load_config(&args.config)?);
+    if !args.detectors.is_empty() {
+        let selected: Vec<&str> = args.detectors.iter().map(|name| name.trim()).collect();
+        registry
+            .select(&selected)
+            .map_err(|e| unknown_detector(e, &registry))?;
+    }
+    for name in &args.exclude {
+        registry
+            .disable(name.trim())
+            .map_err(|e| unknown_detector(e, &registry))?;
+    }
+    Ok(registry)
+}
+
+fn load_config(path: &Option<PathBuf>) -> Result<AnalyzerConfig, Box<dyn Error>> {
+    Ok(match path {
+        Some(path) => AnalyzerConfig::from_file(path)?,
+        None => AnalyzerConfig::default(),
+    })
+}
+
+fn unknown_detector(error: RegistryError, registry: &DetectorRegistry) -> Box<dyn Error> {
+    format!(
+        "{}. Available detectors: {}",
+        error,
+        registry.names().join(", ")
+    )
+    .into()
+}
+
+async fn run_detectors(storage: SharedTxStorage, registry: &DetectorRegistry) -> Findings {
+    let report = registry.run_report(&AnalysisContext::new(storage)).await;
+    for timing in report.slowest() {
+        info!("{} took {:.3?}", timing.name, timing.elapsed);
+    }
+    info!("Anomaly count: {}", report.findings.anomalies.len());
+    info!("Pattern count: {}", report.findings.patterns.len());
+    report.findings
+}
+
+fn path_str(path: &std::path::Path) -> Result<&str, Box<dyn Error>> {
+    path.to_str()
+        .ok_or_else(|| format!("Path is not valid UTF-8: {}", path.display()).into())
+}
@@ -1,6 +1,10 @@
+use crate::units::{format_eth, format_gwei};
 use chrono::{DateTime, Utc};
+use dashmap::mapref::entry::Entry;
 use dashmap::DashMap;
+use ethers::types::{Address, H256, U256};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
@@ -10,63 +14,221 @@ use tokio::sync::RwLock;
 /// typically for analysis, monitoring, or anomaly detection.
 /// # Fields
 ///
-/// * `hash` – The unique transaction hash (identifier) as a hexadecimal string.
+/// * `hash` – The unique transaction hash (identifier).
 /// * `from` – The sender's address of the transaction.
 /// * `to` – Optional recipient address. `None` indicates a contract creation or unknown recipient.
-/// * `value` – The amount transferred in the transaction, typically in the blockchain's native currency.
-/// * `gas` – The amount of gas used by the transaction.
-/// * `gas_price_gwei` – The gas price in Gwei (1 Gwei = 10⁹ Wei) used to execute the transaction.
+/// * `value` – The exact amount transferred in the transaction, in wei of the native currency.
+/// * `gas` – The gas limit of the transaction.
+/// * `gas_price` – The exact gas price in wei used to execute the transaction.
+///   Use [`crate::units::format_gwei`] to display it in Gwei (1 Gwei = 10⁹ Wei).
 /// * `block_number` – The block number in which the transaction was included.
-/// * `timestamp` – The UTC timestamp of when the transaction was mined, stored as an RFC 3339 string.
-/// * `input` – The raw input data of the transaction, often containing contract call data or payload.
+/// * `timestamp` – The UTC timestamp of when the transaction was mined.
+/// * `input` – The raw input data of the transaction as a `0x`-prefixed hex string,
+///   often containing contract call data or payload.
+/// * `gas_used` – Gas actually consumed, from the receipt.
+/// * `effective_gas_price` – Price per gas actually paid in wei, from the receipt.
+/// * `status` – `Some(true)` if the transaction succeeded, `Some(false)` if it reverted.
+/// * `contract_address` – Address of the contract created by the transaction, if any.
+/// * `tx_type` – EIP-2718 transaction type: `0` legacy, `1` access list (EIP-2930),
+///   `2` dynamic fee (EIP-1559), `3` blob (EIP-4844). `None` if the node omitted it.
+/// * `max_fee_per_gas` – EIP-1559 fee cap in wei.
+/// * `max_priority_fee_per_gas` – EIP-1559 tip cap in wei.
+/// * `base_fee_per_gas` – Base fee in wei of the block that included the
+///   transaction; `None` before the London fork.
+///
+/// `gas_used`, `effective_gas_price`, `status` and `contract_address` are only
+/// known when the scanner fetched receipts (see [`crate::scanner::ScanOptions`])
+/// and are `None` otherwise.
+///
+/// Addresses, hashes and timestamps are parsed once when the record is built, so
+/// detectors never parse strings. With serde, hashes and addresses use their usual
+/// `0x` hex form and the timestamp is written as RFC 3339, so records round-trip
+/// through the NDJSON files written by `scan`.
 ///
 /// # Example
 ///
 /// ```rust,ignore
 /// let tx = TransactionRecord {
-///     hash: "0x123abc...".to_string(),
-///     from: "0xabc123...".to_string(),
-///     to: Some("0xdef456...".to_string()),
-///     value: 10.5,
+///     hash: "0x5c50...8c1e".parse()?,
+///     from: "0xabc1...2345".parse()?,
+///     to: Some("0xdef4...5678".parse()?),
+///     value: parse_eth("10.5")?,
 ///     gas: 21000,
-///     gas_price_gwei: 50.0,
+///     gas_price: parse_gwei("50")?,
 ///     block_number: 12345678,
-///     timestamp: "2025-09-16T12:00:00Z".to_string(),
+///     timestamp: "2025-09-16T12:00:00Z".parse()?,
 ///     input: "".to_string(),
+///     gas_used: Some(21000),
+///     effective_gas_price: Some(parse_gwei("48")?),
+///     status: Some(true),
+///     contract_address: None,
 /// };
-/// println!("Transaction from {} to {:?}", tx.from, tx.to);
+/// println!("{:?} sent {} ETH", tx.from, format_eth(tx.value));
 /// ```
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct TransactionRecord {
-    pub hash: String,
-    pub from: String,
-    pub to: Option<String>,
-    pub value: f64,
+    pub hash: H256,
+    pub from: Address,
+    pub to: Option<Address>,
+    pub value: U256,
     pub gas: u64,
-    pub gas_price_gwei: f64,
+    pub gas_price: U256,
     pub block_number: u64,
-    pub timestamp: String,
+    pub timestamp: DateTime<Utc>,
     pub input: String,
+    #[serde(default)]
+    pub gas_used: Option<u64>,
+    #[serde(default)]
+    pub effective_gas_price: Option<U256>,
+    #[serde(default)]
+    pub status: Option<bool>,
+    #[serde(default)]
+    pub contract_address: Option<Address>,
+    #[serde(default)]
+    pub tx_type: Option<u64>,
+    #[serde(default)]
+    pub max_fee_per_gas: Option<U256>,
+    #[serde(default)]
+    pub max_priority_fee_per_gas: Option<U256>,
+    #[serde(default)]
+    pub base_fee_per_gas: Option<U256>,
+}
+
+impl TransactionRecord {
+    /// Fee paid for this transaction in wei.
+    ///
+    /// With a receipt this is the exact fee, `gas_used * effective_gas_price`.
+    /// Without one it falls back to [`max_fee`](Self::max_fee), which overstates
+    /// the fee of every transaction that did not use its whole gas limit.
+    pub fn fee(&self) -> U256 {
+        match (self.gas_used, self.effective_gas_price) {
+            (Some(gas_used), Some(price)) => price.saturating_mul(U256::from(gas_used)),
+            (Some(gas_used), None) => self.gas_price.saturating_mul(U256::from(gas_used)),
+            _ => self.max_fee(),
+        }
+    }
+
+    /// Upper bound of the fee in wei: `gas * gas_price` with `gas` being the
+    /// gas limit, saturating at `U256::MAX`.
+    pub fn max_fee(&self) -> U256 {
+        self.gas_price.saturating_mul(U256::from(self.gas))
+    }
+
+    /// Price per gas actually paid in wei: the receipt's effective gas price
+    /// when known, otherwise `gas_price`.
+    pub fn price_per_gas(&self) -> U256 {
+        self.effective_gas_price.unwrap_or(self.gas_price)
+    }
+
+    /// Tip per gas paid to the block producer in wei, i.e. the price paid on
+    /// top of the block's base fee. `None` for pre-London blocks.
+    ///
+    /// This applies to every transaction type: a legacy transaction's whole
+    /// gas price above the base fee is a tip as well.
+    pub fn priority_fee_per_gas(&self) -> Option<U256> {
+        self.base_fee_per_gas
+            .map(|base_fee| self.price_per_gas().saturating_sub(base_fee))
+    }
+
+    /// `true` only if a receipt was fetched and the transaction reverted.
+    pub fn is_failed(&self) -> bool {
+        self.status == Some(false)
+    }
+
+    /// 4-byte method selector of a contract call as a `0x`-prefixed hex
+    /// string, e.g. `"0xa9059cbb"`. `None` for plain transfers.
+    pub fn selector(&self) -> Option<&str> {
+        self.input.get(0..10)
+    }
+}
+
+/// An ERC-20 `Transfer(address indexed from, address indexed to, uint256 value)`
+/// event decoded from a transaction log.
+///
+/// Token transfers are where most value moves on chain (USDT, USDC, WETH, ...),
+/// while the native `value` of the emitting transaction is usually zero.
+///
+/// # Fields
+/// * `tx_hash` – Hash of the transaction that emitted the event.
+/// * `log_index` – Position of the log within its block; together with
+///   `tx_hash` it identifies the transfer.
+/// * `block_number` – Block that included the transaction.
+/// * `timestamp` – UTC timestamp of that block.
+/// * `token` – Address of the token contract that emitted the event.
+/// * `from` – Holder the tokens were taken from. The zero address for mints.
+/// * `to` – Holder the tokens were sent to. The zero address for burns.
+/// * `amount` – Transferred amount in the token's smallest unit. The token's
+///   `decimals` are not fetched, so amounts of different tokens are not comparable.
+///
+/// # Example
+/// ```rust,ignore
+/// let transfer = decode_transfer(&log, timestamp).expect("not an ERC-20 Transfer");
+/// println!("{:?} sent {} units of {:?}", transfer.from, transfer.amount, transfer.token);
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenTransfer {
+    pub tx_hash: H256,
+    pub log_index: u64,
+    pub block_number: u64,
+    pub timestamp: DateTime<Utc>,
+    pub token: Address,
+    pub from: Address,
+    pub to: Address,
+    pub amount: U256,
 }
 
+/// A transaction shared between `all_txs` and the indexes of [`TxStorage`].
+pub type TxRef = Arc<TransactionRecord>;
+
+/// A token transfer shared between `token_transfers` and its indexes.
+pub type TransferRef = Arc<TokenTransfer>;
+
 /// A shared in-memory storage for blockchain transactions, organized for
 /// efficient querying by sender, receiver, or globally.
 ///
 /// This structure is used in analytics and anomaly detection systems to
 /// store and access transactions safely in a concurrent environment.
 ///
+/// Every record is allocated once. `all_txs` and the indexes hold [`TxRef`]s
+/// (`Arc<TransactionRecord>`) pointing to that single allocation, so each
+/// additional index costs one pointer per transaction instead of a full copy.
+/// Token transfers are shared the same way through [`TransferRef`].
+///
 /// # Fields
-/// * `by_sender` – A concurrent map (`DashMap`) from sender addresses (`String`)
-///   to vectors of [`TransactionRecord`]. Allows fast lookup of all transactions
-///   sent by a specific account.
+/// * `by_sender` – A concurrent map (`DashMap`) from sender addresses (`Address`)
+///   to the transactions sent by that account. Allows fast lookup of all
+///   transactions sent by a specific account.
+///
+/// * `by_reciever` – A concurrent map (`DashMap`) from receiver addresses (`Address`)
+///   to the transactions received by that account. Allows fast lookup of all
+///   transactions received by a specific account.
 ///
-/// * `by_reciever` – A concurrent map (`DashMap`) from receiver addresses (`String`)
-///   to vectors of [`TransactionRecord`]. Allows fast lookup of all transactions
-///   received by a specific account.
+/// * `all_txs` – A thread-safe global list (`RwLock<Vec<TxRef>>`)
+///   containing all transactions in insertion order. Useful for operations that
+///   need to iterate over the entire dataset, e.g., global anomaly detection.
 ///
-/// * `all_txs` – A thread-safe global list (`RwLock<Vec<TransactionRecord>>`) 
-///   containing all transactions. Useful for operations that need to iterate
-///   over the entire dataset, e.g., global anomaly detection.
+/// * `by_hash` – Every transaction keyed by its hash. It makes
+///   [`insert`](TxStorage::insert) idempotent and [`get`](TxStorage::get) O(1).
+///
+/// * `by_block` – Transactions grouped by block number, in block order. Ordered,
+///   so block ranges can be read with `range`.
+///
+/// * `by_selector` – Contract calls grouped by their 4-byte method selector as
+///   a `0x`-prefixed hex string (see [`TransactionRecord::selector`]).
+///
+/// * `token_transfers` – All decoded ERC-20 [`TokenTransfer`]s, in the same
+///   form as `all_txs`. Empty unless the scanner fetched logs.
+///
+/// * `transfers_by_sender` / `transfers_by_receiver` – Token transfers indexed
+///   by the `from` and `to` of the transfer (the token holders, not the
+///   transaction's sender and the token contract).
+///
+/// * `transfers_by_tx` – Token transfers grouped by the hash of the transaction
+///   that emitted them; used the same way by
+///   [`insert_transfer`](TxStorage::insert_transfer).
+///
+/// * `blocks` – [`BlockHeader`] of every scanned block by number. The scanner
+///   compares them with the node to detect chain reorganizations.
 ///
 /// # Type Aliases
 /// * `SharedTxStorage` – An [`Arc`] around [`TxStorage`] for convenient shared ownership
@@ -83,30 +245,48 @@ pub struct TransactionRecord {
 ///
 /// // Insert a transaction
 /// let tx = TransactionRecord {
-///     hash: "0x123abc...".to_string(),
-///     from: "0xabc123...".to_string(),
-///     to: Some("0xdef456...".to_string()),
-///     value: 10.5,
+///     hash: "0x5c50...8c1e".parse()?,
+///     from: "0xabc1...2345".parse()?,
+///     to: Some("0xdef4...5678".parse()?),
+///     value: parse_eth("10.5")?,
 ///     gas: 21000,
-///     gas_price_gwei: 50.0,
+///     gas_price: parse_gwei("50")?,
 ///     block_number: 12345678,
-///     timestamp: "2025-09-16T12:00:00Z".to_string(),
+///     timestamp: "2025-09-16T12:00:00Z".parse()?,
 ///     input: "".to_string(),
+///     gas_used: Some(21000),
+///     effective_gas_price: Some(parse_gwei("48")?),
+///     status: Some(true),
+///     contract_address: None,
 /// };
 ///
-/// storage.by_sender.entry(tx.from.clone()).or_default().push(tx.clone());
-/// storage.by_reciever.entry(tx.to.clone().unwrap()).or_default().push(tx.clone());
-/// storage.all_txs.write().await.push(tx);
+/// assert!(storage.insert(tx.clone()).await);
+/// // Inserting the same transaction again is a no-op
+/// assert!(!storage.insert(tx).await);
+///
+/// // Every index points to the same record
+/// let stored = storage.get(&hash).unwrap();
+/// assert!(Arc::ptr_eq(&stored, &storage.by_sender.get(&stored.from).unwrap()[0]));
 /// ```
 ///
 /// # Notes
+/// * Always add transactions through [`insert`](TxStorage::insert): pushing to
+///   the public collections directly bypasses deduplication and the other indexes.
 /// * `DashMap` allows concurrent reads and writes without locking the entire map.
 /// * `RwLock` allows multiple concurrent readers or one writer for `all_txs`.
 /// * `SharedTxStorage` (Arc) enables safe sharing across async tasks and threads.
 pub struct TxStorage {
-    pub by_sender: DashMap<String, Vec<TransactionRecord>>,
-    pub by_reciever: DashMap<String, Vec<TransactionRecord>>,
-    pub all_txs: RwLock<Vec<TransactionRecord>>,
+    pub by_sender: DashMap<Address, Vec<TxRef>>,
+    pub by_reciever: DashMap<Address, Vec<TxRef>>,
+    pub all_txs: RwLock<Vec<TxRef>>,
+    pub by_hash: DashMap<H256, TxRef>,
+    pub by_block: RwLock<BTreeMap<u64, Vec<TxRef>>>,
+    pub by_selector: DashMap<String, Vec<TxRef>>,
+    pub token_transfers: RwLock<Vec<TransferRef>>,
+    pub transfers_by_sender: DashMap<Address, Vec<TransferRef>>,
+    pub transfers_by_receiver: DashMap<Address, Vec<TransferRef>>,
+    pub transfers_by_tx: DashMap<H256, Vec<TransferRef>>,
+    pub blocks: RwLock<BTreeMap<u64, BlockHeader>>,
 }
 
 /// Shared ownership of `TxStorage` using an atomic reference count.
@@ -115,6 +295,12 @@ pub struct TxStorage {
 /// the same transaction storage safely.
 pub type SharedTxStorage = Arc<TxStorage>;
 
+impl Default for TxStorage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl TxStorage {
     /// Creates a new, empty `TxStorage` instance.
     ///
@@ -130,8 +316,217 @@ impl TxStorage {
             by_sender: DashMap::new(),
             by_reciever: DashMap::new(),
             all_txs: RwLock::new(Vec::new()),
+            by_hash: DashMap::new(),
+            by_block: RwLock::new(BTreeMap::new()),
+            by_selector: DashMap::new(),
+            token_transfers: RwLock::new(Vec::new()),
+            transfers_by_sender: DashMap::new(),
+            transfers_by_receiver: DashMap::new(),
+            transfers_by_tx: DashMap::new(),
+            blocks: RwLock::new(BTreeMap::new()),
         }
     }
+
+    /// Adds a transaction to `all_txs` and every index, unless a transaction
+    /// with the same hash is already stored.
+    ///
+    /// The record is moved into a single [`TxRef`] that all indexes share.
+    ///
+    /// # Returns
+    /// `true` if the transaction was added, `false` if it was a duplicate.
+    ///
+    /// # Notes
+    /// * The first stored version wins: a duplicate is ignored even if it
+    ///   carries more data (e.g. receipt fields). Roll the block back with
+    ///   [`rollback_from`](Self::rollback_from) to replace it.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// // Overlapping scans do not count a transaction twice
+    /// for tx in block_txs {
+    ///     storage.insert(tx).await;
+    /// }
+    /// ```
+    pub async fn insert(&self, tx: TransactionRecord) -> bool {
+        let tx = Arc::new(tx);
+        match self.by_hash.entry(tx.hash) {
+            Entry::Occupied(_) => return false,
+            Entry::Vacant(entry) => {
+                entry.insert(Arc::clone(&tx));
+            }
+        }
+
+        self.by_sender
+            .entry(tx.from)
+            .or_default()
+            .push(Arc::clone(&tx));
+        if let Some(to) = tx.to {
+            self.by_reciever.entry(to).or_default().push(Arc::clone(&tx));
+        }
+        if let Some(selector) = tx.selector() {
+            self.by_selector
+                .entry(selector.to_string())
+                .or_default()
+                .push(Arc::clone(&tx));
+        }
+        self.by_block
+            .write()
+            .await
+            .entry(tx.block_number)
+            .or_default()
+            .push(Arc::clone(&tx));
+        self.all_txs.write().await.push(tx);
+        true
+    }
+
+    /// Adds a token transfer to `token_transfers` and its indexes, unless the
+    /// same log (transaction hash and log index) is already stored.
+    ///
+    /// # Returns
+    /// `true` if the transfer was added, `false` if it was a duplicate.
+    pub async fn insert_transfer(&self, transfer: TokenTransfer) -> bool {
+        let transfer = Arc::new(transfer);
+        {
+            let mut emitted = self.transfers_by_tx.entry(transfer.tx_hash).or_default();
+            if emitted
+                .iter()
+                .any(|stored| stored.log_index == transfer.log_index)
+            {
+                return false;
+            }
+            emitted.push(Arc::clone(&transfer));
+        }
+
+        self.transfers_by_sender
+            .entry(transfer.from)
+            .or_default()
+            .push(Arc::clone(&transfer));
+        self.transfers_by_receiver
+            .entry(transfer.to)
+            .or_default()
+            .push(Arc::clone(&transfer));
+        self.token_transfers.write().await.push(transfer);
+        true
+    }
+
+    /// Looks up a transaction by hash.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// if let Some(tx) = storage.get(&hash) {
+    ///     println!("{:?} was included in block {}", tx.hash, tx.block_number);
+    /// }
+    /// ```
+    pub fn get(&self, hash: &H256) -> Option<TxRef> {
+        self.by_hash.get(hash).map(|tx| Arc::clone(&tx))
+    }
+
+    /// `true` if a transaction with this hash is stored.
+    pub fn contains(&self, hash: &H256) -> bool {
+        self.by_hash.contains_key(hash)
+    }
+
+    /// Removes the transactions and token transfers of every block before
+    /// `block_number` from the storage and all of its indexes, and returns the
+    /// removed transactions.
+    ///
+    /// Used by long-running watchers to keep a bounded window of recent blocks.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// // Keep the last 1000 blocks
+    /// let pruned = storage.prune_before(head.saturating_sub(999)).await;
+    /// ```
+    pub async fn prune_before(&self, block_number: u64) -> Vec<TxRef> {
+        self.retain_blocks(|number| number >= block_number).await
+    }
+
+    /// Removes every block from `block_number` on, e.g. after a chain
+    /// reorganization orphaned them, and returns the removed transactions.
+    ///
+    /// Transactions, token transfers, all indexes and block headers are rolled
+    /// back together, so the storage looks as if the blocks were never scanned.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let orphaned = storage.rollback_from(fork_block).await;
+    /// println!("{} transactions were orphaned", orphaned.len());
+    /// ```
+    pub async fn rollback_from(&self, block_number: u64) -> Vec<TxRef> {
+        self.retain_blocks(|number| number < block_number).await
+    }
+
+    /// Number of the highest scanned block, if any block header is stored.
+    pub async fn tip(&self) -> Option<u64> {
+        self.blocks.read().await.keys().next_back().copied()
+    }
+
+    /// Keeps only the blocks for which `keep` returns `true` and returns the
+    /// transactions of the removed blocks.
+    async fn retain_blocks<F>(&self, keep: F) -> Vec<TxRef>
+    where
+        F: Fn(u64) -> bool,
+    {
+        let removed = {
+            let mut all_txs = self.all_txs.write().await;
+            let (kept, removed) = all_txs
+                .drain(..)
+                .partition(|tx: &TxRef| keep(tx.block_number));
+            *all_txs = kept;
+            removed
+        };
+        for index in [&self.by_sender, &self.by_reciever] {
+            index.retain(|_, txs| {
+                txs.retain(|tx| keep(tx.block_number));
+                !txs.is_empty()
+            });
+        }
+        self.by_selector.retain(|_, txs| {
+            txs.retain(|tx| keep(tx.block_number));
+            !txs.is_empty()
+        });
+        self.by_hash.retain(|_, tx| keep(tx.block_number));
+        self.by_block.write().await.retain(|number, _| keep(*number));
+
+        self.token_transfers
+            .write()
+            .await
+            .retain(|transfer| keep(transfer.block_number));
+        for index in [&self.transfers_by_sender, &self.transfers_by_receiver] {
+            index.retain(|_, transfers| {
+                transfers.retain(|transfer| keep(transfer.block_number));
+                !transfers.is_empty()
+            });
+        }
+        self.transfers_by_tx.retain(|_, transfers| {
+            transfers.retain(|transfer| keep(transfer.block_number));
+            !transfers.is_empty()
+        });
+
+        self.blocks.write().await.retain(|number, _| keep(*number));
+        removed
+    }
+}
+
+/// Hash and parent hash of a scanned block.
+///
+/// The chain of `parent_hash` links lets the scanner notice when the node
+/// switched to another branch: a stored block whose hash is no longer the
+/// canonical hash at its height has been orphaned by a reorganization.
+///
+/// # Fields
+/// * `number` – Block number.
+/// * `hash` – Block hash.
+/// * `parent_hash` – Hash of the previous block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockHeader {
+    pub number: u64,
+    pub hash: H256,
+    pub parent_hash: H256,
 }
 
 /// Represents the severity level of a detected anomaly.
@@ -170,17 +565,24 @@ pub enum Severity {
 /// severity, timestamps, and descriptive reasons.
 ///
 /// # Variants
-/// * `LargeTx` – A single transaction whose value is unusually large.
+/// * `LargeTx` – A single transaction or token transfer whose value is unusually large.
 ///   Fields:
 ///   - `tx_hash`: The hash of the transaction.
+///   - `token`: Token contract of the transfer, `None` for the native ETH value.
 ///   - `severity`: [`Severity`] of the anomaly (`Strong` or `Weak`).
+///   - `median`: Median of the sender's other amounts of the same asset, in wei or raw
+///     token units; `None` when the sender's history is too short to judge.
+///   - `z_score`: Robust z-score of the amount against that median and its MAD, `None`
+///     without a median, and when all the sender's other amounts are equal: any larger
+///     amount is then flagged, with a reason saying so.
 ///   - `reasons`: List of human-readable explanations.
 ///   - `timestamp`: When the transaction was observed.
 ///
 /// * `HighFrequency` – An account has submitted too many transactions in a short time.
 ///   Fields:
 ///   - `sender`: The account address sending the transactions.
-///   - `count`: Number of transactions in the monitored interval.
+///   - `count`: Number of transactions in the busiest window.
+///   - `window_start`, `window_end`: The busiest window, start inclusive and end exclusive.
 ///   - `reasons`: Explanations why this is flagged.
 ///
 /// * `BurstActivity` – Sudden burst of transactions from a sender, indicating unusual activity.
@@ -192,14 +594,29 @@ pub enum Severity {
 ///   to avoid detection thresholds.
 ///   Fields:
 ///   - `sender`: The account address.
-///   - `count`: Number of transactions in the interval.
+///   - `token`: Token contract of the transfers, `None` for native ETH transactions.
+///   - `count`: Number of transactions in the window.
 ///   - `severity`: [`Severity`] of the anomaly.
+///   - `window_start`, `window_end`: The most suspicious window, start inclusive and end exclusive.
 ///   - `reasons`: Explanations of why it was flagged.
 ///
 /// * `HighFee` – A transaction with unusually high fees.
 ///   Fields:
 ///   - `tx_hash`: Transaction hash.
-///   - `fee_eth`: Fee in ETH.
+///   - `fee`: Exact fee in wei.
+///   - `severity`: [`Severity`] of the anomaly.
+///   - `median`: Median of the sender's other fees in wei, `None` for short histories.
+///   - `z_score`: Robust z-score of the fee against that median and its MAD, `None`
+///     without a median, and when all the sender's other fees are equal: any higher
+///     fee is then flagged, with a reason saying so.
+///   - `reasons`: Explanations.
+///   - `timestamp`: When the transaction was observed.
+///
+/// * `HighPriorityFee` – A transaction whose tip is abnormal relative to its block's base fee.
+///   Fields:
+///   - `tx_hash`: Transaction hash.
+///   - `priority_fee_per_gas`: Tip paid per gas in wei.
+///   - `base_fee_per_gas`: Base fee per gas of the including block in wei.
 ///   - `severity`: [`Severity`] of the anomaly.
 ///   - `reasons`: Explanations.
 ///   - `timestamp`: When the transaction was observed.
@@ -211,6 +628,11 @@ pub enum Severity {
 ///   - `reasons`: Explanations.
 ///   - `timestamp`: When the transaction occurred.
 ///
+/// * `SanctionsListUnavailable` – The sanctions lists could not be loaded, so no
+///   transaction was checked against them.
+///   Fields:
+///   - `reasons`: Which list failed and why.
+///
 /// * `UnusualOp` – A transaction performing an unusual operation (e.g., rare contract call).
 ///   Fields:
 ///   - `tx_hash`: Transaction hash.
@@ -231,7 +653,10 @@ pub enum Severity {
 /// ```rust,ignore
 /// let anomaly = Anomaly::LargeTx {
 ///     tx_hash: "0x123abc...".to_string(),
+///     token: None,
 ///     severity: Severity::Strong,
+///     median: Some(U256::exp18()),
+///     z_score: Some(12.5),
 ///     reasons: vec!["Transaction exceeds local and global thresholds".to_string()],
 ///     timestamp: Utc::now(),
 /// };
@@ -247,7 +672,10 @@ pub enum Severity {
 pub enum Anomaly {
     LargeTx {
         tx_hash: String,
+        token: Option<String>,
         severity: Severity,
+        median: Option<U256>,
+        z_score: Option<f64>,
         reasons: Vec<String>,
         timestamp: DateTime<Utc>,
     },
@@ -256,6 +684,8 @@ pub enum Anomaly {
         count: usize,
         // timestamps: Vec<DateTime<Utc>>,
         // recievers: Vec<String>,
+        window_start: DateTime<Utc>,
+        window_end: DateTime<Utc>,
         reasons: Vec<String>,
     },
     BurstActivity {
@@ -264,13 +694,26 @@ pub enum Anomaly {
     },
     Structuring {
         sender: String,
+        token: Option<String>,
         count: usize,
         severity: Severity,
+        window_start: DateTime<Utc>,
+        window_end: DateTime<Utc>,
         reasons: Vec<String>,
     },
     HighFee {
         tx_hash: String,
-        fee_eth: f64,
+        fee: U256,
+        severity: Severity,
+        median: Option<U256>,
+        z_score: Option<f64>,
+        reasons: Vec<String>,
+        timestamp: DateTime<Utc>,
+    },
+    HighPriorityFee {
+        tx_hash: String,
+        priority_fee_per_gas: U256,
+        base_fee_per_gas: U256,
         severity: Severity,
         reasons: Vec<String>,
         timestamp: DateTime<Utc>,
@@ -281,6 +724,9 @@ pub enum Anomaly {
         reasons: Vec<String>,
         timestamp: DateTime<Utc>,
     },
+    SanctionsListUnavailable {
+        reasons: Vec<String>,
+    },
     UnusualOp {
         tx_hash: String,
         severity: Severity,
@@ -294,6 +740,90 @@ pub enum Anomaly {
         timestamp: DateTime<Utc>,
     },
 }
+
+impl Anomaly {
+    /// Hash of the transaction the anomaly was raised for, `None` for
+    /// anomalies about a sender's activity as a whole.
+    pub fn tx_hash(&self) -> Option<&str> {
+        match self {
+            Anomaly::LargeTx { tx_hash, .. }
+            | Anomaly::HighFee { tx_hash, .. }
+            | Anomaly::HighPriorityFee { tx_hash, .. }
+            | Anomaly::BlacklistedAddress { tx_hash, .. }
+            | Anomaly::UnusualOp { tx_hash, .. }
+            | Anomaly::TimeAnomaly { tx_hash, .. } => Some(tx_hash),
+            Anomaly::HighFrequency { .. }
+            | Anomaly::BurstActivity { .. }
+            | Anomaly::Structuring { .. }
+            | Anomaly::SanctionsListUnavailable { .. } => None,
+        }
+    }
+
+    /// Sender the anomaly is about, `None` for anomalies about a single
+    /// transaction.
+    pub fn sender(&self) -> Option<&str> {
+        match self {
+            Anomaly::HighFrequency { sender, .. }
+            | Anomaly::BurstActivity { sender, .. }
+            | Anomaly::Structuring { sender, .. } => Some(sender),
+            _ => None,
+        }
+    }
+
+    /// Window of an anomaly about a sender's activity over time, `None` for
+    /// the other anomalies.
+    pub fn window(&self) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+        match self {
+            Anomaly::HighFrequency {
+                window_start,
+                window_end,
+                ..
+            }
+            | Anomaly::Structuring {
+                window_start,
+                window_end,
+                ..
+            } => Some((*window_start, *window_end)),
+            _ => None,
+        }
+    }
+
+    /// What the anomaly is about: its kind and transaction, or its kind, sender
+    /// and token for anomalies about a sender's activity.
+    ///
+    /// Severity, statistics, reasons and windows are not part of it, so an
+    /// anomaly re-evaluated over more data keeps its identity. Two windowed
+    /// anomalies with the same identity are the same finding when their
+    /// windows overlap (see [`window`](Self::window)).
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// // The sender's median moved, but it is still the same large transaction.
+    /// assert_eq!(before.identity(), after.identity());
+    /// ```
+    pub fn identity(&self) -> String {
+        match self {
+            Anomaly::LargeTx { tx_hash, token, .. } => {
+                format!("LargeTx {} {}", tx_hash, token.as_deref().unwrap_or("ETH"))
+            }
+            Anomaly::HighFrequency { sender, .. } => format!("HighFrequency {}", sender),
+            Anomaly::BurstActivity { sender, .. } => format!("BurstActivity {}", sender),
+            Anomaly::Structuring { sender, token, .. } => format!(
+                "Structuring {} {}",
+                sender,
+                token.as_deref().unwrap_or("ETH")
+            ),
+            Anomaly::HighFee { tx_hash, .. } => format!("HighFee {}", tx_hash),
+            Anomaly::HighPriorityFee { tx_hash, .. } => format!("HighPriorityFee {}", tx_hash),
+            Anomaly::BlacklistedAddress {
+                tx_hash, addres, ..
+            } => format!("BlacklistedAddress {} {}", tx_hash, addres),
+            Anomaly::SanctionsListUnavailable { .. } => "SanctionsListUnavailable".to_string(),
+            Anomaly::UnusualOp { tx_hash, .. } => format!("UnusualOp {}", tx_hash),
+            Anomaly::TimeAnomaly { tx_hash, .. } => format!("TimeAnomaly {}", tx_hash),
+        }
+    }
+}
  
 /// Represents detected business patterns in blockchain transactions.
 ///
@@ -329,6 +859,7 @@ pub enum Anomaly {
 /// * `Whales` – Large holders performing significant transactions.
 ///   Fields:
 ///   - `sender`: The whale account address.
+///   - `token`: Token contract the account moves large amounts of, `None` for ETH.
 ///
 /// * `ActiveTraders` – Accounts identified as frequent or active traders.
 ///   Fields:
@@ -377,6 +908,7 @@ pub enum BusinessPattern {
     LiquidityProvider,
     Whales {
         sender: String,
+        token: Option<String>,
     },
     ActiveTraders {
         sender: String,
@@ -388,6 +920,48 @@ pub enum BusinessPattern {
     },
 }
 
+impl BusinessPattern {
+    /// Hash of the transaction the pattern was detected in, if it is about a
+    /// single transaction.
+    pub fn tx_hash(&self) -> Option<&str> {
+        match self {
+            BusinessPattern::NFTActivity { tx_hash, .. } => Some(tx_hash),
+            _ => None,
+        }
+    }
+
+    /// Account the pattern is about, if it is about a sender's activity.
+    pub fn sender(&self) -> Option<&str> {
+        match self {
+            BusinessPattern::RegularPayments { sender, .. }
+            | BusinessPattern::BatchPayments { sender, .. }
+            | BusinessPattern::Whales { sender, .. }
+            | BusinessPattern::ActiveTraders { sender, .. }
+            | BusinessPattern::Arbitrage { sender, .. } => Some(sender),
+            _ => None,
+        }
+    }
+
+    /// What the pattern is about: its kind and sender, transaction or DEX,
+    /// without counts or messages (see [`Anomaly::identity`]).
+    pub fn identity(&self) -> String {
+        match self {
+            BusinessPattern::RegularPayments { sender, .. } => {
+                format!("RegularPayments {}", sender)
+            }
+            BusinessPattern::BatchPayments { sender, .. } => format!("BatchPayments {}", sender),
+            BusinessPattern::DEXTrade { dex, .. } => format!("DEXTrade {}", dex),
+            BusinessPattern::NFTActivity { tx_hash, .. } => format!("NFTActivity {}", tx_hash),
+            BusinessPattern::LiquidityProvider => "LiquidityProvider".to_string(),
+            BusinessPattern::Whales { sender, token } => {
+                format!("Whales {} {}", sender, token.as_deref().unwrap_or("ETH"))
+            }
+            BusinessPattern::ActiveTraders { sender, .. } => format!("ActiveTraders {}", sender),
+            BusinessPattern::Arbitrage { sender, .. } => format!("Arbitrage {}", sender),
+        }
+    }
+}
+
 
 /// A CSV-friendly representation of an [`Anomaly`] for exporting or reporting.
 ///
@@ -401,11 +975,22 @@ pub enum BusinessPattern {
 /// * `sender` – Optional account address responsible for the anomaly.
 /// * `addres` – Optional address involved in the anomaly (e.g., blacklisted address).
 /// * `count` – Optional number of transactions (used for frequency or structuring anomalies).
-/// * `fee_eth` – Optional transaction fee in ETH (used for `HighFee` anomalies).
+/// * `fee_eth` – Optional exact transaction fee as a decimal ETH string (used for `HighFee` anomalies).
+/// * `token` – Optional token contract for anomalies found in ERC-20 transfers.
 /// * `severity` – Optional severity level as a string ("Strong" or "Weak").
 /// * `reasons` – Optional human-readable explanation(s) of why the anomaly was flagged,
 ///   concatenated into a single string separated by `; `.
 /// * `timestamp` – Optional timestamp of the transaction, if applicable.
+/// * `window_start`, `window_end` – Optional window of `HighFrequency` and `Structuring` anomalies.
+/// * `median_eth` – Optional sender median in decimal ETH, for native `LargeTx` and
+///   `HighFee` anomalies.
+/// * `median_token_units` – Optional sender median in raw token units, for token `LargeTx`
+///   anomalies.
+/// * `z_score` – Optional robust z-score against the sender median.
+/// * `priority_fee_gwei` – Optional exact priority fee per gas in decimal Gwei, for
+///   `HighPriorityFee` anomalies.
+/// * `base_fee_gwei` – Optional base fee per gas of the including block in decimal
+///   Gwei, for `HighPriorityFee` anomalies.
 ///
 /// # Conversion from [`Anomaly`]
 ///
@@ -417,7 +1002,10 @@ pub enum BusinessPattern {
 /// ```rust,ignore
 /// let anomaly = Anomaly::LargeTx {
 ///     tx_hash: "0x123abc...".to_string(),
+///     token: None,
 ///     severity: Severity::Strong,
+///     median: Some(U256::exp18()),
+///     z_score: Some(12.5),
 ///     reasons: vec!["Exceeded global threshold".to_string()],
 ///     timestamp: Utc::now(),
 /// };
@@ -436,10 +1024,18 @@ pub struct AnomalyCsv {
     pub sender: Option<String>,
     pub addres: Option<String>,
     pub count: Option<usize>,
-    pub fee_eth: Option<f64>,
+    pub fee_eth: Option<String>,
+    pub token: Option<String>,
     pub severity: Option<String>,
     pub reasons: Option<String>,
     pub timestamp: Option<DateTime<Utc>>,
+    pub window_start: Option<DateTime<Utc>>,
+    pub window_end: Option<DateTime<Utc>>,
+    pub median_eth: Option<String>,
+    pub median_token_units: Option<String>,
+    pub z_score: Option<f64>,
+    pub priority_fee_gwei: Option<String>,
+    pub base_fee_gwei: Option<String>,
 }
 
 impl From<&Anomaly> for AnomalyCsv {
@@ -447,7 +1043,10 @@ impl From<&Anomaly> for AnomalyCsv {
         match a {
             Anomaly::LargeTx {
                 tx_hash,
+                token,
                 severity,
+                median,
+                z_score,
                 reasons,
                 timestamp,
             } => Self {
@@ -457,13 +1056,23 @@ impl From<&Anomaly> for AnomalyCsv {
                 addres: None,
                 count: None,
                 fee_eth: None,
+                token: token.clone(),
                 severity: Some(format!("{:?}", severity)),
                 reasons: Some(reasons.join("; ")),
-                timestamp: Some(timestamp.clone()),
+                timestamp: Some(*timestamp),
+                window_start: None,
+                window_end: None,
+                median_eth: median.filter(|_| token.is_none()).map(format_eth),
+                median_token_units: median.filter(|_| token.is_some()).map(|m| m.to_string()),
+                z_score: *z_score,
+                priority_fee_gwei: None,
+                base_fee_gwei: None,
             },
             Anomaly::HighFrequency {
                 sender,
                 count,
+                window_start,
+                window_end,
                 reasons,
             } => Self {
                 type_name: "HighFrequency".into(),
@@ -472,9 +1081,17 @@ impl From<&Anomaly> for AnomalyCsv {
                 addres: None,
                 count: Some(*count),
                 fee_eth: None,
+                token: None,
                 severity: None,
                 reasons: Some(reasons.join("; ")),
                 timestamp: None,
+                window_start: Some(*window_start),
+                window_end: Some(*window_end),
+                median_eth: None,
+                median_token_units: None,
+                z_score: None,
+                priority_fee_gwei: None,
+                base_fee_gwei: None,
             },
             Anomaly::BurstActivity { sender, reasons } => Self {
                 type_name: "BurstActivity".into(),
@@ -483,14 +1100,25 @@ impl From<&Anomaly> for AnomalyCsv {
                 addres: None,
                 count: None,
                 fee_eth: None,
+                token: None,
                 severity: None,
                 reasons: Some(reasons.join("; ")),
                 timestamp: None,
+                window_start: None,
+                window_end: None,
+                median_eth: None,
+                median_token_units: None,
+                z_score: None,
+                priority_fee_gwei: None,
+                base_fee_gwei: None,
             },
             Anomaly::Structuring {
                 sender,
+                token,
                 count,
                 severity,
+                window_start,
+                window_end,
                 reasons,
             } => Self {
                 type_name: "Structuring".into(),
@@ -499,14 +1127,24 @@ impl From<&Anomaly> for AnomalyCsv {
                 addres: None,
                 count: Some(*count),
                 fee_eth: None,
+                token: token.clone(),
                 severity: Some(format!("{:?}", severity)),
                 reasons: Some(reasons.join("; ")),
                 timestamp: None,
+                window_start: Some(*window_start),
+                window_end: Some(*window_end),
+                median_eth: None,
+                median_token_units: None,
+                z_score: None,
+                priority_fee_gwei: None,
+                base_fee_gwei: None,
             },
             Anomaly::HighFee {
                 tx_hash,
-                fee_eth,
+                fee,
                 severity,
+                median,
+                z_score,
                 reasons,
                 timestamp,
             } => Self {
@@ -515,10 +1153,44 @@ impl From<&Anomaly> for AnomalyCsv {
                 sender: None,
                 addres: None,
                 count: None,
-                fee_eth: Some(*fee_eth),
+                fee_eth: Some(format_eth(*fee)),
+                token: None,
                 severity: Some(format!("{:?}", severity)),
                 reasons: Some(reasons.join("; ")),
-                timestamp: Some(timestamp.clone()),
+                timestamp: Some(*timestamp),
+                window_start: None,
+                window_end: None,
+                median_eth: median.map(format_eth),
+                median_token_units: None,
+                z_score: *z_score,
+                priority_fee_gwei: None,
+                base_fee_gwei: None,
+            },
+            Anomaly::HighPriorityFee {
+                tx_hash,
+                priority_fee_per_gas,
+                base_fee_per_gas,
+                severity,
+                reasons,
+                timestamp,
+            } => Self {
+                type_name: "HighPriorityFee".into(),
+                tx_hash: Some(tx_hash.clone()),
+                sender: None,
+                addres: None,
+                count: None,
+                fee_eth: None,
+                token: None,
+                severity: Some(format!("{:?}", severity)),
+                reasons: Some(reasons.join("; ")),
+                timestamp: Some(*timestamp),
+                window_start: None,
+                window_end: None,
+                median_eth: None,
+                median_token_units: None,
+                z_score: None,
+                priority_fee_gwei: Some(format_gwei(*priority_fee_per_gas)),
+                base_fee_gwei: Some(format_gwei(*base_fee_per_gas)),
             },
             Anomaly::BlacklistedAddress {
                 tx_hash,
@@ -532,9 +1204,36 @@ impl From<&Anomaly> for AnomalyCsv {
                 addres: Some(addres.clone()),
                 count: None,
                 fee_eth: None,
+                token: None,
+                severity: None,
+                reasons: Some(reasons.join("; ")),
+                timestamp: Some(*timestamp),
+                window_start: None,
+                window_end: None,
+                median_eth: None,
+                median_token_units: None,
+                z_score: None,
+                priority_fee_gwei: None,
+                base_fee_gwei: None,
+            },
+            Anomaly::SanctionsListUnavailable { reasons } => Self {
+                type_name: "SanctionsListUnavailable".into(),
+                tx_hash: None,
+                sender: None,
+                addres: None,
+                count: None,
+                fee_eth: None,
+                token: None,
                 severity: None,
                 reasons: Some(reasons.join("; ")),
-                timestamp: Some(timestamp.clone()),
+                timestamp: None,
+                window_start: None,
+                window_end: None,
+                median_eth: None,
+                median_token_units: None,
+                z_score: None,
+                priority_fee_gwei: None,
+                base_fee_gwei: None,
             },
             Anomaly::UnusualOp {
                 tx_hash,
@@ -548,9 +1247,17 @@ impl From<&Anomaly> for AnomalyCsv {
                 addres: None,
                 count: None,
                 fee_eth: None,
+                token: None,
                 severity: Some(format!("{:?}", severity)),
                 reasons: Some(reasons.join("; ")),
-                timestamp: Some(timestamp.clone()),
+                timestamp: Some(*timestamp),
+                window_start: None,
+                window_end: None,
+                median_eth: None,
+                median_token_units: None,
+                z_score: None,
+                priority_fee_gwei: None,
+                base_fee_gwei: None,
             },
             Anomaly::TimeAnomaly {
                 tx_hash,
@@ -564,9 +1271,17 @@ impl From<&Anomaly> for AnomalyCsv {
                 addres: None,
                 count: None,
                 fee_eth: None,
+                token: None,
                 severity: Some(format!("{:?}", severity)),
                 reasons: Some(reasons.join("; ")),
-                timestamp: Some(timestamp.clone()),
+                timestamp: Some(*timestamp),
+                window_start: None,
+                window_end: None,
+                median_eth: None,
+                median_token_units: None,
+                z_score: None,
+                priority_fee_gwei: None,
+                base_fee_gwei: None,
             },
         }
     }
@@ -584,6 +1299,7 @@ impl From<&Anomaly> for AnomalyCsv {
 /// * `sender` – Optional account address responsible for the pattern, if applicable.
 /// * `tx_hash` – Optional transaction hash associated with the pattern (e.g., NFT activity).
 /// * `count` – Optional number of transactions, used for batch payments or other multi-tx patterns.
+/// * `token` – Optional token contract, used for whales found in ERC-20 transfers.
 /// * `message` – Optional human-readable description of the detected pattern.
 ///
 /// # Conversion from [`BusinessPattern`]
@@ -614,6 +1330,7 @@ pub struct BusinessPatternCsv {
     pub sender: Option<String>,
     pub tx_hash: Option<String>,
     pub count: Option<usize>,
+    pub token: Option<String>,
     pub message: Option<String>,
 }
 
@@ -625,6 +1342,7 @@ impl From<&BusinessPattern> for BusinessPatternCsv {
                 sender: Some(sender.clone()),
                 tx_hash: None,
                 count: None,
+                token: None,
                 message: Some(message.clone()),
             },
             BusinessPattern::BatchPayments {
@@ -636,6 +1354,7 @@ impl From<&BusinessPattern> for BusinessPatternCsv {
                 sender: Some(sender.clone()),
                 tx_hash: None,
                 count: Some(*count),
+                token: None,
                 message: Some(message.clone()),
             },
             BusinessPattern::DEXTrade { dex: _, message } => Self {
@@ -643,6 +1362,7 @@ impl From<&BusinessPattern> for BusinessPatternCsv {
                 sender: None,
                 tx_hash: None,
                 count: None,
+                token: None,
                 message: Some(message.clone()),
             },
             BusinessPattern::NFTActivity { tx_hash, message } => Self {
@@ -650,6 +1370,7 @@ impl From<&BusinessPattern> for BusinessPatternCsv {
                 sender: None,
                 tx_hash: Some(tx_hash.clone()),
                 count: None,
+                token: None,
                 message: Some(message.clone()),
             },
             BusinessPattern::LiquidityProvider => Self {
@@ -657,13 +1378,15 @@ impl From<&BusinessPattern> for BusinessPatternCsv {
                 sender: None,
                 tx_hash: None,
                 count: None,
+                token: None,
                 message: None,
             },
-            BusinessPattern::Whales { sender } => Self {
+            BusinessPattern::Whales { sender, token } => Self {
                 type_name: "Whales".into(),
                 sender: Some(sender.clone()),
                 tx_hash: None,
                 count: None,
+                token: token.clone(),
                 message: None,
             },
             BusinessPattern::ActiveTraders { sender, message } => Self {
@@ -671,6 +1394,7 @@ impl From<&BusinessPattern> for BusinessPatternCsv {
                 sender: Some(sender.clone()),
                 tx_hash: None,
                 count: None,
+                token: None,
                 message: Some(message.clone()),
             },
             BusinessPattern::Arbitrage { sender, message } => Self {
@@ -678,6 +1402,7 @@ impl From<&BusinessPattern> for BusinessPatternCsv {
                 sender: Some(sender.clone()),
                 tx_hash: None,
                 count: None,
+                token: None,
                 message: Some(message.clone()),
             },
         }
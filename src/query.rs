@@ -0,0 +1,152 @@
+use crate::models::{TransferRef, TxRef, TxStorage};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use ethers::types::{Address, H256};
+use std::ops::RangeInclusive;
+
+/// Read-only access to the transactions under analysis.
+///
+/// Detectors only see the data through this trait (via
+/// [`crate::detector::AnalysisContext::storage`]), so an analysis can run over
+/// the in-memory [`TxStorage`], a storage loaded from a persisted
+/// [`crate::store::TxStore`] or any other source, such as a fixture in a test,
+/// without changing detector code.
+///
+/// Every method returns shared handles ([`TxRef`], [`TransferRef`]) to the
+/// records, so results are cheap to build and hold no lock of the source.
+///
+/// # Notes
+/// * Only [`all_txs`](Self::all_txs), [`senders`](Self::senders),
+///   [`sent_by`](Self::sent_by), [`received_by`](Self::received_by),
+///   [`get`](Self::get), [`token_transfers`](Self::token_transfers) and
+///   [`transfers_sent_by`](Self::transfers_sent_by) must be implemented; the
+///   other methods have default implementations built on them that a source
+///   with better indexes can override.
+/// * Transactions are returned in block order unless stated otherwise: by
+///   ascending block number, and within a block in the order they were added,
+///   which is the block's own order for scanned and imported blocks.
+///
+/// # Example
+/// ```rust,ignore
+/// let txs = ctx.storage.sent_by(&sender).await;
+/// let last_hour = ctx.storage.between(now - Duration::hours(1), now).await;
+/// ```
+#[async_trait]
+pub trait TxQuery: Send + Sync {
+    /// Every transaction.
+    async fn all_txs(&self) -> Vec<TxRef>;
+
+    /// Every account that sent at least one transaction, in no particular order.
+    async fn senders(&self) -> Vec<Address>;
+
+    /// Transactions sent by `sender`.
+    async fn sent_by(&self, sender: &Address) -> Vec<TxRef>;
+
+    /// Transactions received by `receiver`.
+    async fn received_by(&self, receiver: &Address) -> Vec<TxRef>;
+
+    /// The transaction with this hash, if present.
+    async fn get(&self, hash: &H256) -> Option<TxRef>;
+
+    /// Every decoded ERC-20 transfer.
+    async fn token_transfers(&self) -> Vec<TransferRef>;
+
+    /// Token transfers whose `from` is `sender`.
+    async fn transfers_sent_by(&self, sender: &Address) -> Vec<TransferRef>;
+
+    /// Transactions grouped by sender, in no particular order of senders.
+    async fn txs_by_sender(&self) -> Vec<(Address, Vec<TxRef>)> {
+        let mut groups = Vec::new();
+        for sender in self.senders().await {
+            let txs = self.sent_by(&sender).await;
+            groups.push((sender, txs));
+        }
+        groups
+    }
+
+    /// Transactions included in the blocks of `blocks`.
+    async fn in_blocks(&self, blocks: RangeInclusive<u64>) -> Vec<TxRef> {
+        self.all_txs()
+            .await
+            .into_iter()
+            .filter(|tx| blocks.contains(&tx.block_number))
+            .collect()
+    }
+
+    /// Transactions with a timestamp between `start` and `end`, both inclusive.
+    async fn between(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> Vec<TxRef> {
+        self.all_txs()
+            .await
+            .into_iter()
+            .filter(|tx| start <= tx.timestamp && tx.timestamp <= end)
+            .collect()
+    }
+}
+
+/// Sorts `txs` into block order. Concurrent scans add whole blocks in the order
+/// they complete, so the lists of [`TxStorage`] are block-ordered runs; a stable
+/// sort merges them in linear time for already sorted lists.
+fn block_order(mut txs: Vec<TxRef>) -> Vec<TxRef> {
+    txs.sort_by_key(|tx| tx.block_number);
+    txs
+}
+
+#[async_trait]
+impl TxQuery for TxStorage {
+    async fn all_txs(&self) -> Vec<TxRef> {
+        block_order(self.all_txs.read().await.clone())
+    }
+
+    async fn senders(&self) -> Vec<Address> {
+        self.by_sender.iter().map(|entry| *entry.key()).collect()
+    }
+
+    async fn sent_by(&self, sender: &Address) -> Vec<TxRef> {
+        block_order(
+            self.by_sender
+                .get(sender)
+                .map(|txs| txs.clone())
+                .unwrap_or_default(),
+        )
+    }
+
+    async fn received_by(&self, receiver: &Address) -> Vec<TxRef> {
+        block_order(
+            self.by_reciever
+                .get(receiver)
+                .map(|txs| txs.clone())
+                .unwrap_or_default(),
+        )
+    }
+
+    async fn get(&self, hash: &H256) -> Option<TxRef> {
+        TxStorage::get(self, hash)
+    }
+
+    async fn token_transfers(&self) -> Vec<TransferRef> {
+        self.token_transfers.read().await.clone()
+    }
+
+    async fn transfers_sent_by(&self, sender: &Address) -> Vec<TransferRef> {
+        self.transfers_by_sender
+            .get(sender)
+            .map(|transfers| transfers.clone())
+            .unwrap_or_default()
+    }
+
+    async fn txs_by_sender(&self) -> Vec<(Address, Vec<TxRef>)> {
+        self.by_sender
+            .iter()
+            .map(|entry| (*entry.key(), block_order(entry.value().clone())))
+            .collect()
+    }
+
+    async fn in_blocks(&self, blocks: RangeInclusive<u64>) -> Vec<TxRef> {
+        self.by_block
+            .read()
+            .await
+            .range(blocks)
+            .flat_map(|(_, txs)| txs.iter().cloned())
+            .collect()
+    }
+}
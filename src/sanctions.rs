@@ -0,0 +1,367 @@
+use crate::address::parse_address;
+use crate::config::{SanctionsConfig, SanctionsSourceConfig};
+use async_trait::async_trait;
+use ethers::{
+    types::Address,
+    utils::{hex, keccak256},
+};
+use log::{info, warn};
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// OFAC-sanctioned Ethereum addresses as published by 0xB10C, the default
+/// sanctions source.
+pub const OFAC_ETH_LIST_URL: &str = "https://raw.githubusercontent.com/0xB10C/ofac-sanctioned-digital-currency-addresses/lists/sanctioned_addresses_ETH.json";
+
+/// Errors returned while loading a sanctions list. `list` names the file or URL.
+#[derive(Debug)]
+pub enum SanctionsError {
+    /// The file could not be read.
+    Io { list: String, error: io::Error },
+    /// The download failed and no cached copy exists.
+    Http { list: String, error: reqwest::Error },
+    /// The server answered with an error status and no cached copy exists.
+    Status {
+        list: String,
+        status: reqwest::StatusCode,
+    },
+    /// The content is not a list of addresses. `line` is 1-based, 0 for JSON.
+    Parse {
+        list: String,
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for SanctionsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SanctionsError::Io { list, error } => {
+                write!(f, "cannot read sanctions list {}: {}", list, error)
+            }
+            SanctionsError::Http { list, error } => {
+                write!(f, "cannot download sanctions list {}: {}", list, error)
+            }
+            SanctionsError::Status { list, status } => {
+                write!(
+                    f,
+                    "cannot download sanctions list {}: HTTP {}",
+                    list, status
+                )
+            }
+            SanctionsError::Parse {
+                list,
+                line: 0,
+                message,
+            } => write!(f, "invalid sanctions list {}: {}", list, message),
+            SanctionsError::Parse {
+                list,
+                line,
+                message,
+            } => write!(
+                f,
+                "invalid sanctions list {} at line {}: {}",
+                list, line, message
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SanctionsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SanctionsError::Io { error, .. } => Some(error),
+            SanctionsError::Http { error, .. } => Some(error),
+            SanctionsError::Status { .. } | SanctionsError::Parse { .. } => None,
+        }
+    }
+}
+
+/// Sanctioned addresses merged from one or more sources.
+///
+/// # Example
+/// ```rust,ignore
+/// let list = load_sanctions(&sources_from_config(&config.sanctions)).await?;
+/// if list.contains(&tx.from) {
+///     println!("{:?} is sanctioned", tx.from);
+/// }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SanctionsList {
+    addresses: HashSet<Address>,
+    sources: Vec<String>,
+}
+
+impl SanctionsList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the addresses of the source `name`.
+    pub fn extend(&mut self, name: String, addresses: HashSet<Address>) {
+        self.addresses.extend(addresses);
+        self.sources.push(name);
+    }
+
+    pub fn contains(&self, address: &Address) -> bool {
+        self.addresses.contains(address)
+    }
+
+    pub fn len(&self) -> usize {
+        self.addresses.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.addresses.is_empty()
+    }
+
+    /// Names of the merged sources, in loading order.
+    pub fn sources(&self) -> &[String] {
+        &self.sources
+    }
+}
+
+impl FromIterator<Address> for SanctionsList {
+    fn from_iter<I: IntoIterator<Item = Address>>(iter: I) -> Self {
+        Self {
+            addresses: iter.into_iter().collect(),
+            sources: Vec::new(),
+        }
+    }
+}
+
+/// A place a sanctions list is loaded from.
+///
+/// Implemented by [`FileSource`] and [`UrlSource`]; implement it to read lists
+/// from anywhere else, e.g. an internal API, and pass it to [`load_sanctions`].
+///
+/// # Required methods
+/// * `name` – The path, URL or other name used in logs and errors.
+/// * `load` – Reads the list. Must fail rather than return an empty set when
+///   the list cannot be read, so a missing list is never mistaken for a clean one.
+#[async_trait]
+pub trait SanctionsSource: Send + Sync {
+    fn name(&self) -> String;
+
+    async fn load(&self) -> Result<HashSet<Address>, SanctionsError>;
+}
+
+/// A sanctions list on the local disk.
+pub struct FileSource {
+    pub path: PathBuf,
+}
+
+#[async_trait]
+impl SanctionsSource for FileSource {
+    fn name(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    async fn load(&self) -> Result<HashSet<Address>, SanctionsError> {
+        let content = fs::read_to_string(&self.path).map_err(|error| SanctionsError::Io {
+            list: self.name(),
+            error,
+        })?;
+        parse_addresses(&self.name(), &content)
+    }
+}
+
+/// A sanctions list downloaded over HTTP(S) and cached on disk.
+///
+/// # Overview
+/// A cached copy younger than `ttl` is used without any request. Otherwise the
+/// list is downloaded, checked and cached again. When the download fails, an
+/// older cached copy is used with a warning; without one, loading fails.
+pub struct UrlSource {
+    pub url: String,
+    pub cache_dir: PathBuf,
+    pub ttl: Duration,
+}
+
+impl UrlSource {
+    /// File of `cache_dir` holding the last download of this URL.
+    pub fn cache_path(&self) -> PathBuf {
+        let digest = keccak256(self.url.as_bytes());
+        self.cache_dir
+            .join(format!("{}.list", hex::encode(&digest[..8])))
+    }
+
+    fn read_cache(&self, path: &Path) -> Option<HashSet<Address>> {
+        let content = fs::read_to_string(path).ok()?;
+        parse_addresses(&self.url, &content).ok()
+    }
+
+    async fn download(&self) -> Result<String, SanctionsError> {
+        let http = |error| SanctionsError::Http {
+            list: self.url.clone(),
+            error,
+        };
+        let response = reqwest::get(&self.url).await.map_err(http)?;
+        if !response.status().is_success() {
+            return Err(SanctionsError::Status {
+                list: self.url.clone(),
+                status: response.status(),
+            });
+        }
+        response.text().await.map_err(http)
+    }
+}
+
+#[async_trait]
+impl SanctionsSource for UrlSource {
+    fn name(&self) -> String {
+        self.url.clone()
+    }
+
+    async fn load(&self) -> Result<HashSet<Address>, SanctionsError> {
+        let cache = self.cache_path();
+        let age = fs::metadata(&cache)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok());
+        if age.is_some_and(|age| age < self.ttl) {
+            if let Some(addresses) = self.read_cache(&cache) {
+                return Ok(addresses);
+            }
+        }
+
+        let downloaded = self.download().await.and_then(|content| {
+            parse_addresses(&self.url, &content).map(|addresses| (addresses, content))
+        });
+        match downloaded {
+            Ok((addresses, content)) => {
+                if let Err(e) =
+                    fs::create_dir_all(&self.cache_dir).and_then(|_| fs::write(&cache, content))
+                {
+                    warn!("Cannot cache sanctions list {}: {}", self.url, e);
+                }
+                Ok(addresses)
+            }
+            Err(e) => match self.read_cache(&cache) {
+                Some(addresses) => {
+                    warn!("{}; using the cached copy {}", e, cache.display());
+                    Ok(addresses)
+                }
+                None => Err(e),
+            },
+        }
+    }
+}
+
+/// Builds the sources listed in the `[sanctions]` configuration section.
+pub fn sources_from_config(config: &SanctionsConfig) -> Vec<Box<dyn SanctionsSource>> {
+    let cache_dir = config
+        .cache_dir
+        .clone()
+        .unwrap_or_else(|| std::env::temp_dir().join("eth_analyzer").join("sanctions"));
+    config
+        .sources
+        .iter()
+        .map(|source| -> Box<dyn SanctionsSource> {
+            match source {
+                SanctionsSourceConfig::File(path) => Box::new(FileSource { path: path.clone() }),
+                SanctionsSourceConfig::Url(url) => Box::new(UrlSource {
+                    url: url.clone(),
+                    cache_dir: cache_dir.clone(),
+                    ttl: Duration::from_secs(config.cache_ttl_hours * 3600),
+                }),
+            }
+        })
+        .collect()
+}
+
+/// Loads every source and merges them into one list.
+///
+/// # Errors
+/// Fails with the error of the first source that cannot be loaded: a partial
+/// list would silently miss the addresses of the failed source.
+pub async fn load_sanctions(
+    sources: &[Box<dyn SanctionsSource>],
+) -> Result<SanctionsList, SanctionsError> {
+    let mut list = SanctionsList::new();
+    for source in sources {
+        let addresses = source.load().await?;
+        info!(
+            "Loaded {} sanctioned addresses from {}",
+            addresses.len(),
+            source.name()
+        );
+        list.extend(source.name(), addresses);
+    }
+    Ok(list)
+}
+
+/// Parses a sanctions list.
+///
+/// # Formats
+/// * JSON – An array of addresses, or of objects with an `address` field (the
+///   format of the default OFAC list).
+/// * CSV – A header line with an `address` column, then one row per address.
+/// * Text – One address per line.
+///
+/// Empty lines and lines starting with `#` are skipped. Addresses are matched
+/// case-insensitively, with or without their EIP-55 checksum.
+///
+/// # Errors
+/// Any entry that is not an address fails the whole list, with its line.
+pub fn parse_addresses(list: &str, content: &str) -> Result<HashSet<Address>, SanctionsError> {
+    let parse_error = |line, message: String| SanctionsError::Parse {
+        list: list.to_string(),
+        line,
+        message,
+    };
+    let parse = |line, value: &str| {
+        parse_address(value.trim().trim_matches('"')).map_err(|e| parse_error(line, e.to_string()))
+    };
+
+    if content.trim_start().starts_with('[') {
+        let entries: Vec<serde_json::Value> =
+            serde_json::from_str(content).map_err(|e| parse_error(0, e.to_string()))?;
+        return entries
+            .iter()
+            .map(|entry| {
+                let value = match entry {
+                    serde_json::Value::Object(object) => object.get("address"),
+                    value => Some(value),
+                };
+                match value.and_then(|value| value.as_str()) {
+                    Some(value) => parse(0, value),
+                    None => Err(parse_error(0, format!("`{}` is not an address", entry))),
+                }
+            })
+            .collect();
+    }
+
+    let mut column = None;
+    let mut addresses = HashSet::new();
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').collect();
+        let Some(column) = column else {
+            let header = fields.iter().position(|field| {
+                field
+                    .trim()
+                    .trim_matches('"')
+                    .eq_ignore_ascii_case("address")
+            });
+            column = Some(header.unwrap_or(0));
+            if header.is_some() {
+                continue;
+            }
+            addresses.insert(parse(index + 1, fields[0])?);
+            continue;
+        };
+        let field = fields
+            .get(column)
+            .ok_or_else(|| parse_error(index + 1, format!("missing column {}", column + 1)))?;
+        addresses.insert(parse(index + 1, field)?);
+    }
+    Ok(addresses)
+}
@@ -1,12 +1,194 @@
-use crate::models::{SharedTxStorage, TransactionRecord};
+use crate::models::{BlockHeader, SharedTxStorage, TokenTransfer, TransactionRecord, TxRef};
 use chrono::{DateTime, Utc};
-use ethers::{providers::Middleware, utils::hex};
-use futures::stream::{FuturesUnordered, StreamExt};
-use log::info;
-use reqwest;
-use serde::Deserialize;
-use std::collections::HashSet;
+use ethers::{
+    providers::{Middleware, MiddlewareError, ProviderError},
+    types::{Address, Block, Filter, Log, Transaction, TransactionReceipt, H256, U256},
+    utils::{hex, keccak256},
+};
+use futures::{stream, StreamExt, TryStreamExt};
+use log::{info, warn};
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::{
+    sync::Mutex,
+    time::{sleep, sleep_until, Instant},
+};
+
+/// Options controlling what [`scan_block`] fetches for every block and how it
+/// talks to the node.
+///
+/// # Fields
+/// * `fetch_receipts` – Also fetch the receipt of every transaction to fill in
+///   `gas_used`, `effective_gas_price`, `status` and `contract_address`.
+///   Receipts are requested once per block with `eth_getBlockReceipts`; nodes
+///   without that method are queried per transaction instead. Off by default
+///   because it roughly doubles the number of RPC calls.
+/// * `fetch_token_transfers` – Also fetch the logs of every block and decode
+///   ERC-20 `Transfer` events into [`TokenTransfer`]s. Costs one `eth_getLogs`
+///   call per block.
+/// * `max_in_flight` – Maximum number of blocks fetched concurrently (default 8).
+/// * `max_retries` – How many times a block is retried after a transient error
+///   before it is reported as failed (default 3).
+/// * `initial_backoff` – Delay before the first retry; it doubles with every
+///   further attempt (default 500 ms).
+/// * `max_backoff` – Upper bound of the retry delay (default 10 s).
+/// * `requests_per_second` – Optional cap on RPC requests per second, shared by
+///   all blocks of the scan. `None` (the default) disables rate limiting.
+/// * `reorg_depth` – How many of the most recent stored blocks
+///   [`reconcile_reorg`] compares with the node (default 64). `0` disables
+///   reorg detection.
+///
+/// # Example
+/// ```rust,ignore
+/// let options = ScanOptions {
+///     fetch_receipts: true,
+///     max_in_flight: 4,
+///     requests_per_second: Some(25),
+///     ..ScanOptions::default()
+/// };
+/// let report = scan_block(&provider, 1000, 1010, &storage, &options).await;
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScanOptions {
+    pub fetch_receipts: bool,
+    pub fetch_token_transfers: bool,
+    pub max_in_flight: usize,
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub requests_per_second: Option<u32>,
+    pub reorg_depth: u64,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            fetch_receipts: false,
+            fetch_token_transfers: false,
+            max_in_flight: 8,
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(10),
+            requests_per_second: None,
+            reorg_depth: 64,
+        }
+    }
+}
+
+/// Outcome of a [`scan_block`] run.
+///
+/// # Fields
+/// * `scanned_blocks` – Blocks fetched and stored successfully.
+/// * `retries` – Total number of retried block fetches.
+/// * `failed` – Blocks that could not be fetched, in ascending order. Their
+///   transactions are missing from the storage.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ScanReport {
+    pub scanned_blocks: usize,
+    pub retries: usize,
+    pub failed: Vec<FailedBlock>,
+}
+
+impl ScanReport {
+    /// `true` if every block of the range was scanned.
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+/// A block that [`scan_block`] gave up on.
+///
+/// * `block_number` – Number of the block.
+/// * `attempts` – Number of fetch attempts made, including the first one.
+/// * `error` – Message of the last error.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FailedBlock {
+    pub block_number: u64,
+    pub attempts: u32,
+    pub error: String,
+}
+
+/// A block field that does not fit the record it is converted into, such as a
+/// gas limit above `u64::MAX` or a timestamp `DateTime` cannot represent.
+///
+/// [`scan_blocks`] reports the block as failed instead of retrying it, and
+/// [`crate::import`] reports it as an invalid field of the dump.
+///
+/// * `field` – Name of the field in the JSON-RPC block or receipt.
+/// * `value` – The value as the node or the dump returned it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidBlockField {
+    pub field: &'static str,
+    pub value: String,
+}
+
+impl fmt::Display for InvalidBlockField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid value `{}` for `{}`", self.value, self.field)
+    }
+}
+
+impl std::error::Error for InvalidBlockField {}
+
+/// Why [`fetch_block`] could not produce a block: the node request failed, or
+/// the node returned a block that cannot be converted.
+///
+/// `Provider` holds the errors of raw requests made through
+/// [`Middleware::provider`], which cannot be converted into the middleware's
+/// own error type (`MiddlewareError::from_provider_err` recurses forever on
+/// `ProviderError`).
+enum FetchError<E> {
+    Node(E),
+    Provider(ProviderError),
+    Invalid(InvalidBlockField),
+}
+
+impl<E: MiddlewareError> FetchError<E> {
+    /// Whether retrying the request may succeed, see [`is_transient`].
+    fn is_transient(&self) -> bool {
+        match self {
+            FetchError::Node(err) => is_transient(err),
+            FetchError::Provider(err) => is_transient(err),
+            FetchError::Invalid(_) => false,
+        }
+    }
+}
+
+impl<E> From<E> for FetchError<E> {
+    fn from(err: E) -> Self {
+        FetchError::Node(err)
+    }
+}
+
+impl<E: fmt::Display> fmt::Display for FetchError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FetchError::Node(err) => write!(f, "{}", err),
+            FetchError::Provider(err) => write!(f, "{}", err),
+            FetchError::Invalid(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+/// A chain reorganization undone by [`reconcile_reorg`].
+///
+/// * `fork_block` – First block that was no longer canonical. Every stored
+///   block from this one on was rolled back.
+/// * `orphaned` – Transactions removed from the storage. Some of them may be
+///   included again by the new branch.
+/// * `rescan` – Outcome of scanning the rolled back range again on the new branch.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reorg {
+    pub fork_block: u64,
+    pub orphaned: Vec<TxRef>,
+    pub rescan: ScanReport,
+}
+
+/// Signature of the ERC-20 (and ERC-721) `Transfer` event.
+pub const TRANSFER_EVENT: &str = "Transfer(address,address,uint256)";
 
 /// Scans a range of blockchain blocks and stores their transactions in `TxStorage`.
 ///
@@ -15,135 +197,737 @@ use std::sync::Arc;
 /// converts them into [`TransactionRecord`]s, and stores them in a shared
 /// [`TxStorage`] instance.
 ///
-/// Transactions are stored in three ways:
+/// Transactions are stored in four ways, through [`crate::models::TxStorage::insert`]:
 /// 1. Globally in `storage.all_txs` (protected by a `RwLock`).
 /// 2. Indexed by sender in `storage.by_sender` (`DashMap`).
 /// 3. Indexed by receiver in `storage.by_reciever` (`DashMap`), if a `to` address exists.
+/// 4. Indexed by hash in `storage.by_hash` (`DashMap`).
+///
+/// With [`ScanOptions::fetch_token_transfers`], decoded ERC-20 transfers are stored
+/// the same way in `storage.token_transfers`, `storage.transfers_by_sender` and
+/// `storage.transfers_by_receiver`.
 ///
-/// The function processes multiple blocks concurrently using `FuturesUnordered`
-/// for efficient asynchronous execution.
+/// At most `options.max_in_flight` blocks are fetched concurrently. A block whose
+/// fetch fails with a transient error (a transport error, a timeout or a rate
+/// limit response) is retried with exponential backoff up to `options.max_retries`
+/// times; errors the node will repeat, such as an unsupported method or a
+/// malformed response, fail the block immediately. Failed blocks do not abort
+/// the scan: they are listed in the returned [`ScanReport`].
 ///
 /// # Parameters
 /// * `provider` – A reference-counted Ethereum provider implementing [`Middleware`].
 /// * `start_block` – The starting block number (inclusive) to scan.
 /// * `end_block` – The ending block number (inclusive) to scan.
 /// * `storage` – Shared transaction storage (`SharedTxStorage`) for storing results.
+/// * `options` – What to fetch besides the block body and how to pace
+///   requests, see [`ScanOptions`].
 ///
 /// # Returns
-/// Returns `Result<(), M::Error>`:
-/// * `Ok(())` on success.
-/// * Propagates any errors from the provider.
+/// A [`ScanReport`] with the number of scanned blocks, the number of retries
+/// and the blocks that ultimately failed.
 ///
 /// # Notes
-/// * The block timestamp is converted to UTC and stored in RFC 3339 format in each `TransactionRecord`.
-/// * Gas prices and values are converted from Wei to ETH/Gwei using helper functions (`wei_to_eth`, `wei_to_gwei`).
-/// * This function clones the provider and storage references for each concurrent block scan.
-/// * Transaction hashes, addresses, and input data are converted to string representations.
-/// * The function uses `futures::stream::FuturesUnordered` to run multiple block fetches in parallel.
+/// * The block timestamp is converted to a UTC `DateTime` and stored in each `TransactionRecord`.
+/// * Values and gas prices are stored exactly in wei; see [`crate::units`] for lossless formatting.
+/// * The block's base fee and the EIP-1559 fee caps and transaction type are
+///   recorded for every transaction. When a node omits `gasPrice` for a dynamic
+///   fee transaction, it is derived from the caps and the base fee.
+/// * Without receipts, the receipt fields of each record are `None` and
+///   [`TransactionRecord::fee`] falls back to the gas limit.
+/// * A block is stored only once all of its requests succeeded, so a retried
+///   block is never stored twice or half.
+/// * Insertion is idempotent: scanning overlapping ranges into the same storage
+///   skips transactions and token transfers that are already stored.
+/// * Blocks the node does not know yet (`null` response) are skipped.
+/// * The hash and parent hash of every block are kept in `storage.blocks`, so
+///   a later [`reconcile_reorg`] can detect chain reorganizations.
+/// * Transaction hashes and addresses are stored as `H256`/`Address`; input data as a hex string.
 ///
 /// # Example
 ///
 /// ```rust,ignore
 /// let provider = Arc::new(your_provider);
 /// let storage = Arc::new(TxStorage::new());
-/// scan_block(&provider, 1000, 1010, &storage).await.unwrap();
+/// let report = scan_block(&provider, 1000, 1010, &storage, &ScanOptions::default()).await;
+/// for failed in &report.failed {
+///     eprintln!("block {} failed: {}", failed.block_number, failed.error);
+/// }
 /// ```
 ///
-/// # Panics
-/// * Panics if the block timestamp cannot be converted to a valid `DateTime<Utc>`.
+/// # Errors
+/// The scan itself does not fail. A block whose timestamp or gas values are out
+/// of range is listed in [`ScanReport::failed`] with an [`InvalidBlockField`]
+/// error, like a block the node could not deliver.
 pub async fn scan_block<M>(
     provider: &Arc<M>,
     start_block: u64,
     end_block: u64,
     storage: &SharedTxStorage,
-) -> Result<(), M::Error>
+    options: &ScanOptions,
+) -> ScanReport
 where
     M: Middleware + 'static,
 {
-    let mut futures = FuturesUnordered::new();
+    scan_blocks(provider, start_block..=end_block, storage, options, |_| {
+        Ok(())
+    })
+    .await
+}
 
-    for block_number in start_block..=end_block {
-        let provider = Arc::clone(provider);
-        let storage = Arc::clone(storage);
+/// Scans an arbitrary set of blocks like [`scan_block`] and calls `on_block`
+/// for every block before it is added to `storage`.
+///
+/// This is the building block of resumable scans: `blocks` is the list of
+/// blocks still pending in a [`crate::checkpoint::Checkpoint`], and `on_block`
+/// persists the block and marks it as completed.
+///
+/// # Parameters
+/// * `provider` – A reference-counted Ethereum provider implementing [`Middleware`].
+/// * `blocks` – Block numbers to scan, in any order.
+/// * `storage` – Shared transaction storage (`SharedTxStorage`) for storing results.
+/// * `options` – See [`ScanOptions`].
+/// * `on_block` – Called once per fetched block, one block at a time. If it
+///   returns an error, the block is reported as failed and not stored.
+///
+/// # Returns
+/// A [`ScanReport`], as for [`scan_block`].
+///
+/// # Example
+/// ```rust,ignore
+/// let pending = checkpoint.pending(start_block, end_block);
+/// let report = scan_blocks(&provider, pending, &storage, &options, |block| {
+///     checkpoint.mark_completed(block.number)
+/// })
+/// .await;
+/// ```
+pub async fn scan_blocks<M, I, F>(
+    provider: &Arc<M>,
+    blocks: I,
+    storage: &SharedTxStorage,
+    options: &ScanOptions,
+    mut on_block: F,
+) -> ScanReport
+where
+    M: Middleware + 'static,
+    I: IntoIterator<Item = u64>,
+    F: FnMut(&ScannedBlock) -> io::Result<()>,
+{
+    let limiter = options.requests_per_second.map(RateLimiter::new);
+    let limiter = limiter.as_ref();
 
-        futures.push(async move {
-            if let Some(block) = provider.get_block_with_txs(block_number).await? {
-                let ts: i64 = block.timestamp.as_u64() as i64;
-                let datetime: DateTime<Utc> =
-                    DateTime::<Utc>::from_timestamp(ts, 0).expect("invalid timestamp");
-                let timestamp_str = datetime.to_rfc3339();
+    let mut outcomes = stream::iter(blocks)
+        .map(|block_number| async move {
+            let mut attempt: u32 = 0;
+            loop {
+                match fetch_block(provider, block_number, options, limiter).await {
+                    Ok(block) => return (block_number, attempt, Ok(block)),
+                    Err(err) if attempt < options.max_retries && err.is_transient() => {
+                        let delay = backoff(options, attempt);
+                        warn!(
+                            "Block {} failed ({}), retrying in {:?}",
+                            block_number, err, delay
+                        );
+                        sleep(delay).await;
+                        attempt += 1;
+                    }
+                    Err(err) => return (block_number, attempt, Err(err.to_string())),
+                }
+            }
+        })
+        .buffer_unordered(options.max_in_flight.max(1));
 
-                info!(
-                    "Processing block {} ({} transactions)",
+    let mut report = ScanReport::default();
+    while let Some((block_number, retries, outcome)) = outcomes.next().await {
+        report.retries += retries as usize;
+        let outcome = match outcome {
+            Ok(Some(block)) => match on_block(&block) {
+                Ok(()) => {
+                    store_block(storage, block).await;
+                    Ok(())
+                }
+                Err(err) => Err(format!("cannot persist block: {}", err)),
+            },
+            Ok(None) => Ok(()),
+            Err(error) => Err(error),
+        };
+        match outcome {
+            Ok(()) => report.scanned_blocks += 1,
+            Err(error) => {
+                warn!(
+                    "Giving up on block {} after {} attempts: {}",
                     block_number,
-                    block.transactions.len()
+                    retries + 1,
+                    error
                 );
+                report.failed.push(FailedBlock {
+                    block_number,
+                    attempts: retries + 1,
+                    error,
+                });
+            }
+        }
+    }
+    report.failed.sort_by_key(|failed| failed.block_number);
 
-                let mut batch: Vec<TransactionRecord> =
-                    Vec::with_capacity(block.transactions.len());
-                for tx in block.transactions {
-                    batch.push(TransactionRecord {
-                        hash: format!("{:?}", tx.hash),
-                        from: format!("{:?}", tx.from),
-                        to: tx.to.map(|addr| format!("{:?}", addr)),
-                        value: wei_to_eth(tx.value.as_u128()),
-                        gas: tx.gas.as_u64(),
-                        gas_price_gwei: wei_to_gwei(tx.gas_price.unwrap_or_default().as_u128()),
-                        block_number,
-                        timestamp: timestamp_str.clone(),
-                        input: format!("0x{}", hex::encode(&tx.input)),
-                    });
-                }
+    report
+}
 
-                {
-                    let mut all_txs = storage.all_txs.write().await;
-                    all_txs.extend(batch.clone());
-                }
+/// Everything fetched for one block, as passed to the `on_block` callback of
+/// [`scan_blocks`].
+///
+/// * `number` – Block number.
+/// * `header` – Hash and parent hash of the block; `None` for pending blocks,
+///   which have no hash yet.
+/// * `txs` – Transactions of the block, in block order.
+/// * `transfers` – Decoded ERC-20 transfers; empty unless
+///   [`ScanOptions::fetch_token_transfers`] is set.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScannedBlock {
+    pub number: u64,
+    pub header: Option<BlockHeader>,
+    pub txs: Vec<TransactionRecord>,
+    pub transfers: Vec<TokenTransfer>,
+}
 
-                for tx in batch {
-                    storage
-                        .by_sender
-                        .entry(tx.from.clone())
-                        .or_default()
-                        .push(tx.clone());
-                    if let Some(to) = &tx.to {
-                        storage.by_reciever.entry(to.clone()).or_default().push(tx);
-                    }
-                }
-            }
-            Ok::<(), M::Error>(())
+/// Fetches one block with its receipts and token transfers as requested by
+/// `options`. Returns `Ok(None)` if the node does not know the block.
+async fn fetch_block<M>(
+    provider: &Arc<M>,
+    block_number: u64,
+    options: &ScanOptions,
+    limiter: Option<&RateLimiter>,
+) -> Result<Option<ScannedBlock>, FetchError<M::Error>>
+where
+    M: Middleware + 'static,
+{
+    RateLimiter::acquire(limiter).await;
+    let block = match provider.get_block_with_txs(block_number).await? {
+        Some(block) => block,
+        None => return Ok(None),
+    };
+
+    let timestamp = block_timestamp(&block).map_err(FetchError::Invalid)?;
+
+    info!(
+        "Processing block {} ({} transactions)",
+        block_number,
+        block.transactions.len()
+    );
+
+    // Receipts and logs are requested by the hash of the fetched block, so that
+    // near the head they cannot come from another branch than its transactions.
+    // Pending blocks have no hash and are requested by number.
+    let receipts = if options.fetch_receipts {
+        fetch_receipts_for(provider, &block, block_number, options, limiter).await?
+    } else {
+        HashMap::new()
+    };
+
+    let transfers = if options.fetch_token_transfers {
+        RateLimiter::acquire(limiter).await;
+        let filter = match block.hash {
+            Some(hash) => Filter::new().at_block_hash(hash),
+            None => Filter::new().select(block_number),
+        }
+        .event(TRANSFER_EVENT);
+        provider
+            .get_logs(&filter)
+            .await?
+            .iter()
+            .filter(|log| in_block(log.block_hash, block.hash, "log", block_number))
+            .filter_map(|log| decode_transfer(log, timestamp))
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    scanned_block(block, block_number, timestamp, receipts, transfers)
+        .map(Some)
+        .map_err(FetchError::Invalid)
+}
+
+/// Converts the timestamp of `block` into a `DateTime`.
+///
+/// # Errors
+/// Returns an [`InvalidBlockField`] for `timestamp` if it does not fit an
+/// `i64` or is out of the range of `DateTime`.
+pub fn block_timestamp<T>(block: &Block<T>) -> Result<DateTime<Utc>, InvalidBlockField> {
+    i64::try_from(block.timestamp)
+        .ok()
+        .and_then(|ts| DateTime::<Utc>::from_timestamp(ts, 0))
+        .ok_or_else(|| InvalidBlockField {
+            field: "timestamp",
+            value: block.timestamp.to_string(),
+        })
+}
+
+/// Converts a gas amount into a `u64`, or an [`InvalidBlockField`] for `field`.
+fn gas_u64(gas: U256, field: &'static str) -> Result<u64, InvalidBlockField> {
+    u64::try_from(gas).map_err(|_| InvalidBlockField {
+        field,
+        value: gas.to_string(),
+    })
+}
+
+/// Converts a block as returned by `eth_getBlockByNumber` with full
+/// transactions into a [`ScannedBlock`].
+///
+/// # Parameters
+/// * `block` – The block with its transactions.
+/// * `block_number` – Number of the block; pending blocks have none of their own.
+/// * `timestamp` – Block timestamp, stamped on every transaction.
+/// * `receipts` – Receipts by transaction hash; transactions without one keep
+///   the receipt fields empty.
+/// * `transfers` – Token transfers already decoded from the block's logs.
+///
+/// # Errors
+/// Returns an [`InvalidBlockField`] if the gas limit of a transaction or the
+/// gas used by its receipt does not fit a `u64`.
+///
+/// # Notes
+/// * Used by the scanner and by [`crate::import`], so blocks read from a node
+///   and from a file produce identical records.
+pub fn scanned_block(
+    block: Block<Transaction>,
+    block_number: u64,
+    timestamp: DateTime<Utc>,
+    mut receipts: HashMap<H256, TransactionReceipt>,
+    transfers: Vec<TokenTransfer>,
+) -> Result<ScannedBlock, InvalidBlockField> {
+    let header = block.hash.map(|hash| BlockHeader {
+        number: block_number,
+        hash,
+        parent_hash: block.parent_hash,
+    });
+    let base_fee_per_gas = block.base_fee_per_gas;
+
+    let mut txs: Vec<TransactionRecord> = Vec::with_capacity(block.transactions.len());
+    for tx in block.transactions {
+        let receipt = receipts.remove(&tx.hash);
+        txs.push(TransactionRecord {
+            hash: tx.hash,
+            from: tx.from,
+            to: tx.to,
+            value: tx.value,
+            gas: gas_u64(tx.gas, "gas")?,
+            gas_price: tx
+                .gas_price
+                .or_else(|| eip1559_gas_price(&tx, base_fee_per_gas))
+                .unwrap_or_default(),
+            block_number,
+            timestamp,
+            input: format!("0x{}", hex::encode(&tx.input)),
+            gas_used: receipt
+                .as_ref()
+                .and_then(|r| r.gas_used)
+                .map(|gas| gas_u64(gas, "gasUsed"))
+                .transpose()?,
+            effective_gas_price: receipt.as_ref().and_then(|r| r.effective_gas_price),
+            status: receipt
+                .as_ref()
+                .and_then(|r| r.status)
+                .map(|status| status.as_u64() == 1),
+            contract_address: receipt.as_ref().and_then(|r| r.contract_address),
+            tx_type: tx.transaction_type.map(|tx_type| tx_type.as_u64()),
+            max_fee_per_gas: tx.max_fee_per_gas,
+            max_priority_fee_per_gas: tx.max_priority_fee_per_gas,
+            base_fee_per_gas,
         });
     }
 
-    while let Some(res) = futures.next().await {
-        res?;
+    Ok(ScannedBlock {
+        number: block_number,
+        header,
+        txs,
+        transfers,
+    })
+}
+
+/// Adds the transactions and token transfers of one block to `storage` and its
+/// indexes, skipping those that are already stored.
+pub async fn store_block(storage: &SharedTxStorage, block: ScannedBlock) {
+    if let Some(header) = block.header {
+        storage.blocks.write().await.insert(header.number, header);
     }
 
-    Ok(())
+    let mut duplicates = 0;
+    for tx in block.txs {
+        if !storage.insert(tx).await {
+            duplicates += 1;
+        }
+    }
+    for transfer in block.transfers {
+        storage.insert_transfer(transfer).await;
+    }
+    if duplicates > 0 {
+        info!(
+            "Block {}: skipped {} transactions already in the storage",
+            block.number, duplicates
+        );
+    }
 }
 
-#[derive(Deserialize)]
-struct SanctionedAddress {
-    address: String,
+/// Compares the most recent stored blocks with the node and returns the first
+/// block that is no longer canonical.
+///
+/// # Overview
+/// Starting at the highest stored block, each stored [`BlockHeader`] is compared
+/// with the hash the node reports at the same height. The walk stops at the
+/// first block that is still canonical and whose parent hash matches the stored
+/// block below it, so a poll without a reorg costs a single `eth_getBlockByNumber`
+/// call. Blocks the node no longer has (the new branch is shorter) count as
+/// orphaned.
+///
+/// # Parameters
+/// * `provider` – A reference-counted Ethereum provider implementing [`Middleware`].
+/// * `storage` – Storage whose `blocks` are checked.
+/// * `max_depth` – Maximum number of stored blocks to compare.
+///
+/// # Returns
+/// `Some(fork_block)` if the stored chain diverged from the node's, `None` if
+/// it is consistent or no block header is stored.
+///
+/// # Notes
+/// * A reorg deeper than `max_depth` is only partially detected: the lowest
+///   compared block is returned and a warning is logged.
+/// * Blocks stored without a header (e.g. from old scans) are not checked.
+pub async fn find_reorg<M>(
+    provider: &Arc<M>,
+    storage: &SharedTxStorage,
+    max_depth: u64,
+) -> Result<Option<u64>, M::Error>
+where
+    M: Middleware + 'static,
+{
+    let headers = storage.blocks.read().await.clone();
+    let mut fork_block = None;
+
+    for header in headers.values().rev().take(max_depth as usize) {
+        let canonical = provider
+            .get_block(header.number)
+            .await?
+            .and_then(|block| block.hash);
+        if canonical != Some(header.hash) {
+            fork_block = Some(header.number);
+            continue;
+        }
+        let linked = header
+            .number
+            .checked_sub(1)
+            .and_then(|parent| headers.get(&parent))
+            .is_none_or(|parent| parent.hash == header.parent_hash);
+        if linked {
+            return Ok(fork_block);
+        }
+    }
+
+    if let Some(fork_block) = fork_block {
+        warn!(
+            "Reorg reaches block {}, the reorg depth limit; older blocks may be orphaned too",
+            fork_block
+        );
+    }
+    Ok(fork_block)
 }
 
-pub async fn fetch_sanctioned_addresses() -> Result<HashSet<String>, reqwest::Error> {
-    let url = "https://raw.githubusercontent.com/0xB10C/ofac-sanctioned-digital-currency-addresses/lists/sanctioned_addresses_ETH.json";
-    let resp = reqwest::get(url).await?;
+/// Detects a chain reorganization with [`find_reorg`], rolls back the orphaned
+/// blocks and scans them again on the new branch.
+///
+/// # Parameters
+/// * `provider` – A reference-counted Ethereum provider implementing [`Middleware`].
+/// * `storage` – Storage to check and repair.
+/// * `options` – Scan options; `options.reorg_depth` bounds the check.
+///
+/// # Returns
+/// The [`Reorg`] that was undone, or `None` if the storage is consistent with
+/// the node.
+///
+/// # Notes
+/// * Rolled back blocks are removed from `all_txs`, `by_sender`, `by_reciever`,
+///   the token transfer indexes and `blocks` with [`crate::models::TxStorage::rollback_from`].
+/// * Findings computed before the reorg may reference orphaned transactions;
+///   run the detectors again (see [`crate::watch::watch`], which retracts them).
+///
+/// # Example
+/// ```rust,ignore
+/// let report = scan_block(&provider, start, head, &storage, &options).await;
+/// if let Some(reorg) = reconcile_reorg(&provider, &storage, &options).await? {
+///     println!("Reorg at block {}: {} transactions orphaned", reorg.fork_block, reorg.orphaned.len());
+/// }
+/// ```
+pub async fn reconcile_reorg<M>(
+    provider: &Arc<M>,
+    storage: &SharedTxStorage,
+    options: &ScanOptions,
+) -> Result<Option<Reorg>, M::Error>
+where
+    M: Middleware + 'static,
+{
+    let Some(fork_block) = find_reorg(provider, storage, options.reorg_depth).await? else {
+        return Ok(None);
+    };
+    let old_tip = storage.tip().await.unwrap_or(fork_block);
+    let orphaned = storage.rollback_from(fork_block).await;
+    warn!(
+        "Chain reorganization at block {}: {} transactions orphaned, rescanning up to block {}",
+        fork_block,
+        orphaned.len(),
+        old_tip
+    );
 
-    if resp.status().is_success() {
-        let addresses: Vec<SanctionedAddress> = resp.json().await?;
-        Ok(addresses.into_iter().map(|a| a.address).collect())
-    } else {
-        eprintln!("⚠️ Ошибка загрузки данных: {}", resp.status());
-        Ok(HashSet::new())
+    let rescan = scan_block(provider, fork_block, old_tip, storage, options).await;
+    Ok(Some(Reorg {
+        fork_block,
+        orphaned,
+        rescan,
+    }))
+}
+
+/// Whether retrying the request can help.
+///
+/// JSON-RPC error responses are transient unless they report a malformed
+/// request or an unsupported method (codes -32700 to -32600, -32601 and
+/// -32602); rate limits such as -32005 or 429 are retried. Responses that cannot
+/// be deserialized are permanent, transport errors are transient.
+fn is_transient<E: MiddlewareError>(err: &E) -> bool {
+    match err.as_error_response() {
+        Some(response) => !matches!(response.code, -32700 | -32600 | -32601 | -32602),
+        None => !err.is_serde_error(),
     }
 }
 
-fn wei_to_eth(wei: u128) -> f64 {
-    wei as f64 / 1e18
+/// Whether the node rejected the request because it does not implement the
+/// method: code -32601, or an error message saying so for nodes that use
+/// another code.
+fn is_unsupported_method<E: MiddlewareError>(err: &E) -> bool {
+    match err.as_error_response() {
+        Some(response) => {
+            let message = response.message.to_lowercase();
+            response.code == -32601
+                || [
+                    "method not found",
+                    "not supported",
+                    "unsupported",
+                    "does not exist",
+                ]
+                .iter()
+                .any(|needle| message.contains(needle))
+        }
+        None => false,
+    }
+}
+
+/// Whether the node rejected the parameters of the request: code -32602.
+fn is_invalid_params<E: MiddlewareError>(err: &E) -> bool {
+    err.as_error_response()
+        .is_some_and(|response| response.code == -32602)
+}
+
+/// Delay before retry number `attempt + 1`: `initial_backoff * 2^attempt`,
+/// capped at `max_backoff`.
+fn backoff(options: &ScanOptions, attempt: u32) -> Duration {
+    options
+        .initial_backoff
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(options.max_backoff)
+}
+
+/// Spaces RPC requests evenly so that at most `requests_per_second` are sent.
+struct RateLimiter {
+    interval: Duration,
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    fn new(requests_per_second: u32) -> Self {
+        Self {
+            interval: Duration::from_secs(1) / requests_per_second.max(1),
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Waits for the next free request slot; returns immediately without a limiter.
+    async fn acquire(limiter: Option<&Self>) {
+        let Some(limiter) = limiter else {
+            return;
+        };
+        let mut next = limiter.next.lock().await;
+        sleep_until(*next).await;
+        *next = Instant::now().max(*next) + limiter.interval;
+    }
 }
 
-fn wei_to_gwei(wei: u128) -> f64 {
-    wei as f64 / 1e9
+/// Decodes an ERC-20 `Transfer` event from a log.
+///
+/// Returns `None` for any other log, including ERC-721 transfers (which index
+/// the token id as a fourth topic instead of putting the amount in the data),
+/// logs removed by a reorg, pending logs without a transaction hash and logs
+/// without an index or whose index does not fit a `u64`: transfers are told
+/// apart by `(tx_hash, log_index)`, so a guessed index would merge two of them.
+///
+/// # Parameters
+/// * `log` – Log as returned by `eth_getLogs` or a receipt.
+/// * `timestamp` – Timestamp of the block that included the log.
+///
+/// # Example
+/// ```rust,ignore
+/// let transfers: Vec<TokenTransfer> = receipt
+///     .logs
+///     .iter()
+///     .filter_map(|log| decode_transfer(log, timestamp))
+///     .collect();
+/// ```
+pub fn decode_transfer(log: &Log, timestamp: DateTime<Utc>) -> Option<TokenTransfer> {
+    if log.removed == Some(true)
+        || log.topics.len() != 3
+        || log.topics[0] != H256::from(keccak256(TRANSFER_EVENT))
+        || log.data.len() != 32
+    {
+        return None;
+    }
+
+    Some(TokenTransfer {
+        tx_hash: log.transaction_hash?,
+        log_index: u64::try_from(log.log_index?).ok()?,
+        block_number: log.block_number?.as_u64(),
+        timestamp,
+        token: log.address,
+        from: Address::from(log.topics[1]),
+        to: Address::from(log.topics[2]),
+        amount: U256::from_big_endian(&log.data),
+    })
+}
+
+/// Price per gas of an EIP-1559 transaction for nodes that omit `gasPrice`:
+/// `min(max_fee_per_gas, base_fee + max_priority_fee_per_gas)`.
+fn eip1559_gas_price(tx: &Transaction, base_fee_per_gas: Option<U256>) -> Option<U256> {
+    let max_fee = tx.max_fee_per_gas?;
+    let tip = tx.max_priority_fee_per_gas?;
+    let base_fee = base_fee_per_gas?;
+    Some(max_fee.min(base_fee.saturating_add(tip)))
+}
+
+/// Fetches the receipts of all transactions of `block`, keyed by transaction hash.
+///
+/// Uses a single `eth_getBlockReceipts` call, see [`block_receipts`], and falls
+/// back to one `eth_getTransactionReceipt` call per transaction when the node
+/// cannot serve the whole block, at most `options.max_in_flight` at a time. Any
+/// other error, e.g. a rate limit or a timeout, is returned so that the block is
+/// retried with backoff by [`scan_blocks`]. Receipts of another block (the
+/// transaction was included again on a new branch) are dropped; transactions
+/// without a receipt are missing from the map, with a warning.
+async fn fetch_receipts_for<M>(
+    provider: &Arc<M>,
+    block: &Block<Transaction>,
+    block_number: u64,
+    options: &ScanOptions,
+    limiter: Option<&RateLimiter>,
+) -> Result<HashMap<H256, TransactionReceipt>, FetchError<M::Error>>
+where
+    M: Middleware + 'static,
+{
+    RateLimiter::acquire(limiter).await;
+    let transactions = &block.transactions;
+    let receipts: Vec<TransactionReceipt> =
+        match block_receipts(provider, block, block_number, limiter).await? {
+            Some(receipts) => receipts,
+            None => stream::iter(transactions)
+                .map(|tx| async move {
+                    RateLimiter::acquire(limiter).await;
+                    provider.get_transaction_receipt(tx.hash).await
+                })
+                .buffer_unordered(options.max_in_flight.max(1))
+                .try_collect::<Vec<_>>()
+                .await?
+                .into_iter()
+                .flatten()
+                .collect(),
+        };
+
+    let receipts: HashMap<H256, TransactionReceipt> = receipts
+        .into_iter()
+        .filter(|receipt| in_block(receipt.block_hash, block.hash, "receipt", block_number))
+        .map(|receipt| (receipt.transaction_hash, receipt))
+        .collect();
+    let missing = transactions
+        .iter()
+        .filter(|tx| !receipts.contains_key(&tx.hash))
+        .count();
+    if missing > 0 {
+        warn!(
+            "Block {}: {} of {} transactions have no receipt, their gas used and status are unknown",
+            block_number,
+            missing,
+            transactions.len()
+        );
+    }
+    Ok(receipts)
+}
+
+/// Receipts of the whole `block` from one `eth_getBlockReceipts` call, or `None`
+/// when the node does not support that method.
+///
+/// The block is requested by hash, and by number for pending blocks or nodes
+/// that reject a hash there (-32602, invalid params).
+async fn block_receipts<M>(
+    provider: &Arc<M>,
+    block: &Block<Transaction>,
+    block_number: u64,
+    limiter: Option<&RateLimiter>,
+) -> Result<Option<Vec<TransactionReceipt>>, FetchError<M::Error>>
+where
+    M: Middleware + 'static,
+{
+    let per_transaction = |err: &dyn fmt::Display| {
+        warn!(
+            "eth_getBlockReceipts is not supported ({}), fetching the receipts of block {} per transaction",
+            err, block_number
+        );
+        Ok(None)
+    };
+
+    if let Some(hash) = block.hash {
+        match provider
+            .provider()
+            .request("eth_getBlockReceipts", [hash])
+            .await
+        {
+            Ok(receipts) => return Ok(Some(receipts)),
+            Err(err) if is_unsupported_method(&err) => return per_transaction(&err),
+            Err(err) if is_invalid_params(&err) => {
+                warn!(
+                    "eth_getBlockReceipts rejects block hashes ({}), fetching the receipts of block {} by number",
+                    err, block_number
+                );
+                RateLimiter::acquire(limiter).await;
+            }
+            Err(err) => return Err(FetchError::Provider(err)),
+        }
+    }
+    match provider.get_block_receipts(block_number).await {
+        Ok(receipts) => Ok(Some(receipts)),
+        Err(err) if is_unsupported_method(&err) || is_invalid_params(&err) => per_transaction(&err),
+        Err(err) => Err(FetchError::Node(err)),
+    }
+}
+
+/// Whether a log or receipt with `item_hash` belongs to the block with `block_hash`.
+/// Anything goes for pending blocks, which have no hash.
+fn in_block(
+    item_hash: Option<H256>,
+    block_hash: Option<H256>,
+    item: &str,
+    block_number: u64,
+) -> bool {
+    let Some(block_hash) = block_hash else {
+        return true;
+    };
+    if item_hash == Some(block_hash) {
+        return true;
+    }
+    warn!(
+        "Dropping a {} of block {} from block {:?} instead of {:?}",
+        item, block_number, item_hash, block_hash
+    );
+    false
 }
@@ -0,0 +1,464 @@
+use crate::{
+    address::format_address,
+    models::{TokenTransfer, TransactionRecord},
+    query::TxQuery,
+    units::{cmp_scaled, format_eth, mean, to_f64},
+};
+use chrono::{DateTime, Utc};
+use ethers::types::{Address, H256, U256, U512};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap},
+};
+
+/// Values sorted once, so that any percentile is a lookup.
+///
+/// # Example
+/// ```rust,ignore
+/// let fees = Distribution::new(txs.iter().map(|tx| tx.fee()).collect());
+/// let p99 = fees.percentile(99.0);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Distribution {
+    sorted: Vec<U256>,
+}
+
+impl Distribution {
+    pub fn new(mut values: Vec<U256>) -> Self {
+        values.sort_unstable();
+        Self { sorted: values }
+    }
+
+    /// Value at the `perc` percentile (0–100), rounded down to the nearest
+    /// sample; zero when there are no values.
+    pub fn percentile(&self, perc: f64) -> U256 {
+        if self.sorted.is_empty() {
+            return U256::zero();
+        }
+        let idx = ((perc / 100.0) * (self.sorted.len() as f64 - 1.0)) as usize;
+        self.sorted[idx.min(self.sorted.len() - 1)]
+    }
+
+    /// The values in ascending order.
+    pub fn values(&self) -> &[U256] {
+        &self.sorted
+    }
+
+    pub fn len(&self) -> usize {
+        self.sorted.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sorted.is_empty()
+    }
+}
+
+/// Scale that makes the median absolute deviation of normally distributed
+/// values an estimate of their standard deviation.
+pub const MAD_SCALE: f64 = 1.4826;
+
+/// Scale that makes the mean absolute deviation of normally distributed values
+/// an estimate of their standard deviation.
+pub const MEAN_DEVIATION_SCALE: f64 = 1.2533;
+
+/// Median and median absolute deviation (MAD) of a sender's history, computed
+/// without the value being judged.
+///
+/// Unlike a mean, neither statistic moves when a few values of the history
+/// are extreme, and leaving the judged value out keeps an outlier from raising
+/// its own baseline.
+///
+/// # Fields
+/// * `median` – Median of the other values.
+/// * `mad` – Median of the absolute deviations of the other values from `median`.
+/// * `mean_deviation` – Mean of those deviations, the spread used when more than
+///   half of the other values equal the median and `mad` is zero.
+/// * `history` – Number of other values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Baseline {
+    pub median: U256,
+    pub mad: U256,
+    pub mean_deviation: U256,
+    pub history: usize,
+}
+
+impl Baseline {
+    /// The spread and the scale that turns it into a standard deviation; `None`
+    /// when every other value is the same and no deviation can be measured.
+    fn spread(&self) -> Option<(U256, f64)> {
+        if !self.mad.is_zero() {
+            Some((self.mad, MAD_SCALE))
+        } else if !self.mean_deviation.is_zero() {
+            Some((self.mean_deviation, MEAN_DEVIATION_SCALE))
+        } else {
+            None
+        }
+    }
+
+    /// Robust z-score of `value`: its distance from the median in scaled MADs,
+    /// `(value - median) / (MAD_SCALE * mad)`, negative below the median.
+    ///
+    /// Falls back to the mean absolute deviation when the MAD is zero, and
+    /// returns `None` when every other value is the same: the distance of a
+    /// different value is then unbounded, see [`exceeds`](Self::exceeds).
+    /// Rounded to the precision of `f64`, for reports only.
+    pub fn z_score(&self, value: U256) -> Option<f64> {
+        let (spread, scale) = self.spread()?;
+        let scale = scale * to_f64(spread);
+        Some(if value >= self.median {
+            to_f64(value - self.median) / scale
+        } else {
+            -to_f64(self.median - value) / scale
+        })
+    }
+
+    /// Whether the z-score of `value` is above `threshold`, compared exactly
+    /// (see [`cmp_scaled`]).
+    ///
+    /// Without a spread every other value is the same and no z-score exists.
+    /// The value then exceeds only when it is more than `flat_ratio` times that
+    /// value: ten transfers of 1 ETH followed by one of 1000 ETH do, while a
+    /// payment from an account that only made zero-value calls before never does.
+    pub fn exceeds(&self, value: U256, threshold: f64, flat_ratio: f64) -> bool {
+        if value <= self.median {
+            return false;
+        }
+        match self.spread() {
+            Some((spread, scale)) => {
+                cmp_scaled(value - self.median, threshold * scale, spread) == Ordering::Greater
+            }
+            None => {
+                !self.median.is_zero()
+                    && cmp_scaled(value, flat_ratio, self.median) == Ordering::Greater
+            }
+        }
+    }
+}
+
+/// Absolute deviations of a history from one median, sorted, and their sum.
+#[derive(Debug, Clone, PartialEq)]
+struct Deviations {
+    median: U256,
+    sorted: Vec<U256>,
+    sum: U512,
+}
+
+/// The values of one sender, sorted, with what is needed to compute a
+/// leave-one-out [`Baseline`] for any of them in logarithmic time.
+///
+/// Leaving one value out of a sorted history moves the median to one of at
+/// most three values, depending on whether the removed value lies below,
+/// at or above the middle. The absolute deviations from each of them are
+/// sorted once, so the MAD without a value is a lookup next to that value's
+/// own deviation.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct History {
+    sorted: Vec<U256>,
+    deviations: Vec<Deviations>,
+}
+
+impl History {
+    pub fn new(mut values: Vec<U256>) -> Self {
+        values.sort_unstable();
+        let mut deviations: Vec<Deviations> = Vec::new();
+        let len = values.len();
+        if len >= 2 {
+            // Removing the first, the last or (for an even rest) the upper middle
+            // value covers every median a leave-one-out can have.
+            for removed in [0, len - 1, (len - 1) / 2] {
+                let median = median_without(&values, removed);
+                if deviations.iter().any(|d| d.median == median) {
+                    continue;
+                }
+                let mut sorted: Vec<U256> = values.iter().map(|v| abs_diff(*v, median)).collect();
+                sorted.sort_unstable();
+                let sum = sorted
+                    .iter()
+                    .fold(U512::zero(), |acc, d| acc + U512::from(*d));
+                deviations.push(Deviations {
+                    median,
+                    sorted,
+                    sum,
+                });
+            }
+        }
+        Self {
+            sorted: values,
+            deviations,
+        }
+    }
+
+    /// Number of values in the history.
+    pub fn len(&self) -> usize {
+        self.sorted.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sorted.is_empty()
+    }
+
+    /// Median and MAD of the history without one occurrence of `value`.
+    ///
+    /// Returns `None` when `value` is not part of the history or is its only value.
+    pub fn leave_one_out(&self, value: U256) -> Option<Baseline> {
+        let len = self.sorted.len();
+        let removed = self.sorted.partition_point(|v| *v < value);
+        if len < 2 || self.sorted.get(removed) != Some(&value) {
+            return None;
+        }
+        let median = median_without(&self.sorted, removed);
+        let deviations = self
+            .deviations
+            .iter()
+            .find(|d| d.median == median)
+            .expect("every leave-one-out median is precomputed");
+        let own = abs_diff(value, median);
+        let skipped = deviations.sorted.partition_point(|d| *d < own);
+        let mean_deviation = (deviations.sum - U512::from(own)) / U512::from(len - 1);
+        Some(Baseline {
+            median,
+            mad: median_without(&deviations.sorted, skipped),
+            mean_deviation: U256::try_from(mean_deviation)
+                .expect("mean of U256 deviations fits into U256"),
+            history: len - 1,
+        })
+    }
+}
+
+/// Median of the sorted `values` without the one at index `removed`.
+fn median_without(values: &[U256], removed: usize) -> U256 {
+    let at = |i: usize| {
+        if i < removed {
+            values[i]
+        } else {
+            values[i + 1]
+        }
+    };
+    let len = values.len() - 1;
+    let (low, high) = (at((len - 1) / 2), at(len / 2));
+    // Rounded down, without overflowing for values close to `U256::MAX`.
+    low / 2 + high / 2 + (low % 2 + high % 2) / 2
+}
+
+fn abs_diff(a: U256, b: U256) -> U256 {
+    if a > b {
+        a - b
+    } else {
+        b - a
+    }
+}
+
+/// A single movement of value: the native value of a transaction or the
+/// amount of one ERC-20 transfer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Movement {
+    pub hash: H256,
+    pub from: Address,
+    pub amount: U256,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl From<&TransactionRecord> for Movement {
+    fn from(tx: &TransactionRecord) -> Self {
+        Self {
+            hash: tx.hash,
+            from: tx.from,
+            amount: tx.value,
+            timestamp: tx.timestamp,
+        }
+    }
+}
+
+impl From<&TokenTransfer> for Movement {
+    fn from(transfer: &TokenTransfer) -> Self {
+        Self {
+            hash: transfer.tx_hash,
+            from: transfer.from,
+            amount: transfer.amount,
+            timestamp: transfer.timestamp,
+        }
+    }
+}
+
+type SenderMovements = HashMap<Address, Vec<Movement>>;
+
+/// All movements of one asset with their statistics. `token` is `None` for ETH.
+///
+/// Value-based detectors compare amounts within one asset only: raw amounts of
+/// different tokens have different decimals and prices.
+///
+/// # Fields
+/// * `token` – Token contract, `None` for native ETH.
+/// * `movements` – Every movement, in storage order.
+/// * `by_sender` – Movements grouped by sender, each group sorted by timestamp.
+/// * `values` – Every amount, sorted for percentiles.
+#[derive(Debug, Clone, Default)]
+pub struct AssetStats {
+    pub token: Option<Address>,
+    pub movements: Vec<Movement>,
+    pub by_sender: SenderMovements,
+    pub values: Distribution,
+    local_means: HashMap<Address, U256>,
+    histories: HashMap<Address, History>,
+}
+
+impl AssetStats {
+    fn new(
+        token: Option<Address>,
+        movements: Vec<Movement>,
+        mut by_sender: SenderMovements,
+    ) -> Self {
+        let values = Distribution::new(movements.iter().map(|m| m.amount).collect());
+        let mut local_means = HashMap::with_capacity(by_sender.len());
+        let mut histories = HashMap::with_capacity(by_sender.len());
+        for (sender, movements) in by_sender.iter_mut() {
+            movements.sort_by_key(|m| m.timestamp);
+            let amounts: Vec<U256> = movements.iter().map(|m| m.amount).collect();
+            local_means.insert(*sender, mean(&amounts));
+            histories.insert(*sender, History::new(amounts));
+        }
+        Self {
+            token,
+            movements,
+            by_sender,
+            values,
+            local_means,
+            histories,
+        }
+    }
+
+    /// Mean amount sent by `sender`, zero for unknown senders.
+    pub fn local_mean(&self, sender: &Address) -> U256 {
+        self.local_means.get(sender).copied().unwrap_or_default()
+    }
+
+    /// Median and MAD of the other amounts `sender` moved, see [`History::leave_one_out`].
+    pub fn baseline(&self, sender: &Address, amount: U256) -> Option<Baseline> {
+        self.histories.get(sender)?.leave_one_out(amount)
+    }
+
+    /// Canonical address of the token, `None` for ETH.
+    pub fn token_label(&self) -> Option<String> {
+        self.token.as_ref().map(format_address)
+    }
+
+    /// Exact ETH for the native asset, raw units for tokens (their decimals are unknown).
+    pub fn format_amount(&self, amount: U256) -> String {
+        match self.token {
+            None => format!("{} ETH", format_eth(amount)),
+            Some(token) => format!("{} units of token {:?}", amount, token),
+        }
+    }
+}
+
+/// Statistics of the analyzed data that several detectors need, computed
+/// once per run.
+///
+/// # Overview
+/// Percentile thresholds and per-sender means used to be recomputed by every
+/// detector, some of them per transaction, which made a run quadratic in the
+/// size of the data. A [`DatasetStats`] sorts every distribution, averages the
+/// amounts of every sender once, and sorts every sender's amounts and fees into a
+/// [`History`] for leave-one-out baselines; [`AnalysisContext::stats`](crate::detector::AnalysisContext::stats)
+/// builds it on first use and shares it with every detector of the run.
+///
+/// # Fields
+/// * `assets` – Native ETH first, then every token seen in the token transfers,
+///   ordered by token address.
+/// * `fees` – Fee of every transaction ([`TransactionRecord::fee`]).
+/// * `priority_fees` – Tip per gas of every post-London transaction.
+/// * `gas_prices` – Gas price of every transaction.
+///
+/// # Example
+/// ```rust,ignore
+/// let stats = ctx.stats().await;
+/// let threshold = stats.native().values.percentile(99.0);
+/// let usual_fee = stats.fee_baseline(&tx.from, tx.fee()).map(|b| b.median);
+/// ```
+#[derive(Debug, Clone)]
+pub struct DatasetStats {
+    pub assets: Vec<AssetStats>,
+    pub fees: Distribution,
+    pub priority_fees: Distribution,
+    pub gas_prices: Distribution,
+    fee_histories: HashMap<Address, History>,
+}
+
+impl DatasetStats {
+    /// Reads every transaction and token transfer of `storage` once.
+    ///
+    /// Native movements and the distributions come from [`TxQuery::all_txs`],
+    /// per-sender groups from [`TxQuery::txs_by_sender`] and token movements
+    /// from [`TxQuery::token_transfers`], grouped by their `from`.
+    pub async fn compute(storage: &dyn TxQuery) -> Self {
+        let all_txs = storage.all_txs().await;
+        let txs_by_sender = storage.txs_by_sender().await;
+
+        let mut fee_histories = HashMap::with_capacity(txs_by_sender.len());
+        for (sender, txs) in txs_by_sender.iter() {
+            let fees: Vec<U256> = txs.iter().map(|tx| tx.fee()).collect();
+            fee_histories.insert(*sender, History::new(fees));
+        }
+        let native = AssetStats::new(
+            None,
+            all_txs
+                .iter()
+                .map(|tx| Movement::from(tx.as_ref()))
+                .collect(),
+            txs_by_sender
+                .iter()
+                .map(|(sender, txs)| {
+                    (
+                        *sender,
+                        txs.iter().map(|tx| Movement::from(tx.as_ref())).collect(),
+                    )
+                })
+                .collect(),
+        );
+
+        let mut tokens: BTreeMap<Address, (Vec<Movement>, SenderMovements)> = BTreeMap::new();
+        for transfer in storage.token_transfers().await {
+            let (movements, by_sender) = tokens.entry(transfer.token).or_default();
+            let movement = Movement::from(transfer.as_ref());
+            movements.push(movement.clone());
+            by_sender.entry(transfer.from).or_default().push(movement);
+        }
+
+        let mut assets = vec![native];
+        assets.extend(tokens.into_iter().map(|(token, (movements, by_sender))| {
+            AssetStats::new(Some(token), movements, by_sender)
+        }));
+
+        Self {
+            assets,
+            fees: Distribution::new(all_txs.iter().map(|tx| tx.fee()).collect()),
+            priority_fees: Distribution::new(
+                all_txs
+                    .iter()
+                    .filter_map(|tx| tx.priority_fee_per_gas())
+                    .collect(),
+            ),
+            gas_prices: Distribution::new(all_txs.iter().map(|tx| tx.gas_price).collect()),
+            fee_histories,
+        }
+    }
+
+    /// Statistics of native ETH.
+    pub fn native(&self) -> &AssetStats {
+        &self.assets[0]
+    }
+
+    /// Native ETH and, with `include_tokens`, every token.
+    pub fn assets(&self, include_tokens: bool) -> &[AssetStats] {
+        if include_tokens {
+            &self.assets
+        } else {
+            &self.assets[..1]
+        }
+    }
+
+    /// Median and MAD of the other fees `sender` paid, see [`History::leave_one_out`].
+    pub fn fee_baseline(&self, sender: &Address, fee: U256) -> Option<Baseline> {
+        self.fee_histories.get(sender)?.leave_one_out(fee)
+    }
+}
@@ -0,0 +1,511 @@
+use crate::address::format_address;
+use crate::models::{BlockHeader, SharedTxStorage, TokenTransfer, TransactionRecord, TxStorage};
+use async_trait::async_trait;
+use rusqlite::{params, Connection};
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Version of the SQLite schema written by [`SqliteStore`], kept in
+/// `PRAGMA user_version`.
+pub const SCHEMA_VERSION: i64 = 1;
+
+/// Errors returned by a [`TxStore`].
+#[derive(Debug)]
+pub enum StoreError {
+    /// The SQLite database could not be opened, read or written.
+    Sqlite(rusqlite::Error),
+    /// A stored record could not be encoded or decoded.
+    Json(serde_json::Error),
+    /// The database was written by a newer version of the analyzer.
+    UnsupportedSchema(i64),
+    /// A block number above `i64::MAX`, which SQLite cannot store.
+    BlockOutOfRange(u64),
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::Sqlite(e) => write!(f, "transaction store error: {}", e),
+            StoreError::Json(e) => write!(f, "invalid record in transaction store: {}", e),
+            StoreError::UnsupportedSchema(version) => write!(
+                f,
+                "transaction store has schema version {} but at most {} is supported",
+                version, SCHEMA_VERSION
+            ),
+            StoreError::BlockOutOfRange(number) => write!(
+                f,
+                "block number {} is too large for the transaction store",
+                number
+            ),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StoreError::Sqlite(e) => Some(e),
+            StoreError::Json(e) => Some(e),
+            StoreError::UnsupportedSchema(_) | StoreError::BlockOutOfRange(_) => None,
+        }
+    }
+}
+
+impl From<rusqlite::Error> for StoreError {
+    fn from(e: rusqlite::Error) -> Self {
+        StoreError::Sqlite(e)
+    }
+}
+
+impl From<serde_json::Error> for StoreError {
+    fn from(e: serde_json::Error) -> Self {
+        StoreError::Json(e)
+    }
+}
+
+/// A place scanned blocks are kept in: the in-memory [`TxStorage`] or the
+/// on-disk [`SqliteStore`].
+///
+/// Blocks are written once, e.g. by `scan --db`, and read back by block range
+/// with [`load_storage`] as often as needed, so detectors can run repeatedly
+/// over a long history without asking the node again.
+///
+/// # Notes
+/// * Writes are idempotent: transactions already stored (same hash) and token
+///   transfers already stored (same transaction hash and log index) are skipped.
+/// * Transactions are returned in block order and, within a block, in the order
+///   they were inserted.
+///
+/// # Example
+/// ```rust,ignore
+/// let store = SqliteStore::open("mainnet.sqlite")?;
+/// for block in blocks {
+///     store.insert_block(block.header, &block.txs, &block.transfers).await?;
+/// }
+/// let storage = load_storage(&store, 19_000_000..=19_100_000).await?;
+/// ```
+#[async_trait]
+pub trait TxStore: Send + Sync {
+    /// Adds one block: its header if known, its transactions and its token
+    /// transfers. Returns the number of transactions that were not stored yet.
+    async fn insert_block(
+        &self,
+        header: Option<BlockHeader>,
+        txs: &[TransactionRecord],
+        transfers: &[TokenTransfer],
+    ) -> Result<usize, StoreError>;
+
+    /// Transactions of the blocks in `blocks`.
+    async fn txs_in_blocks(
+        &self,
+        blocks: RangeInclusive<u64>,
+    ) -> Result<Vec<TransactionRecord>, StoreError>;
+
+    /// Token transfers of the blocks in `blocks`.
+    async fn transfers_in_blocks(
+        &self,
+        blocks: RangeInclusive<u64>,
+    ) -> Result<Vec<TokenTransfer>, StoreError>;
+
+    /// Headers of the blocks in `blocks`, in ascending order.
+    async fn headers_in_blocks(
+        &self,
+        blocks: RangeInclusive<u64>,
+    ) -> Result<Vec<BlockHeader>, StoreError>;
+
+    /// Lowest and highest block with a stored header or transaction, `None`
+    /// if the store is empty.
+    async fn block_bounds(&self) -> Result<Option<(u64, u64)>, StoreError>;
+
+    /// Removes every block from `block_number` on and returns the number of
+    /// removed transactions.
+    async fn rollback_from(&self, block_number: u64) -> Result<usize, StoreError>;
+}
+
+#[async_trait]
+impl TxStore for TxStorage {
+    async fn insert_block(
+        &self,
+        header: Option<BlockHeader>,
+        txs: &[TransactionRecord],
+        transfers: &[TokenTransfer],
+    ) -> Result<usize, StoreError> {
+        if let Some(header) = header {
+            self.blocks.write().await.insert(header.number, header);
+        }
+        let mut inserted = 0;
+        for tx in txs {
+            if self.insert(tx.clone()).await {
+                inserted += 1;
+            }
+        }
+        for transfer in transfers {
+            self.insert_transfer(transfer.clone()).await;
+        }
+        Ok(inserted)
+    }
+
+    async fn txs_in_blocks(
+        &self,
+        blocks: RangeInclusive<u64>,
+    ) -> Result<Vec<TransactionRecord>, StoreError> {
+        Ok(self
+            .by_block
+            .read()
+            .await
+            .range(blocks)
+            .flat_map(|(_, txs)| txs.iter().map(|tx| tx.as_ref().clone()))
+            .collect())
+    }
+
+    async fn transfers_in_blocks(
+        &self,
+        blocks: RangeInclusive<u64>,
+    ) -> Result<Vec<TokenTransfer>, StoreError> {
+        let mut transfers: Vec<TokenTransfer> = self
+            .token_transfers
+            .read()
+            .await
+            .iter()
+            .filter(|transfer| blocks.contains(&transfer.block_number))
+            .map(|transfer| transfer.as_ref().clone())
+            .collect();
+        transfers.sort_by_key(|transfer| (transfer.block_number, transfer.log_index));
+        Ok(transfers)
+    }
+
+    async fn headers_in_blocks(
+        &self,
+        blocks: RangeInclusive<u64>,
+    ) -> Result<Vec<BlockHeader>, StoreError> {
+        Ok(self
+            .blocks
+            .read()
+            .await
+            .range(blocks)
+            .map(|(_, h)| *h)
+            .collect())
+    }
+
+    async fn block_bounds(&self) -> Result<Option<(u64, u64)>, StoreError> {
+        let by_block = self.by_block.read().await;
+        let blocks = self.blocks.read().await;
+        let numbers = || by_block.keys().chain(blocks.keys()).copied();
+        Ok(numbers().min().zip(numbers().max()))
+    }
+
+    async fn rollback_from(&self, block_number: u64) -> Result<usize, StoreError> {
+        Ok(TxStorage::rollback_from(self, block_number).await.len())
+    }
+}
+
+/// A [`TxStore`] kept in a single SQLite file.
+///
+/// The file can be copied and shared: it contains everything needed to run
+/// the detectors over the stored blocks.
+///
+/// # Layout
+/// * `transactions` – One row per transaction, keyed by hash, with the block
+///   number, sender and receiver as indexed columns and the full
+///   [`TransactionRecord`] as JSON.
+/// * `token_transfers` – One row per [`TokenTransfer`], keyed by transaction
+///   hash and log index.
+/// * `blocks` – One row per [`BlockHeader`].
+///
+/// # Notes
+/// * SQLite calls are blocking. Each call is short (one block or one range
+///   query), so they run directly on the calling task.
+/// * The schema version is checked on open; files written by a newer version
+///   are rejected with [`StoreError::UnsupportedSchema`].
+///
+/// # Example
+/// ```rust,ignore
+/// let store = SqliteStore::open("mainnet.sqlite")?;
+/// if let Some((first, last)) = store.block_bounds().await? {
+///     println!("blocks {}..={} stored", first, last);
+/// }
+/// ```
+pub struct SqliteStore {
+    path: PathBuf,
+    connection: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    /// Opens the store at `path`, creating the file and its tables if needed.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, StoreError> {
+        let path = path.as_ref().to_path_buf();
+        let connection = Connection::open(&path)?;
+
+        let version: i64 = connection.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if version > SCHEMA_VERSION {
+            return Err(StoreError::UnsupportedSchema(version));
+        }
+        connection.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             CREATE TABLE IF NOT EXISTS transactions (
+                 hash TEXT PRIMARY KEY,
+                 block_number INTEGER NOT NULL,
+                 from_address TEXT NOT NULL,
+                 to_address TEXT,
+                 record TEXT NOT NULL
+             );
+             CREATE INDEX IF NOT EXISTS transactions_block ON transactions (block_number);
+             CREATE INDEX IF NOT EXISTS transactions_from ON transactions (from_address);
+             CREATE INDEX IF NOT EXISTS transactions_to ON transactions (to_address);
+             CREATE TABLE IF NOT EXISTS token_transfers (
+                 tx_hash TEXT NOT NULL,
+                 log_index INTEGER NOT NULL,
+                 block_number INTEGER NOT NULL,
+                 record TEXT NOT NULL,
+                 PRIMARY KEY (tx_hash, log_index)
+             );
+             CREATE INDEX IF NOT EXISTS token_transfers_block ON token_transfers (block_number);
+             CREATE TABLE IF NOT EXISTS blocks (
+                 number INTEGER PRIMARY KEY,
+                 hash TEXT NOT NULL,
+                 parent_hash TEXT NOT NULL
+             );",
+        )?;
+        connection.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+
+        Ok(Self {
+            path,
+            connection: Mutex::new(connection),
+        })
+    }
+
+    /// Path of the database file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Blocking version of [`TxStore::insert_block`], for synchronous callers
+    /// such as the `on_block` callback of [`crate::scanner::scan_blocks`].
+    ///
+    /// The block is written in one SQLite transaction, so it is stored entirely
+    /// or not at all.
+    pub fn write_block(
+        &self,
+        header: Option<BlockHeader>,
+        txs: &[TransactionRecord],
+        transfers: &[TokenTransfer],
+    ) -> Result<usize, StoreError> {
+        let mut connection = self.lock();
+        let db = connection.transaction()?;
+        let mut inserted = 0;
+        {
+            if let Some(header) = header {
+                db.execute(
+                    "INSERT OR REPLACE INTO blocks (number, hash, parent_hash) VALUES (?1, ?2, ?3)",
+                    params![
+                        stored_block(header.number)?,
+                        format!("{:?}", header.hash),
+                        format!("{:?}", header.parent_hash)
+                    ],
+                )?;
+            }
+
+            let mut insert_tx = db.prepare_cached(
+                "INSERT OR IGNORE INTO transactions (hash, block_number, from_address, to_address, record)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+            )?;
+            for tx in txs {
+                inserted += insert_tx.execute(params![
+                    format!("{:?}", tx.hash),
+                    stored_block(tx.block_number)?,
+                    format_address(&tx.from),
+                    tx.to.as_ref().map(format_address),
+                    serde_json::to_string(tx)?
+                ])?;
+            }
+
+            let mut insert_transfer = db.prepare_cached(
+                "INSERT OR IGNORE INTO token_transfers (tx_hash, log_index, block_number, record)
+                 VALUES (?1, ?2, ?3, ?4)",
+            )?;
+            for transfer in transfers {
+                insert_transfer.execute(params![
+                    format!("{:?}", transfer.tx_hash),
+                    transfer.log_index as i64,
+                    stored_block(transfer.block_number)?,
+                    serde_json::to_string(transfer)?
+                ])?;
+            }
+        }
+        db.commit()?;
+        Ok(inserted)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Connection> {
+        // A panic while holding the lock cannot leave a half-written block
+        // behind: uncommitted SQLite transactions are rolled back on drop.
+        self.connection
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Decodes the JSON `record` column of every row returned by `sql`.
+    fn records<T: serde::de::DeserializeOwned>(
+        &self,
+        sql: &str,
+        blocks: RangeInclusive<u64>,
+    ) -> Result<Vec<T>, StoreError> {
+        let connection = self.lock();
+        let mut statement = connection.prepare_cached(sql)?;
+        let rows = statement.query_map(
+            params![block_bound(*blocks.start()), block_bound(*blocks.end())],
+            |row| row.get::<_, String>(0),
+        )?;
+        let mut records = Vec::new();
+        for row in rows {
+            records.push(serde_json::from_str(&row?)?);
+        }
+        Ok(records)
+    }
+}
+
+#[async_trait]
+impl TxStore for SqliteStore {
+    async fn insert_block(
+        &self,
+        header: Option<BlockHeader>,
+        txs: &[TransactionRecord],
+        transfers: &[TokenTransfer],
+    ) -> Result<usize, StoreError> {
+        self.write_block(header, txs, transfers)
+    }
+
+    async fn txs_in_blocks(
+        &self,
+        blocks: RangeInclusive<u64>,
+    ) -> Result<Vec<TransactionRecord>, StoreError> {
+        self.records(
+            "SELECT record FROM transactions WHERE block_number BETWEEN ?1 AND ?2
+             ORDER BY block_number, rowid",
+            blocks,
+        )
+    }
+
+    async fn transfers_in_blocks(
+        &self,
+        blocks: RangeInclusive<u64>,
+    ) -> Result<Vec<TokenTransfer>, StoreError> {
+        self.records(
+            "SELECT record FROM token_transfers WHERE block_number BETWEEN ?1 AND ?2
+             ORDER BY block_number, log_index",
+            blocks,
+        )
+    }
+
+    async fn headers_in_blocks(
+        &self,
+        blocks: RangeInclusive<u64>,
+    ) -> Result<Vec<BlockHeader>, StoreError> {
+        let connection = self.lock();
+        let mut statement = connection.prepare_cached(
+            "SELECT number, hash, parent_hash FROM blocks WHERE number BETWEEN ?1 AND ?2
+             ORDER BY number",
+        )?;
+        let rows = statement.query_map(
+            params![block_bound(*blocks.start()), block_bound(*blocks.end())],
+            |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                ))
+            },
+        )?;
+        let mut headers = Vec::new();
+        for row in rows {
+            let (number, hash, parent_hash) = row?;
+            headers.push(BlockHeader {
+                number: number as u64,
+                hash: serde_json::from_value(serde_json::Value::String(hash))?,
+                parent_hash: serde_json::from_value(serde_json::Value::String(parent_hash))?,
+            });
+        }
+        Ok(headers)
+    }
+
+    async fn block_bounds(&self) -> Result<Option<(u64, u64)>, StoreError> {
+        let connection = self.lock();
+        let (first, last): (Option<i64>, Option<i64>) = connection.query_row(
+            "SELECT MIN(n), MAX(n) FROM (
+                 SELECT block_number AS n FROM transactions
+                 UNION ALL SELECT number AS n FROM blocks
+             )",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        let bounds = first.zip(last);
+        Ok(bounds.map(|(first, last)| (first as u64, last as u64)))
+    }
+
+    async fn rollback_from(&self, block_number: u64) -> Result<usize, StoreError> {
+        let mut connection = self.lock();
+        let db = connection.transaction()?;
+        let block_number = block_bound(block_number);
+        let removed = db.execute(
+            "DELETE FROM transactions WHERE block_number >= ?1",
+            [block_number],
+        )?;
+        db.execute(
+            "DELETE FROM token_transfers WHERE block_number >= ?1",
+            [block_number],
+        )?;
+        db.execute("DELETE FROM blocks WHERE number >= ?1", [block_number])?;
+        db.commit()?;
+        Ok(removed)
+    }
+}
+
+/// A block number to store; SQLite integers cannot hold numbers above `i64::MAX`.
+fn stored_block(number: u64) -> Result<i64, StoreError> {
+    i64::try_from(number).map_err(|_| StoreError::BlockOutOfRange(number))
+}
+
+/// A block number as a query bound. No stored block is above `i64::MAX` (see
+/// [`stored_block`]), so larger bounds are clamped to it.
+fn block_bound(number: u64) -> i64 {
+    i64::try_from(number).unwrap_or(i64::MAX)
+}
+
+/// Loads the blocks of `blocks` from `store` into a new in-memory storage the
+/// detectors can run on.
+///
+/// # Parameters
+/// * `store` – Any [`TxStore`], usually a [`SqliteStore`].
+/// * `blocks` – Inclusive range of block numbers to load.
+///
+/// # Returns
+/// A [`SharedTxStorage`] with the transactions, token transfers and block
+/// headers of the range and all of its indexes built.
+///
+/// # Example
+/// ```rust,ignore
+/// let store = SqliteStore::open("mainnet.sqlite")?;
+/// let storage = load_storage(&store, 19_000_000..=19_100_000).await?;
+/// let findings = registry.run(&AnalysisContext::new(storage)).await;
+/// ```
+pub async fn load_storage<S>(
+    store: &S,
+    blocks: RangeInclusive<u64>,
+) -> Result<SharedTxStorage, StoreError>
+where
+    S: TxStore + ?Sized,
+{
+    let storage = TxStorage::new();
+    for header in store.headers_in_blocks(blocks.clone()).await? {
+        storage.blocks.write().await.insert(header.number, header);
+    }
+    for tx in store.txs_in_blocks(blocks.clone()).await? {
+        storage.insert(tx).await;
+    }
+    for transfer in store.transfers_in_blocks(blocks).await? {
+        storage.insert_transfer(transfer).await;
+    }
+    Ok(Arc::new(storage))
+}
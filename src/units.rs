@@ -0,0 +1,138 @@
+use ethers::{
+    types::{U256, U512},
+    utils::{format_units, ConversionError},
+};
+use std::cmp::Ordering;
+
+/// Number of decimal places kept when a floating point multiplier from the
+/// configuration is applied to an exact amount (see [`cmp_scaled`]).
+const MULTIPLIER_DECIMALS: u32 = 6;
+
+/// Formats a wei amount as an exact decimal ETH string.
+///
+/// Unlike a conversion to `f64`, no digit is lost: every one of the 18
+/// decimals is kept, and trailing zeros are trimmed.
+///
+/// # Example
+/// ```rust,ignore
+/// assert_eq!(format_eth(U256::exp10(18)), "1");
+/// assert_eq!(format_eth(U256::from(1)), "0.000000000000000001");
+/// ```
+pub fn format_eth(wei: U256) -> String {
+    format_decimal(wei, "ether")
+}
+
+/// Formats a wei amount as an exact decimal Gwei string (1 Gwei = 10⁹ wei).
+pub fn format_gwei(wei: U256) -> String {
+    format_decimal(wei, "gwei")
+}
+
+/// Parses a decimal ETH amount such as `"1.5"` into wei.
+///
+/// # Errors
+/// Returns a [`ConversionError`] if the string is not a non-negative decimal
+/// number, has more than 18 decimals or does not fit into a `U256`. Extra
+/// decimals are rejected rather than truncated.
+pub fn parse_eth(eth: &str) -> Result<U256, ConversionError> {
+    parse_decimal(eth, 18)
+}
+
+/// Parses a decimal Gwei amount such as `"30.5"` into wei.
+///
+/// # Errors
+/// Same as [`parse_eth`], with at most 9 decimals.
+pub fn parse_gwei(gwei: &str) -> Result<U256, ConversionError> {
+    parse_decimal(gwei, 9)
+}
+
+/// Converts a wei amount to ETH as `f64`.
+///
+/// # Notes
+/// * The result is rounded to the precision of `f64`; use it for plots and
+///   logs, never for comparisons or reports. Use [`format_eth`] for exact output.
+/// * Does not panic for amounts above `u128::MAX`.
+pub fn to_eth_f64(wei: U256) -> f64 {
+    format_eth(wei).parse().unwrap_or(f64::MAX)
+}
+
+/// Converts a wei amount to Gwei as `f64`. Same caveats as [`to_eth_f64`].
+pub fn to_gwei_f64(wei: U256) -> f64 {
+    format_gwei(wei).parse().unwrap_or(f64::MAX)
+}
+
+/// Converts a raw amount (wei, token units) to `f64`. Same caveats as [`to_eth_f64`].
+pub fn to_f64(value: U256) -> f64 {
+    if value <= U256::from(u128::MAX) {
+        value.as_u128() as f64
+    } else {
+        value.to_string().parse().unwrap_or(f64::MAX)
+    }
+}
+
+/// Exact comparison of `value` with `multiplier * base`.
+///
+/// The multiplier is a configuration value such as `k_local = 5.0`; it is
+/// rounded to six decimals and both sides are compared as 512-bit integers,
+/// so the comparison neither loses precision nor overflows.
+///
+/// # Example
+/// ```rust,ignore
+/// // Is the transaction more than five times the sender's mean?
+/// let large = cmp_scaled(tx.value, 5.0, mean) == Ordering::Greater;
+/// ```
+pub fn cmp_scaled(value: U256, multiplier: f64, base: U256) -> Ordering {
+    let scale = U256::exp10(MULTIPLIER_DECIMALS as usize);
+    let multiplier = U256::from(
+        (multiplier.max(0.0) * 10f64.powi(MULTIPLIER_DECIMALS as i32)).round() as u128,
+    );
+    value.full_mul(scale).cmp(&base.full_mul(multiplier))
+}
+
+/// Exact arithmetic mean of `values`, rounded down. Returns zero for an empty slice.
+///
+/// The sum is accumulated in 512 bits, so it cannot overflow even for amounts
+/// close to `U256::MAX`.
+pub fn mean(values: &[U256]) -> U256 {
+    if values.is_empty() {
+        return U256::zero();
+    }
+    let sum = values
+        .iter()
+        .fold(U512::zero(), |acc, value| acc + U512::from(*value));
+    let mean = sum / U512::from(values.len());
+    U256::try_from(mean).expect("mean of U256 values fits into U256")
+}
+
+/// Sum of `values`, saturating at `U256::MAX`.
+pub fn saturating_sum<'a, I>(values: I) -> U256
+where
+    I: IntoIterator<Item = &'a U256>,
+{
+    values
+        .into_iter()
+        .fold(U256::zero(), |acc, value| acc.saturating_add(*value))
+}
+
+fn parse_decimal(amount: &str, decimals: usize) -> Result<U256, ConversionError> {
+    let (whole, fraction) = amount.trim().split_once('.').unwrap_or((amount.trim(), ""));
+    if fraction.len() > decimals {
+        return Err(ConversionError::ParseOverflow);
+    }
+    let digits = format!("{}{:0<width$}", whole, fraction, width = decimals);
+    Ok(U256::from_dec_str(&digits)?)
+}
+
+fn format_decimal(wei: U256, unit: &str) -> String {
+    let formatted = format_units(wei, unit).expect("U256 always fits the ether and gwei units");
+    match formatted.split_once('.') {
+        Some((whole, fraction)) => {
+            let fraction = fraction.trim_end_matches('0');
+            if fraction.is_empty() {
+                whole.to_string()
+            } else {
+                format!("{}.{}", whole, fraction)
+            }
+        }
+        None => formatted,
+    }
+}
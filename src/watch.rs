@@ -0,0 +1,426 @@
+use crate::{
+    address::{format_address, parse_address},
+    detector::{AnalysisContext, DetectorRegistry, Findings},
+    models::{Anomaly, BusinessPattern, SharedTxStorage, TxRef, TxStorage},
+    scanner::{find_reorg, scan_blocks, FailedBlock, ScanOptions},
+};
+use chrono::{DateTime, Utc};
+use ethers::providers::Middleware;
+use log::{info, warn};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::ControlFlow;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::sleep;
+
+/// Blocks [`watch`] keeps by default: about 3.3 hours of mainnet blocks, more
+/// than the one-hour windows of the frequency and structuring detectors.
+pub const DEFAULT_RETAIN_BLOCKS: u64 = 1000;
+
+/// Polls a block may fail on before [`watch`] gives up on it by default.
+pub const DEFAULT_MAX_FAILED_POLLS: u32 = 5;
+
+/// Options of [`watch`].
+///
+/// # Fields
+/// * `poll_interval` – Delay between two polls of the chain head (default 12 s,
+///   one mainnet slot).
+/// * `retain_blocks` – Keep only the transactions of this many most recent
+///   blocks in the storage (default [`DEFAULT_RETAIN_BLOCKS`]). Every poll reruns
+///   the detectors over them, so this bounds both memory and the time of a poll.
+/// * `max_failed_polls` – Give up on a block once it failed on this many polls
+///   (default [`DEFAULT_MAX_FAILED_POLLS`]), so that a block the node can never
+///   serve is not requested forever. `1` never retries a failed block.
+/// * `scan` – How each new block is fetched, see [`ScanOptions`]. Its
+///   `reorg_depth` bounds the reorg check made on every poll.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WatchOptions {
+    pub poll_interval: Duration,
+    pub retain_blocks: u64,
+    pub max_failed_polls: u32,
+    pub scan: ScanOptions,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        Self {
+            poll_interval: Duration::from_secs(12),
+            retain_blocks: DEFAULT_RETAIN_BLOCKS,
+            max_failed_polls: DEFAULT_MAX_FAILED_POLLS,
+            scan: ScanOptions::default(),
+        }
+    }
+}
+
+/// What [`watch`] reports after processing a new head.
+///
+/// # Fields
+/// * `head` – Number of the processed head.
+/// * `findings` – Findings that were not reported before.
+/// * `retracted` – Previously reported findings that referenced orphaned
+///   transactions or their senders and are no longer produced by the detectors.
+/// * `fork_block` – First orphaned block if a chain reorganization was undone
+///   during this poll.
+/// * `orphaned` – Transactions removed by that reorganization. Those included
+///   again by the new branch are back in the storage.
+/// * `tracked` – Number of reported findings the watcher remembers to avoid
+///   reporting them twice. Findings whose transactions were pruned are forgotten.
+/// * `given_up` – Blocks the watcher stopped retrying during this poll, with
+///   their last failure: they failed on `max_failed_polls` polls or fell out of
+///   the retained blocks. Their transactions are missing from the analysis.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WatchUpdate {
+    pub head: u64,
+    pub findings: Findings,
+    pub retracted: Findings,
+    pub fork_block: Option<u64>,
+    pub orphaned: Vec<TxRef>,
+    pub tracked: usize,
+    pub given_up: Vec<FailedBlock>,
+}
+
+/// Follows the chain head, ingests every new block into `storage` and runs the
+/// detectors of `registry` after each batch of new blocks.
+///
+/// # Overview
+/// Every `options.poll_interval`, the watcher asks the node for the latest block
+/// number and checks the recent blocks for a chain reorganization with
+/// [`find_reorg`]. Orphaned blocks are rolled back and scanned again together
+/// with the new blocks since the last poll; blocks that fail are retried on the
+/// next polls until `options.max_failed_polls` is reached or they fall out of
+/// the retained blocks, and are then reported in [`WatchUpdate::given_up`]. The enabled detectors then run over the whole storage, so sender
+/// baselines and time windows include the history, and only findings that were
+/// not reported before are passed to `on_update`.
+///
+/// Detection is not incremental: every poll reruns the detectors over all the
+/// retained blocks, rebuilding the dataset statistics. Blocks older than the
+/// last `options.retain_blocks` are pruned first, so memory and the time of a
+/// poll stay bounded however long the watcher runs. The retention should cover
+/// the longest detector window. The `blacklist` detector keeps its sanctions
+/// lists across polls and reloads them after their TTL.
+///
+/// # Parameters
+/// * `provider` – A reference-counted Ethereum provider implementing [`Middleware`].
+/// * `start_block` – First block to ingest; usually the current head.
+/// * `registry` – Detectors to run, with their configuration and selection.
+/// * `storage` – Storage the blocks are added to.
+/// * `options` – Poll interval, retention and scan options, see [`WatchOptions`].
+/// * `on_update` – Called once per processed head with a [`WatchUpdate`]
+///   (possibly without findings). Return [`ControlFlow::Break`] to stop watching.
+///
+/// # Notes
+/// * A finding is new if no finding with the same [`Anomaly::identity`] was
+///   reported before: a large transaction is reported once even though the
+///   sender's median and its z-score change with every new transaction. A
+///   `HighFrequency` or `Structuring` anomaly is reported again only for a
+///   window that does not overlap the one reported last.
+/// * After a reorg, findings are re-evaluated: a reported finding about an
+///   orphaned transaction or its sender is retracted unless the detectors still
+///   produce it, and may be reported again later if the new branch includes the
+///   transaction in another block.
+/// * Errors of the node are logged and retried on the next poll; the watcher
+///   only stops when `on_update` breaks. Only a failed block is eventually
+///   given up on.
+///
+/// # Example
+/// ```rust,ignore
+/// let head = provider.get_block_number().await?.as_u64();
+/// watch(&provider, head, &registry, &storage, &WatchOptions::default(), |update| {
+///     for anomaly in &update.retracted.anomalies {
+///         println!("block {}: retracted {:?}", update.head, anomaly);
+///     }
+///     for anomaly in &update.findings.anomalies {
+///         println!("block {}: {:?}", update.head, anomaly);
+///     }
+///     ControlFlow::Continue(())
+/// })
+/// .await;
+/// ```
+pub async fn watch<M, F>(
+    provider: &Arc<M>,
+    start_block: u64,
+    registry: &DetectorRegistry,
+    storage: &SharedTxStorage,
+    options: &WatchOptions,
+    mut on_update: F,
+) where
+    M: Middleware + 'static,
+    F: FnMut(WatchUpdate) -> ControlFlow<()>,
+{
+    let mut next_block = start_block;
+    // Failed blocks with the number of polls they failed on and their last failure.
+    let mut failed: BTreeMap<u64, (u32, FailedBlock)> = BTreeMap::new();
+    let mut reported = Reported::default();
+
+    loop {
+        let head = match provider.get_block_number().await {
+            Ok(head) => head.as_u64(),
+            Err(err) => {
+                warn!("Cannot read the chain head: {}", err);
+                sleep(options.poll_interval).await;
+                continue;
+            }
+        };
+
+        let mut fork_block = None;
+        let mut orphaned = Vec::new();
+        match find_reorg(provider, storage, options.scan.reorg_depth).await {
+            Err(err) => warn!("Cannot check for a chain reorganization: {}", err),
+            Ok(None) => {}
+            Ok(Some(fork)) => {
+                orphaned = storage.rollback_from(fork).await;
+                warn!(
+                    "Chain reorganization at block {}: {} transactions orphaned",
+                    fork,
+                    orphaned.len()
+                );
+                failed.retain(|block_number, _| *block_number < fork);
+                next_block = next_block.min(fork);
+                fork_block = Some(fork);
+            }
+        }
+
+        if head >= next_block || !failed.is_empty() || fork_block.is_some() {
+            // Failed blocks that would be pruned right away are not worth retrying.
+            let horizon = (head + 1).saturating_sub(options.retain_blocks);
+            let retried = failed.split_off(&horizon);
+            let mut given_up: Vec<FailedBlock> =
+                failed.into_values().map(|(_, last)| last).collect();
+
+            let blocks: Vec<u64> = retried.keys().copied().chain(next_block..=head).collect();
+            info!("Ingesting {} blocks up to head {}", blocks.len(), head);
+
+            let report = scan_blocks(provider, blocks, storage, &options.scan, |_| Ok(())).await;
+            failed = BTreeMap::new();
+            for last in report.failed {
+                let polls = retried
+                    .get(&last.block_number)
+                    .map_or(0, |(polls, _)| *polls)
+                    + 1;
+                if polls >= options.max_failed_polls {
+                    given_up.push(last);
+                } else {
+                    failed.insert(last.block_number, (polls, last));
+                }
+            }
+            for block in &given_up {
+                warn!(
+                    "Giving up on block {}, its transactions are not analyzed: {}",
+                    block.block_number, block.error
+                );
+            }
+            next_block = next_block.max(head + 1);
+
+            let pruned = storage.prune_before(horizon).await;
+            reported.forget_pruned(&pruned, storage).await;
+
+            let ctx = AnalysisContext::new(Arc::clone(storage));
+            let current = registry.run(&ctx).await;
+            let retracted = reported.retract(&current, &orphaned);
+            let findings = reported.unreported(current);
+            let update = WatchUpdate {
+                head,
+                findings,
+                retracted,
+                fork_block,
+                orphaned,
+                tracked: reported.len(),
+                given_up,
+            };
+            if on_update(update).is_break() {
+                return;
+            }
+        }
+        sleep(options.poll_interval).await;
+    }
+}
+
+/// A finding the watcher keeps track of, see [`Anomaly::identity`].
+trait Finding: Clone {
+    fn identity(&self) -> String;
+    fn window(&self) -> Option<(DateTime<Utc>, DateTime<Utc>)>;
+}
+
+impl Finding for Anomaly {
+    fn identity(&self) -> String {
+        Anomaly::identity(self)
+    }
+
+    fn window(&self) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+        Anomaly::window(self)
+    }
+}
+
+impl Finding for BusinessPattern {
+    fn identity(&self) -> String {
+        BusinessPattern::identity(self)
+    }
+
+    fn window(&self) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+        None
+    }
+}
+
+/// `true` if `a` and `b` are the same finding: same identity and, for
+/// windowed anomalies, overlapping windows.
+fn same_finding<T: Finding>(a: &T, b: &T) -> bool {
+    let overlap = match (a.window(), b.window()) {
+        (Some((a_start, a_end)), Some((b_start, b_end))) => a_start < b_end && b_start < a_end,
+        _ => true,
+    };
+    overlap && a.identity() == b.identity()
+}
+
+/// Findings reported so far, grouped by identity.
+#[derive(Default)]
+struct Reported {
+    anomalies: HashMap<String, Vec<Anomaly>>,
+    patterns: HashMap<String, Vec<BusinessPattern>>,
+}
+
+impl Reported {
+    /// Keeps the findings that were not reported yet and records them.
+    fn unreported(&mut self, findings: Findings) -> Findings {
+        Findings {
+            anomalies: unreported(findings.anomalies, &mut self.anomalies),
+            patterns: unreported(findings.patterns, &mut self.patterns),
+        }
+    }
+
+    /// Number of recorded findings.
+    fn len(&self) -> usize {
+        self.anomalies.values().map(Vec::len).sum::<usize>()
+            + self.patterns.values().map(Vec::len).sum::<usize>()
+    }
+
+    /// Forgets the recorded findings whose evidence was pruned from `storage`,
+    /// so that a long-running watcher remembers no more than it retains:
+    /// findings about a `pruned` transaction, windows that end before the
+    /// oldest retained transaction and senders without a retained transaction.
+    /// The detectors cannot produce these findings again.
+    async fn forget_pruned(&mut self, pruned: &[TxRef], storage: &TxStorage) {
+        if pruned.is_empty() {
+            return;
+        }
+        let hashes: HashSet<String> = pruned.iter().map(|tx| format!("{:?}", tx.hash)).collect();
+        let oldest = storage
+            .all_txs
+            .read()
+            .await
+            .iter()
+            .map(|tx| tx.timestamp)
+            .min();
+        let is_pruned = |tx_hash: Option<&str>,
+                         sender: Option<&str>,
+                         window: Option<(DateTime<Utc>, DateTime<Utc>)>| {
+            if let Some(tx_hash) = tx_hash {
+                hashes.contains(tx_hash)
+            } else if let Some((_, end)) = window {
+                oldest.is_none_or(|oldest| end <= oldest)
+            } else if let Some(sender) = sender {
+                parse_address(sender).is_ok_and(|sender| {
+                    !storage.by_sender.contains_key(&sender)
+                        && !storage.transfers_by_sender.contains_key(&sender)
+                })
+            } else {
+                false
+            }
+        };
+
+        forget(&mut self.anomalies, |anomaly| {
+            is_pruned(anomaly.tx_hash(), anomaly.sender(), anomaly.window())
+        });
+        forget(&mut self.patterns, |pattern| {
+            is_pruned(pattern.tx_hash(), pattern.sender(), None)
+        });
+    }
+
+    /// Removes and returns the reported findings that reference one of the
+    /// `orphaned` transactions or their senders and are missing from `current`.
+    fn retract(&mut self, current: &Findings, orphaned: &[TxRef]) -> Findings {
+        if orphaned.is_empty() {
+            return Findings::default();
+        }
+        let hashes: HashSet<String> = orphaned.iter().map(|tx| format!("{:?}", tx.hash)).collect();
+        let senders: HashSet<String> = orphaned.iter().map(|tx| format_address(&tx.from)).collect();
+        let touches = |tx_hash: Option<&str>, sender: Option<&str>| {
+            tx_hash.is_some_and(|tx_hash| hashes.contains(tx_hash))
+                || sender.is_some_and(|sender| senders.contains(sender))
+        };
+
+        Findings {
+            anomalies: retract(&mut self.anomalies, &current.anomalies, |anomaly| {
+                touches(anomaly.tx_hash(), anomaly.sender())
+            }),
+            patterns: retract(&mut self.patterns, &current.patterns, |pattern| {
+                touches(pattern.tx_hash(), pattern.sender())
+            }),
+        }
+    }
+}
+
+/// Forgets the findings matching `pruned`.
+fn forget<T, F>(reported: &mut HashMap<String, Vec<T>>, pruned: F)
+where
+    F: Fn(&T) -> bool,
+{
+    reported.retain(|_, known| {
+        known.retain(|finding| !pruned(finding));
+        !known.is_empty()
+    });
+}
+
+/// Keeps the findings that are not in `reported` and records them.
+///
+/// A finding that was reported before replaces its recorded version, so the
+/// window of an ongoing activity anomaly follows the activity.
+fn unreported<T: Finding>(findings: Vec<T>, reported: &mut HashMap<String, Vec<T>>) -> Vec<T> {
+    findings
+        .into_iter()
+        .filter(|finding| {
+            let known = reported.entry(finding.identity()).or_default();
+            match known.iter_mut().find(|known| same_finding(*known, finding)) {
+                Some(known) => {
+                    *known = finding.clone();
+                    false
+                }
+                None => {
+                    known.push(finding.clone());
+                    true
+                }
+            }
+        })
+        .collect()
+}
+
+/// Removes the reported findings matching `touches` that are not in `current`.
+fn retract<T, F>(reported: &mut HashMap<String, Vec<T>>, current: &[T], touches: F) -> Vec<T>
+where
+    T: Finding,
+    F: Fn(&T) -> bool,
+{
+    let mut by_identity: HashMap<String, Vec<&T>> = HashMap::new();
+    for finding in current {
+        by_identity
+            .entry(finding.identity())
+            .or_default()
+            .push(finding);
+    }
+    let mut retracted = Vec::new();
+    for (identity, known) in reported.iter_mut() {
+        let current = by_identity
+            .get(identity)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        let (stale, kept): (Vec<T>, Vec<T>) = known.drain(..).partition(|finding| {
+            touches(finding)
+                && !current
+                    .iter()
+                    .any(|current| same_finding(*current, finding))
+        });
+        *known = kept;
+        retracted.extend(stale);
+    }
+    reported.retain(|_, known| !known.is_empty());
+    retracted
+}
@@ -1,95 +1,120 @@
 #[cfg(test)]
 mod test {
-    use super::*;
-    use chrono::{Duration, Utc};
-    use dashmap::DashMap;
+    use async_trait::async_trait;
+    use chrono::{DateTime, Duration, Utc};
     use eth_analyzer::{
+        address::{checksummed, format_address, parse_address},
         analize::{
-            detect_active_traders, detect_arbitrage, detect_dex_trade, detect_high_fee,
-            detect_high_frequency, detect_large_tx, detect_liquid_provider, detect_nft_activity,
+            detect_arbitrage, detect_blacklist_adresses, detect_dex_trade, detect_high_fee,
+            detect_high_frequency, detect_high_priority_fee, detect_large_tx, detect_nft_activity,
             detect_regular_payments, detect_structuring, detect_time_anomalies, detect_unusual_op,
             detect_whales,
         },
+        checkpoint::Checkpoint,
+        config::{
+            AnalyzerConfig, ConfigError, HighFeeConfig, HighFrequencyConfig, HighPriorityFeeConfig,
+            LargeTxConfig, RegularPaymentsConfig, SanctionsConfig, SanctionsSourceConfig,
+            StructuringConfig, TimeAnomalyConfig, UnusualOpConfig, WhalesConfig,
+        },
         csv::{export_anomalies_csv, export_patterns_csv},
-        models::{Anomaly, BusinessPattern, Severity, TransactionRecord, TxStorage, AnomalyCsv, BusinessPatternCsv},
+        detector::{
+            AnalysisContext, BlacklistDetector, Detector, DetectorKind, DetectorRegistry, Findings,
+            RegistryError,
+        },
+        fixture::{RecordingClient, ReplayClient},
+        import::{
+            group_by_block, import_file, read_csv, read_ndjson, read_rpc_blocks, ImportError,
+            ImportFormat,
+        },
+        models::{
+            Anomaly, BlockHeader, BusinessPattern, Severity, TokenTransfer, TransactionRecord,
+            TransferRef, TxRef, TxStorage,
+        },
+        query::TxQuery,
+        sanctions::{
+            load_sanctions, parse_addresses, FileSource, SanctionsError, SanctionsList,
+            SanctionsSource, UrlSource,
+        },
+        scanner::{
+            find_reorg, reconcile_reorg, scan_block, scan_blocks, store_block, FailedBlock,
+            ScanOptions, TRANSFER_EVENT,
+        },
+        stats::{DatasetStats, History},
+        store::{load_storage, SqliteStore, StoreError, TxStore, SCHEMA_VERSION},
+        units::{cmp_scaled, format_eth, mean, parse_eth, parse_gwei, to_eth_f64},
+        watch::{watch, WatchOptions, WatchUpdate},
+    };
+    use ethers::{
+        providers::{JsonRpcError, MockProvider, MockResponse, Provider},
+        types::{
+            Address, Block, Bytes, Filter, Log, Transaction, TransactionReceipt, H160, H256, U256,
+            U64,
+        },
+        utils::keccak256,
     };
-    use ethers::types::H160;
-    use once_cell::sync::Lazy;
+    use std::fs;
     use std::{collections::HashSet, sync::Arc};
-    use tokio::sync::RwLock;
     use tempfile::NamedTempFile;
-    use std::fs;
+    use tokio::sync::RwLock;
+
+    /// Wraps records for tests that fill single collections of the storage.
+    fn shared(txs: Vec<TransactionRecord>) -> Vec<TxRef> {
+        txs.into_iter().map(Arc::new).collect()
+    }
 
     fn create_test_storage() -> Arc<TxStorage> {
-        Arc::new(TxStorage {
-            by_sender: DashMap::new(),
-            by_reciever: DashMap::new(),
-            all_txs: RwLock::new(vec![]),
-        })
+        Arc::new(TxStorage::new())
     }
 
-    fn txs_in_interval(
-        txs: &[TransactionRecord],
-        start: chrono::DateTime<Utc>,
-        end: chrono::DateTime<Utc>,
-    ) -> Vec<TransactionRecord> {
-        txs.iter()
-            .filter(|tx| {
-                if let Ok(ts) = chrono::DateTime::parse_from_rfc3339(&tx.timestamp) {
-                    let ts_utc = ts.with_timezone(&Utc);
-                    ts_utc >= start && ts_utc <= end
-                } else {
-                    false
-                }
-            })
-            .cloned()
-            .collect()
+    fn eth(value: &str) -> U256 {
+        parse_eth(value).unwrap()
     }
 
-    fn create_storage_with_txs(txs: Vec<TransactionRecord>) -> Arc<TxStorage> {
-        let storage = TxStorage {
-            by_sender: DashMap::new(),
-            by_reciever: DashMap::new(),
-            all_txs: RwLock::new(vec![]),
-        };
-        for tx in &txs {
-            storage
-                .by_sender
-                .entry(tx.from.clone())
-                .or_default()
-                .push(tx.clone());
-            if let Some(to) = &tx.to {
-                storage
-                    .by_reciever
-                    .entry(to.clone())
-                    .or_default()
-                    .push(tx.clone());
-            }
-        }
-        tokio::runtime::Handle::current().block_on(async {
-            let mut all_txs = storage.all_txs.write().await;
-            all_txs.extend(txs);
-        });
-        Arc::new(storage)
+    fn gwei(value: &str) -> U256 {
+        parse_gwei(value).unwrap()
+    }
+
+    /// Deterministic transaction hash derived from a readable test name.
+    fn hash(name: &str) -> H256 {
+        H256::from(keccak256(name))
+    }
+
+    /// Parses `0x` addresses, otherwise derives a deterministic address from the name.
+    fn addr(name: &str) -> Address {
+        name.parse()
+            .unwrap_or_else(|_| Address::from_slice(&keccak256(name)[12..]))
+    }
+
+    /// Full hex form used by anomalies and patterns.
+    fn hex<T: std::fmt::Debug>(value: T) -> String {
+        format!("{:?}", value)
     }
 
     fn make_tx(
-        hash: &str,
+        name: &str,
         from: &str,
         to: Option<&str>,
-        value: f64,
-        timestamp: String,
+        value: U256,
+        timestamp: DateTime<Utc>,
     ) -> TransactionRecord {
         TransactionRecord {
-            hash: hash.to_string(),
-            from: from.to_string(),
-            to: to.map(str::to_string),
+            hash: hash(name),
+            from: addr(from),
+            to: to.map(addr),
             value,
             gas: 21000,
-            gas_price_gwei: 50.0,
+            gas_price: gwei("50"),
             block_number: 1,
             timestamp,
             input: "0x".to_string(),
+            gas_used: None,
+            effective_gas_price: None,
+            status: None,
+            contract_address: None,
+            tx_type: None,
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+            base_fee_per_gas: None,
         }
     }
 
@@ -98,49 +123,74 @@ mod test {
         let storage = create_test_storage();
 
         let tx1 = TransactionRecord {
-            hash: "tx1".to_string(),
-            from: "sender1".to_string(),
-            to: Some("receiver1".to_string()),
-            value: 1000.0,
+            hash: hash("tx1"),
+            from: addr("sender1"),
+            to: Some(addr("receiver1")),
+            value: eth("1000"),
             gas: 21000,
-            gas_price_gwei: 50.0,
+            gas_price: gwei("50"),
             block_number: 1,
-            timestamp: Utc::now().to_rfc3339(),
+            timestamp: Utc::now(),
             input: "".to_string(),
+            gas_used: None,
+            effective_gas_price: None,
+            status: None,
+            contract_address: None,
+            tx_type: None,
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+            base_fee_per_gas: None,
         };
 
         let tx2 = TransactionRecord {
-            hash: "tx2".to_string(),
-            from: "sender1".to_string(),
-            to: Some("receiver1".to_string()),
-            value: 1.0,
+            hash: hash("tx2"),
+            from: addr("sender1"),
+            to: Some(addr("receiver1")),
+            value: eth("1"),
             gas: 2100,
-            gas_price_gwei: 10.0,
+            gas_price: gwei("10"),
             block_number: 1,
-            timestamp: Utc::now().to_rfc3339(),
+            timestamp: Utc::now(),
             input: "".to_string(),
+            gas_used: None,
+            effective_gas_price: None,
+            status: None,
+            contract_address: None,
+            tx_type: None,
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+            base_fee_per_gas: None,
         };
 
         let tx3 = TransactionRecord {
-            hash: "tx3".to_string(),
-            from: "sender1".to_string(),
-            to: Some("receiver1".to_string()),
-            value: 20.0,
+            hash: hash("tx3"),
+            from: addr("sender1"),
+            to: Some(addr("receiver1")),
+            value: eth("20"),
             gas: 2100,
-            gas_price_gwei: 10.0,
+            gas_price: gwei("10"),
             block_number: 1,
-            timestamp: Utc::now().to_rfc3339(),
+            timestamp: Utc::now(),
             input: "".to_string(),
+            gas_used: None,
+            effective_gas_price: None,
+            status: None,
+            contract_address: None,
+            tx_type: None,
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+            base_fee_per_gas: None,
         };
 
         {
             let mut all_txs = storage.all_txs.write().await;
-            all_txs.push(tx1);
-            all_txs.push(tx2);
-            all_txs.push(tx3);
+            all_txs.push(Arc::new(tx1));
+            all_txs.push(Arc::new(tx2));
+            all_txs.push(Arc::new(tx3));
         }
 
-        let anomalies = detect_large_tx(&storage).await;
+        let ctx = AnalysisContext::new(storage.clone());
+        let anomalies = detect_large_tx(&ctx, &LargeTxConfig::default()).await;
 
         assert_eq!(anomalies.len(), 1);
         let anomaly = &anomalies[0];
@@ -148,7 +198,7 @@ mod test {
             Anomaly::LargeTx {
                 severity, tx_hash, ..
             } => {
-                assert_eq!(tx_hash, "tx1");
+                assert_eq!(*tx_hash, hex(hash("tx1")));
                 assert_eq!(*severity, Severity::Weak);
             }
             _ => panic!("Wrong Anomaly type"),
@@ -161,25 +211,38 @@ mod test {
 
         let now = Utc::now();
 
-        let sender = "sender1".to_string();
+        let sender = addr("sender1");
         let mut txs = Vec::new();
         for i in 0..11 {
             txs.push(TransactionRecord {
-                hash: format!("tx{}", i),
-                from: sender.clone(),
-                to: Some("receiver1".to_string()),
-                value: 1.0,
+                hash: hash(&format!("tx{}", i)),
+                from: sender,
+                to: Some(addr("receiver1")),
+                value: eth("1"),
                 gas: 21000,
-                gas_price_gwei: 50.0,
+                gas_price: gwei("50"),
                 block_number: 1,
-                timestamp: (now - Duration::minutes(i)).to_rfc3339(),
+                timestamp: (now - Duration::minutes(i)),
                 input: "".to_string(),
+                gas_used: None,
+                effective_gas_price: None,
+                status: None,
+                contract_address: None,
+                tx_type: None,
+                max_fee_per_gas: None,
+                max_priority_fee_per_gas: None,
+                base_fee_per_gas: None,
             });
         }
 
-        storage.by_sender.insert(sender.clone(), txs);
+        storage.by_sender.insert(sender, shared(txs));
 
-        let anomalies = detect_high_frequency(&storage).await;
+        let config = HighFrequencyConfig {
+            threshold: 10,
+            ..HighFrequencyConfig::default()
+        };
+        let ctx = AnalysisContext::new(storage.clone());
+        let anomalies = detect_high_frequency(&ctx, &config).await;
 
         assert_eq!(anomalies.len(), 1);
         let anomaly = &anomalies[0];
@@ -187,7 +250,7 @@ mod test {
             Anomaly::HighFrequency {
                 sender: s, count, ..
             } => {
-                assert_eq!(s, "sender1");
+                assert_eq!(*s, hex(addr("sender1")));
                 assert_eq!(*count, 11);
             }
             _ => panic!("Wrong anomaly type"),
@@ -199,58 +262,87 @@ mod test {
         let storage = create_test_storage();
 
         let tx1 = TransactionRecord {
-            hash: "tx1".to_string(),
-            from: "sender1".to_string(),
-            to: Some("receiver1".to_string()),
-            value: 1.0,
+            hash: hash("tx1"),
+            from: addr("sender1"),
+            to: Some(addr("receiver1")),
+            value: eth("1"),
             gas: 21,
-            gas_price_gwei: 50.0,
+            gas_price: gwei("50"),
             block_number: 1,
-            timestamp: Utc::now().to_rfc3339(),
+            timestamp: Utc::now(),
             input: "".to_string(),
+            gas_used: None,
+            effective_gas_price: None,
+            status: None,
+            contract_address: None,
+            tx_type: None,
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+            base_fee_per_gas: None,
         };
 
         let tx2 = TransactionRecord {
-            hash: "tx2".to_string(),
-            from: "sender1".to_string(),
-            to: Some("receiver1".to_string()),
-            value: 1.0,
+            hash: hash("tx2"),
+            from: addr("sender1"),
+            to: Some(addr("receiver1")),
+            value: eth("1"),
             gas: 21,
-            gas_price_gwei: 1.0,
+            gas_price: gwei("1"),
             block_number: 1,
-            timestamp: Utc::now().to_rfc3339(),
+            timestamp: Utc::now(),
             input: "".to_string(),
+            gas_used: None,
+            effective_gas_price: None,
+            status: None,
+            contract_address: None,
+            tx_type: None,
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+            base_fee_per_gas: None,
         };
 
         let tx3 = TransactionRecord {
-            hash: "tx3".to_string(),
-            from: "sender1".to_string(),
-            to: Some("receiver1".to_string()),
-            value: 20.0,
+            hash: hash("tx3"),
+            from: addr("sender1"),
+            to: Some(addr("receiver1")),
+            value: eth("20"),
             gas: 21,
-            gas_price_gwei: 1.0,
+            gas_price: gwei("1"),
             block_number: 1,
-            timestamp: Utc::now().to_rfc3339(),
+            timestamp: Utc::now(),
             input: "".to_string(),
+            gas_used: None,
+            effective_gas_price: None,
+            status: None,
+            contract_address: None,
+            tx_type: None,
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+            base_fee_per_gas: None,
         };
 
         {
             let mut all_txs = storage.all_txs.write().await;
-            all_txs.push(tx1);
-            all_txs.push(tx2);
-            all_txs.push(tx3);
+            all_txs.push(Arc::new(tx1));
+            all_txs.push(Arc::new(tx2));
+            all_txs.push(Arc::new(tx3));
         }
 
-        let anomalies = detect_high_fee(&storage).await;
+        let ctx = AnalysisContext::new(storage.clone());
+        let anomalies = detect_high_fee(&ctx, &HighFeeConfig::default()).await;
 
         assert_eq!(anomalies.len(), 1);
         let anomaly = &anomalies[0];
         match anomaly {
             Anomaly::HighFee {
-                severity, tx_hash, ..
+                severity,
+                tx_hash,
+                fee,
+                ..
             } => {
-                assert_eq!(tx_hash, "tx1");
+                assert_eq!(*tx_hash, hex(hash("tx1")));
                 assert_eq!(*severity, Severity::Weak);
+                assert_eq!(*fee, gwei("1050"));
             }
             _ => panic!("Wrong Anomaly type"),
         }
@@ -261,77 +353,134 @@ mod test {
         let storage = create_test_storage();
 
         let tx1 = TransactionRecord {
-            hash: "tx1".to_string(),
-            from: "sender1".to_string(),
-            to: Some("receiver1".to_string()),
-            value: 1.0,
+            hash: hash("tx1"),
+            from: addr("sender1"),
+            to: Some(addr("receiver1")),
+            value: eth("1"),
             gas: 21,
-            gas_price_gwei: 500.0,
+            gas_price: gwei("500"),
             block_number: 1,
-            timestamp: Utc::now().to_rfc3339(),
+            timestamp: Utc::now(),
             input: "0xffwhkfhk".to_string(),
+            gas_used: None,
+            effective_gas_price: None,
+            status: None,
+            contract_address: None,
+            tx_type: None,
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+            base_fee_per_gas: None,
         };
 
         let tx2 = TransactionRecord {
-            hash: "tx2".to_string(),
-            from: "sender1".to_string(),
-            to: Some("receiver1".to_string()),
-            value: 1.0,
+            hash: hash("tx2"),
+            from: addr("sender1"),
+            to: Some(addr("receiver1")),
+            value: eth("1"),
             gas: 21,
-            gas_price_gwei: 50.0,
+            gas_price: gwei("50"),
             block_number: 1,
-            timestamp: Utc::now().to_rfc3339(),
+            timestamp: Utc::now(),
             input: "0xfddflfl".to_string(),
+            gas_used: None,
+            effective_gas_price: None,
+            status: None,
+            contract_address: None,
+            tx_type: None,
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+            base_fee_per_gas: None,
         };
 
         let tx3 = TransactionRecord {
-            hash: "tx3".to_string(),
-            from: "sender1".to_string(),
-            to: Some("receiver1".to_string()),
-            value: 1000.0,
+            hash: hash("tx3"),
+            from: addr("sender1"),
+            to: Some(addr("receiver1")),
+            value: eth("1000"),
             gas: 21,
-            gas_price_gwei: 50.0,
+            gas_price: gwei("50"),
             block_number: 1,
-            timestamp: Utc::now().to_rfc3339(),
+            timestamp: Utc::now(),
             input: "0xfpjrfpjw".to_string(),
+            gas_used: None,
+            effective_gas_price: None,
+            status: None,
+            contract_address: None,
+            tx_type: None,
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+            base_fee_per_gas: None,
         };
 
         let tx4 = TransactionRecord {
-            hash: "tx4".to_string(),
-            from: "sender1".to_string(),
-            to: Some("receiver1".to_string()),
-            value: 1.0,
+            hash: hash("tx4"),
+            from: addr("sender1"),
+            to: Some(addr("receiver1")),
+            value: eth("1"),
             gas: 21,
-            gas_price_gwei: 50.0,
+            gas_price: gwei("50"),
             block_number: 1,
-            timestamp: Utc::now().to_rfc3339(),
+            timestamp: Utc::now(),
             input: "0xfwrhfwurhf".to_string(),
+            gas_used: None,
+            effective_gas_price: None,
+            status: None,
+            contract_address: None,
+            tx_type: None,
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+            base_fee_per_gas: None,
         };
 
         let tx5 = TransactionRecord {
-            hash: "tx5".to_string(),
-            from: "sender1".to_string(),
-            to: Some("receiver1".to_string()),
-            value: 1.0,
+            hash: hash("tx5"),
+            from: addr("sender1"),
+            to: Some(addr("receiver1")),
+            value: eth("1"),
             gas: 21,
-            gas_price_gwei: 50.0,
+            gas_price: gwei("50"),
             block_number: 1,
-            timestamp: Utc::now().to_rfc3339(),
+            timestamp: Utc::now(),
             input: "3xgkfkjsfuwh".to_string(),
+            gas_used: None,
+            effective_gas_price: None,
+            status: None,
+            contract_address: None,
+            tx_type: None,
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+            base_fee_per_gas: None,
         };
 
         {
             let mut all_txs = storage.all_txs.write().await;
-            all_txs.push(tx1);
-            all_txs.push(tx2);
-            all_txs.push(tx3);
-            all_txs.push(tx4);
-            all_txs.push(tx5);
+            all_txs.push(Arc::new(tx1));
+            all_txs.push(Arc::new(tx2));
+            all_txs.push(Arc::new(tx3));
+            all_txs.push(Arc::new(tx4));
+            all_txs.push(Arc::new(tx5));
         }
 
-        let anomalies = detect_unusual_op(&storage).await;
+        let ctx = AnalysisContext::new(storage.clone());
+        let anomalies = detect_unusual_op(&ctx, &UnusualOpConfig::default()).await;
 
         assert_eq!(anomalies.len(), 3);
+
+        // Transactions flagged earlier in the same run are not reported again,
+        // while a fresh context starts with nothing flagged.
+        let ctx = AnalysisContext::new(storage.clone());
+        ctx.flag(hash("tx3")).await;
+        let anomalies = detect_unusual_op(&ctx, &UnusualOpConfig::default()).await;
+        assert_eq!(anomalies.len(), 2);
+
+        let ctx = AnalysisContext::new(storage);
+        assert!(!ctx.is_flagged(hash("tx3")).await);
+        assert_eq!(
+            detect_unusual_op(&ctx, &UnusualOpConfig::default())
+                .await
+                .len(),
+            3
+        );
     }
 
     #[tokio::test]
@@ -349,32 +498,41 @@ mod test {
                 &format!("hash{}", i),
                 "sender1",
                 Some("receiver1"),
-                10.0, // каждая транзакция по 10
-                (now - Duration::minutes(i)).to_rfc3339(),
+                eth("10"), // каждая транзакция по 10
+                now - Duration::minutes(i),
             ));
         }
 
-        storage.by_sender.insert("sender1".to_string(), txs.clone());
+        storage
+            .by_sender
+            .insert(addr("sender1"), shared(txs.clone()));
 
         {
             let mut all_txs = storage.all_txs.write().await;
-            all_txs.extend(txs.clone());
+            all_txs.extend(shared(txs.clone()));
         }
 
-        let anomalies = detect_structuring(&storage).await;
+        let ctx = AnalysisContext::new(storage.clone());
+        let anomalies = detect_structuring(&ctx, &StructuringConfig::default()).await;
 
         assert_eq!(anomalies.len(), 1);
 
         match &anomalies[0] {
             Anomaly::Structuring {
                 sender,
+                token,
                 count,
                 severity,
+                window_start,
+                window_end,
                 reasons,
             } => {
-                assert_eq!(sender, "sender1");
+                assert_eq!(*token, None);
+                assert_eq!(*sender, hex(addr("sender1")));
                 assert_eq!(*count, 12);
                 assert_eq!(*severity, Severity::Strong);
+                assert_eq!(*window_start, now - Duration::minutes(11));
+                assert_eq!(*window_end, now + Duration::minutes(49));
                 assert!(reasons[0].contains("Transations count"));
                 assert!(reasons[0].contains("Transations sum"));
             }
@@ -393,29 +551,34 @@ mod test {
             "tx1",
             "sender1",
             Some("receiver1"),
-            10.0,
-            (now.date().and_hms(3, 0, 0)).to_rfc3339(),
+            eth("10"),
+            now.date_naive().and_hms_opt(3, 0, 0).unwrap().and_utc(),
         );
 
-        let now = Utc::now().date().and_hms(12, 0, 0); 
+        let now = Utc::now()
+            .date_naive()
+            .and_hms_opt(12, 0, 0)
+            .unwrap()
+            .and_utc();
         let mut burst_txs = vec![];
         for i in 0..5 {
             burst_txs.push(make_tx(
                 &format!("burst{}", i),
                 "sender2",
                 Some("receiver2"),
-                1.0,
-                (now + Duration::minutes(i)).to_rfc3339(),
+                eth("1"),
+                now + Duration::minutes(i),
             ));
         }
 
         {
             let mut all_txs = storage.all_txs.write().await;
-            all_txs.push(tx1);
-            all_txs.extend(burst_txs);
+            all_txs.push(Arc::new(tx1));
+            all_txs.extend(shared(burst_txs));
         }
 
-        let anomalies = detect_time_anomalies(&storage).await;
+        let ctx = AnalysisContext::new(storage.clone());
+        let anomalies = detect_time_anomalies(&ctx, &TimeAnomalyConfig::default()).await;
 
         assert_eq!(anomalies.len(), 2);
 
@@ -427,12 +590,12 @@ mod test {
                 Anomaly::TimeAnomaly {
                     tx_hash, severity, ..
                 } => {
-                    assert_eq!(tx_hash, "tx1");
+                    assert_eq!(*tx_hash, hex(hash("tx1")));
                     assert_eq!(severity, Severity::Weak);
                     time_anomaly_found = true;
                 }
                 Anomaly::BurstActivity { sender, reasons } => {
-                    assert_eq!(sender, "sender2");
+                    assert_eq!(*sender, hex(addr("sender2")));
                     assert!(reasons[0].contains("Detected Burst activity"));
                     burst_anomaly_found = true;
                 }
@@ -456,89 +619,114 @@ mod test {
             "tx1",
             "sender1",
             Some("receiver1"),
-            100.0,
-            (now - Duration::hours(3)).to_rfc3339(),
+            eth("100"),
+            now - Duration::hours(3),
         );
         let tx2 = make_tx(
             "tx2",
             "sender1",
             Some("receiver1"),
-            100.0,
-            (now - Duration::hours(2)).to_rfc3339(),
+            eth("100"),
+            now - Duration::hours(2),
         );
         let tx3 = make_tx(
             "tx3",
             "sender1",
             Some("receiver1"),
-            100.0,
-            (now - Duration::hours(1)).to_rfc3339(),
+            eth("100"),
+            now - Duration::hours(1),
         );
 
         storage
             .by_sender
-            .insert("sender1".to_string(), vec![tx1, tx2, tx3]);
+            .insert(addr("sender1"), shared(vec![tx1, tx2, tx3]));
 
-        let patterns = detect_regular_payments(&storage).await;
+        let ctx = AnalysisContext::new(storage.clone());
+        let patterns = detect_regular_payments(&ctx, &RegularPaymentsConfig::default()).await;
 
         assert_eq!(patterns.len(), 1);
 
         match &patterns[0] {
             BusinessPattern::RegularPayments { sender, message } => {
-                assert_eq!(sender, "sender1");
+                assert_eq!(*sender, hex(addr("sender1")));
                 assert!(message.contains("Detected regular payments"));
             }
             _ => panic!("Ожидается BusinessPattern::RegularPayments"),
         }
 
-        let tx4 = make_tx("tx4", "sender2", Some("receiver2"), 50.0, now.to_rfc3339());
-        storage.by_sender.insert("sender2".to_string(), vec![tx4]);
+        let tx4 = make_tx("tx4", "sender2", Some("receiver2"), eth("50"), now);
+        storage.by_sender.insert(addr("sender2"), shared(vec![tx4]));
 
-        let patterns = detect_regular_payments(&storage).await;
+        let ctx = AnalysisContext::new(storage.clone());
+        let patterns = detect_regular_payments(&ctx, &RegularPaymentsConfig::default()).await;
         assert_eq!(patterns.len(), 1);
     }
 
     #[tokio::test]
     async fn test_detect_dex_trade() {
         let tx1 = TransactionRecord {
-            hash: "tx1".to_string(),
-            from: "sender1".to_string(),
-            to: Some("0x1111111111111111111111111111111111111111".to_string()),
-            value: 10.0,
+            hash: hash("tx1"),
+            from: addr("sender1"),
+            to: Some(addr("0x1111111111111111111111111111111111111111")),
+            value: eth("10"),
             gas: 21,
-            gas_price_gwei: 1.0,
+            gas_price: gwei("1"),
             block_number: 1,
-            timestamp: Utc::now().to_rfc3339(),
+            timestamp: Utc::now(),
             input: "".to_string(),
+            gas_used: None,
+            effective_gas_price: None,
+            status: None,
+            contract_address: None,
+            tx_type: None,
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+            base_fee_per_gas: None,
         };
 
         let tx2 = TransactionRecord {
-            hash: "tx2".to_string(),
-            from: "sender2".to_string(),
-            to: Some("0x2222222222222222222222222222222222222222".to_string()),
-            value: 15.0,
+            hash: hash("tx2"),
+            from: addr("sender2"),
+            to: Some(addr("0x2222222222222222222222222222222222222222")),
+            value: eth("15"),
             gas: 21,
-            gas_price_gwei: 1.0,
+            gas_price: gwei("1"),
             block_number: 1,
-            timestamp: Utc::now().to_rfc3339(),
+            timestamp: Utc::now(),
             input: "".to_string(),
+            gas_used: None,
+            effective_gas_price: None,
+            status: None,
+            contract_address: None,
+            tx_type: None,
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+            base_fee_per_gas: None,
         };
 
         let tx3 = TransactionRecord {
-            hash: "tx3".to_string(),
-            from: "sender3".to_string(),
+            hash: hash("tx3"),
+            from: addr("sender3"),
             to: None,
-            value: 20.0,
+            value: eth("20"),
             gas: 21,
-            gas_price_gwei: 1.0,
+            gas_price: gwei("1"),
             block_number: 1,
-            timestamp: Utc::now().to_rfc3339(),
+            timestamp: Utc::now(),
             input: "".to_string(),
+            gas_used: None,
+            effective_gas_price: None,
+            status: None,
+            contract_address: None,
+            tx_type: None,
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+            base_fee_per_gas: None,
         };
 
         let storage = Arc::new(TxStorage {
-            by_sender: DashMap::new(),
-            by_reciever: DashMap::new(),
-            all_txs: RwLock::new(vec![tx1, tx2, tx3]),
+            all_txs: RwLock::new(shared(vec![tx1, tx2, tx3])),
+            ..TxStorage::new()
         });
 
         let dex_address: H160 = "0x1111111111111111111111111111111111111111"
@@ -548,7 +736,8 @@ mod test {
         let mut dex_contracts = HashSet::new();
         dex_contracts.insert(dex_address);
 
-        let patterns = detect_dex_trade(&storage, &dex_contracts).await;
+        let ctx = AnalysisContext::new(storage.clone());
+        let patterns = detect_dex_trade(&ctx, &dex_contracts).await;
 
         assert_eq!(patterns.len(), 1);
     }
@@ -558,29 +747,38 @@ mod test {
         let storage = create_test_storage();
 
         let tx1 = TransactionRecord {
-            hash: "tx1".to_string(),
-            from: "sender1".to_string(),
-            to: Some("receiver".to_string()),
-            value: 100.0,
+            hash: hash("tx1"),
+            from: addr("sender1"),
+            to: Some(addr("receiver")),
+            value: eth("100"),
             gas: 21000,
-            gas_price_gwei: 50.0,
+            gas_price: gwei("50"),
             block_number: 1,
-            timestamp: Utc::now().to_rfc3339(),
+            timestamp: Utc::now(),
             input: "0x80ac58cd".to_string(),
+            gas_used: None,
+            effective_gas_price: None,
+            status: None,
+            contract_address: None,
+            tx_type: None,
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+            base_fee_per_gas: None,
         };
 
         {
             let mut all_txs = storage.all_txs.write().await;
-            all_txs.push(tx1);
+            all_txs.push(Arc::new(tx1));
         }
 
-        let patterns = detect_nft_activity(&storage).await;
+        let ctx = AnalysisContext::new(storage.clone());
+        let patterns = detect_nft_activity(&ctx).await;
 
         assert_eq!(patterns.len(), 1);
 
         match &patterns[0] {
             BusinessPattern::NFTActivity { tx_hash, .. } => {
-                assert_eq!(tx_hash, "tx1");
+                assert_eq!(*tx_hash, hex(hash("tx1")));
             }
             _ => panic!("Ожидается"),
         }
@@ -589,45 +787,68 @@ mod test {
     #[tokio::test]
     async fn test_detect_arbitrage() {
         let tx1 = TransactionRecord {
-            hash: "tx1".to_string(),
-            from: "sender1".to_string(),
-            to: Some("0x1111111111111111111111111111111111111111".to_string()),
-            value: 10.0,
+            hash: hash("tx1"),
+            from: addr("sender1"),
+            to: Some(addr("0x1111111111111111111111111111111111111111")),
+            value: eth("10"),
             gas: 21,
-            gas_price_gwei: 1.0,
+            gas_price: gwei("1"),
             block_number: 1,
-            timestamp: Utc::now().to_rfc3339(),
+            timestamp: Utc::now(),
             input: "multicall".to_string(),
+            gas_used: None,
+            effective_gas_price: None,
+            status: None,
+            contract_address: None,
+            tx_type: None,
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+            base_fee_per_gas: None,
         };
 
         let tx2 = TransactionRecord {
-            hash: "tx2".to_string(),
-            from: "sender2".to_string(),
-            to: Some("0x2222222222222222222222222222222222222222".to_string()),
-            value: 15.0,
+            hash: hash("tx2"),
+            from: addr("sender2"),
+            to: Some(addr("0x2222222222222222222222222222222222222222")),
+            value: eth("15"),
             gas: 21,
-            gas_price_gwei: 1.0,
+            gas_price: gwei("1"),
             block_number: 1,
-            timestamp: Utc::now().to_rfc3339(),
+            timestamp: Utc::now(),
             input: "".to_string(),
+            gas_used: None,
+            effective_gas_price: None,
+            status: None,
+            contract_address: None,
+            tx_type: None,
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+            base_fee_per_gas: None,
         };
 
         let tx3 = TransactionRecord {
-            hash: "tx3".to_string(),
-            from: "sender3".to_string(),
+            hash: hash("tx3"),
+            from: addr("sender3"),
             to: None,
-            value: 20.0,
+            value: eth("20"),
             gas: 21,
-            gas_price_gwei: 1.0,
+            gas_price: gwei("1"),
             block_number: 1,
-            timestamp: Utc::now().to_rfc3339(),
+            timestamp: Utc::now(),
             input: "".to_string(),
+            gas_used: None,
+            effective_gas_price: None,
+            status: None,
+            contract_address: None,
+            tx_type: None,
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+            base_fee_per_gas: None,
         };
 
         let storage = Arc::new(TxStorage {
-            by_sender: DashMap::new(),
-            by_reciever: DashMap::new(),
-            all_txs: RwLock::new(vec![tx1, tx2, tx3]),
+            all_txs: RwLock::new(shared(vec![tx1, tx2, tx3])),
+            ..TxStorage::new()
         });
 
         let dex_address: H160 = "0x1111111111111111111111111111111111111111"
@@ -637,8 +858,14 @@ mod test {
         let mut dex_contracts = HashSet::new();
         dex_contracts.insert(dex_address);
 
-        let patterns = detect_dex_trade(&storage, &dex_contracts).await;
+        let ctx = AnalysisContext::new(storage.clone());
+        let patterns = detect_arbitrage(&ctx, &dex_contracts).await;
 
+        assert_eq!(patterns.len(), 1);
+        match &patterns[0] {
+            BusinessPattern::Arbitrage { sender, .. } => assert_eq!(*sender, hex(addr("sender1"))),
+            _ => panic!("Ожидается BusinessPattern::Arbitrage"),
+        }
     }
 
     #[tokio::test]
@@ -646,71 +873,109 @@ mod test {
         let storage = create_test_storage();
 
         let tx1 = TransactionRecord {
-            hash: "tx1".to_string(),
-            from: "sender1".to_string(),
-            to: Some("receiver1".to_string()),
-            value: 200.0,
+            hash: hash("tx1"),
+            from: addr("sender1"),
+            to: Some(addr("receiver1")),
+            value: eth("200"),
             gas: 21000,
-            gas_price_gwei: 50.0,
+            gas_price: gwei("50"),
             block_number: 1,
-            timestamp: Utc::now().to_rfc3339(),
+            timestamp: Utc::now(),
             input: "".to_string(),
+            gas_used: None,
+            effective_gas_price: None,
+            status: None,
+            contract_address: None,
+            tx_type: None,
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+            base_fee_per_gas: None,
         };
 
         let tx2 = TransactionRecord {
-            hash: "tx2".to_string(),
-            from: "sender1".to_string(),
-            to: Some("receiver1".to_string()),
-            value: 250.0,
+            hash: hash("tx2"),
+            from: addr("sender1"),
+            to: Some(addr("receiver1")),
+            value: eth("250"),
             gas: 21000,
-            gas_price_gwei: 50.0,
+            gas_price: gwei("50"),
             block_number: 1,
-            timestamp: Utc::now().to_rfc3339(),
+            timestamp: Utc::now(),
             input: "".to_string(),
+            gas_used: None,
+            effective_gas_price: None,
+            status: None,
+            contract_address: None,
+            tx_type: None,
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+            base_fee_per_gas: None,
         };
 
         let tx3 = TransactionRecord {
-            hash: "tx3".to_string(),
-            from: "sender2".to_string(),
-            to: Some("receiver2".to_string()),
-            value: 10.0,
+            hash: hash("tx3"),
+            from: addr("sender2"),
+            to: Some(addr("receiver2")),
+            value: eth("10"),
             gas: 21000,
-            gas_price_gwei: 50.0,
+            gas_price: gwei("50"),
             block_number: 1,
-            timestamp: Utc::now().to_rfc3339(),
+            timestamp: Utc::now(),
             input: "".to_string(),
+            gas_used: None,
+            effective_gas_price: None,
+            status: None,
+            contract_address: None,
+            tx_type: None,
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+            base_fee_per_gas: None,
         };
 
         let tx4 = TransactionRecord {
-            hash: "tx4".to_string(),
-            from: "sender2".to_string(),
-            to: Some("receiver2".to_string()),
-            value: 15.0,
+            hash: hash("tx4"),
+            from: addr("sender2"),
+            to: Some(addr("receiver2")),
+            value: eth("15"),
             gas: 21000,
-            gas_price_gwei: 50.0,
+            gas_price: gwei("50"),
             block_number: 1,
-            timestamp: Utc::now().to_rfc3339(),
+            timestamp: Utc::now(),
             input: "".to_string(),
+            gas_used: None,
+            effective_gas_price: None,
+            status: None,
+            contract_address: None,
+            tx_type: None,
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+            base_fee_per_gas: None,
         };
 
         {
             let mut all_txs = storage.all_txs.write().await;
-            all_txs.push(tx1.clone());
-            all_txs.push(tx2.clone());
-            all_txs.push(tx3.clone());
-            all_txs.push(tx4.clone());
+            all_txs.push(Arc::new(tx1.clone()));
+            all_txs.push(Arc::new(tx2.clone()));
+            all_txs.push(Arc::new(tx3.clone()));
+            all_txs.push(Arc::new(tx4.clone()));
         }
 
-        storage.by_sender.insert("sender1".to_string(), vec![tx1.clone(), tx2.clone()]);
-        storage.by_sender.insert("sender2".to_string(), vec![tx3.clone(), tx4.clone()]);
+        storage
+            .by_sender
+            .insert(addr("sender1"), shared(vec![tx1.clone(), tx2.clone()]));
+        storage
+            .by_sender
+            .insert(addr("sender2"), shared(vec![tx3.clone(), tx4.clone()]));
 
-        let patterns = detect_whales(&storage).await;
+        let ctx = AnalysisContext::new(storage.clone());
+        let patterns = detect_whales(&ctx, &WhalesConfig::default()).await;
 
         assert_eq!(patterns.len(), 1);
 
         match &patterns[0] {
-            BusinessPattern::Whales { sender } => {
-                assert_eq!(sender, "sender1");
+            BusinessPattern::Whales { sender, token } => {
+                assert_eq!(*token, None);
+                assert_eq!(*sender, hex(addr("sender1")));
             }
             _ => panic!("Ожидается BusinessPattern::Whales"),
         }
@@ -725,8 +990,11 @@ mod test {
         let anomalies = vec![
             Anomaly::Structuring {
                 sender: "sender1".to_string(),
+                token: None,
                 count: 12,
                 severity: Severity::Strong,
+                window_start: Utc::now() - Duration::hours(1),
+                window_end: Utc::now(),
                 reasons: vec!["Suspicious activity".to_string()],
             },
             Anomaly::TimeAnomaly {
@@ -735,6 +1003,14 @@ mod test {
                 reasons: vec!["Night time transaction".to_string()],
                 timestamp: Utc::now(),
             },
+            Anomaly::HighPriorityFee {
+                tx_hash: "tx456".to_string(),
+                priority_fee_per_gas: gwei("70.5"),
+                base_fee_per_gas: gwei("30"),
+                severity: Severity::Strong,
+                reasons: vec!["Tip above the base fee".to_string()],
+                timestamp: Utc::now(),
+            },
         ];
         export_anomalies_csv(&anomalies, &path).expect("CSV export failed");
 
@@ -743,6 +1019,13 @@ mod test {
         assert!(content.contains("tx123"));
         assert!(content.contains("Strong"));
         assert!(content.contains("Weak"));
+        assert!(content
+            .lines()
+            .next()
+            .unwrap()
+            .ends_with(",z_score,priority_fee_gwei,base_fee_gwei"));
+        let row = content.lines().find(|line| line.contains("tx456")).unwrap();
+        assert!(row.ends_with(",70.5,30"), "{}", row);
     }
 
     #[test]
@@ -768,4 +1051,2874 @@ mod test {
         assert!(content.contains("DEX"));
     }
 
+    #[test]
+    fn test_config_toml_overrides_and_defaults() {
+        let config = AnalyzerConfig::from_toml_str(
+            r#"
+            [large_tx]
+            z_threshold = 5.0
+
+            [time_anomalies]
+            night_end_hour = 4
+
+            [dex]
+            routers = ["0x1111111111111111111111111111111111111111"]
+            "#,
+        )
+        .expect("valid config");
+
+        assert_eq!(config.large_tx.z_threshold, 5.0);
+        assert_eq!(config.large_tx.flat_ratio, 10.0);
+        assert_eq!(config.large_tx.min_history, 5);
+        assert_eq!(config.large_tx.percentile, 99.0);
+        assert_eq!(config.time_anomalies.night_end_hour, 4);
+        assert_eq!(config.time_anomalies.burst_min_count, 5);
+        assert_eq!(config.high_frequency, HighFrequencyConfig::default());
+        assert_eq!(config.dex.routers.len(), 1);
+    }
+
+    #[test]
+    fn test_config_json_file() {
+        let file = tempfile::Builder::new().suffix(".json").tempfile().unwrap();
+        fs::write(file.path(), r#"{ "batch_payments": { "min_count": 3 } }"#).unwrap();
+
+        let config = AnalyzerConfig::from_file(file.path()).expect("valid config");
+
+        assert_eq!(config.batch_payments.min_count, 3);
+        assert_eq!(config.batch_payments.interval_minutes, 5);
+    }
+
+    #[test]
+    fn test_example_config_matches_defaults() {
+        let config = AnalyzerConfig::from_file("config.example.toml").expect("valid config");
+        assert_eq!(config, AnalyzerConfig::default());
+    }
+
+    #[test]
+    fn test_config_validation() {
+        let err = AnalyzerConfig::from_toml_str("[high_fee]\npercentile = 150.0").unwrap_err();
+        assert!(
+            matches!(err, ConfigError::Invalid(ref msg) if msg.contains("high_fee.percentile"))
+        );
+
+        let err = AnalyzerConfig::from_toml_str("[whales]\nunknown = 1").unwrap_err();
+        assert!(matches!(err, ConfigError::Toml(_)));
+
+        let err =
+            AnalyzerConfig::from_toml_str("[structuring]\nwindow_minutes = 30\nstep_minutes = 45")
+                .unwrap_err();
+        assert!(
+            matches!(err, ConfigError::Invalid(ref msg) if msg.contains("structuring.step_minutes"))
+        );
+
+        let err = AnalyzerConfig::from_toml_str("[sanctions]\nsources = []").unwrap_err();
+        assert!(matches!(err, ConfigError::Invalid(ref msg) if msg.contains("sanctions.sources")));
+
+        let err = AnalyzerConfig::from_toml_str(
+            "[sanctions]\nsources = [{ url = \"ftp://example.com/list.txt\" }]",
+        )
+        .unwrap_err();
+        assert!(matches!(err, ConfigError::Invalid(ref msg) if msg.contains("ftp://")));
+    }
+
+    struct CountingDetector;
+
+    #[async_trait]
+    impl Detector for CountingDetector {
+        fn name(&self) -> &str {
+            "tx_count"
+        }
+
+        fn kind(&self) -> DetectorKind {
+            DetectorKind::Pattern
+        }
+
+        async fn run(&self, ctx: &AnalysisContext) -> Findings {
+            let count = ctx.storage.all_txs().await.len();
+            Findings::from_patterns(vec![BusinessPattern::BatchPayments {
+                sender: "all".to_string(),
+                count,
+                message: format!("{} transactions", count),
+            }])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_registry_runs_custom_and_selected_detectors() {
+        let storage = create_test_storage();
+        storage.all_txs.write().await.push(Arc::new(make_tx(
+            "tx1",
+            "sender1",
+            Some("receiver1"),
+            eth("1"),
+            Utc::now(),
+        )));
+
+        let mut registry = DetectorRegistry::with_builtin(&AnalyzerConfig::default());
+        registry.register(CountingDetector).unwrap();
+        assert_eq!(
+            registry.register(CountingDetector),
+            Err(RegistryError::DuplicateDetector("tx_count".to_string()))
+        );
+
+        registry.select(&["tx_count", "nft_activity"]).unwrap();
+        assert!(registry.is_enabled("tx_count"));
+        assert!(!registry.is_enabled("large_tx"));
+
+        let findings = registry.run(&AnalysisContext::new(storage.clone())).await;
+        assert!(findings.anomalies.is_empty());
+        assert_eq!(findings.patterns.len(), 1);
+        assert!(matches!(
+            findings.patterns[0],
+            BusinessPattern::BatchPayments { count: 1, .. }
+        ));
+
+        registry.disable("tx_count").unwrap();
+        let findings = registry.run(&AnalysisContext::new(storage)).await;
+        assert!(findings.is_empty());
+
+        assert_eq!(
+            registry.select(&["no_such_detector"]),
+            Err(RegistryError::UnknownDetector(
+                "no_such_detector".to_string()
+            ))
+        );
+        assert!(registry.is_enabled("nft_activity"));
+    }
+
+    struct OrderedDetector {
+        name: &'static str,
+        dependencies: Vec<&'static str>,
+    }
+
+    #[async_trait]
+    impl Detector for OrderedDetector {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn kind(&self) -> DetectorKind {
+            DetectorKind::Anomaly
+        }
+
+        fn dependencies(&self) -> &[&str] {
+            &self.dependencies
+        }
+
+        async fn run(&self, ctx: &AnalysisContext) -> Findings {
+            ctx.flag(hash(self.name)).await;
+            Findings::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_registry_dependency_order() {
+        let mut registry = DetectorRegistry::new();
+        registry
+            .register(OrderedDetector {
+                name: "reader",
+                dependencies: vec!["writer", "not_registered"],
+            })
+            .unwrap();
+        registry
+            .register(OrderedDetector {
+                name: "writer",
+                dependencies: vec![],
+            })
+            .unwrap();
+        registry
+            .register(OrderedDetector {
+                name: "other",
+                dependencies: vec![],
+            })
+            .unwrap();
+        assert_eq!(registry.names(), vec!["writer", "reader", "other"]);
+
+        assert_eq!(
+            registry.register(OrderedDetector {
+                name: "cycle",
+                dependencies: vec!["cycle"],
+            }),
+            Err(RegistryError::DependencyCycle("cycle".to_string()))
+        );
+        assert!(registry.get("cycle").is_none());
+
+        let builtin = DetectorRegistry::with_builtin(&AnalyzerConfig::default());
+        let names = builtin.names();
+        let position = |name: &str| names.iter().position(|n| *n == name).unwrap();
+        for dependency in builtin.get("unusual_op").unwrap().dependencies() {
+            assert!(position(dependency) < position("unusual_op"));
+        }
+    }
+
+    struct SlowDetector {
+        name: &'static str,
+        dependencies: Vec<&'static str>,
+    }
+
+    #[async_trait]
+    impl Detector for SlowDetector {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn kind(&self) -> DetectorKind {
+            DetectorKind::Pattern
+        }
+
+        fn dependencies(&self) -> &[&str] {
+            &self.dependencies
+        }
+
+        async fn run(&self, ctx: &AnalysisContext) -> Findings {
+            let seen = ctx.flagged_hashes().await.len();
+            tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+            ctx.flag(hash(self.name)).await;
+            Findings::from_patterns(vec![BusinessPattern::BatchPayments {
+                sender: self.name.to_string(),
+                count: seen,
+                message: format!("{} saw {} flags", self.name, seen),
+            }])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_registry_runs_independent_detectors_concurrently() {
+        let mut registry = DetectorRegistry::new();
+        for (name, dependencies) in [
+            ("summary", vec!["first", "second"]),
+            ("first", vec![]),
+            ("second", vec![]),
+            ("third", vec![]),
+        ] {
+            registry
+                .register(SlowDetector { name, dependencies })
+                .unwrap();
+        }
+
+        let started = std::time::Instant::now();
+        let report = registry
+            .run_report(&AnalysisContext::new(create_test_storage()))
+            .await;
+        // Three independent detectors overlap, the summary waits for two of them.
+        assert!(started.elapsed() < std::time::Duration::from_millis(1100));
+
+        let names: Vec<&str> = report.timings.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, vec!["first", "second", "summary", "third"]);
+        assert!(report
+            .timings
+            .iter()
+            .all(|t| t.elapsed >= std::time::Duration::from_millis(300)));
+        assert_eq!(report.slowest().len(), 4);
+
+        let seen: Vec<(&str, usize)> = report
+            .findings
+            .patterns
+            .iter()
+            .map(|pattern| match pattern {
+                BusinessPattern::BatchPayments { sender, count, .. } => (sender.as_str(), *count),
+                other => panic!("unexpected pattern {:?}", other),
+            })
+            .collect();
+        // Independent detectors start together, the summary only after both of
+        // its dependencies flagged their hash.
+        assert_eq!(seen[0], ("first", 0));
+        assert_eq!(seen[1], ("second", 0));
+        assert_eq!(seen[2].0, "summary");
+        assert!(seen[2].1 >= 2);
+
+        registry.disable("first").unwrap();
+        let report = registry
+            .run_report(&AnalysisContext::new(create_test_storage()))
+            .await;
+        assert_eq!(report.timings.len(), 3);
+        assert_eq!(report.findings.patterns.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_runs_do_not_share_flags() {
+        let mut registry = DetectorRegistry::with_builtin(&AnalyzerConfig::default());
+        // The sanctions list is fetched over the network.
+        registry.disable("blacklist").unwrap();
+
+        let busy = create_test_storage();
+        {
+            let mut all_txs = busy.all_txs.write().await;
+            for i in 0..20 {
+                let value = if i == 0 { eth("10000") } else { eth("1") };
+                all_txs.push(Arc::new(make_tx(
+                    &format!("busy{}", i),
+                    "0x0000000000000000000000000000000000000001",
+                    Some("0x0000000000000000000000000000000000000002"),
+                    value,
+                    Utc::now(),
+                )));
+            }
+        }
+        let quiet = create_test_storage();
+
+        let busy_ctx = AnalysisContext::new(busy);
+        let quiet_ctx = AnalysisContext::new(quiet);
+        let (busy_findings, quiet_findings) =
+            tokio::join!(registry.run(&busy_ctx), registry.run(&quiet_ctx));
+
+        assert!(!busy_findings.anomalies.is_empty());
+        assert!(busy_ctx.is_flagged(hash("busy0")).await);
+        assert!(quiet_findings.is_empty());
+        assert!(quiet_ctx.flagged_hashes().await.is_empty());
+    }
+
+    #[test]
+    fn test_units_are_lossless() {
+        let wei = eth("1234567.000000000000000001");
+        assert_eq!(format_eth(wei), "1234567.000000000000000001");
+        assert_eq!(format_eth(eth("2")), "2");
+        assert_eq!(format_eth(U256::zero()), "0");
+        assert!(parse_eth("0.0000000000000000001").is_err());
+        assert!(parse_eth("-1").is_err());
+        assert!(parse_eth("1e18").is_err());
+
+        // Far beyond u128::MAX: formatting must neither panic nor truncate.
+        let huge = U256::MAX;
+        assert_eq!(parse_eth(&format_eth(huge)).unwrap(), huge);
+        assert!(to_eth_f64(huge) > 1e58);
+
+        assert_eq!(mean(&[huge, huge]), huge);
+        assert_eq!(mean(&[eth("1"), eth("2")]), eth("1.5"));
+        assert_eq!(mean(&[]), U256::zero());
+
+        assert_eq!(
+            cmp_scaled(eth("5"), 5.0, eth("1")),
+            std::cmp::Ordering::Equal
+        );
+        assert_eq!(
+            cmp_scaled(eth("5") + 1, 5.0, eth("1")),
+            std::cmp::Ordering::Greater
+        );
+        assert_eq!(cmp_scaled(huge, 0.5, huge), std::cmp::Ordering::Greater);
+    }
+
+    #[tokio::test]
+    async fn test_detect_large_tx_exact_amounts() {
+        let storage = create_test_storage();
+        {
+            let mut all_txs = storage.all_txs.write().await;
+            // Values that differ only in the last wei would compare equal as f64.
+            for i in 0..10 {
+                all_txs.push(Arc::new(make_tx(
+                    &format!("tx{}", i),
+                    "sender1",
+                    Some("receiver1"),
+                    eth("100000"),
+                    Utc::now(),
+                )));
+            }
+            all_txs.push(Arc::new(make_tx(
+                "tx_max",
+                "sender1",
+                Some("receiver1"),
+                eth("100000") + 1,
+                Utc::now(),
+            )));
+            all_txs.push(Arc::new(make_tx(
+                "tx_token",
+                "sender2",
+                Some("receiver1"),
+                U256::MAX,
+                Utc::now(),
+            )));
+        }
+        storage
+            .by_sender
+            .insert(addr("sender1"), storage.all_txs.read().await[..11].to_vec());
+
+        let ctx = AnalysisContext::new(storage);
+        let config = LargeTxConfig {
+            percentile: 90.0,
+            ..LargeTxConfig::default()
+        };
+        let anomalies = detect_large_tx(&ctx, &config).await;
+
+        let hashes: Vec<&str> = anomalies
+            .iter()
+            .map(|anomaly| match anomaly {
+                Anomaly::LargeTx { tx_hash, .. } => tx_hash.as_str(),
+                _ => panic!("Wrong Anomaly type"),
+            })
+            .collect();
+        assert_eq!(hashes, vec![hex(hash("tx_max")), hex(hash("tx_token"))]);
+    }
+
+    #[test]
+    fn test_transaction_record_serde_round_trip() {
+        let tx = make_tx(
+            "tx1",
+            "0x7a250d5630B4cF539739dF2C5dAcb4c659F2488D",
+            None,
+            eth("1.5"),
+            "2025-09-16T12:00:00Z".parse().unwrap(),
+        );
+
+        let json = serde_json::to_string(&tx).unwrap();
+        assert!(json.contains("\"2025-09-16T12:00:00Z\""));
+        assert!(json.contains("\"0x7a250d5630b4cf539739df2c5dacb4c659f2488d\""));
+        let parsed: TransactionRecord = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, tx);
+
+        // Malformed data is rejected once, at ingestion, instead of panicking in a detector.
+        let broken = json.replace("2025-09-16T12:00:00Z", "yesterday");
+        assert!(serde_json::from_str::<TransactionRecord>(&broken).is_err());
+        let broken = json.replace("0x7a250d5630b4cf539739df2c5dacb4c659f2488d", "sender1");
+        assert!(serde_json::from_str::<TransactionRecord>(&broken).is_err());
+    }
+
+    #[test]
+    fn test_fee_uses_receipt_when_available() {
+        let mut tx = make_tx("tx1", "sender1", Some("receiver1"), eth("1"), Utc::now());
+        tx.gas = 100_000;
+        tx.gas_price = gwei("50");
+        assert_eq!(tx.fee(), gwei("5000000"));
+        assert_eq!(tx.fee(), tx.max_fee());
+        assert!(!tx.is_failed());
+
+        tx.gas_used = Some(21_000);
+        tx.effective_gas_price = Some(gwei("40"));
+        tx.status = Some(false);
+        assert_eq!(tx.fee(), gwei("840000"));
+        assert_eq!(tx.max_fee(), gwei("5000000"));
+        assert!(tx.is_failed());
+    }
+
+    fn mocked_block(number: u64, txs: &[(&str, u64)]) -> Block<Transaction> {
+        Block {
+            number: Some(U64::from(number)),
+            timestamp: U256::from(1_700_000_000u64),
+            transactions: txs
+                .iter()
+                .map(|(name, gas)| Transaction {
+                    hash: hash(name),
+                    from: addr("sender1"),
+                    to: Some(addr("receiver1")),
+                    value: eth("1"),
+                    gas: U256::from(*gas),
+                    gas_price: Some(gwei("50")),
+                    block_number: Some(U64::from(number)),
+                    ..Transaction::default()
+                })
+                .collect(),
+            ..Block::default()
+        }
+    }
+
+    fn mocked_receipt(name: &str, gas_used: u64, status: u64) -> TransactionReceipt {
+        TransactionReceipt {
+            transaction_hash: hash(name),
+            gas_used: Some(U256::from(gas_used)),
+            effective_gas_price: Some(gwei("40")),
+            status: Some(U64::from(status)),
+            ..TransactionReceipt::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_scan_block_with_receipts() {
+        // The mock transport answers requests in reverse push order.
+        let (provider, mock) = Provider::mocked();
+        mock.push::<Vec<TransactionReceipt>, _>(vec![
+            mocked_receipt("ok", 21_000, 1),
+            mocked_receipt("reverted", 30_000, 0),
+        ])
+        .unwrap();
+        mock.push(mocked_block(7, &[("ok", 100_000), ("reverted", 100_000)]))
+            .unwrap();
+
+        let storage = create_test_storage();
+        let options = ScanOptions {
+            fetch_receipts: true,
+            ..ScanOptions::default()
+        };
+        let report = scan_block(&Arc::new(provider), 7, 7, &storage, &options).await;
+        assert!(report.is_complete());
+
+        let all_txs = storage.all_txs.read().await;
+        assert_eq!(all_txs.len(), 2);
+        assert_eq!(all_txs[0].gas_used, Some(21_000));
+        assert_eq!(all_txs[0].status, Some(true));
+        assert_eq!(all_txs[0].fee(), gwei("840000"));
+        assert!(all_txs[1].is_failed());
+        assert_eq!(all_txs[1].fee(), gwei("1200000"));
+    }
+
+    #[tokio::test]
+    async fn test_scan_block_falls_back_to_transaction_receipts() {
+        let (provider, mock) = Provider::mocked();
+        mock.push(mocked_receipt("ok", 21_000, 1)).unwrap();
+        mock.push_response(MockResponse::Error(JsonRpcError {
+            code: -32601,
+            message: "the method eth_getBlockReceipts does not exist".to_string(),
+            data: None,
+        }));
+        mock.push(mocked_block(8, &[("ok", 100_000)])).unwrap();
+
+        let storage = create_test_storage();
+        let options = ScanOptions {
+            fetch_receipts: true,
+            ..ScanOptions::default()
+        };
+        let report = scan_block(&Arc::new(provider), 8, 8, &storage, &options).await;
+        assert!(report.is_complete());
+
+        let all_txs = storage.all_txs.read().await;
+        assert_eq!(all_txs[0].gas_used, Some(21_000));
+        assert_eq!(all_txs[0].effective_gas_price, Some(gwei("40")));
+    }
+
+    #[tokio::test]
+    async fn test_block_receipts_by_hash_fall_back_on_invalid_params() {
+        // The node rejects the block hash, then answers by block number.
+        let main = block_hash("main", 10);
+        let mut receipt = mocked_receipt("ok", 21_000, 1);
+        receipt.block_hash = Some(main);
+        let mock = MockProvider::new();
+        mock.push::<Vec<TransactionReceipt>, _>(vec![receipt])
+            .unwrap();
+        mock.push_response(rpc_error(-32602, "invalid params"));
+        mock.push(chain_block("main", 10, &[("ok", 100_000)]))
+            .unwrap();
+        let provider = Arc::new(Provider::new(mock.clone()));
+
+        let storage = create_test_storage();
+        let options = ScanOptions {
+            fetch_receipts: true,
+            ..ScanOptions::default()
+        };
+        assert!(scan_block(&provider, 10, 10, &storage, &options)
+            .await
+            .is_complete());
+        mock.assert_request("eth_getBlockByNumber", ("0xa", true))
+            .unwrap();
+        mock.assert_request("eth_getBlockReceipts", [main]).unwrap();
+        mock.assert_request("eth_getBlockReceipts", ["0xa"])
+            .unwrap();
+        assert_eq!(storage.all_txs.read().await[0].gas_used, Some(21_000));
+
+        // Both forms are rejected: the receipts are fetched per transaction.
+        let mut receipt = mocked_receipt("next", 21_000, 1);
+        receipt.block_hash = Some(block_hash("main", 11));
+        let mock = MockProvider::new();
+        mock.push(receipt).unwrap();
+        mock.push_response(rpc_error(-32602, "invalid params"));
+        mock.push_response(rpc_error(-32602, "invalid params"));
+        mock.push(chain_block("main", 11, &[("next", 100_000)]))
+            .unwrap();
+        let provider = Arc::new(Provider::new(mock.clone()));
+
+        let storage = create_test_storage();
+        assert!(scan_block(&provider, 11, 11, &storage, &options)
+            .await
+            .is_complete());
+        assert_eq!(storage.all_txs.read().await[0].gas_used, Some(21_000));
+        mock.assert_request("eth_getBlockByNumber", ("0xb", true))
+            .unwrap();
+        mock.assert_request("eth_getBlockReceipts", [block_hash("main", 11)])
+            .unwrap();
+        mock.assert_request("eth_getBlockReceipts", ["0xb"])
+            .unwrap();
+        mock.assert_request("eth_getTransactionReceipt", [hash("next")])
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_block_receipts_are_retried_not_split() {
+        // Responses are popped in reverse order: the block, a rate-limited
+        // eth_getBlockReceipts, then the block and its receipts again.
+        let (provider, mock) = Provider::mocked();
+        mock.push::<Vec<TransactionReceipt>, _>(vec![mocked_receipt("ok", 21_000, 1)])
+            .unwrap();
+        mock.push(mocked_block(8, &[("ok", 100_000)])).unwrap();
+        mock.push_response(rpc_error(-32005, "rate limit exceeded"));
+        mock.push(mocked_block(8, &[("ok", 100_000)])).unwrap();
+
+        let storage = create_test_storage();
+        let options = ScanOptions {
+            fetch_receipts: true,
+            ..fast_retries(1)
+        };
+        let report = scan_block(&Arc::new(provider), 8, 8, &storage, &options).await;
+        assert!(report.is_complete());
+        assert_eq!(report.retries, 1);
+        assert_eq!(storage.all_txs.read().await[0].gas_used, Some(21_000));
+
+        // Same when the receipts are requested by block hash.
+        let mut receipt = mocked_receipt("ok", 21_000, 1);
+        receipt.block_hash = Some(block_hash("main", 9));
+        let (provider, mock) = Provider::mocked();
+        mock.push::<Vec<TransactionReceipt>, _>(vec![receipt])
+            .unwrap();
+        mock.push(chain_block("main", 9, &[("ok", 100_000)]))
+            .unwrap();
+        mock.push_response(rpc_error(-32005, "rate limit exceeded"));
+        mock.push(chain_block("main", 9, &[("ok", 100_000)]))
+            .unwrap();
+
+        let storage = create_test_storage();
+        let report = scan_block(&Arc::new(provider), 9, 9, &storage, &options).await;
+        assert!(report.is_complete());
+        assert_eq!(report.retries, 1);
+        assert_eq!(storage.all_txs.read().await[0].gas_used, Some(21_000));
+    }
+
+    #[tokio::test]
+    async fn test_detect_high_priority_fee() {
+        let storage = create_test_storage();
+        {
+            let mut all_txs = storage.all_txs.write().await;
+            for i in 0..20 {
+                let mut tx = make_tx(
+                    &format!("tx{}", i),
+                    "sender1",
+                    Some("receiver1"),
+                    eth("1"),
+                    Utc::now(),
+                );
+                // 1 gwei tip over a 30 gwei base fee: normal.
+                tx.base_fee_per_gas = Some(gwei("30"));
+                tx.gas_price = gwei("31");
+                all_txs.push(Arc::new(tx));
+            }
+
+            // Legacy transaction overpaying by 70 gwei.
+            let mut legacy = make_tx("legacy", "sender2", Some("receiver1"), eth("1"), Utc::now());
+            legacy.tx_type = Some(0);
+            legacy.base_fee_per_gas = Some(gwei("30"));
+            legacy.gas_price = gwei("100");
+            all_txs.push(Arc::new(legacy));
+
+            // EIP-1559 transaction whose receipt shows a 65 gwei tip.
+            let mut dynamic = make_tx(
+                "dynamic",
+                "sender3",
+                Some("receiver1"),
+                eth("1"),
+                Utc::now(),
+            );
+            dynamic.tx_type = Some(2);
+            dynamic.base_fee_per_gas = Some(gwei("30"));
+            dynamic.max_fee_per_gas = Some(gwei("200"));
+            dynamic.max_priority_fee_per_gas = Some(gwei("65"));
+            dynamic.gas_price = gwei("95");
+            dynamic.effective_gas_price = Some(gwei("95"));
+            all_txs.push(Arc::new(dynamic));
+
+            // Pre-London block: no base fee, never flagged.
+            let mut old = make_tx("old", "sender4", Some("receiver1"), eth("1"), Utc::now());
+            old.gas_price = gwei("1000");
+            all_txs.push(Arc::new(old));
+        }
+
+        let ctx = AnalysisContext::new(storage);
+        let anomalies = detect_high_priority_fee(&ctx, &HighPriorityFeeConfig::default()).await;
+
+        assert_eq!(anomalies.len(), 2);
+        match &anomalies[0] {
+            Anomaly::HighPriorityFee {
+                tx_hash,
+                priority_fee_per_gas,
+                base_fee_per_gas,
+                severity,
+                ..
+            } => {
+                assert_eq!(*tx_hash, hex(hash("legacy")));
+                assert_eq!(*priority_fee_per_gas, gwei("70"));
+                assert_eq!(*base_fee_per_gas, gwei("30"));
+                assert_eq!(*severity, Severity::Strong);
+            }
+            _ => panic!("Wrong Anomaly type"),
+        }
+        match &anomalies[1] {
+            Anomaly::HighPriorityFee {
+                tx_hash,
+                priority_fee_per_gas,
+                severity,
+                ..
+            } => {
+                assert_eq!(*tx_hash, hex(hash("dynamic")));
+                assert_eq!(*priority_fee_per_gas, gwei("65"));
+                assert_eq!(*severity, Severity::Weak);
+            }
+            _ => panic!("Wrong Anomaly type"),
+        }
+        assert!(ctx.is_flagged(hash("dynamic")).await);
+        assert!(!ctx.is_flagged(hash("old")).await);
+    }
+
+    #[tokio::test]
+    async fn test_scan_block_records_eip1559_fields() {
+        let mut block = mocked_block(9, &[("dynamic", 21_000)]);
+        block.base_fee_per_gas = Some(gwei("30"));
+        let tx = &mut block.transactions[0];
+        tx.transaction_type = Some(U64::from(2));
+        tx.gas_price = None;
+        tx.max_fee_per_gas = Some(gwei("100"));
+        tx.max_priority_fee_per_gas = Some(gwei("2"));
+
+        let (provider, mock) = Provider::mocked();
+        mock.push(block).unwrap();
+
+        let storage = create_test_storage();
+        let report = scan_block(&Arc::new(provider), 9, 9, &storage, &ScanOptions::default()).await;
+        assert!(report.is_complete());
+
+        let all_txs = storage.all_txs.read().await;
+        let record = &all_txs[0];
+        assert_eq!(record.tx_type, Some(2));
+        assert_eq!(record.base_fee_per_gas, Some(gwei("30")));
+        assert_eq!(record.max_fee_per_gas, Some(gwei("100")));
+        assert_eq!(record.max_priority_fee_per_gas, Some(gwei("2")));
+        assert_eq!(record.gas_price, gwei("32"));
+        assert_eq!(record.priority_fee_per_gas(), Some(gwei("2")));
+    }
+
+    fn make_transfer(name: &str, token: &str, from: &str, amount: U256) -> TokenTransfer {
+        TokenTransfer {
+            tx_hash: hash(name),
+            log_index: 0,
+            block_number: 1,
+            timestamp: Utc::now(),
+            token: addr(token),
+            from: addr(from),
+            to: addr("receiver1"),
+            amount,
+        }
+    }
+
+    fn transfer_log(name: &str, token: &str, from: &str, to: &str, amount: u64) -> Log {
+        let mut data = [0u8; 32];
+        U256::from(amount).to_big_endian(&mut data);
+        Log {
+            address: addr(token),
+            topics: vec![
+                H256::from(keccak256(TRANSFER_EVENT)),
+                H256::from(addr(from)),
+                H256::from(addr(to)),
+            ],
+            data: Bytes::from(data.to_vec()),
+            block_number: Some(U64::from(10)),
+            transaction_hash: Some(hash(name)),
+            log_index: Some(U256::from(3)),
+            ..Log::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_scan_block_decodes_token_transfers() {
+        let transfer = transfer_log("usdt", "usdt_token", "holder1", "holder2", 5_000_000);
+
+        // ERC-721 transfer: the token id is a fourth topic and the data is empty.
+        let mut nft = transfer_log("nft", "nft_token", "holder1", "holder2", 0);
+        nft.topics.push(H256::from_low_u64_be(42));
+        nft.data = Bytes::default();
+
+        let mut removed = transfer_log("removed", "usdt_token", "holder1", "holder2", 1);
+        removed.removed = Some(true);
+
+        let mut unindexed = transfer_log("unindexed", "usdt_token", "holder1", "holder2", 2);
+        unindexed.log_index = None;
+
+        let (provider, mock) = Provider::mocked();
+        mock.push::<Vec<Log>, _>(vec![transfer, nft, removed, unindexed])
+            .unwrap();
+        mock.push(mocked_block(10, &[("usdt", 60_000)])).unwrap();
+
+        let storage = create_test_storage();
+        let options = ScanOptions {
+            fetch_token_transfers: true,
+            ..ScanOptions::default()
+        };
+        let report = scan_block(&Arc::new(provider), 10, 10, &storage, &options).await;
+        assert!(report.is_complete());
+
+        let transfers = storage.token_transfers.read().await;
+        assert_eq!(transfers.len(), 1);
+        let transfer = &transfers[0];
+        assert_eq!(transfer.tx_hash, hash("usdt"));
+        assert_eq!(transfer.log_index, 3);
+        assert_eq!(transfer.block_number, 10);
+        assert_eq!(transfer.token, addr("usdt_token"));
+        assert_eq!(transfer.from, addr("holder1"));
+        assert_eq!(transfer.to, addr("holder2"));
+        assert_eq!(transfer.amount, U256::from(5_000_000));
+        assert_eq!(transfer.timestamp.timestamp(), 1_700_000_000);
+        assert_eq!(
+            storage
+                .transfers_by_sender
+                .get(&addr("holder1"))
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(
+            storage
+                .transfers_by_receiver
+                .get(&addr("holder2"))
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_scan_block_pins_receipts_and_logs_to_the_block_hash() {
+        let main = block_hash("main", 10);
+        let other = block_hash("other", 10);
+        let mut transfer = transfer_log("ok", "usdt_token", "holder1", "holder2", 5);
+        transfer.block_hash = Some(main);
+        let mut stale_transfer = transfer_log("moved", "usdt_token", "holder1", "holder2", 6);
+        stale_transfer.block_hash = Some(other);
+        let mut receipt = mocked_receipt("ok", 21_000, 1);
+        receipt.block_hash = Some(main);
+        let mut stale_receipt = mocked_receipt("moved", 30_000, 1);
+        stale_receipt.block_hash = Some(other);
+
+        let mock = MockProvider::new();
+        mock.push::<Vec<Log>, _>(vec![transfer, stale_transfer])
+            .unwrap();
+        mock.push::<Vec<TransactionReceipt>, _>(vec![receipt, stale_receipt])
+            .unwrap();
+        mock.push(chain_block(
+            "main",
+            10,
+            &[("ok", 100_000), ("moved", 100_000)],
+        ))
+        .unwrap();
+        let provider = Arc::new(Provider::new(mock.clone()));
+
+        let storage = create_test_storage();
+        let options = ScanOptions {
+            fetch_receipts: true,
+            fetch_token_transfers: true,
+            ..ScanOptions::default()
+        };
+        let report = scan_block(&provider, 10, 10, &storage, &options).await;
+        assert!(report.is_complete());
+
+        mock.assert_request("eth_getBlockByNumber", ("0xa", true))
+            .unwrap();
+        mock.assert_request("eth_getBlockReceipts", [main]).unwrap();
+        mock.assert_request(
+            "eth_getLogs",
+            [Filter::new().at_block_hash(main).event(TRANSFER_EVENT)],
+        )
+        .unwrap();
+
+        // Records of the same transactions in a block of another branch are dropped.
+        let all_txs = storage.all_txs.read().await;
+        assert_eq!(all_txs[0].gas_used, Some(21_000));
+        assert_eq!(all_txs[1].gas_used, None);
+        let transfers = storage.token_transfers.read().await;
+        assert_eq!(transfers.len(), 1);
+        assert_eq!(transfers[0].tx_hash, hash("ok"));
+    }
+
+    #[tokio::test]
+    async fn test_value_detectors_run_per_token() {
+        let storage = create_test_storage();
+        {
+            let mut transfers = storage.token_transfers.write().await;
+            // Token "a": many small transfers and one large one.
+            for i in 0..20 {
+                transfers.push(Arc::new(make_transfer(
+                    &format!("a{}", i),
+                    "token_a",
+                    "small",
+                    U256::from(10),
+                )));
+            }
+            transfers.push(Arc::new(make_transfer(
+                "a_large",
+                "token_a",
+                "big",
+                U256::from(1_000_000),
+            )));
+            // Token "b" has huge raw amounts everywhere and must not be compared with "a".
+            for i in 0..20 {
+                transfers.push(Arc::new(make_transfer(
+                    &format!("b{}", i),
+                    "token_b",
+                    "small",
+                    U256::exp10(30),
+                )));
+            }
+            for transfer in transfers.iter() {
+                storage
+                    .transfers_by_sender
+                    .entry(transfer.from)
+                    .or_default()
+                    .push(transfer.clone());
+            }
+        }
+
+        let ctx = AnalysisContext::new(storage.clone());
+        let anomalies = detect_large_tx(&ctx, &LargeTxConfig::default()).await;
+        assert_eq!(anomalies.len(), 1);
+        match &anomalies[0] {
+            Anomaly::LargeTx {
+                tx_hash,
+                token,
+                reasons,
+                ..
+            } => {
+                assert_eq!(*tx_hash, hex(hash("a_large")));
+                assert_eq!(*token, Some(hex(addr("token_a"))));
+                assert!(reasons[0].contains("1000000 units of token"));
+            }
+            _ => panic!("Wrong Anomaly type"),
+        }
+        assert!(ctx.is_flagged(hash("a_large")).await);
+
+        let patterns = detect_whales(&ctx, &WhalesConfig::default()).await;
+        assert_eq!(
+            patterns,
+            vec![BusinessPattern::Whales {
+                sender: hex(addr("big")),
+                token: Some(hex(addr("token_a"))),
+            }]
+        );
+
+        let anomalies = detect_structuring(&ctx, &StructuringConfig::default()).await;
+        let mut flagged: Vec<(String, Option<String>)> = anomalies
+            .iter()
+            .map(|anomaly| match anomaly {
+                Anomaly::Structuring { sender, token, .. } => (sender.clone(), token.clone()),
+                _ => panic!("Wrong Anomaly type"),
+            })
+            .collect();
+        flagged.sort();
+        let mut expected = vec![
+            (hex(addr("small")), Some(hex(addr("token_a")))),
+            (hex(addr("small")), Some(hex(addr("token_b")))),
+            (hex(addr("big")), Some(hex(addr("token_a")))),
+        ];
+        expected.sort();
+        assert_eq!(flagged, expected);
+
+        let native_only = LargeTxConfig {
+            include_tokens: false,
+            ..LargeTxConfig::default()
+        };
+        assert!(
+            detect_large_tx(&AnalysisContext::new(storage), &native_only)
+                .await
+                .is_empty()
+        );
+    }
+
+    fn rpc_error(code: i64, message: &str) -> MockResponse {
+        MockResponse::Error(JsonRpcError {
+            code,
+            message: message.to_string(),
+            data: None,
+        })
+    }
+
+    fn fast_retries(max_retries: u32) -> ScanOptions {
+        ScanOptions {
+            max_retries,
+            initial_backoff: std::time::Duration::from_millis(1),
+            ..ScanOptions::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_scan_block_retries_transient_errors() {
+        let (provider, mock) = Provider::mocked();
+        mock.push(mocked_block(11, &[("ok", 21_000)])).unwrap();
+        mock.push_response(rpc_error(-32005, "limit exceeded"));
+        mock.push_response(rpc_error(429, "too many requests"));
+
+        let storage = create_test_storage();
+        let report = scan_block(&Arc::new(provider), 11, 11, &storage, &fast_retries(3)).await;
+
+        assert!(report.is_complete());
+        assert_eq!(report.scanned_blocks, 1);
+        assert_eq!(report.retries, 2);
+        assert_eq!(storage.all_txs.read().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_scan_block_reports_failed_blocks() {
+        // Transient errors beyond the retry limit.
+        let (provider, mock) = Provider::mocked();
+        mock.push_response(rpc_error(-32005, "limit exceeded"));
+        mock.push_response(rpc_error(-32005, "limit exceeded"));
+
+        let storage = create_test_storage();
+        let report = scan_block(&Arc::new(provider), 12, 12, &storage, &fast_retries(1)).await;
+        assert_eq!(report.scanned_blocks, 0);
+        assert_eq!(report.retries, 1);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].block_number, 12);
+        assert_eq!(report.failed[0].attempts, 2);
+        assert!(report.failed[0].error.contains("limit exceeded"));
+
+        // Permanent errors are not retried; a half-fetched block is not stored.
+        let (provider, mock) = Provider::mocked();
+        mock.push_response(rpc_error(-32602, "invalid params"));
+        mock.push(mocked_block(13, &[("ok", 21_000)])).unwrap();
+
+        let options = ScanOptions {
+            fetch_token_transfers: true,
+            ..fast_retries(3)
+        };
+        let report = scan_block(&Arc::new(provider), 13, 13, &storage, &options).await;
+        assert_eq!(
+            report.failed,
+            vec![FailedBlock {
+                block_number: 13,
+                attempts: 1,
+                error: report.failed[0].error.clone(),
+            }]
+        );
+        assert_eq!(report.retries, 0);
+        assert!(storage.all_txs.read().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_scan_block_reports_blocks_with_invalid_fields() {
+        let storage = create_test_storage();
+        let mut huge_gas = mocked_block(21, &[("huge_gas", 0)]);
+        huge_gas.transactions[0].gas = U256::MAX;
+        let huge_timestamp = Block {
+            timestamp: U256::MAX,
+            ..mocked_block(22, &[("huge_timestamp", 21_000)])
+        };
+
+        for (number, block, field) in [(21, huge_gas, "gas"), (22, huge_timestamp, "timestamp")] {
+            let (provider, mock) = Provider::mocked();
+            mock.push(block).unwrap();
+            let report = scan_block(
+                &Arc::new(provider),
+                number,
+                number,
+                &storage,
+                &fast_retries(3),
+            )
+            .await;
+
+            // Not retried: the node would return the same block again.
+            assert_eq!(report.scanned_blocks, 0);
+            assert_eq!(report.retries, 0);
+            assert_eq!(report.failed.len(), 1);
+            assert_eq!(report.failed[0].block_number, number);
+            assert_eq!(report.failed[0].attempts, 1);
+            assert!(report.failed[0].error.contains(&format!("`{}`", field)));
+        }
+        assert!(storage.all_txs.read().await.is_empty());
+
+        let (provider, mock) = Provider::mocked();
+        let mut receipt = mocked_receipt("ok", 21_000, 1);
+        receipt.gas_used = Some(U256::MAX);
+        mock.push::<Vec<TransactionReceipt>, _>(vec![receipt])
+            .unwrap();
+        mock.push(mocked_block(23, &[("ok", 21_000)])).unwrap();
+        let options = ScanOptions {
+            fetch_receipts: true,
+            ..fast_retries(3)
+        };
+        let report = scan_block(&Arc::new(provider), 23, 23, &storage, &options).await;
+        assert_eq!(report.failed.len(), 1);
+        assert!(report.failed[0].error.contains("`gasUsed`"));
+        assert!(storage.all_txs.read().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_scan_block_rate_limit() {
+        let (provider, mock) = Provider::mocked();
+        for number in 14..=16 {
+            mock.push(mocked_block(number, &[])).unwrap();
+        }
+
+        let storage = create_test_storage();
+        let options = ScanOptions {
+            requests_per_second: Some(20),
+            ..ScanOptions::default()
+        };
+        let started = std::time::Instant::now();
+        let report = scan_block(&Arc::new(provider), 14, 16, &storage, &options).await;
+
+        assert_eq!(report.scanned_blocks, 3);
+        // Three requests at 20 per second are spaced by at least 50 ms each.
+        assert!(started.elapsed() >= std::time::Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_checkpoint_survives_reopen_and_torn_writes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("scan.checkpoint");
+
+        let mut checkpoint = Checkpoint::create(&path).unwrap();
+        checkpoint.mark_completed(5).unwrap();
+        checkpoint.mark_completed(7).unwrap();
+        checkpoint.mark_completed(7).unwrap();
+        drop(checkpoint);
+
+        // Simulate a crash in the middle of writing block 12.
+        let mut content = fs::read_to_string(&path).unwrap();
+        assert_eq!(content, "5\n7\n");
+        content.push_str("12");
+        fs::write(&path, content).unwrap();
+
+        let mut checkpoint = Checkpoint::open(&path).unwrap();
+        assert_eq!(
+            checkpoint.completed().iter().copied().collect::<Vec<_>>(),
+            vec![5, 7]
+        );
+        assert_eq!(checkpoint.pending(4, 8), vec![4, 6, 8]);
+        checkpoint.mark_completed(9).unwrap();
+        drop(checkpoint);
+
+        let checkpoint = Checkpoint::open(&path).unwrap();
+        assert!(checkpoint.is_completed(9));
+        assert!(!checkpoint.is_completed(12));
+        assert!(!checkpoint.is_completed(129));
+
+        fs::write(&path, "5\nnot a block\n").unwrap();
+        assert!(Checkpoint::open(&path).is_err());
+
+        let mut checkpoint = Checkpoint::create(&path).unwrap();
+        assert!(checkpoint.completed().is_empty());
+
+        // Blocks orphaned by a reorg are pending again, also after a reopen.
+        for block_number in [3, 4, 5, 6] {
+            checkpoint.mark_completed(block_number).unwrap();
+        }
+        checkpoint.rollback_from(5).unwrap();
+        assert_eq!(checkpoint.pending(3, 6), vec![5, 6]);
+        checkpoint.mark_completed(5).unwrap();
+        drop(checkpoint);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "3\n4\n5\n");
+    }
+
+    #[tokio::test]
+    async fn test_scan_blocks_resumes_from_checkpoint() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut checkpoint = Checkpoint::create(dir.path().join("scan.checkpoint")).unwrap();
+        checkpoint.mark_completed(20).unwrap();
+
+        // Only the pending block is requested from the node.
+        let (provider, mock) = Provider::mocked();
+        mock.push(mocked_block(21, &[("b21", 21_000)])).unwrap();
+
+        let storage = create_test_storage();
+        let pending = checkpoint.pending(20, 21);
+        assert_eq!(pending, vec![21]);
+        let report = scan_blocks(
+            &Arc::new(provider),
+            pending,
+            &storage,
+            &ScanOptions::default(),
+            |block| checkpoint.mark_completed(block.number),
+        )
+        .await;
+
+        assert!(report.is_complete());
+        assert_eq!(report.scanned_blocks, 1);
+        assert_eq!(storage.all_txs.read().await[0].hash, hash("b21"));
+        assert_eq!(checkpoint.pending(20, 21), Vec::<u64>::new());
+
+        // A block that cannot be persisted is reported and not stored.
+        let (provider, mock) = Provider::mocked();
+        mock.push(mocked_block(22, &[("b22", 21_000)])).unwrap();
+        let report = scan_blocks(
+            &Arc::new(provider),
+            [22],
+            &storage,
+            &ScanOptions::default(),
+            |_| Err(std::io::Error::other("disk full")),
+        )
+        .await;
+
+        assert_eq!(report.failed[0].block_number, 22);
+        assert!(report.failed[0].error.contains("disk full"));
+        assert_eq!(storage.all_txs.read().await.len(), 1);
+    }
+
+    /// Reports every stored transaction as a large transaction, with a
+    /// statistic that changes whenever the storage grows.
+    struct EveryTxDetector;
+
+    #[async_trait]
+    impl Detector for EveryTxDetector {
+        fn name(&self) -> &str {
+            "every_tx"
+        }
+
+        fn kind(&self) -> DetectorKind {
+            DetectorKind::Anomaly
+        }
+
+        async fn run(&self, ctx: &AnalysisContext) -> Findings {
+            let all_txs = ctx.storage.all_txs().await;
+            Findings::from_anomalies(
+                all_txs
+                    .iter()
+                    .map(|tx| Anomaly::LargeTx {
+                        tx_hash: hex(tx.hash),
+                        token: None,
+                        severity: Severity::Weak,
+                        median: Some(U256::from(all_txs.len())),
+                        z_score: None,
+                        reasons: vec![],
+                        timestamp: tx.timestamp,
+                    })
+                    .collect(),
+            )
+        }
+    }
+
+    #[tokio::test]
+    async fn test_watch_does_not_repeat_findings_whose_statistics_changed() {
+        // Two polls of one block each, nothing is pruned.
+        let (provider, mock) = Provider::mocked();
+        mock.push(mocked_block(31, &[("b31", 21_000)])).unwrap();
+        mock.push(U64::from(31)).unwrap();
+        mock.push(mocked_block(30, &[("b30", 21_000)])).unwrap();
+        mock.push(U64::from(30)).unwrap();
+
+        let mut registry = DetectorRegistry::new();
+        registry.register(EveryTxDetector).unwrap();
+        let storage = create_test_storage();
+        let options = WatchOptions {
+            poll_interval: std::time::Duration::from_millis(1),
+            scan: ScanOptions {
+                max_in_flight: 1,
+                ..ScanOptions::default()
+            },
+            ..WatchOptions::default()
+        };
+
+        let mut updates = Vec::new();
+        watch(
+            &Arc::new(provider),
+            30,
+            &registry,
+            &storage,
+            &options,
+            |update| {
+                updates.push(update);
+                if updates.len() == 2 {
+                    std::ops::ControlFlow::Break(())
+                } else {
+                    std::ops::ControlFlow::Continue(())
+                }
+            },
+        )
+        .await;
+
+        assert_eq!(tx_hashes(&updates[0].findings), vec![hex(hash("b30"))]);
+        // The median of b30 moved from 1 to 2, it is still the same finding.
+        assert_eq!(tx_hashes(&updates[1].findings), vec![hex(hash("b31"))]);
+        assert!(updates[1].retracted.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_watch_reports_only_new_findings() {
+        // Two polls: the head moves from 30 to 32. Responses are popped in reverse order.
+        let (provider, mock) = Provider::mocked();
+        mock.push(mocked_block(32, &[("b32", 21_000)])).unwrap();
+        mock.push(mocked_block(31, &[("b31", 21_000)])).unwrap();
+        mock.push(U64::from(32)).unwrap();
+        mock.push(mocked_block(30, &[("b30", 21_000)])).unwrap();
+        mock.push(U64::from(30)).unwrap();
+
+        let mut registry = DetectorRegistry::new();
+        registry.register(EveryTxDetector).unwrap();
+        let storage = create_test_storage();
+        let options = WatchOptions {
+            poll_interval: std::time::Duration::from_millis(1),
+            retain_blocks: 2,
+            scan: ScanOptions {
+                max_in_flight: 1,
+                ..ScanOptions::default()
+            },
+            ..WatchOptions::default()
+        };
+
+        let mut heads = Vec::new();
+        let mut tracked_counts = Vec::new();
+        watch(
+            &Arc::new(provider),
+            30,
+            &registry,
+            &storage,
+            &options,
+            |WatchUpdate {
+                 head,
+                 findings,
+                 tracked,
+                 ..
+             }| {
+                tracked_counts.push(tracked);
+                let hashes: Vec<String> = findings
+                    .anomalies
+                    .iter()
+                    .map(|anomaly| match anomaly {
+                        Anomaly::LargeTx { tx_hash, .. } => tx_hash.clone(),
+                        _ => panic!("Wrong Anomaly type"),
+                    })
+                    .collect();
+                heads.push((head, hashes));
+                if head == 32 {
+                    std::ops::ControlFlow::Break(())
+                } else {
+                    std::ops::ControlFlow::Continue(())
+                }
+            },
+        )
+        .await;
+
+        assert_eq!(heads.len(), 2);
+        assert_eq!(heads[0], (30, vec![hex(hash("b30"))]));
+        let (head, mut hashes) = heads[1].clone();
+        hashes.sort();
+        let mut expected = vec![hex(hash("b31")), hex(hash("b32"))];
+        expected.sort();
+        assert_eq!((head, hashes), (32, expected));
+
+        // Only the last two blocks are retained, and only their findings are
+        // remembered: b30 was forgotten with its block.
+        let all_txs = storage.all_txs.read().await;
+        assert_eq!(all_txs.len(), 2);
+        assert_eq!(tracked_counts, vec![1, 2]);
+        assert!(all_txs.iter().all(|tx| tx.block_number >= 31));
+        assert!(storage
+            .by_sender
+            .get(&addr("sender1"))
+            .unwrap()
+            .iter()
+            .all(|tx| tx.block_number >= 31));
+    }
+
+    /// Hash of block `number` on the chain branch `branch`.
+    fn block_hash(branch: &str, number: u64) -> H256 {
+        hash(&format!("block-{}{}", branch, number))
+    }
+
+    /// Full block `number` of `branch` whose parent is on the same branch.
+    fn chain_block(branch: &str, number: u64, txs: &[(&str, u64)]) -> Block<Transaction> {
+        Block {
+            hash: Some(block_hash(branch, number)),
+            parent_hash: block_hash(branch, number - 1),
+            ..mocked_block(number, txs)
+        }
+    }
+
+    /// Header-only block as returned by `eth_getBlockByNumber` without transactions.
+    fn chain_header(branch: &str, number: u64) -> Block<H256> {
+        Block {
+            number: Some(U64::from(number)),
+            hash: Some(block_hash(branch, number)),
+            parent_hash: block_hash(branch, number - 1),
+            ..Block::default()
+        }
+    }
+
+    fn tx_hashes(findings: &Findings) -> Vec<String> {
+        let mut hashes: Vec<String> = findings
+            .anomalies
+            .iter()
+            .filter_map(|anomaly| anomaly.tx_hash().map(str::to_string))
+            .collect();
+        hashes.sort();
+        hashes
+    }
+
+    #[tokio::test]
+    async fn test_rollback_from_updates_indexes() {
+        let storage = create_test_storage();
+        for (name, block) in [("t1", 1), ("t2", 2), ("t3", 3)] {
+            let mut tx = make_tx(name, "alice", Some("bob"), eth("1"), Utc::now());
+            tx.block_number = block;
+            storage.insert(tx).await;
+        }
+        let mut transfer = make_transfer("t3", "token", "alice", U256::from(10));
+        transfer.block_number = 3;
+        storage.insert_transfer(transfer).await;
+        for number in 1..=3 {
+            storage.blocks.write().await.insert(
+                number,
+                BlockHeader {
+                    number,
+                    hash: block_hash("a", number),
+                    parent_hash: block_hash("a", number - 1),
+                },
+            );
+        }
+
+        let orphaned = storage.rollback_from(2).await;
+
+        let mut orphaned: Vec<H256> = orphaned.iter().map(|tx| tx.hash).collect();
+        orphaned.sort();
+        let mut expected = vec![hash("t2"), hash("t3")];
+        expected.sort();
+        assert_eq!(orphaned, expected);
+        assert_eq!(storage.all_txs.read().await.len(), 1);
+        assert_eq!(storage.by_sender.get(&addr("alice")).unwrap().len(), 1);
+        assert_eq!(storage.by_reciever.get(&addr("bob")).unwrap().len(), 1);
+        assert!(storage.token_transfers.read().await.is_empty());
+        assert!(storage.transfers_by_sender.get(&addr("alice")).is_none());
+        assert!(storage.transfers_by_tx.is_empty());
+        assert!(storage.contains(&hash("t1")) && !storage.contains(&hash("t2")));
+        let by_block = storage.by_block.read().await;
+        assert_eq!(by_block.keys().copied().collect::<Vec<_>>(), vec![1]);
+        assert_eq!(storage.tip().await, Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_storage_indexes_share_records() {
+        let storage = create_test_storage();
+        let mut call = make_tx("call", "alice", Some("bob"), eth("0"), Utc::now());
+        call.input = "0xa9059cbb0000".to_string();
+        storage.insert(call).await;
+        storage
+            .insert(make_tx("plain", "alice", None, eth("1"), Utc::now()))
+            .await;
+
+        let stored = storage.get(&hash("call")).unwrap();
+        let all_txs = storage.all_txs.read().await;
+        assert!(Arc::ptr_eq(&stored, &all_txs[0]));
+        assert!(Arc::ptr_eq(
+            &stored,
+            &storage.by_sender.get(&addr("alice")).unwrap()[0]
+        ));
+        assert!(Arc::ptr_eq(
+            &stored,
+            &storage.by_reciever.get(&addr("bob")).unwrap()[0]
+        ));
+        assert!(Arc::ptr_eq(&stored, &storage.by_block.read().await[&1][0]));
+        // One allocation referenced by all_txs, by_hash, by_sender, by_reciever,
+        // by_block, by_selector and the handle above.
+        assert_eq!(Arc::strong_count(&stored), 7);
+
+        assert_eq!(stored.selector(), Some("0xa9059cbb"));
+        assert_eq!(storage.by_selector.len(), 1);
+        assert_eq!(storage.by_selector.get("0xa9059cbb").unwrap().len(), 1);
+        assert_eq!(storage.by_block.read().await[&1].len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_reorg_rescans_orphaned_blocks() {
+        // Blocks 10..=12 are scanned on branch "a", then the node switches to
+        // branch "b" from block 11. Responses are popped in reverse order.
+        let (provider, mock) = Provider::mocked();
+        mock.push(chain_block("b", 12, &[("b12", 21_000)])).unwrap();
+        mock.push(chain_block("b", 11, &[("b11", 21_000)])).unwrap();
+        mock.push(chain_header("a", 10)).unwrap();
+        mock.push(chain_header("b", 11)).unwrap();
+        mock.push(chain_header("b", 12)).unwrap();
+        mock.push(chain_block("a", 12, &[("a12", 21_000)])).unwrap();
+        mock.push(chain_block("a", 11, &[("a11", 21_000)])).unwrap();
+        mock.push(chain_block("a", 10, &[("a10", 21_000)])).unwrap();
+
+        let provider = Arc::new(provider);
+        let storage = create_test_storage();
+        let options = ScanOptions {
+            max_in_flight: 1,
+            ..ScanOptions::default()
+        };
+        let report = scan_block(&provider, 10, 12, &storage, &options).await;
+        assert!(report.is_complete());
+
+        let reorg = reconcile_reorg(&provider, &storage, &options)
+            .await
+            .unwrap()
+            .expect("reorg not detected");
+
+        assert_eq!(reorg.fork_block, 11);
+        let mut orphaned: Vec<H256> = reorg.orphaned.iter().map(|tx| tx.hash).collect();
+        orphaned.sort();
+        let mut expected = vec![hash("a11"), hash("a12")];
+        expected.sort();
+        assert_eq!(orphaned, expected);
+        assert!(reorg.rescan.is_complete());
+        assert_eq!(reorg.rescan.scanned_blocks, 2);
+
+        let mut stored: Vec<H256> = storage
+            .all_txs
+            .read()
+            .await
+            .iter()
+            .map(|tx| tx.hash)
+            .collect();
+        stored.sort();
+        let mut expected = vec![hash("a10"), hash("b11"), hash("b12")];
+        expected.sort();
+        assert_eq!(stored, expected);
+        assert_eq!(storage.by_sender.get(&addr("sender1")).unwrap().len(), 3);
+        let blocks = storage.blocks.read().await;
+        assert_eq!(blocks[&10].hash, block_hash("a", 10));
+        assert_eq!(blocks[&12].hash, block_hash("b", 12));
+    }
+
+    #[tokio::test]
+    async fn test_find_reorg_checks_only_the_tip_without_reorg() {
+        let (provider, mock) = Provider::mocked();
+        mock.push(chain_header("a", 11)).unwrap();
+        mock.push(chain_block("a", 11, &[("a11", 21_000)])).unwrap();
+        mock.push(chain_block("a", 10, &[("a10", 21_000)])).unwrap();
+
+        let provider = Arc::new(provider);
+        let storage = create_test_storage();
+        let options = ScanOptions {
+            max_in_flight: 1,
+            ..ScanOptions::default()
+        };
+        scan_block(&provider, 10, 11, &storage, &options).await;
+
+        // A single response is left for the tip; a second request would fail.
+        assert_eq!(find_reorg(&provider, &storage, 64).await.unwrap(), None);
+        assert_eq!(find_reorg(&provider, &storage, 0).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_watch_gives_up_on_failed_blocks() {
+        // Poll 1 (head 31) fails on block 30; poll 2 (head 32) drops it as it falls
+        // out of the two retained blocks and fails on 32; poll 3 fails on 32 again
+        // and gives up on it. Responses are popped in reverse order.
+        let (provider, mock) = Provider::mocked();
+        mock.push_response(rpc_error(-32602, "invalid params"));
+        mock.push(U64::from(32)).unwrap();
+        mock.push_response(rpc_error(-32602, "invalid params"));
+        mock.push(U64::from(32)).unwrap();
+        mock.push(mocked_block(31, &[("b31", 21_000)])).unwrap();
+        mock.push_response(rpc_error(-32602, "invalid params"));
+        mock.push(U64::from(31)).unwrap();
+
+        let registry = DetectorRegistry::new();
+        let storage = create_test_storage();
+        let options = WatchOptions {
+            poll_interval: std::time::Duration::from_millis(1),
+            retain_blocks: 2,
+            max_failed_polls: 2,
+            scan: ScanOptions {
+                max_in_flight: 1,
+                ..ScanOptions::default()
+            },
+        };
+
+        let mut updates = Vec::new();
+        watch(
+            &Arc::new(provider),
+            30,
+            &registry,
+            &storage,
+            &options,
+            |update| {
+                updates.push(update);
+                if updates.len() == 3 {
+                    std::ops::ControlFlow::Break(())
+                } else {
+                    std::ops::ControlFlow::Continue(())
+                }
+            },
+        )
+        .await;
+
+        let given_up = |update: &WatchUpdate| -> Vec<u64> {
+            update
+                .given_up
+                .iter()
+                .map(|failed| failed.block_number)
+                .collect()
+        };
+        assert_eq!(given_up(&updates[0]), Vec::<u64>::new());
+        assert_eq!(given_up(&updates[1]), vec![30]);
+        assert_eq!(given_up(&updates[2]), vec![32]);
+        assert!(updates[2].given_up[0].error.contains("invalid params"));
+        assert_eq!(storage.all_txs.read().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_watch_retracts_findings_of_orphaned_blocks() {
+        // Poll 1 ingests 10 and 11 on branch "a"; poll 2 finds block 11 replaced
+        // by branch "b". Responses are popped in reverse order.
+        let (provider, mock) = Provider::mocked();
+        mock.push(chain_block("b", 11, &[("b11", 21_000)])).unwrap();
+        mock.push(chain_header("a", 10)).unwrap();
+        mock.push(chain_header("b", 11)).unwrap();
+        mock.push(U64::from(11)).unwrap();
+        mock.push(chain_block("a", 11, &[("a11", 21_000)])).unwrap();
+        mock.push(chain_block("a", 10, &[("a10", 21_000)])).unwrap();
+        mock.push(U64::from(11)).unwrap();
+
+        let mut registry = DetectorRegistry::new();
+        registry.register(EveryTxDetector).unwrap();
+        let storage = create_test_storage();
+        let options = WatchOptions {
+            poll_interval: std::time::Duration::from_millis(1),
+            scan: ScanOptions {
+                max_in_flight: 1,
+                ..ScanOptions::default()
+            },
+            ..WatchOptions::default()
+        };
+
+        let mut updates = Vec::new();
+        watch(
+            &Arc::new(provider),
+            10,
+            &registry,
+            &storage,
+            &options,
+            |update| {
+                updates.push(update);
+                if updates.len() == 2 {
+                    std::ops::ControlFlow::Break(())
+                } else {
+                    std::ops::ControlFlow::Continue(())
+                }
+            },
+        )
+        .await;
+
+        let mut expected = vec![hex(hash("a10")), hex(hash("a11"))];
+        expected.sort();
+        assert_eq!(tx_hashes(&updates[0].findings), expected);
+        assert_eq!(updates[0].fork_block, None);
+
+        assert_eq!(updates[1].fork_block, Some(11));
+        assert_eq!(updates[1].orphaned.len(), 1);
+        assert_eq!(tx_hashes(&updates[1].retracted), vec![hex(hash("a11"))]);
+        assert_eq!(tx_hashes(&updates[1].findings), vec![hex(hash("b11"))]);
+    }
+
+    #[tokio::test]
+    async fn test_overlapping_scans_do_not_duplicate() {
+        let (provider, mock) = Provider::mocked();
+        mock.push(mocked_block(3, &[("b3", 21_000)])).unwrap();
+        mock.push(mocked_block(2, &[("b2", 21_000)])).unwrap();
+        mock.push(mocked_block(2, &[("b2", 21_000)])).unwrap();
+        mock.push(mocked_block(1, &[("b1", 21_000)])).unwrap();
+
+        let provider = Arc::new(provider);
+        let storage = create_test_storage();
+        let options = ScanOptions {
+            max_in_flight: 1,
+            ..ScanOptions::default()
+        };
+        assert!(scan_block(&provider, 1, 2, &storage, &options)
+            .await
+            .is_complete());
+        assert!(scan_block(&provider, 2, 3, &storage, &options)
+            .await
+            .is_complete());
+
+        assert_eq!(storage.all_txs.read().await.len(), 3);
+        assert_eq!(storage.by_sender.get(&addr("sender1")).unwrap().len(), 3);
+        assert_eq!(
+            storage.by_reciever.get(&addr("receiver1")).unwrap().len(),
+            3
+        );
+        assert_eq!(storage.by_hash.len(), 3);
+        assert_eq!(storage.get(&hash("b2")).unwrap().block_number, 2);
+        assert!(storage.get(&hash("unknown")).is_none());
+
+        // Direct insertion is idempotent as well
+        let tx = storage.get(&hash("b3")).unwrap();
+        assert!(!storage.insert(tx.as_ref().clone()).await);
+        let transfer = make_transfer("b3", "token", "sender1", U256::from(5));
+        assert!(storage.insert_transfer(transfer.clone()).await);
+        assert!(!storage.insert_transfer(transfer).await);
+        assert_eq!(storage.token_transfers.read().await.len(), 1);
+        assert_eq!(
+            storage
+                .transfers_by_sender
+                .get(&addr("sender1"))
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    /// Writes blocks 1..=3 to `store` and checks what it returns.
+    async fn check_tx_store<S: TxStore>(store: &S) {
+        assert_eq!(store.block_bounds().await.unwrap(), None);
+        for number in 1..=3u64 {
+            let mut first = make_tx(
+                &format!("b{}a", number),
+                "alice",
+                Some("bob"),
+                eth("1"),
+                Utc::now(),
+            );
+            first.block_number = number;
+            let mut second = make_tx(
+                &format!("b{}b", number),
+                "carol",
+                None,
+                eth("2"),
+                Utc::now(),
+            );
+            second.block_number = number;
+            let mut transfer = make_transfer(
+                &format!("b{}a", number),
+                "token",
+                "alice",
+                U256::from(number),
+            );
+            transfer.block_number = number;
+            let header = BlockHeader {
+                number,
+                hash: block_hash("a", number),
+                parent_hash: block_hash("a", number - 1),
+            };
+            let inserted = store
+                .insert_block(Some(header), &[first.clone(), second], &[transfer.clone()])
+                .await
+                .unwrap();
+            assert_eq!(inserted, 2);
+            // Writing the same block again changes nothing.
+            let inserted = store
+                .insert_block(Some(header), &[first], &[transfer])
+                .await
+                .unwrap();
+            assert_eq!(inserted, 0);
+        }
+
+        assert_eq!(store.block_bounds().await.unwrap(), Some((1, 3)));
+        let txs = store.txs_in_blocks(2..=3).await.unwrap();
+        let hashes: Vec<H256> = txs.iter().map(|tx| tx.hash).collect();
+        assert_eq!(
+            hashes,
+            vec![hash("b2a"), hash("b2b"), hash("b3a"), hash("b3b")]
+        );
+        assert_eq!(txs[0].value, eth("1"));
+        assert_eq!(txs[1].to, None);
+        let transfers = store.transfers_in_blocks(1..=2).await.unwrap();
+        assert_eq!(transfers.len(), 2);
+        assert_eq!(transfers[1].amount, U256::from(2));
+        let headers = store.headers_in_blocks(3..=10).await.unwrap();
+        assert_eq!(headers.len(), 1);
+        assert_eq!(headers[0].hash, block_hash("a", 3));
+
+        // The full range of block numbers covers every block.
+        assert_eq!(store.txs_in_blocks(0..=u64::MAX).await.unwrap().len(), 6);
+        assert_eq!(
+            store.transfers_in_blocks(0..=u64::MAX).await.unwrap().len(),
+            3
+        );
+        assert_eq!(
+            store.headers_in_blocks(0..=u64::MAX).await.unwrap().len(),
+            3
+        );
+        assert_eq!(store.rollback_from(u64::MAX).await.unwrap(), 0);
+        assert_eq!(store.block_bounds().await.unwrap(), Some((1, 3)));
+
+        assert_eq!(store.rollback_from(3).await.unwrap(), 2);
+        assert_eq!(store.block_bounds().await.unwrap(), Some((1, 2)));
+        assert!(store.txs_in_blocks(3..=3).await.unwrap().is_empty());
+        assert!(store.headers_in_blocks(3..=3).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_tx_stores_behave_alike() {
+        check_tx_store(&TxStorage::new()).await;
+
+        let dir = tempfile::tempdir().unwrap();
+        let store = SqliteStore::open(dir.path().join("txs.sqlite")).unwrap();
+        check_tx_store(&store).await;
+
+        // SQLite cannot hold block numbers above i64::MAX: such a block is rejected.
+        let mut tx = make_tx("far", "alice", None, eth("1"), Utc::now());
+        tx.block_number = u64::MAX;
+        assert!(matches!(
+            store.insert_block(None, &[tx], &[]).await,
+            Err(StoreError::BlockOutOfRange(u64::MAX))
+        ));
+        assert_eq!(store.block_bounds().await.unwrap(), Some((1, 2)));
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_store_persists_between_runs() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("txs.sqlite");
+        {
+            let store = SqliteStore::open(&path).unwrap();
+            let mut txs = Vec::new();
+            for i in 0..5 {
+                txs.push(make_tx(
+                    &format!("small{}", i),
+                    "sender1",
+                    Some("receiver1"),
+                    eth("1"),
+                    Utc::now(),
+                ));
+            }
+            txs.push(make_tx(
+                "large",
+                "sender1",
+                Some("receiver1"),
+                eth("1000"),
+                Utc::now(),
+            ));
+            store.write_block(None, &txs, &[]).unwrap();
+        }
+
+        // A later run loads the stored blocks and runs the detectors on them.
+        let store = SqliteStore::open(&path).unwrap();
+        let storage = load_storage(&store, 1..=1).await.unwrap();
+        assert_eq!(storage.all_txs.read().await.len(), 6);
+        assert_eq!(storage.by_sender.get(&addr("sender1")).unwrap().len(), 6);
+        assert!(storage.contains(&hash("large")));
+
+        let ctx = AnalysisContext::new(storage);
+        let anomalies = detect_large_tx(&ctx, &LargeTxConfig::default()).await;
+        assert_eq!(
+            tx_hashes(&Findings::from_anomalies(anomalies)),
+            vec![hex(hash("large"))]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_store_rejects_newer_schema() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("txs.sqlite");
+        drop(SqliteStore::open(&path).unwrap());
+        let connection = rusqlite::Connection::open(&path).unwrap();
+        connection
+            .pragma_update(None, "user_version", SCHEMA_VERSION + 1)
+            .unwrap();
+        drop(connection);
+
+        match SqliteStore::open(&path) {
+            Err(StoreError::UnsupportedSchema(version)) => assert_eq!(version, SCHEMA_VERSION + 1),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("newer schema accepted"),
+        }
+    }
+
+    /// Minimal [`TxQuery`] over a plain list, relying on the default methods.
+    struct FixtureTxs(Vec<TxRef>);
+
+    #[async_trait]
+    impl TxQuery for FixtureTxs {
+        async fn all_txs(&self) -> Vec<TxRef> {
+            self.0.clone()
+        }
+
+        async fn senders(&self) -> Vec<Address> {
+            let senders: HashSet<Address> = self.0.iter().map(|tx| tx.from).collect();
+            senders.into_iter().collect()
+        }
+
+        async fn sent_by(&self, sender: &Address) -> Vec<TxRef> {
+            self.0
+                .iter()
+                .filter(|tx| tx.from == *sender)
+                .cloned()
+                .collect()
+        }
+
+        async fn received_by(&self, receiver: &Address) -> Vec<TxRef> {
+            self.0
+                .iter()
+                .filter(|tx| tx.to == Some(*receiver))
+                .cloned()
+                .collect()
+        }
+
+        async fn get(&self, hash: &H256) -> Option<TxRef> {
+            self.0.iter().find(|tx| tx.hash == *hash).cloned()
+        }
+
+        async fn token_transfers(&self) -> Vec<TransferRef> {
+            Vec::new()
+        }
+
+        async fn transfers_sent_by(&self, _sender: &Address) -> Vec<TransferRef> {
+            Vec::new()
+        }
+    }
+
+    fn query_fixture(now: DateTime<Utc>) -> Vec<TransactionRecord> {
+        let mut txs = Vec::new();
+        for i in 0..6u64 {
+            let mut tx = make_tx(
+                &format!("payroll_{}", i),
+                "payroll",
+                Some("employee"),
+                eth("1"),
+                now - Duration::days(6 - i as i64),
+            );
+            tx.block_number = 10 + i;
+            txs.push(tx);
+        }
+        for i in 0..4u64 {
+            let mut tx = make_tx(
+                &format!("small_{}", i),
+                "spender",
+                Some("shop"),
+                eth("0.1"),
+                now - Duration::hours(i as i64),
+            );
+            tx.block_number = 20 + i;
+            txs.push(tx);
+        }
+        let mut whale = make_tx("whale", "spender", Some("shop"), eth("500"), now);
+        whale.block_number = 30;
+        whale.gas_price = gwei("5000");
+        txs.push(whale);
+        txs
+    }
+
+    #[tokio::test]
+    async fn test_tx_query_sources_are_interchangeable() {
+        let now = Utc::now();
+        let storage = create_test_storage();
+        for tx in query_fixture(now) {
+            storage.insert(tx).await;
+        }
+        let fixture = Arc::new(FixtureTxs(shared(query_fixture(now))));
+
+        let from_storage = AnalysisContext::new(storage.clone());
+        let from_fixture = AnalysisContext::new(fixture.clone());
+        let config = AnalyzerConfig::default();
+
+        assert_eq!(
+            detect_large_tx(&from_storage, &config.large_tx).await,
+            detect_large_tx(&from_fixture, &config.large_tx).await
+        );
+        assert_eq!(
+            detect_high_fee(&from_storage, &config.high_fee).await,
+            detect_high_fee(&from_fixture, &config.high_fee).await
+        );
+        assert_eq!(
+            detect_regular_payments(&from_storage, &config.regular_payments).await,
+            detect_regular_payments(&from_fixture, &config.regular_payments).await
+        );
+        let anomalies = detect_large_tx(&from_fixture, &config.large_tx).await;
+        assert!(anomalies
+            .iter()
+            .any(|a| a.tx_hash() == Some(hex(hash("whale")).as_str())));
+
+        // Default range queries of the fixture agree with the storage indexes.
+        let hashes = |txs: Vec<TxRef>| {
+            let mut hashes: Vec<H256> = txs.iter().map(|tx| tx.hash).collect();
+            hashes.sort();
+            hashes
+        };
+        assert_eq!(
+            hashes(storage.in_blocks(12..=21).await),
+            hashes(fixture.in_blocks(12..=21).await)
+        );
+        assert_eq!(hashes(fixture.in_blocks(12..=21).await).len(), 6);
+        let last_day = fixture.between(now - Duration::days(1), now).await;
+        assert_eq!(last_day.len(), 6);
+        assert_eq!(
+            hashes(storage.between(now - Duration::days(1), now).await),
+            hashes(last_day)
+        );
+        assert_eq!(
+            hashes(storage.received_by(&addr("employee")).await),
+            hashes(fixture.received_by(&addr("employee")).await)
+        );
+
+        // Blocks added out of order, as concurrent scans complete them, are
+        // still returned in block order.
+        let shuffled = create_test_storage();
+        for tx in query_fixture(now).into_iter().rev() {
+            shuffled.insert(tx).await;
+        }
+        let block_numbers =
+            |txs: Vec<TxRef>| txs.iter().map(|tx| tx.block_number).collect::<Vec<u64>>();
+        let all = block_numbers(shuffled.all_txs().await);
+        assert_eq!(all, block_numbers(fixture.all_txs().await));
+        assert!(all.is_sorted());
+        assert_eq!(
+            block_numbers(shuffled.sent_by(&addr("spender")).await),
+            vec![20, 21, 22, 23, 30]
+        );
+        for (_, txs) in shuffled.txs_by_sender().await {
+            assert!(block_numbers(txs).is_sorted());
+        }
+    }
+
+    #[test]
+    fn test_read_csv_transactions() {
+        let csv = format!(
+            "\
+hash,from,to,value,gas_price,block_number,timestamp,status,extra
+{},0xAbCdEf0000000000000000000000000000000001,,1000000000000000000,0x3b9aca00,10,2024-01-01T00:00:00Z,1,x
+{},abcdef0000000000000000000000000000000001,0x0000000000000000000000000000000000000002,0x10,,11,1704067260,false,y
+",
+            hex(H256::from_low_u64_be(1)),
+            hex(H256::from_low_u64_be(2))
+        );
+        let txs = read_csv(csv.as_bytes()).unwrap();
+        assert_eq!(txs.len(), 2);
+        assert_eq!(txs[0].hash, H256::from_low_u64_be(1));
+        assert_eq!(txs[0].from, txs[1].from);
+        assert_eq!(txs[0].to, None);
+        assert_eq!(txs[0].value, eth("1"));
+        assert_eq!(txs[0].gas_price, gwei("1"));
+        assert_eq!(txs[0].input, "0x");
+        assert_eq!(txs[0].status, Some(true));
+        assert_eq!(txs[1].value, U256::from(16));
+        assert_eq!(txs[1].gas_price, U256::zero());
+        assert_eq!(txs[1].status, Some(false));
+        assert_eq!(txs[1].timestamp - txs[0].timestamp, Duration::minutes(1));
+
+        let invalid = "hash,from,value,block_number,timestamp\n0x01,0x02,1,1,0\n";
+        match read_csv(invalid.as_bytes()) {
+            Err(ImportError::InvalidField {
+                record,
+                field,
+                value,
+            }) => {
+                assert_eq!((record, field, value.as_str()), (2, "hash", "0x01"));
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(matches!(
+            read_csv("hash,from\n0x01,0x02\n".as_bytes()),
+            Err(ImportError::Csv(_))
+        ));
+    }
+
+    #[test]
+    fn test_read_ndjson_round_trip() {
+        let now = DateTime::<Utc>::from_timestamp(1_700_000_000, 0).unwrap();
+        let txs = vec![
+            make_tx("a", "alice", Some("bob"), eth("1"), now),
+            make_tx("b", "bob", None, eth("2"), now),
+        ];
+        let mut ndjson = String::new();
+        for tx in &txs {
+            ndjson.push_str(&serde_json::to_string(tx).unwrap());
+            ndjson.push_str("\n\n");
+        }
+        let read: Vec<TransactionRecord> = read_ndjson(ndjson.as_bytes()).unwrap();
+        assert_eq!(read, txs);
+
+        ndjson.push_str("{\"hash\": 1}\n");
+        match read_ndjson::<TransactionRecord, _>(ndjson.as_bytes()) {
+            Err(ImportError::Json { record, .. }) => assert_eq!(record, 5),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rpc_block_import_matches_scan() {
+        let block = chain_block("main", 7, &[("a", 21_000), ("b", 50_000)]);
+        let (provider, mock) = Provider::mocked();
+        mock.push(block.clone()).unwrap();
+        let scanned = create_test_storage();
+        let report = scan_block(&Arc::new(provider), 7, 7, &scanned, &ScanOptions::default()).await;
+        assert!(report.is_complete());
+
+        // A saved batch response: one block wrapped in its envelope, one unknown block.
+        let dump = serde_json::json!([
+            {"jsonrpc": "2.0", "id": 1, "result": block},
+            {"jsonrpc": "2.0", "id": 2, "result": null},
+        ]);
+        let file = tempfile::Builder::new().suffix(".json").tempfile().unwrap();
+        fs::write(file.path(), dump.to_string()).unwrap();
+        assert_eq!(
+            ImportFormat::from_path(file.path()),
+            Some(ImportFormat::RpcBlocks)
+        );
+        let blocks = import_file(file.path(), None).unwrap();
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(
+            blocks[0].header.map(|h| h.hash),
+            Some(block_hash("main", 7))
+        );
+
+        let imported = create_test_storage();
+        for block in blocks {
+            store_block(&imported, block).await;
+        }
+        assert_eq!(
+            *imported.all_txs.read().await,
+            *scanned.all_txs.read().await
+        );
+        assert_eq!(*imported.blocks.read().await, *scanned.blocks.read().await);
+
+        let pending = Block {
+            number: None,
+            ..mocked_block(8, &[])
+        };
+        let dump = serde_json::to_string(&pending).unwrap();
+        assert!(matches!(
+            read_rpc_blocks(dump.as_bytes()),
+            Err(ImportError::InvalidField {
+                field: "number",
+                ..
+            })
+        ));
+
+        let mut huge_gas = mocked_block(9, &[("huge_gas", 0)]);
+        huge_gas.transactions[0].gas = U256::MAX;
+        let dump = serde_json::to_string(&vec![mocked_block(8, &[]), huge_gas]).unwrap();
+        match read_rpc_blocks(dump.as_bytes()) {
+            Err(ImportError::InvalidField {
+                record,
+                field,
+                value,
+            }) => {
+                assert_eq!((record, field), (2, "gas"));
+                assert_eq!(value, U256::MAX.to_string());
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_imported_transactions_run_through_detectors() {
+        let now = Utc::now();
+        let mut txs = query_fixture(now);
+        txs.reverse();
+        let transfer = TokenTransfer {
+            block_number: 30,
+            ..make_transfer("t", "token", "spender", U256::from(5))
+        };
+        let blocks = group_by_block(txs, vec![transfer]);
+        assert!(blocks
+            .windows(2)
+            .all(|pair| pair[0].number < pair[1].number));
+        assert_eq!(blocks.last().unwrap().transfers.len(), 1);
+
+        let storage = create_test_storage();
+        for block in blocks {
+            store_block(&storage, block).await;
+        }
+        assert_eq!(storage.token_transfers.read().await.len(), 1);
+        let anomalies = detect_large_tx(
+            &AnalysisContext::new(storage),
+            &AnalyzerConfig::default().large_tx,
+        )
+        .await;
+        assert!(anomalies
+            .iter()
+            .any(|a| a.tx_hash() == Some(hex(hash("whale")).as_str())));
+    }
+
+    #[tokio::test]
+    async fn test_recorded_scan_replays_offline() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = ScanOptions {
+            fetch_receipts: true,
+            fetch_token_transfers: true,
+            ..fast_retries(3)
+        };
+
+        // Requests: the block (rate limited once, then answered), receipts, logs.
+        let mock = MockProvider::new();
+        mock.push::<Vec<Log>, _>(vec![transfer_log(
+            "ok",
+            "usdt_token",
+            "sender1",
+            "holder2",
+            7,
+        )])
+        .unwrap();
+        mock.push::<Vec<TransactionReceipt>, _>(vec![mocked_receipt("ok", 21_000, 1)])
+            .unwrap();
+        mock.push(mocked_block(10, &[("ok", 100_000)])).unwrap();
+        mock.push_response(rpc_error(-32005, "limit exceeded"));
+        let recording = Arc::new(Provider::new(
+            RecordingClient::create(mock, dir.path()).unwrap(),
+        ));
+        let recorded = create_test_storage();
+        let report = scan_block(&recording, 10, 10, &recorded, &options).await;
+        assert!(report.is_complete());
+        assert_eq!(report.retries, 1);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 3);
+
+        let replay = Arc::new(Provider::new(ReplayClient::open(dir.path()).unwrap()));
+        let replayed = create_test_storage();
+        let report = scan_block(&replay, 10, 10, &replayed, &options).await;
+        assert!(report.is_complete());
+        assert_eq!(report.retries, 1);
+        assert_eq!(
+            *replayed.all_txs.read().await,
+            *recorded.all_txs.read().await
+        );
+        assert_eq!(replayed.all_txs.read().await[0].gas_used, Some(21_000));
+        assert_eq!(
+            *replayed.token_transfers.read().await,
+            *recorded.token_transfers.read().await
+        );
+        assert_eq!(replayed.token_transfers.read().await.len(), 1);
+
+        // A block that was never recorded fails with the request to record.
+        let report = scan_block(&replay, 11, 11, &replayed, &fast_retries(0)).await;
+        assert_eq!(report.failed.len(), 1);
+        assert!(report.failed[0]
+            .error
+            .contains("no recorded response for eth_getBlockByNumber"));
+    }
+
+    const SANCTIONED: &str = "0x8589427373D6D84E98730D7795D8f6f8731FDA16";
+    const SANCTIONED_2: &str = "0x722122dF12D4e14e13Ac3b6895a86e84145b6967";
+
+    #[test]
+    fn test_parse_sanctions_list_formats() {
+        let expected: HashSet<Address> = [addr(SANCTIONED), addr(SANCTIONED_2)].into();
+
+        let json = format!(
+            r#"[{{"address": "{}"}}, "{}"]"#,
+            SANCTIONED,
+            SANCTIONED_2.to_lowercase()
+        );
+        assert_eq!(parse_addresses("json", &json).unwrap(), expected);
+
+        let csv = format!(
+            "name,address\nmixer,{}\nmixer,{}\n",
+            SANCTIONED.to_uppercase().replace("0X", "0x"),
+            SANCTIONED_2
+        );
+        assert_eq!(parse_addresses("csv", &csv).unwrap(), expected);
+
+        let text = format!("# OFAC\n{}\n\n{}\n", SANCTIONED, SANCTIONED_2);
+        assert_eq!(parse_addresses("text", &text).unwrap(), expected);
+
+        let err =
+            parse_addresses("text", &format!("{}\nnot-an-address\n", SANCTIONED)).unwrap_err();
+        assert!(matches!(err, SanctionsError::Parse { line: 2, .. }));
+        assert!(err.to_string().contains("at line 2"));
+    }
+
+    #[tokio::test]
+    async fn test_load_sanctions_merges_sources() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = dir.path().join("first.txt");
+        let second = dir.path().join("second.json");
+        fs::write(&first, format!("{}\n", SANCTIONED)).unwrap();
+        fs::write(&second, format!(r#"["{}"]"#, SANCTIONED_2)).unwrap();
+
+        let sources: Vec<Box<dyn SanctionsSource>> = vec![
+            Box::new(FileSource { path: first }),
+            Box::new(FileSource { path: second }),
+        ];
+        let list = load_sanctions(&sources).await.unwrap();
+        assert_eq!(list.len(), 2);
+        assert!(list.contains(&addr(SANCTIONED)));
+        assert!(list.contains(&addr(SANCTIONED_2)));
+        assert_eq!(list.sources().len(), 2);
+
+        let missing: Vec<Box<dyn SanctionsSource>> = vec![Box::new(FileSource {
+            path: dir.path().join("missing.txt"),
+        })];
+        assert!(matches!(
+            load_sanctions(&missing).await,
+            Err(SanctionsError::Io { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_url_source_uses_fresh_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = UrlSource {
+            // Nothing listens there: the list can only come from the cache.
+            url: "http://127.0.0.1:9/sanctions.txt".to_string(),
+            cache_dir: dir.path().to_path_buf(),
+            ttl: std::time::Duration::from_secs(3600),
+        };
+        assert!(source.load().await.is_err());
+
+        fs::write(source.cache_path(), format!("{}\n", SANCTIONED)).unwrap();
+        let addresses = source.load().await.unwrap();
+        assert_eq!(addresses, [addr(SANCTIONED)].into());
+
+        // A stale cache is still used when the download fails.
+        let stale = UrlSource {
+            ttl: std::time::Duration::ZERO,
+            ..source
+        };
+        assert_eq!(stale.load().await.unwrap(), addresses);
+    }
+
+    #[tokio::test]
+    async fn test_detect_blacklist_adresses() {
+        let storage = create_test_storage();
+        {
+            let mut all_txs = storage.all_txs.write().await;
+            all_txs.push(Arc::new(make_tx(
+                "from_sanctioned",
+                SANCTIONED,
+                Some("receiver"),
+                eth("1"),
+                Utc::now(),
+            )));
+            all_txs.push(Arc::new(make_tx(
+                "to_sanctioned",
+                "sender",
+                Some(SANCTIONED_2),
+                eth("1"),
+                Utc::now(),
+            )));
+            all_txs.push(Arc::new(make_tx(
+                "clean",
+                "sender",
+                Some("receiver"),
+                eth("1"),
+                Utc::now(),
+            )));
+        }
+        let ctx = AnalysisContext::new(storage);
+        let blacklist: SanctionsList = [addr(SANCTIONED), addr(SANCTIONED_2)].into_iter().collect();
+
+        let anomalies = detect_blacklist_adresses(&ctx, &blacklist).await;
+        let flagged: HashSet<String> = anomalies
+            .iter()
+            .filter_map(|anomaly| anomaly.tx_hash().map(str::to_string))
+            .collect();
+        assert_eq!(anomalies.len(), 2);
+        assert_eq!(
+            flagged,
+            [
+                format!("{:?}", hash("from_sanctioned")),
+                format!("{:?}", hash("to_sanctioned"))
+            ]
+            .into()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_blacklist_reports_unavailable_sanctions_list() {
+        let dir = tempfile::tempdir().unwrap();
+        let detector = BlacklistDetector::new(SanctionsConfig {
+            sources: vec![SanctionsSourceConfig::File(dir.path().join("missing.txt"))],
+            ..SanctionsConfig::default()
+        });
+        let ctx = AnalysisContext::new(create_test_storage());
+
+        let findings = detector.run(&ctx).await;
+        assert_eq!(findings.anomalies.len(), 1);
+        assert!(matches!(
+            &findings.anomalies[0],
+            Anomaly::SanctionsListUnavailable { reasons } if reasons[0].contains("missing.txt")
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_blacklist_keeps_sanctions_list_for_its_ttl() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sanctions.txt");
+        fs::write(&path, format!("{:?}\n", addr(SANCTIONED))).unwrap();
+        let config = SanctionsConfig {
+            sources: vec![SanctionsSourceConfig::File(path.clone())],
+            ..SanctionsConfig::default()
+        };
+        let storage = create_test_storage();
+        storage.all_txs.write().await.push(Arc::new(make_tx(
+            "from_sanctioned",
+            SANCTIONED,
+            Some("receiver"),
+            eth("1"),
+            Utc::now(),
+        )));
+        let ctx = AnalysisContext::new(storage);
+
+        let cached = BlacklistDetector::new(config.clone());
+        let expired = BlacklistDetector::new(SanctionsConfig {
+            cache_ttl_hours: 0,
+            ..config
+        });
+        assert_eq!(cached.run(&ctx).await.anomalies.len(), 1);
+        assert_eq!(expired.run(&ctx).await.anomalies.len(), 1);
+
+        // The list is not read again before its TTL expires.
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            cached.run(&ctx).await.anomalies[..],
+            [Anomaly::BlacklistedAddress { .. }]
+        ));
+        assert!(matches!(
+            expired.run(&ctx).await.anomalies[..],
+            [Anomaly::SanctionsListUnavailable { .. }]
+        ));
+    }
+
+    #[test]
+    fn test_parse_address_ignores_case() {
+        let lowercase = "0x7a250d5630b4cf539739df2c5dacb4c659f2488d";
+        let expected = parse_address(lowercase).unwrap();
+        for spelling in [
+            "0x7a250d5630B4cF539739dF2C5dAcb4c659F2488D",
+            "0X7A250D5630B4CF539739DF2C5DACB4C659F2488D",
+            "7a250d5630b4cf539739df2c5dacb4c659f2488d",
+            "  0x7a250d5630B4cF539739dF2C5dAcb4c659F2488D\n",
+        ] {
+            assert_eq!(parse_address(spelling).unwrap(), expected, "{}", spelling);
+        }
+        assert_eq!(format_address(&expected), lowercase);
+        assert_eq!(
+            checksummed(&expected),
+            "0x7a250d5630B4cF539739dF2C5dAcb4c659F2488D"
+        );
+
+        for invalid in [
+            "",
+            "0x",
+            "0x7a250d5630b4cf539739df2c5dacb4c659f2488",
+            "0x7g250d5630b4cf539739df2c5dacb4c659f2488d",
+        ] {
+            assert!(parse_address(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mixed_case_addresses_match_everywhere() {
+        // Imported in EIP-55 case, listed in uppercase and lowercase.
+        let csv = format!(
+            "hash,from,to,value,block_number,timestamp\n\
+             {},{},0x7A250D5630B4CF539739DF2C5DACB4C659F2488D,1,1,1704067200\n",
+            hex(H256::from_low_u64_be(1)),
+            SANCTIONED
+        );
+        let storage = create_test_storage();
+        {
+            let mut all_txs = storage.all_txs.write().await;
+            for tx in read_csv(csv.as_bytes()).unwrap() {
+                all_txs.push(Arc::new(tx));
+            }
+        }
+        let ctx = AnalysisContext::new(storage);
+
+        let sanctions = parse_addresses("list", &SANCTIONED.to_lowercase()).unwrap();
+        let blacklist: SanctionsList = sanctions.into_iter().collect();
+        let anomalies = detect_blacklist_adresses(&ctx, &blacklist).await;
+        assert_eq!(anomalies.len(), 1);
+        assert!(matches!(
+            &anomalies[0],
+            Anomaly::BlacklistedAddress { addres, .. } if *addres == SANCTIONED.to_lowercase()
+        ));
+
+        let config = AnalyzerConfig::from_toml_str(
+            "[dex]\nrouters = [\"0x7a250d5630b4cf539739df2c5dacb4c659f2488d\"]",
+        )
+        .unwrap();
+        let routers = config.dex.routers.iter().copied().collect();
+        assert_eq!(detect_dex_trade(&ctx, &routers).await.len(), 1);
+        assert_eq!(
+            config.dex.routers[0],
+            AnalyzerConfig::default().dex.routers[0]
+        );
+
+        let err = AnalyzerConfig::from_toml_str("[dex]\nrouters = [\"0x1234\"]").unwrap_err();
+        assert!(matches!(err, ConfigError::Toml(_)));
+    }
+
+    /// Storage holding one quiet transaction at `origin` and `count` transactions
+    /// of 10 ETH from `sender1`, one per minute from `origin + first_minute`.
+    async fn historical_storage(
+        origin: DateTime<Utc>,
+        first_minute: i64,
+        count: i64,
+    ) -> Arc<TxStorage> {
+        let storage = create_test_storage();
+        storage
+            .insert(make_tx(
+                "quiet",
+                "quiet",
+                Some("receiver1"),
+                eth("1"),
+                origin,
+            ))
+            .await;
+        for i in 0..count {
+            storage
+                .insert(make_tx(
+                    &format!("historical{}", i),
+                    "sender1",
+                    Some("receiver1"),
+                    eth("10"),
+                    origin + Duration::minutes(first_minute + i),
+                ))
+                .await;
+        }
+        storage
+    }
+
+    #[tokio::test]
+    async fn test_high_frequency_windows_follow_the_data() {
+        let origin: DateTime<Utc> = "2021-01-01T00:00:00Z".parse().unwrap();
+        // 21 transactions from 02:05 to 02:25, years before `Utc::now()`.
+        let ctx = AnalysisContext::new(historical_storage(origin, 125, 21).await);
+
+        let anomalies = detect_high_frequency(&ctx, &HighFrequencyConfig::default()).await;
+        assert_eq!(anomalies.len(), 1);
+        match &anomalies[0] {
+            Anomaly::HighFrequency {
+                sender,
+                count,
+                window_start,
+                window_end,
+                ..
+            } => {
+                assert_eq!(*sender, hex(addr("sender1")));
+                assert_eq!(*count, 21);
+                // The earliest of the 10-minute steps holding all of them.
+                assert_eq!(*window_start, origin + Duration::minutes(90));
+                assert_eq!(*window_end, origin + Duration::minutes(150));
+            }
+            other => panic!("unexpected anomaly {:?}", other),
+        }
+        assert!(ctx.is_flagged(hash("historical0")).await);
+        assert!(!ctx.is_flagged(hash("quiet")).await);
+
+        let hourly = HighFrequencyConfig {
+            step_minutes: 60,
+            ..HighFrequencyConfig::default()
+        };
+        let ctx = AnalysisContext::new(historical_storage(origin, 125, 21).await);
+        match &detect_high_frequency(&ctx, &hourly).await[0] {
+            Anomaly::HighFrequency { window_start, .. } => {
+                assert_eq!(*window_start, origin + Duration::hours(2))
+            }
+            other => panic!("unexpected anomaly {:?}", other),
+        }
+
+        // No 15-minute window holds more than 15 of them.
+        let short = HighFrequencyConfig {
+            window_minutes: 15,
+            step_minutes: 5,
+            ..HighFrequencyConfig::default()
+        };
+        let ctx = AnalysisContext::new(historical_storage(origin, 125, 21).await);
+        assert!(detect_high_frequency(&ctx, &short).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_structuring_windows_follow_the_data() {
+        let origin: DateTime<Utc> = "2021-01-01T00:00:00Z".parse().unwrap();
+        let ctx = AnalysisContext::new(historical_storage(origin, 180, 12).await);
+
+        let anomalies = detect_structuring(&ctx, &StructuringConfig::default()).await;
+        assert_eq!(anomalies.len(), 1);
+        match &anomalies[0] {
+            Anomaly::Structuring {
+                sender,
+                count,
+                severity,
+                window_start,
+                window_end,
+                ..
+            } => {
+                assert_eq!(*sender, hex(addr("sender1")));
+                assert_eq!(*count, 12);
+                assert_eq!(*severity, Severity::Strong);
+                assert_eq!(*window_start, origin + Duration::minutes(140));
+                assert_eq!(*window_end, origin + Duration::minutes(200));
+            }
+            other => panic!("unexpected anomaly {:?}", other),
+        }
+
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_str().unwrap();
+        export_anomalies_csv(&anomalies, path).unwrap();
+        let content = fs::read_to_string(path).unwrap();
+        assert!(content.contains("window_start,window_end"));
+        assert!(content.contains("2021-01-01T02:20:00Z,2021-01-01T03:20:00Z"));
+    }
+
+    #[tokio::test]
+    async fn test_dataset_stats_are_computed_once_per_run() {
+        let storage = create_test_storage();
+        for i in 1..=100u64 {
+            let sender = if i % 2 == 0 { "even" } else { "odd" };
+            storage
+                .insert(make_tx(
+                    &format!("stats{}", i),
+                    sender,
+                    Some("receiver1"),
+                    eth(&i.to_string()),
+                    Utc::now(),
+                ))
+                .await;
+        }
+        storage
+            .insert_transfer(make_transfer("stats1", "token", "odd", U256::from(7)))
+            .await;
+
+        let ctx = AnalysisContext::new(storage.clone());
+        let stats = ctx.stats().await;
+        let native = stats.native();
+        assert_eq!(native.values.len(), 100);
+        assert_eq!(native.values.percentile(99.0), eth("99"));
+        assert_eq!(native.values.percentile(100.0), eth("100"));
+        assert_eq!(native.local_mean(&addr("even")), eth("51"));
+        assert_eq!(native.local_mean(&addr("odd")), eth("50"));
+        assert_eq!(native.local_mean(&addr("nobody")), U256::zero());
+        assert_eq!(
+            stats
+                .fee_baseline(&addr("even"), gwei("50") * U256::from(21000))
+                .map(|baseline| baseline.median),
+            Some(gwei("50") * U256::from(21000))
+        );
+        assert_eq!(stats.assets(false).len(), 1);
+        assert_eq!(stats.assets(true).len(), 2);
+        assert_eq!(stats.assets[1].token, Some(addr("token")));
+        assert_eq!(stats.assets[1].values.values(), &[U256::from(7)]);
+
+        // Later changes of the storage belong to the next run.
+        storage
+            .insert(make_tx("late", "odd", None, eth("1000"), Utc::now()))
+            .await;
+        assert!(std::ptr::eq(stats, ctx.stats().await));
+        assert_eq!(ctx.stats().await.native().values.len(), 100);
+        let next_run = DatasetStats::compute(storage.as_ref()).await;
+        assert_eq!(next_run.native().values.percentile(100.0), eth("1000"));
+    }
+
+    /// Median of sorted values, rounded down like the statistics module.
+    fn naive_median(sorted: &[U256]) -> U256 {
+        let (low, high) = (sorted[(sorted.len() - 1) / 2], sorted[sorted.len() / 2]);
+        (low + high) / 2
+    }
+
+    #[test]
+    fn test_history_leave_one_out_matches_brute_force() {
+        for len in 1..12u64 {
+            for seed in 0..5u64 {
+                let values: Vec<U256> = (0..len)
+                    .map(|i| U256::from((i * 7 + seed * 3) % (4 + seed * 5)))
+                    .collect();
+                let history = History::new(values.clone());
+                for (i, value) in values.iter().enumerate() {
+                    let mut others: Vec<U256> = values.clone();
+                    others.remove(i);
+                    let baseline = history.leave_one_out(*value);
+                    if others.is_empty() {
+                        assert_eq!(baseline, None);
+                        continue;
+                    }
+                    others.sort();
+                    let median = naive_median(&others);
+                    let mut deviations: Vec<U256> = others
+                        .iter()
+                        .map(|v| {
+                            if *v > median {
+                                *v - median
+                            } else {
+                                median - *v
+                            }
+                        })
+                        .collect();
+                    deviations.sort();
+                    let sum = deviations.iter().fold(U256::zero(), |acc, d| acc + d);
+
+                    let baseline = baseline.expect("value is part of the history");
+                    assert_eq!(baseline.median, median, "{:?} without {}", values, value);
+                    assert_eq!(baseline.mad, naive_median(&deviations));
+                    assert_eq!(baseline.mean_deviation, sum / U256::from(others.len()));
+                    assert_eq!(baseline.history, others.len());
+                }
+            }
+        }
+        assert_eq!(
+            History::new(vec![eth("1"), eth("2")]).leave_one_out(eth("3")),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_large_tx_uses_robust_sender_baselines() {
+        let storage = create_test_storage();
+        let insert = |name: String, sender: &str, value: U256| {
+            let storage = storage.clone();
+            let tx = make_tx(&name, sender, Some("receiver1"), value, Utc::now());
+            async move { storage.insert(tx).await }
+        };
+        // Two outliers used to raise the mean above five times themselves.
+        for (i, value) in ["1", "1.1", "0.9", "1.05", "0.95", "1.2"]
+            .iter()
+            .enumerate()
+        {
+            insert(format!("masked{}", i), "masked", eth(value)).await;
+        }
+        insert("masked_a".to_string(), "masked", eth("50")).await;
+        insert("masked_b".to_string(), "masked", eth("50")).await;
+        // Too short a history to judge locally.
+        for (i, value) in ["1", "1", "40"].iter().enumerate() {
+            insert(format!("short{}", i), "short", eth(value)).await;
+        }
+        // A constant history: a transfer of more than `flat_ratio` times it stands out.
+        for i in 0..10 {
+            insert(format!("steady{}", i), "steady", eth("1")).await;
+        }
+        insert("steady_large".to_string(), "steady", eth("1000")).await;
+
+        let config = LargeTxConfig {
+            // No global threshold, only the sender baselines.
+            percentile: 100.0,
+            ..LargeTxConfig::default()
+        };
+        let anomalies = detect_large_tx(&AnalysisContext::new(storage.clone()), &config).await;
+
+        assert_eq!(anomalies.len(), 3);
+        for (anomaly, name) in anomalies[0..2].iter().zip(["masked_a", "masked_b"]) {
+            match anomaly {
+                Anomaly::LargeTx {
+                    tx_hash,
+                    severity,
+                    median,
+                    z_score,
+                    reasons,
+                    ..
+                } => {
+                    assert_eq!(*tx_hash, hex(hash(name)));
+                    assert_eq!(*severity, Severity::Weak);
+                    assert_eq!(*median, Some(eth("1.05")));
+                    // 48.95 ETH above the median, the MAD of the others is 0.1 ETH.
+                    let z = z_score.unwrap();
+                    assert!((z - 48.95 / (0.1 * 1.4826)).abs() < 1e-6, "{}", z);
+                    assert!(reasons[1].contains("median 1.05 ETH of the sender's 7 other"));
+                }
+                _ => panic!("Wrong Anomaly type"),
+            }
+        }
+        match &anomalies[2] {
+            Anomaly::LargeTx {
+                tx_hash,
+                median,
+                z_score,
+                reasons,
+                ..
+            } => {
+                assert_eq!(*tx_hash, hex(hash("steady_large")));
+                assert_eq!(*median, Some(eth("1")));
+                assert_eq!(*z_score, None);
+                assert!(reasons[1].contains("10 other transactions, which all moved exactly 1 ETH"));
+            }
+            _ => panic!("Wrong Anomaly type"),
+        }
+
+        let csv_path = tempfile::NamedTempFile::new().unwrap();
+        let path = csv_path.path().to_str().unwrap();
+        export_anomalies_csv(&anomalies, path).unwrap();
+        let content = fs::read_to_string(path).unwrap();
+        assert!(content.contains("median_eth,median_token_units,z_score"));
+        assert!(content.contains(",1.05,,"));
+        assert!(!content.contains(&eth("1.05").to_string()));
+
+        let strict = LargeTxConfig {
+            min_history: 8,
+            ..config
+        };
+        let hashes: Vec<String> = detect_large_tx(&AnalysisContext::new(storage), &strict)
+            .await
+            .iter()
+            .map(|anomaly| match anomaly {
+                Anomaly::LargeTx { tx_hash, .. } => tx_hash.clone(),
+                _ => panic!("Wrong Anomaly type"),
+            })
+            .collect();
+        assert_eq!(hashes, vec![hex(hash("steady_large"))]);
+    }
+
+    #[tokio::test]
+    async fn test_large_tx_ignores_payments_after_zero_value_calls() {
+        let storage = create_test_storage();
+        // A bot that only called contracts without value, then pays once.
+        for i in 0..20 {
+            storage
+                .insert(make_tx(
+                    &format!("call{}", i),
+                    "bot",
+                    Some("receiver1"),
+                    U256::zero(),
+                    Utc::now(),
+                ))
+                .await;
+        }
+        storage
+            .insert(make_tx(
+                "payment",
+                "bot",
+                Some("receiver1"),
+                eth("2"),
+                Utc::now(),
+            ))
+            .await;
+        // A constant history, and a transfer below `flat_ratio` times it.
+        for i in 0..10 {
+            storage
+                .insert(make_tx(
+                    &format!("steady{}", i),
+                    "steady",
+                    Some("receiver1"),
+                    eth("1"),
+                    Utc::now(),
+                ))
+                .await;
+        }
+        storage
+            .insert(make_tx(
+                "steady_more",
+                "steady",
+                Some("receiver1"),
+                eth("5"),
+                Utc::now(),
+            ))
+            .await;
+
+        let config = LargeTxConfig {
+            percentile: 100.0,
+            ..LargeTxConfig::default()
+        };
+        let anomalies = detect_large_tx(&AnalysisContext::new(storage), &config).await;
+        assert!(anomalies.is_empty(), "{:?}", anomalies);
+
+        let baseline = History::new(vec![U256::zero(); 3])
+            .leave_one_out(U256::zero())
+            .unwrap();
+        assert!(!baseline.exceeds(eth("1000"), 3.5, 10.0));
+        let baseline = History::new(vec![eth("1"); 3])
+            .leave_one_out(eth("1"))
+            .unwrap();
+        assert!(!baseline.exceeds(eth("10"), 3.5, 10.0));
+        assert!(baseline.exceeds(eth("10.000001"), 3.5, 10.0));
+    }
+
+    #[tokio::test]
+    async fn test_high_fee_uses_robust_sender_baselines() {
+        let storage = create_test_storage();
+        for (i, price) in ["20", "21", "22", "20", "23", "21", "500"]
+            .iter()
+            .enumerate()
+        {
+            let mut tx = make_tx(
+                &format!("fee{}", i),
+                "sender1",
+                Some("receiver1"),
+                eth("1"),
+                Utc::now(),
+            );
+            tx.gas_price = gwei(price);
+            storage.insert(tx).await;
+        }
+        let config = HighFeeConfig {
+            percentile: 100.0,
+            ..HighFeeConfig::default()
+        };
+        let anomalies = detect_high_fee(&AnalysisContext::new(storage), &config).await;
+
+        assert_eq!(anomalies.len(), 1);
+        match &anomalies[0] {
+            Anomaly::HighFee {
+                tx_hash,
+                median,
+                z_score,
+                ..
+            } => {
+                assert_eq!(*tx_hash, hex(hash("fee6")));
+                assert_eq!(*median, Some(gwei("21") * U256::from(21000)));
+                assert!(z_score.unwrap() > 100.0);
+            }
+            _ => panic!("Wrong Anomaly type"),
+        }
+    }
 }